@@ -0,0 +1,60 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+
+//! Errors returned instead of panicking on a PE state the pseudocode cannot
+//! handle.
+//!
+//! A [`TranslationError`] is not an architectural fault: translation and
+//! permission faults are reported in a `FaultRecord`. It means the
+//! configuration of the PE is outside what the architecture defines or what
+//! this crate models, so no meaningful result exists.
+
+use std::fmt;
+
+use crate::pe::*;
+use crate::shared_vmsa::PrivilegeLevel;
+
+/// Configuration of a PE that translation cannot proceed with.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TranslationError {
+    /// `register.field` holds a reserved value.
+    ReservedValue {
+        register: &'static str,
+        field: &'static str,
+        value: u64,
+    },
+    /// The configuration is architecturally UNPREDICTABLE.
+    Unpredictable(&'static str),
+    /// PSTATE or a register refers to an Exception level that is not implemented.
+    ELNotImplemented(PrivilegeLevel),
+    /// The configuration needs behaviour this crate does not model yet.
+    Unsupported(&'static str),
+    /// The feature set is inconsistent.
+    Feature(FeatureError),
+}
+
+/// Alias for configuration checks that do not involve a translation.
+pub type ConfigError = TranslationError;
+
+impl fmt::Display for TranslationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReservedValue {
+                register,
+                field,
+                value,
+            } => write!(f, "{}.{} has reserved value {:#b}", register, field, value),
+            Self::Unpredictable(what) => write!(f, "UNPREDICTABLE: {}", what),
+            Self::ELNotImplemented(el) => write!(f, "{:?} is not implemented", el),
+            Self::Unsupported(what) => write!(f, "not supported: {}", what),
+            Self::Feature(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TranslationError {}
+
+impl From<FeatureError> for TranslationError {
+    fn from(err: FeatureError) -> Self {
+        Self::Feature(err)
+    }
+}
@@ -0,0 +1,774 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+#![allow(non_camel_case_types)]
+
+//! AArch64 ID registers.
+//!
+//! [`IdRegisters`] converts between the ID register values reported by an
+//! implementation and the [`FeatureSet`] and [`ImplementationDefined`]
+//! parameters consulted by the pseudocode. Only the fields that describe
+//! modelled behaviour are decoded; the other fields are encoded as zero.
+
+use crate::pe::*;
+
+mycelium_bitfield::bitfield! {
+    /// AArch64 Processor Feature Register 0
+    pub struct ID_AA64PFR0_EL1_REG<u64> {
+        pub const EL0 = 4;
+        pub const EL1 = 4;
+        pub const EL2 = 4;
+        pub const EL3 = 4;
+        pub const FP = 4;
+        pub const AdvSIMD = 4;
+        pub const GIC = 4;
+        pub const RAS = 4;
+        pub const SVE = 4;
+        pub const SEL2 = 4;
+        pub const MPAM = 4;
+        pub const AMU = 4;
+        pub const DIT = 4;
+        pub const RME = 4;
+        pub const CSV2 = 4;
+        pub const CSV3 = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// AArch64 Processor Feature Register 1
+    pub struct ID_AA64PFR1_EL1_REG<u64> {
+        pub const BT = 4;
+        pub const SSBS = 4;
+        pub const MTE = 4;
+        pub const RAS_frac = 4;
+        pub const MPAM_frac = 4;
+        const _RES0 = 4;
+        pub const SME = 4;
+        pub const RNDR_trap = 4;
+        pub const CSV2_frac = 4;
+        pub const NMI = 4;
+        pub const MTE_frac = 4;
+        pub const GCS = 4;
+        pub const THE = 4;
+        pub const MTEX = 4;
+        pub const DF2 = 4;
+        pub const PFAR = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// AArch64 Memory Model Feature Register 0
+    pub struct ID_AA64MMFR0_EL1_REG<u64> {
+        pub const PARange = 4;
+        pub const ASIDBits = 4;
+        pub const BigEnd = 4;
+        pub const SNSMem = 4;
+        pub const BigEndEL0 = 4;
+        pub const TGran16 = 4;
+        pub const TGran64 = 4;
+        pub const TGran4 = 4;
+        pub const TGran16_2 = 4;
+        pub const TGran64_2 = 4;
+        pub const TGran4_2 = 4;
+        pub const ExS = 4;
+        const _RES0 = 8;
+        pub const FGT = 4;
+        pub const ECV = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// AArch64 Memory Model Feature Register 1
+    pub struct ID_AA64MMFR1_EL1_REG<u64> {
+        pub const HAFDBS = 4;
+        pub const VMIDBits = 4;
+        pub const VH = 4;
+        pub const HPDS = 4;
+        pub const LO = 4;
+        pub const PAN = 4;
+        pub const SpecSEI = 4;
+        pub const XNX = 4;
+        pub const TWED = 4;
+        pub const ETS = 4;
+        pub const HCX = 4;
+        pub const AFP = 4;
+        pub const nTLBPA = 4;
+        pub const TIDCP1 = 4;
+        pub const CMOW = 4;
+        pub const ECBHB = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// AArch64 Memory Model Feature Register 2
+    pub struct ID_AA64MMFR2_EL1_REG<u64> {
+        pub const CnP = 4;
+        pub const UAO = 4;
+        pub const LSM = 4;
+        pub const IESB = 4;
+        pub const VARange = 4;
+        pub const CCIDX = 4;
+        pub const NV = 4;
+        pub const ST = 4;
+        pub const AT = 4;
+        pub const IDS = 4;
+        pub const FWB = 4;
+        const _RES0 = 4;
+        pub const TTL = 4;
+        pub const BBM = 4;
+        pub const EVT = 4;
+        pub const E0PD = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// AArch64 Memory Model Feature Register 3
+    pub struct ID_AA64MMFR3_EL1_REG<u64> {
+        pub const TCRX = 4;
+        pub const SCTLRX = 4;
+        pub const S1PIE = 4;
+        pub const S2PIE = 4;
+        pub const S1POE = 4;
+        pub const S2POE = 4;
+        pub const AIE = 4;
+        pub const MEC = 4;
+        pub const D128 = 4;
+        pub const D128_2 = 4;
+        pub const SNERR = 4;
+        pub const ANERR = 4;
+        const _RES0 = 4;
+        pub const SDERR = 4;
+        pub const ADERR = 4;
+        pub const Spec_FPACC = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// AArch64 Instruction Set Attribute Register 0
+    pub struct ID_AA64ISAR0_EL1_REG<u64> {
+        const _RES0 = 4;
+        pub const AES = 4;
+        pub const SHA1 = 4;
+        pub const SHA2 = 4;
+        pub const CRC32 = 4;
+        pub const Atomic = 4;
+        pub const TME = 4;
+        pub const RDM = 4;
+        pub const SHA3 = 4;
+        pub const SM3 = 4;
+        pub const SM4 = 4;
+        pub const DP = 4;
+        pub const FHM = 4;
+        pub const TS = 4;
+        pub const TLB = 4;
+        pub const RNDR = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// AArch64 Instruction Set Attribute Register 1
+    pub struct ID_AA64ISAR1_EL1_REG<u64> {
+        pub const DPB = 4;
+        pub const APA = 4;
+        pub const API = 4;
+        pub const JSCVT = 4;
+        pub const FCMA = 4;
+        pub const LRCPC = 4;
+        pub const GPA = 4;
+        pub const GPI = 4;
+        pub const FRINTTS = 4;
+        pub const SB = 4;
+        pub const SPECRES = 4;
+        pub const BF16 = 4;
+        pub const DGH = 4;
+        pub const I8MM = 4;
+        pub const XS = 4;
+        pub const LS64 = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// AArch64 Instruction Set Attribute Register 2
+    pub struct ID_AA64ISAR2_EL1_REG<u64> {
+        pub const WFxT = 4;
+        pub const RPRES = 4;
+        pub const GPA3 = 4;
+        pub const APA3 = 4;
+        pub const MOPS = 4;
+        pub const BC = 4;
+        pub const PAC_frac = 4;
+        pub const CLRBHB = 4;
+        pub const SYSREG_128 = 4;
+        pub const SYSINSTR_128 = 4;
+        pub const PRFMSLC = 4;
+        const _RES0 = 4;
+        pub const RPRFM = 4;
+        pub const CSSC = 4;
+        pub const LUT = 4;
+        pub const ATS1A = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// AArch64 Debug Feature Register 0
+    pub struct ID_AA64DFR0_EL1_REG<u64> {
+        pub const DebugVer = 4;
+        pub const TraceVer = 4;
+        pub const PMUVer = 4;
+        pub const BRPs = 4;
+        pub const PMSS = 4;
+        pub const WRPs = 4;
+        pub const SEBEP = 4;
+        pub const CTX_CMPs = 4;
+        pub const PMSVer = 4;
+        pub const DoubleLock = 4;
+        pub const TraceFilt = 4;
+        pub const TraceBuffer = 4;
+        pub const MTPMU = 4;
+        pub const BRBE = 4;
+        pub const ExtTrcBuff = 4;
+        pub const HPMN0 = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// MPAM ID Register
+    pub struct MPAMIDR_EL1_REG<u64> {
+        pub const PARTID_MAX = 16;
+        const _RES0_0 = 1;
+        pub const HAS_HCR = 1;
+        pub const VPMR_MAX = 3;
+        const _RES0_1 = 11;
+        pub const PMG_MAX = 8;
+        const _RES0_2 = 17;
+        pub const HAS_ALTSP = 1;
+        pub const HAS_TIDR = 1;
+        pub const SP4 = 1;
+        pub const HAS_FORCE_NS = 1;
+        pub const HAS_SDEFLT = 1;
+    }
+}
+
+/// ID register values of a processing element.
+#[derive(Copy, Clone, Debug)]
+pub struct IdRegisters {
+    pub ID_AA64PFR0_EL1: ID_AA64PFR0_EL1_REG,
+    pub ID_AA64PFR1_EL1: ID_AA64PFR1_EL1_REG,
+    pub ID_AA64MMFR0_EL1: ID_AA64MMFR0_EL1_REG,
+    pub ID_AA64MMFR1_EL1: ID_AA64MMFR1_EL1_REG,
+    pub ID_AA64MMFR2_EL1: ID_AA64MMFR2_EL1_REG,
+    pub ID_AA64MMFR3_EL1: ID_AA64MMFR3_EL1_REG,
+    pub ID_AA64ISAR0_EL1: ID_AA64ISAR0_EL1_REG,
+    pub ID_AA64ISAR1_EL1: ID_AA64ISAR1_EL1_REG,
+    pub ID_AA64ISAR2_EL1: ID_AA64ISAR2_EL1_REG,
+    pub ID_AA64DFR0_EL1: ID_AA64DFR0_EL1_REG,
+    pub MPAMIDR_EL1: MPAMIDR_EL1_REG,
+}
+
+/// ID_AA64MMFR0_EL1.PARange encodings and the physical address size they stand for.
+const PARANGE: [(u64, u64); 8] = [
+    (0b0000, 32),
+    (0b0001, 36),
+    (0b0010, 40),
+    (0b0011, 42),
+    (0b0100, 44),
+    (0b0101, 48),
+    (0b0110, 52),
+    (0b0111, 56),
+];
+
+impl IdRegisters {
+    /// Registers from raw values, in the order of the struct fields.
+    #[allow(clippy::too_many_arguments)]
+    pub const fn from_bits(
+        pfr0: u64,
+        pfr1: u64,
+        mmfr0: u64,
+        mmfr1: u64,
+        mmfr2: u64,
+        mmfr3: u64,
+        isar0: u64,
+        isar1: u64,
+        isar2: u64,
+        dfr0: u64,
+        mpamidr: u64,
+    ) -> Self {
+        Self {
+            ID_AA64PFR0_EL1: ID_AA64PFR0_EL1_REG::from_bits(pfr0),
+            ID_AA64PFR1_EL1: ID_AA64PFR1_EL1_REG::from_bits(pfr1),
+            ID_AA64MMFR0_EL1: ID_AA64MMFR0_EL1_REG::from_bits(mmfr0),
+            ID_AA64MMFR1_EL1: ID_AA64MMFR1_EL1_REG::from_bits(mmfr1),
+            ID_AA64MMFR2_EL1: ID_AA64MMFR2_EL1_REG::from_bits(mmfr2),
+            ID_AA64MMFR3_EL1: ID_AA64MMFR3_EL1_REG::from_bits(mmfr3),
+            ID_AA64ISAR0_EL1: ID_AA64ISAR0_EL1_REG::from_bits(isar0),
+            ID_AA64ISAR1_EL1: ID_AA64ISAR1_EL1_REG::from_bits(isar1),
+            ID_AA64ISAR2_EL1: ID_AA64ISAR2_EL1_REG::from_bits(isar2),
+            ID_AA64DFR0_EL1: ID_AA64DFR0_EL1_REG::from_bits(dfr0),
+            MPAMIDR_EL1: MPAMIDR_EL1_REG::from_bits(mpamidr),
+        }
+    }
+
+    /// Decode the implemented features and IMPLEMENTATION DEFINED parameters.
+    ///
+    /// Fails if the registers describe an inconsistent set of features.
+    pub fn decode(&self) -> Result<(FeatureSet, ImplementationDefined), FeatureError> {
+        use Feature::*;
+
+        let pfr0 = self.ID_AA64PFR0_EL1;
+        let pfr1 = self.ID_AA64PFR1_EL1;
+        let mmfr0 = self.ID_AA64MMFR0_EL1;
+        let mmfr1 = self.ID_AA64MMFR1_EL1;
+        let mmfr2 = self.ID_AA64MMFR2_EL1;
+        let mmfr3 = self.ID_AA64MMFR3_EL1;
+        let isar0 = self.ID_AA64ISAR0_EL1;
+        let isar1 = self.ID_AA64ISAR1_EL1;
+        let isar2 = self.ID_AA64ISAR2_EL1;
+
+        let mut features = vec![];
+        let mut add = |cond: bool, feats: &[Feature]| {
+            if cond {
+                features.extend_from_slice(feats);
+            }
+        };
+
+        // ID_AA64PFR0_EL1
+        let el2 = pfr0.get(ID_AA64PFR0_EL1_REG::EL2);
+        add(el2 != 0, &[FEAT_AA64EL2]);
+        add(el2 == 0b0010, &[FEAT_AA32EL2]);
+        let el3 = pfr0.get(ID_AA64PFR0_EL1_REG::EL3);
+        add(el3 != 0, &[FEAT_AA64EL3]);
+        add(el3 == 0b0010, &[FEAT_AA32EL3]);
+        add(pfr0.get(ID_AA64PFR0_EL1_REG::SVE) != 0, &[FEAT_SVE]);
+        add(pfr0.get(ID_AA64PFR0_EL1_REG::SEL2) != 0, &[FEAT_SEL2]);
+        add(pfr0.get(ID_AA64PFR0_EL1_REG::RME) != 0, &[FEAT_RME]);
+        let mpam = pfr0.get(ID_AA64PFR0_EL1_REG::MPAM);
+        let mpam_frac = pfr1.get(ID_AA64PFR1_EL1_REG::MPAM_frac);
+        add(mpam != 0 || mpam_frac != 0, &[FEAT_MPAM]);
+        add(mpam == 0 && mpam_frac == 1, &[FEAT_MPAMv0p1]);
+        add(mpam == 1 && mpam_frac == 1, &[FEAT_MPAMv1p1]);
+
+        // ID_AA64PFR1_EL1
+        let mte = pfr1.get(ID_AA64PFR1_EL1_REG::MTE);
+        add(mte >= 0b0001, &[FEAT_MTE]);
+        add(mte >= 0b0010, &[FEAT_MTE2]);
+        add(mte >= 0b0011, &[FEAT_MTE3]);
+        add(pfr1.get(ID_AA64PFR1_EL1_REG::SME) != 0, &[FEAT_SME]);
+        add(pfr1.get(ID_AA64PFR1_EL1_REG::THE) != 0, &[FEAT_THE]);
+
+        // ID_AA64MMFR0_EL1
+        let parange = mmfr0.get(ID_AA64MMFR0_EL1_REG::PARange);
+        let pamax = PARANGE
+            .iter()
+            .find(|(enc, _)| *enc == parange)
+            // Reserved encodings are treated as the largest defined size
+            .map_or(56, |(_, bits)| *bits);
+        add(pamax >= 52, &[FEAT_LPA]);
+        let tgran4 = mmfr0.get(ID_AA64MMFR0_EL1_REG::TGran4);
+        let tgran16 = mmfr0.get(ID_AA64MMFR0_EL1_REG::TGran16);
+        let tgran64 = mmfr0.get(ID_AA64MMFR0_EL1_REG::TGran64);
+        add(tgran4 == 0b0001 || tgran16 == 0b0010, &[FEAT_LPA2]);
+        let s1_tgran4 = tgran4 != 0b1111;
+        let s1_tgran16 = tgran16 != 0b0000;
+        let s1_tgran64 = tgran64 != 0b1111;
+        // 0b0000 means stage 2 support is identified by the stage 1 field
+        let s2_tgran = |field: u64, s1: bool| match field {
+            0b0000 => s1,
+            0b0001 => false,
+            _ => true,
+        };
+
+        // ID_AA64MMFR1_EL1
+        let hafdbs = mmfr1.get(ID_AA64MMFR1_EL1_REG::HAFDBS);
+        add(hafdbs >= 0b0010, &[FEAT_HAFDBS]);
+        add(hafdbs >= 0b0011, &[FEAT_HAFT]);
+        add(hafdbs >= 0b0100, &[FEAT_HDBSS]);
+        add(
+            mmfr1.get(ID_AA64MMFR1_EL1_REG::VMIDBits) == 0b0010,
+            &[FEAT_VMID16],
+        );
+        add(mmfr1.get(ID_AA64MMFR1_EL1_REG::VH) != 0, &[FEAT_VHE]);
+        let hpds = mmfr1.get(ID_AA64MMFR1_EL1_REG::HPDS);
+        add(hpds >= 0b0001, &[FEAT_HPDS]);
+        add(hpds >= 0b0010, &[FEAT_HPDS2]);
+        add(mmfr1.get(ID_AA64MMFR1_EL1_REG::LO) != 0, &[FEAT_LOR]);
+        let pan = mmfr1.get(ID_AA64MMFR1_EL1_REG::PAN);
+        add(pan >= 0b0001, &[FEAT_PAN]);
+        add(pan >= 0b0010, &[FEAT_PAN2]);
+        add(pan >= 0b0011, &[FEAT_PAN3]);
+        add(mmfr1.get(ID_AA64MMFR1_EL1_REG::XNX) != 0, &[FEAT_XNX]);
+        add(mmfr1.get(ID_AA64MMFR1_EL1_REG::HCX) != 0, &[FEAT_HCX]);
+        add(mmfr1.get(ID_AA64MMFR1_EL1_REG::CMOW) != 0, &[FEAT_CMOW]);
+
+        // ID_AA64MMFR2_EL1
+        add(mmfr2.get(ID_AA64MMFR2_EL1_REG::LSM) != 0, &[FEAT_LSMAOC]);
+        let varange = mmfr2.get(ID_AA64MMFR2_EL1_REG::VARange);
+        add(varange >= 0b0001, &[FEAT_LVA]);
+        add(varange >= 0b0010, &[FEAT_LVA3]);
+        add(mmfr2.get(ID_AA64MMFR2_EL1_REG::UAO) != 0, &[FEAT_UAO]);
+        add(mmfr2.get(ID_AA64MMFR2_EL1_REG::ST) != 0, &[FEAT_TTST]);
+        add(mmfr2.get(ID_AA64MMFR2_EL1_REG::AT) != 0, &[FEAT_LSE2]);
+        add(mmfr2.get(ID_AA64MMFR2_EL1_REG::FWB) != 0, &[FEAT_S2FWB]);
+        add(mmfr2.get(ID_AA64MMFR2_EL1_REG::TTL) != 0, &[FEAT_TTL]);
+        add(mmfr2.get(ID_AA64MMFR2_EL1_REG::E0PD) != 0, &[FEAT_E0PD]);
+
+        // ID_AA64MMFR3_EL1
+        add(mmfr3.get(ID_AA64MMFR3_EL1_REG::TCRX) != 0, &[FEAT_TCR2]);
+        add(mmfr3.get(ID_AA64MMFR3_EL1_REG::SCTLRX) != 0, &[FEAT_SCTLR2]);
+        add(mmfr3.get(ID_AA64MMFR3_EL1_REG::S1PIE) != 0, &[FEAT_S1PIE]);
+        add(mmfr3.get(ID_AA64MMFR3_EL1_REG::S2PIE) != 0, &[FEAT_S2PIE]);
+        add(mmfr3.get(ID_AA64MMFR3_EL1_REG::S1POE) != 0, &[FEAT_S1POE]);
+        add(mmfr3.get(ID_AA64MMFR3_EL1_REG::S2POE) != 0, &[FEAT_S2POE]);
+        add(mmfr3.get(ID_AA64MMFR3_EL1_REG::AIE) != 0, &[FEAT_AIE]);
+        add(mmfr3.get(ID_AA64MMFR3_EL1_REG::MEC) != 0, &[FEAT_MEC]);
+        add(mmfr3.get(ID_AA64MMFR3_EL1_REG::D128) != 0, &[FEAT_D128]);
+
+        // ID_AA64ISAR0_EL1
+        let atomic = isar0.get(ID_AA64ISAR0_EL1_REG::Atomic);
+        add(atomic >= 0b0010, &[FEAT_LSE]);
+        add(atomic >= 0b0011, &[FEAT_LSE128]);
+        add(isar0.get(ID_AA64ISAR0_EL1_REG::TME) != 0, &[FEAT_TME]);
+
+        // ID_AA64ISAR1_EL1 and ID_AA64ISAR2_EL1
+        add(
+            isar1.get(ID_AA64ISAR1_EL1_REG::APA) != 0
+                || isar1.get(ID_AA64ISAR1_EL1_REG::API) != 0
+                || isar2.get(ID_AA64ISAR2_EL1_REG::APA3) != 0,
+            &[FEAT_PAuth],
+        );
+        let lrcpc = isar1.get(ID_AA64ISAR1_EL1_REG::LRCPC);
+        add(lrcpc >= 0b0001, &[FEAT_LRCPC]);
+        add(lrcpc >= 0b0010, &[FEAT_LRCPC2]);
+        add(lrcpc >= 0b0011, &[FEAT_LRCPC3]);
+        add(isar1.get(ID_AA64ISAR1_EL1_REG::XS) != 0, &[FEAT_XS]);
+        add(isar1.get(ID_AA64ISAR1_EL1_REG::LS64) != 0, &[FEAT_LS64]);
+        add(isar2.get(ID_AA64ISAR2_EL1_REG::MOPS) != 0, &[FEAT_MOPS]);
+
+        let features = FeatureSet::from_features(&features)?;
+        let impdef = ImplementationDefined {
+            pamax,
+            asid_bits: if mmfr0.get(ID_AA64MMFR0_EL1_REG::ASIDBits) == 0b0010 {
+                16
+            } else {
+                8
+            },
+            s1_tgran4,
+            s1_tgran16,
+            s1_tgran64,
+            s2_tgran4: s2_tgran(mmfr0.get(ID_AA64MMFR0_EL1_REG::TGran4_2), s1_tgran4),
+            s2_tgran16: s2_tgran(mmfr0.get(ID_AA64MMFR0_EL1_REG::TGran16_2), s1_tgran16),
+            s2_tgran64: s2_tgran(mmfr0.get(ID_AA64MMFR0_EL1_REG::TGran64_2), s1_tgran64),
+            partid_max: if features.contains(FEAT_MPAM) {
+                self.MPAMIDR_EL1.get(MPAMIDR_EL1_REG::PARTID_MAX)
+            } else {
+                0
+            },
+            pmg_max: if features.contains(FEAT_MPAM) {
+                self.MPAMIDR_EL1.get(MPAMIDR_EL1_REG::PMG_MAX)
+            } else {
+                0
+            },
+            // Not reported in the ID registers
+            secure_only: false,
+            af_update_only: hafdbs == 0b0001,
+        };
+
+        Ok((features, impdef))
+    }
+
+    /// Encode `features` and `impdef` as ID register values.
+    ///
+    /// Each field takes the lowest value that identifies the features, so
+    /// decoding the result gives back the same configuration. FEAT_LPA is
+    /// identified by PARange and so follows `impdef.pamax`. FEAT_ASID2,
+    /// FEAT_MTE4 and FEAT_MTE_PERM are reported in registers not modelled
+    /// here and are lost, as are the extension level and `impdef.secure_only`.
+    pub fn encode(features: &FeatureSet, impdef: &ImplementationDefined) -> Self {
+        use Feature::*;
+
+        let has = |feat: Feature| features.contains(feat) as u64;
+        // Highest level implemented out of a field's successive values
+        let level =
+            |feats: &[Feature]| feats.iter().filter(|f| features.contains(**f)).count() as u64;
+        let lpa2 = features.contains(FEAT_LPA2);
+
+        let el2 = match (has(FEAT_AA64EL2), has(FEAT_AA32EL2)) {
+            (0, _) => 0b0000,
+            (_, 0) => 0b0001,
+            _ => 0b0010,
+        };
+        let el3 = match (has(FEAT_AA64EL3), has(FEAT_AA32EL3)) {
+            (0, _) => 0b0000,
+            (_, 0) => 0b0001,
+            _ => 0b0010,
+        };
+        let (mpam, mpam_frac) = if features.contains(FEAT_MPAMv1p1) {
+            (1, 1)
+        } else if features.contains(FEAT_MPAMv0p1) {
+            (0, 1)
+        } else {
+            (has(FEAT_MPAM), 0)
+        };
+        let pfr0 = ID_AA64PFR0_EL1_REG::new()
+            .with(ID_AA64PFR0_EL1_REG::EL0, 0b0001)
+            .with(ID_AA64PFR0_EL1_REG::EL1, 0b0001)
+            .with(ID_AA64PFR0_EL1_REG::EL2, el2)
+            .with(ID_AA64PFR0_EL1_REG::EL3, el3)
+            .with(ID_AA64PFR0_EL1_REG::SVE, has(FEAT_SVE))
+            .with(ID_AA64PFR0_EL1_REG::SEL2, has(FEAT_SEL2))
+            .with(ID_AA64PFR0_EL1_REG::MPAM, mpam)
+            .with(ID_AA64PFR0_EL1_REG::RME, has(FEAT_RME));
+        let pfr1 = ID_AA64PFR1_EL1_REG::new()
+            .with(
+                ID_AA64PFR1_EL1_REG::MTE,
+                level(&[FEAT_MTE, FEAT_MTE2, FEAT_MTE3]),
+            )
+            .with(ID_AA64PFR1_EL1_REG::MPAM_frac, mpam_frac)
+            .with(ID_AA64PFR1_EL1_REG::SME, has(FEAT_SME))
+            .with(ID_AA64PFR1_EL1_REG::THE, has(FEAT_THE));
+
+        let parange = PARANGE
+            .iter()
+            .find(|(_, bits)| *bits >= impdef.pamax)
+            .map_or(0b0111, |(enc, _)| *enc);
+        let s2_tgran = |s2: bool, s1: bool, lpa2: bool| match (s2, s1) {
+            (true, true) | (false, false) => 0b0000,
+            (false, true) => 0b0001,
+            (true, false) if lpa2 => 0b0011,
+            (true, false) => 0b0010,
+        };
+        let mmfr0 = ID_AA64MMFR0_EL1_REG::new()
+            .with(ID_AA64MMFR0_EL1_REG::PARange, parange)
+            .with(
+                ID_AA64MMFR0_EL1_REG::ASIDBits,
+                if impdef.asid_bits == 16 {
+                    0b0010
+                } else {
+                    0b0000
+                },
+            )
+            .with(
+                ID_AA64MMFR0_EL1_REG::TGran4,
+                match (impdef.s1_tgran4, lpa2) {
+                    (false, _) => 0b1111,
+                    (true, false) => 0b0000,
+                    (true, true) => 0b0001,
+                },
+            )
+            .with(
+                ID_AA64MMFR0_EL1_REG::TGran16,
+                match (impdef.s1_tgran16, lpa2) {
+                    (false, _) => 0b0000,
+                    (true, false) => 0b0001,
+                    (true, true) => 0b0010,
+                },
+            )
+            .with(
+                ID_AA64MMFR0_EL1_REG::TGran64,
+                if impdef.s1_tgran64 { 0b0000 } else { 0b1111 },
+            )
+            .with(
+                ID_AA64MMFR0_EL1_REG::TGran4_2,
+                s2_tgran(impdef.s2_tgran4, impdef.s1_tgran4, lpa2),
+            )
+            .with(
+                ID_AA64MMFR0_EL1_REG::TGran16_2,
+                s2_tgran(impdef.s2_tgran16, impdef.s1_tgran16, lpa2),
+            )
+            .with(
+                ID_AA64MMFR0_EL1_REG::TGran64_2,
+                s2_tgran(impdef.s2_tgran64, impdef.s1_tgran64, false),
+            );
+        let mmfr1 = ID_AA64MMFR1_EL1_REG::new()
+            .with(
+                ID_AA64MMFR1_EL1_REG::HAFDBS,
+                if features.contains(FEAT_HDBSS) {
+                    0b0100
+                } else if features.contains(FEAT_HAFT) {
+                    0b0011
+                } else if features.contains(FEAT_HAFDBS) {
+                    0b0010
+                } else if impdef.af_update_only {
+                    0b0001
+                } else {
+                    0b0000
+                },
+            )
+            .with(ID_AA64MMFR1_EL1_REG::VMIDBits, has(FEAT_VMID16) << 1)
+            .with(ID_AA64MMFR1_EL1_REG::VH, has(FEAT_VHE))
+            .with(ID_AA64MMFR1_EL1_REG::HPDS, level(&[FEAT_HPDS, FEAT_HPDS2]))
+            .with(ID_AA64MMFR1_EL1_REG::LO, has(FEAT_LOR))
+            .with(
+                ID_AA64MMFR1_EL1_REG::PAN,
+                level(&[FEAT_PAN, FEAT_PAN2, FEAT_PAN3]),
+            )
+            .with(ID_AA64MMFR1_EL1_REG::XNX, has(FEAT_XNX))
+            .with(ID_AA64MMFR1_EL1_REG::HCX, has(FEAT_HCX))
+            .with(ID_AA64MMFR1_EL1_REG::CMOW, has(FEAT_CMOW));
+        let mmfr2 = ID_AA64MMFR2_EL1_REG::new()
+            .with(ID_AA64MMFR2_EL1_REG::UAO, has(FEAT_UAO))
+            .with(ID_AA64MMFR2_EL1_REG::LSM, has(FEAT_LSMAOC))
+            .with(ID_AA64MMFR2_EL1_REG::VARange, level(&[FEAT_LVA, FEAT_LVA3]))
+            .with(ID_AA64MMFR2_EL1_REG::ST, has(FEAT_TTST))
+            .with(ID_AA64MMFR2_EL1_REG::AT, has(FEAT_LSE2))
+            .with(ID_AA64MMFR2_EL1_REG::FWB, has(FEAT_S2FWB))
+            .with(ID_AA64MMFR2_EL1_REG::TTL, has(FEAT_TTL))
+            .with(ID_AA64MMFR2_EL1_REG::E0PD, has(FEAT_E0PD));
+        let mmfr3 = ID_AA64MMFR3_EL1_REG::new()
+            .with(ID_AA64MMFR3_EL1_REG::TCRX, has(FEAT_TCR2))
+            .with(ID_AA64MMFR3_EL1_REG::SCTLRX, has(FEAT_SCTLR2))
+            .with(ID_AA64MMFR3_EL1_REG::S1PIE, has(FEAT_S1PIE))
+            .with(ID_AA64MMFR3_EL1_REG::S2PIE, has(FEAT_S2PIE))
+            .with(ID_AA64MMFR3_EL1_REG::S1POE, has(FEAT_S1POE))
+            .with(ID_AA64MMFR3_EL1_REG::S2POE, has(FEAT_S2POE))
+            .with(ID_AA64MMFR3_EL1_REG::AIE, has(FEAT_AIE))
+            .with(ID_AA64MMFR3_EL1_REG::MEC, has(FEAT_MEC))
+            .with(ID_AA64MMFR3_EL1_REG::D128, has(FEAT_D128))
+            .with(ID_AA64MMFR3_EL1_REG::D128_2, has(FEAT_D128));
+        let isar0 = ID_AA64ISAR0_EL1_REG::new()
+            .with(
+                ID_AA64ISAR0_EL1_REG::Atomic,
+                match level(&[FEAT_LSE, FEAT_LSE128]) {
+                    0 => 0b0000,
+                    n => n + 1,
+                },
+            )
+            .with(ID_AA64ISAR0_EL1_REG::TME, has(FEAT_TME));
+        let isar1 = ID_AA64ISAR1_EL1_REG::new()
+            .with(ID_AA64ISAR1_EL1_REG::API, has(FEAT_PAuth))
+            .with(
+                ID_AA64ISAR1_EL1_REG::LRCPC,
+                level(&[FEAT_LRCPC, FEAT_LRCPC2, FEAT_LRCPC3]),
+            )
+            .with(ID_AA64ISAR1_EL1_REG::XS, has(FEAT_XS))
+            .with(ID_AA64ISAR1_EL1_REG::LS64, has(FEAT_LS64));
+        let isar2 = ID_AA64ISAR2_EL1_REG::new().with(ID_AA64ISAR2_EL1_REG::MOPS, has(FEAT_MOPS));
+        let mpamidr = if features.contains(FEAT_MPAM) {
+            MPAMIDR_EL1_REG::new()
+                .with(MPAMIDR_EL1_REG::PARTID_MAX, impdef.partid_max)
+                .with(MPAMIDR_EL1_REG::PMG_MAX, impdef.pmg_max)
+        } else {
+            MPAMIDR_EL1_REG::new()
+        };
+
+        Self {
+            ID_AA64PFR0_EL1: pfr0,
+            ID_AA64PFR1_EL1: pfr1,
+            ID_AA64MMFR0_EL1: mmfr0,
+            ID_AA64MMFR1_EL1: mmfr1,
+            ID_AA64MMFR2_EL1: mmfr2,
+            ID_AA64MMFR3_EL1: mmfr3,
+            ID_AA64ISAR0_EL1: isar0,
+            ID_AA64ISAR1_EL1: isar1,
+            ID_AA64ISAR2_EL1: isar2,
+            // Armv8.0 debug, the lowest version an AArch64 PE can report
+            ID_AA64DFR0_EL1: ID_AA64DFR0_EL1_REG::new().with(ID_AA64DFR0_EL1_REG::DebugVer, 0b0110),
+            MPAMIDR_EL1: mpamidr,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::*;
+    use crate::shared_translation::*;
+    use crate::shared_vmsa::*;
+    use crate::sysregs::*;
+    use Feature::*;
+
+    #[test]
+    fn mpamidr_fields() {
+        let mpamidr = MPAMIDR_EL1_REG::from_bits(0x2200_00ff_0002_003f);
+        assert_eq!(mpamidr.get(MPAMIDR_EL1_REG::PARTID_MAX), 0x3f);
+        assert_eq!(mpamidr.get(MPAMIDR_EL1_REG::HAS_HCR), 1);
+        assert_eq!(mpamidr.get(MPAMIDR_EL1_REG::PMG_MAX), 0xff);
+        assert_eq!(mpamidr.get(MPAMIDR_EL1_REG::HAS_ALTSP), 1);
+        assert_eq!(mpamidr.get(MPAMIDR_EL1_REG::HAS_SDEFLT), 1);
+        assert_eq!(mpamidr.get(MPAMIDR_EL1_REG::HAS_FORCE_NS), 0);
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        for features in [
+            &[][..],
+            &[FEAT_AA64EL2, FEAT_AA64EL3, FEAT_SEL2, FEAT_MPAM, FEAT_LPA][..],
+            &[
+                FEAT_AA64EL2,
+                FEAT_AA64EL3,
+                FEAT_RME,
+                FEAT_MEC,
+                FEAT_SCTLR2,
+                FEAT_TCR2,
+                FEAT_S1PIE,
+                FEAT_D128,
+                FEAT_LPA,
+                FEAT_LPA2,
+            ][..],
+        ] {
+            let mut pe = ProcessorElement::new();
+            pe.features = FeatureSet::from_features(features).unwrap();
+            // FEAT_LPA is identified by PARange
+            if pe.features.contains(FEAT_LPA) {
+                pe.impdef.pamax = 52;
+            }
+            if pe.features.contains(FEAT_MPAM) {
+                pe.impdef.partid_max = 0x1ff;
+                pe.impdef.pmg_max = 0x7;
+            }
+
+            let id = IdRegisters::encode(&pe.features, &pe.impdef);
+            // Write the registers out and read them back
+            let id = IdRegisters::from_bits(
+                id.ID_AA64PFR0_EL1.bits(),
+                id.ID_AA64PFR1_EL1.bits(),
+                id.ID_AA64MMFR0_EL1.bits(),
+                id.ID_AA64MMFR1_EL1.bits(),
+                id.ID_AA64MMFR2_EL1.bits(),
+                id.ID_AA64MMFR3_EL1.bits(),
+                id.ID_AA64ISAR0_EL1.bits(),
+                id.ID_AA64ISAR1_EL1.bits(),
+                id.ID_AA64ISAR2_EL1.bits(),
+                id.ID_AA64DFR0_EL1.bits(),
+                id.MPAMIDR_EL1.bits(),
+            );
+            let (features, impdef) = id.decode().unwrap();
+
+            assert_eq!(
+                features.iter().collect::<Vec<_>>(),
+                pe.features.iter().collect::<Vec<_>>(),
+                "{:?}",
+                features
+            );
+            assert_eq!(impdef, pe.impdef, "{:?}", features);
+        }
+    }
+
+    #[test]
+    fn access_flag_only_updates() {
+        let mut id = IdRegisters::encode(&FeatureSet::new(), &ImplementationDefined::new());
+        assert_eq!(
+            id.ID_AA64DFR0_EL1.get(ID_AA64DFR0_EL1_REG::DebugVer),
+            0b0110
+        );
+        id.ID_AA64MMFR1_EL1 = id
+            .ID_AA64MMFR1_EL1
+            .with(ID_AA64MMFR1_EL1_REG::HAFDBS, 0b0001);
+
+        // HAFDBS 0b0001 is not FEAT_HAFDBS, but the Access flag is still updated
+        let mut pe = ProcessorElement::from_id_registers(&id).unwrap();
+        assert!(!pe.features.contains(FEAT_HAFDBS));
+        assert!(pe.impdef.af_update_only);
+        pe.PSTATE.set(ProcState::EL, 1);
+        pe.regs.TCR_EL1 = TCR_EL1_REG::new()
+            .with(TCR_EL1_REG::HA, 1)
+            .with(TCR_EL1_REG::HD, 1);
+        let walkparams =
+            AArch64GetS1TTWParams(&pe, Regime::Regime_EL10, SecurityState::SS_NonSecure, 0)
+                .unwrap();
+        assert_eq!((walkparams.get_ha(), walkparams.get_hd()), (1, 0));
+
+        let id = IdRegisters::encode(&pe.features, &pe.impdef);
+        assert_eq!(
+            id.ID_AA64MMFR1_EL1.get(ID_AA64MMFR1_EL1_REG::HAFDBS),
+            0b0001
+        );
+    }
+}
@@ -12,6 +12,11 @@
 
 use pyo3::prelude::*;
 
+mod error;
+mod idregs;
+mod pe;
+mod physmem;
+mod python;
 mod shared;
 mod shared_mec;
 mod shared_memory;
@@ -19,18 +24,18 @@ mod shared_mpam;
 mod shared_translation;
 mod shared_vmsa;
 mod stubs;
+mod sysregs;
 
 mod translation64;
-
-/// Formats the sum of two numbers as string.
-#[pyfunction]
-fn sum_as_string(a: usize, b: usize) -> PyResult<String> {
-    Ok((a + b).to_string())
-}
+mod translation64_addrcalc;
+mod translation64_attrs;
+mod translation64_faults;
+mod translation64_gpc;
+mod translation64_ttentry;
+mod translation64_walk;
 
 /// A Python module implemented in Rust.
 #[pymodule]
-fn libarmv8(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(sum_as_string, m)?)?;
-    Ok(())
+fn libarmv8(py: Python, m: &PyModule) -> PyResult<()> {
+    python::register(py, m)
 }
@@ -0,0 +1,813 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+
+//! Processing element context.
+//!
+//! The pseudocode reads `PSTATE`, the system registers and the implemented
+//! features as globals. Here they are owned by a [`ProcessorElement`] that is
+//! passed explicitly to every function that depends on them, so that several
+//! independent PEs can be modelled in the same process.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::ConfigError;
+use crate::idregs::IdRegisters;
+use crate::shared::{HaveEL, ProcState};
+use crate::shared_vmsa::PrivilegeLevel;
+use crate::sysregs::SystemRegisters;
+
+/// Architecture extensions checked by the pseudocode with `IsFeatureImplemented()`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Feature {
+    FEAT_AA32EL2,
+    FEAT_AA32EL3,
+    FEAT_AA64EL2,
+    FEAT_AA64EL3,
+    FEAT_AIE,
+    FEAT_ASID2,
+    FEAT_CMOW,
+    FEAT_D128,
+    FEAT_E0PD,
+    FEAT_HAFDBS,
+    FEAT_HAFT,
+    FEAT_HCX,
+    FEAT_HDBSS,
+    FEAT_HPDS,
+    FEAT_HPDS2,
+    FEAT_LOR,
+    FEAT_LPA,
+    FEAT_LPA2,
+    FEAT_LRCPC,
+    FEAT_LRCPC2,
+    FEAT_LRCPC3,
+    FEAT_LS64,
+    FEAT_LSE,
+    FEAT_LSE2,
+    FEAT_LSE128,
+    FEAT_LSMAOC,
+    FEAT_LVA,
+    FEAT_LVA3,
+    FEAT_MEC,
+    FEAT_MOPS,
+    FEAT_MPAM,
+    FEAT_MPAMv0p1,
+    FEAT_MPAMv1p1,
+    FEAT_MTE,
+    FEAT_MTE2,
+    FEAT_MTE3,
+    FEAT_MTE4,
+    FEAT_MTE_PERM,
+    FEAT_PAN,
+    FEAT_PAN2,
+    FEAT_PAN3,
+    FEAT_PAuth,
+    FEAT_RME,
+    FEAT_S1PIE,
+    FEAT_S1POE,
+    FEAT_S2FWB,
+    FEAT_S2PIE,
+    FEAT_S2POE,
+    FEAT_SCTLR2,
+    FEAT_SEL2,
+    FEAT_SME,
+    FEAT_SVE,
+    FEAT_TCR2,
+    FEAT_THE,
+    FEAT_TME,
+    FEAT_TTL,
+    FEAT_TTST,
+    FEAT_UAO,
+    FEAT_VHE,
+    FEAT_VMID16,
+    FEAT_XNX,
+    FEAT_XS,
+}
+
+impl Feature {
+    /// Every feature, in declaration order.
+    pub const ALL: &'static [Self] = &[
+        Self::FEAT_AA32EL2,
+        Self::FEAT_AA32EL3,
+        Self::FEAT_AA64EL2,
+        Self::FEAT_AA64EL3,
+        Self::FEAT_AIE,
+        Self::FEAT_ASID2,
+        Self::FEAT_CMOW,
+        Self::FEAT_D128,
+        Self::FEAT_E0PD,
+        Self::FEAT_HAFDBS,
+        Self::FEAT_HAFT,
+        Self::FEAT_HCX,
+        Self::FEAT_HDBSS,
+        Self::FEAT_HPDS,
+        Self::FEAT_HPDS2,
+        Self::FEAT_LOR,
+        Self::FEAT_LPA,
+        Self::FEAT_LPA2,
+        Self::FEAT_LRCPC,
+        Self::FEAT_LRCPC2,
+        Self::FEAT_LRCPC3,
+        Self::FEAT_LS64,
+        Self::FEAT_LSE,
+        Self::FEAT_LSE2,
+        Self::FEAT_LSE128,
+        Self::FEAT_LSMAOC,
+        Self::FEAT_LVA,
+        Self::FEAT_LVA3,
+        Self::FEAT_MEC,
+        Self::FEAT_MOPS,
+        Self::FEAT_MPAM,
+        Self::FEAT_MPAMv0p1,
+        Self::FEAT_MPAMv1p1,
+        Self::FEAT_MTE,
+        Self::FEAT_MTE2,
+        Self::FEAT_MTE3,
+        Self::FEAT_MTE4,
+        Self::FEAT_MTE_PERM,
+        Self::FEAT_PAN,
+        Self::FEAT_PAN2,
+        Self::FEAT_PAN3,
+        Self::FEAT_PAuth,
+        Self::FEAT_RME,
+        Self::FEAT_S1PIE,
+        Self::FEAT_S1POE,
+        Self::FEAT_S2FWB,
+        Self::FEAT_S2PIE,
+        Self::FEAT_S2POE,
+        Self::FEAT_SCTLR2,
+        Self::FEAT_SEL2,
+        Self::FEAT_SME,
+        Self::FEAT_SVE,
+        Self::FEAT_TCR2,
+        Self::FEAT_THE,
+        Self::FEAT_TME,
+        Self::FEAT_TTL,
+        Self::FEAT_TTST,
+        Self::FEAT_UAO,
+        Self::FEAT_VHE,
+        Self::FEAT_VMID16,
+        Self::FEAT_XNX,
+        Self::FEAT_XS,
+    ];
+}
+
+impl FromStr for Feature {
+    type Err = UnknownName;
+
+    /// Parse a feature from its name, e.g. `"FEAT_LPA2"`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|feat| format!("{:?}", feat) == name)
+            .ok_or_else(|| UnknownName(name.to_string()))
+    }
+}
+
+/// Features that cannot be implemented without another one.
+///
+/// Each entry `(feature, requirement)` means `feature` needs `requirement`.
+const FEATURE_REQUIREMENTS: &[(Feature, Feature)] = {
+    use Feature::*;
+    &[
+        (FEAT_RME, FEAT_AA64EL3),
+        (FEAT_MEC, FEAT_RME),
+        (FEAT_MEC, FEAT_SCTLR2),
+        (FEAT_SEL2, FEAT_AA64EL2),
+        (FEAT_VHE, FEAT_AA64EL2),
+        (FEAT_HAFT, FEAT_HAFDBS),
+        (FEAT_HDBSS, FEAT_HAFDBS),
+        (FEAT_LVA3, FEAT_LVA),
+        (FEAT_MTE2, FEAT_MTE),
+        (FEAT_MTE3, FEAT_MTE2),
+        (FEAT_MTE4, FEAT_MTE2),
+        (FEAT_MTE_PERM, FEAT_MTE2),
+        (FEAT_PAN2, FEAT_PAN),
+        (FEAT_PAN3, FEAT_PAN2),
+        (FEAT_S1PIE, FEAT_TCR2),
+        (FEAT_S2PIE, FEAT_S1PIE),
+        (FEAT_S1POE, FEAT_TCR2),
+        (FEAT_S2POE, FEAT_S1POE),
+        (FEAT_AIE, FEAT_TCR2),
+        (FEAT_THE, FEAT_TCR2),
+        (FEAT_D128, FEAT_TCR2),
+        (FEAT_D128, FEAT_S1PIE),
+        (FEAT_MPAMv0p1, FEAT_MPAM),
+        (FEAT_MPAMv1p1, FEAT_MPAM),
+        (FEAT_LRCPC2, FEAT_LRCPC),
+        (FEAT_LRCPC3, FEAT_LRCPC2),
+        (FEAT_LSE128, FEAT_LSE),
+        (FEAT_HPDS2, FEAT_HPDS),
+    ]
+};
+
+/// Architecture extension levels.
+///
+/// Each Armv9.x level includes the Armv8.(x+5) level.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Default, Debug)]
+pub enum ArchVersion {
+    #[default]
+    Armv8_0,
+    Armv8_1,
+    Armv8_2,
+    Armv8_3,
+    Armv8_4,
+    Armv8_5,
+    Armv8_6,
+    Armv8_7,
+    Armv8_8,
+    Armv8_9,
+    Armv9_0,
+    Armv9_1,
+    Armv9_2,
+    Armv9_3,
+    Armv9_4,
+    Armv9_5,
+}
+
+impl ArchVersion {
+    /// Every extension level, from oldest to newest.
+    pub const ALL: &'static [Self] = &[
+        Self::Armv8_0,
+        Self::Armv8_1,
+        Self::Armv8_2,
+        Self::Armv8_3,
+        Self::Armv8_4,
+        Self::Armv8_5,
+        Self::Armv8_6,
+        Self::Armv8_7,
+        Self::Armv8_8,
+        Self::Armv8_9,
+        Self::Armv9_0,
+        Self::Armv9_1,
+        Self::Armv9_2,
+        Self::Armv9_3,
+        Self::Armv9_4,
+        Self::Armv9_5,
+    ];
+
+    /// Major and minor version numbers.
+    pub const fn number(self) -> (u8, u8) {
+        use ArchVersion::*;
+        match self {
+            Armv8_0 => (8, 0),
+            Armv8_1 => (8, 1),
+            Armv8_2 => (8, 2),
+            Armv8_3 => (8, 3),
+            Armv8_4 => (8, 4),
+            Armv8_5 => (8, 5),
+            Armv8_6 => (8, 6),
+            Armv8_7 => (8, 7),
+            Armv8_8 => (8, 8),
+            Armv8_9 => (8, 9),
+            Armv9_0 => (9, 0),
+            Armv9_1 => (9, 1),
+            Armv9_2 => (9, 2),
+            Armv9_3 => (9, 3),
+            Armv9_4 => (9, 4),
+            Armv9_5 => (9, 5),
+        }
+    }
+
+    /// Returns TRUE if an implementation of `self` is also an implementation of `other`.
+    pub const fn includes(self, other: Self) -> bool {
+        match (self.number(), other.number()) {
+            ((8, minor), (8, other_minor)) | ((9, minor), (9, other_minor)) => minor >= other_minor,
+            ((9, minor), (8, other_minor)) => minor + 5 >= other_minor,
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for ArchVersion {
+    type Err = UnknownName;
+
+    /// Parse an extension level from its name, e.g. `"Armv8_2"`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|version| format!("{:?}", version) == name)
+            .ok_or_else(|| UnknownName(name.to_string()))
+    }
+}
+
+/// Features that an implementation of an extension level must have.
+///
+/// Each entry `(version, feature, condition)` means `feature` is mandatory from
+/// `version`, if `condition` is implemented when there is one.
+const MANDATORY_FEATURES: &[(ArchVersion, Feature, Option<Feature>)] = {
+    use ArchVersion::*;
+    use Feature::*;
+    &[
+        (Armv8_1, FEAT_HPDS, None),
+        (Armv8_1, FEAT_LOR, None),
+        (Armv8_1, FEAT_LSE, None),
+        (Armv8_1, FEAT_PAN, None),
+        (Armv8_1, FEAT_VHE, Some(FEAT_AA64EL2)),
+        (Armv8_2, FEAT_HPDS2, None),
+        (Armv8_2, FEAT_PAN2, None),
+        (Armv8_2, FEAT_UAO, None),
+        (Armv8_2, FEAT_XNX, Some(FEAT_AA64EL2)),
+        (Armv8_3, FEAT_LRCPC, None),
+        (Armv8_3, FEAT_PAuth, None),
+        (Armv8_4, FEAT_LRCPC2, None),
+        (Armv8_4, FEAT_LSE2, None),
+        (Armv8_4, FEAT_S2FWB, Some(FEAT_AA64EL2)),
+        (Armv8_4, FEAT_TTL, None),
+        (Armv8_4, FEAT_TTST, None),
+        (Armv8_5, FEAT_E0PD, None),
+        (Armv8_7, FEAT_HCX, Some(FEAT_AA64EL2)),
+        (Armv8_7, FEAT_PAN3, None),
+        (Armv8_7, FEAT_XS, None),
+        (Armv8_8, FEAT_MOPS, None),
+        (Armv8_9, FEAT_SCTLR2, None),
+        (Armv8_9, FEAT_TCR2, None),
+        (Armv9_0, FEAT_SVE, None),
+    ]
+};
+
+/// A combination of features that no implementation can have.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FeatureError {
+    /// `feature` cannot be implemented without `requires`.
+    Requires { feature: Feature, requires: Feature },
+    /// `feature` cannot be left out of an implementation of `version`.
+    Mandatory {
+        feature: Feature,
+        version: ArchVersion,
+    },
+}
+
+impl fmt::Display for FeatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Requires { feature, requires } => {
+                write!(f, "{:?} requires {:?}", feature, requires)
+            }
+            Self::Mandatory { feature, version } => {
+                let (major, minor) = version.number();
+                write!(f, "{:?} is mandatory in Armv{}.{}", feature, major, minor)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FeatureError {}
+
+/// A name that does not match a [`Feature`] or [`ArchVersion`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct UnknownName(pub String);
+
+impl fmt::Display for UnknownName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown name {:?}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownName {}
+
+/// Set of implemented architecture extensions.
+///
+/// The set is always consistent: operations that would leave a feature
+/// without one of its requirements, or leave out a feature mandatory in the
+/// set's extension level, are refused.
+#[derive(Clone, Default, Eq, PartialEq, Debug)]
+pub struct FeatureSet {
+    version: ArchVersion,
+    features: BTreeSet<Feature>,
+}
+
+impl FeatureSet {
+    /// The empty Armv8.0 set, i.e. no optional features implemented.
+    pub const fn new() -> Self {
+        Self {
+            version: ArchVersion::Armv8_0,
+            features: BTreeSet::new(),
+        }
+    }
+
+    /// Build an Armv8.0 set from `features`, in any order.
+    pub fn from_features(features: &[Feature]) -> Result<Self, FeatureError> {
+        Self::from_profile(ArchVersion::Armv8_0, features)
+    }
+
+    /// Build a set with the mandatory features of `version` and the
+    /// `optional` ones.
+    ///
+    /// Features that are mandatory only when another feature is implemented,
+    /// such as FEAT_VHE with FEAT_AA64EL2, are added when `optional` has it.
+    pub fn from_profile(version: ArchVersion, optional: &[Feature]) -> Result<Self, FeatureError> {
+        let mut set = Self {
+            version,
+            features: optional.iter().copied().collect(),
+        };
+        set.add_mandatory();
+        set.check()?;
+        Ok(set)
+    }
+
+    /// The extension level whose mandatory features the set includes.
+    pub fn version(&self) -> ArchVersion {
+        self.version
+    }
+
+    pub fn contains(&self, feat: Feature) -> bool {
+        self.features.contains(&feat)
+    }
+
+    /// Add `feat`, which must have its requirements already in the set, along
+    /// with the features it makes mandatory.
+    pub fn insert(&mut self, feat: Feature) -> Result<(), FeatureError> {
+        let mut candidate = self.clone();
+        candidate.features.insert(feat);
+        candidate.add_mandatory();
+        self.replace(candidate)
+    }
+
+    /// Remove `feat`, which must not be required by another feature in the set
+    /// nor be mandatory, along with the features it makes mandatory.
+    pub fn remove(&mut self, feat: Feature) -> Result<(), FeatureError> {
+        let mut candidate = self.clone();
+        candidate.features.remove(&feat);
+        for &(version, feature, condition) in MANDATORY_FEATURES {
+            if condition == Some(feat) && self.version.includes(version) {
+                candidate.features.remove(&feature);
+            }
+        }
+        self.replace(candidate)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Feature> + '_ {
+        self.features.iter().copied()
+    }
+
+    fn replace(&mut self, candidate: Self) -> Result<(), FeatureError> {
+        candidate.check()?;
+        *self = candidate;
+        Ok(())
+    }
+
+    /// Returns TRUE if `feature` must be in the set.
+    fn is_mandatory(&self, feature: Feature) -> bool {
+        MANDATORY_FEATURES
+            .iter()
+            .any(|&(version, feat, condition)| {
+                feat == feature
+                    && self.version.includes(version)
+                    && condition.is_none_or(|cond| self.contains(cond))
+            })
+    }
+
+    fn add_mandatory(&mut self) {
+        for &(_, feature, _) in MANDATORY_FEATURES {
+            if self.is_mandatory(feature) {
+                self.features.insert(feature);
+            }
+        }
+    }
+
+    fn check(&self) -> Result<(), FeatureError> {
+        for &(feature, requires) in FEATURE_REQUIREMENTS {
+            if self.contains(feature) && !self.contains(requires) {
+                return Err(FeatureError::Requires { feature, requires });
+            }
+        }
+        for &(_, feature, _) in MANDATORY_FEATURES {
+            if self.is_mandatory(feature) && !self.contains(feature) {
+                return Err(FeatureError::Mandatory {
+                    feature,
+                    version: self.version,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// IMPLEMENTATION DEFINED parameters of the memory system that are not
+/// expressed as `FEAT_*` extensions.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ImplementationDefined {
+    /// Physical address size in bits, as returned by `AArch64.PAMax()`.
+    pub pamax: u64,
+    /// Number of ASID bits, 8 or 16.
+    pub asid_bits: u64,
+    /// Stage 1 translation granules supported.
+    pub s1_tgran4: bool,
+    pub s1_tgran16: bool,
+    pub s1_tgran64: bool,
+    /// Stage 2 translation granules supported.
+    pub s2_tgran4: bool,
+    pub s2_tgran16: bool,
+    pub s2_tgran64: bool,
+    /// MPAMIDR_EL1.PARTID_MAX
+    pub partid_max: u64,
+    /// MPAMIDR_EL1.PMG_MAX
+    pub pmg_max: u64,
+    /// Secure state is the only Security state when EL3 is not implemented,
+    /// as returned by `SecureOnlyImplementation()`.
+    pub secure_only: bool,
+    /// Hardware update of the Access flag without hardware update of dirty
+    /// state, ID_AA64MMFR1_EL1.HAFDBS == 0b0001. Ignored when FEAT_HAFDBS is
+    /// implemented.
+    pub af_update_only: bool,
+}
+
+impl ImplementationDefined {
+    /// A 48-bit PA implementation with 16-bit ASIDs and every granule size.
+    pub const fn new() -> Self {
+        Self {
+            pamax: 48,
+            asid_bits: 16,
+            s1_tgran4: true,
+            s1_tgran16: true,
+            s1_tgran64: true,
+            s2_tgran4: true,
+            s2_tgran16: true,
+            s2_tgran64: true,
+            partid_max: 0,
+            pmg_max: 0,
+            secure_only: false,
+            af_update_only: false,
+        }
+    }
+}
+
+impl Default for ImplementationDefined {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Architectural state of a single processing element.
+#[derive(Clone, Debug)]
+pub struct ProcessorElement {
+    pub PSTATE: ProcState,
+    pub regs: SystemRegisters,
+    /// Implemented architecture extensions.
+    pub features: FeatureSet,
+    pub impdef: ImplementationDefined,
+}
+
+impl ProcessorElement {
+    /// A PE in reset state with no optional features implemented.
+    pub fn new() -> Self {
+        Self {
+            PSTATE: ProcState::new(),
+            regs: SystemRegisters::new(),
+            features: FeatureSet::new(),
+            impdef: ImplementationDefined::new(),
+        }
+    }
+
+    /// A PE in reset state implementing the mandatory features of `version`
+    /// and the `optional` ones. The PA size is 52 bits with FEAT_LPA and 48
+    /// bits otherwise.
+    pub fn from_profile(version: ArchVersion, optional: &[Feature]) -> Result<Self, FeatureError> {
+        let features = FeatureSet::from_profile(version, optional)?;
+        let mut impdef = ImplementationDefined::new();
+        if features.contains(Feature::FEAT_LPA) {
+            impdef.pamax = 52;
+        }
+        Ok(Self {
+            features,
+            impdef,
+            ..Self::new()
+        })
+    }
+
+    /// Mark `feat` as implemented.
+    pub fn with_feature(mut self, feat: Feature) -> Result<Self, FeatureError> {
+        self.features.insert(feat)?;
+        Ok(self)
+    }
+
+    /// Mark `feat` as not implemented.
+    pub fn without_feature(mut self, feat: Feature) -> Result<Self, FeatureError> {
+        self.features.remove(feat)?;
+        Ok(self)
+    }
+
+    /// A PE in reset state configured from the values of its ID registers.
+    pub fn from_id_registers(id: &IdRegisters) -> Result<Self, FeatureError> {
+        let (features, impdef) = id.decode()?;
+        Ok(Self {
+            features,
+            impdef,
+            ..Self::new()
+        })
+    }
+
+    /// PSTATE.EL, which must be an implemented Exception level.
+    pub fn current_el(&self) -> Result<PrivilegeLevel, ConfigError> {
+        let el = self.PSTATE.get_EL();
+        if !HaveEL(self, el) {
+            return Err(ConfigError::ELNotImplemented(el));
+        }
+        Ok(el)
+    }
+
+    /// The ID register values describing this PE.
+    pub fn id_registers(&self) -> IdRegisters {
+        IdRegisters::encode(&self.features, &self.impdef)
+    }
+}
+
+impl Default for ProcessorElement {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns TRUE if the architecture extension `feat` is implemented by the PE.
+pub fn IsFeatureImplemented(pe: &ProcessorElement, feat: Feature) -> bool {
+    pe.features.contains(feat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared::*;
+    use crate::shared_memory::*;
+    use crate::shared_vmsa::*;
+
+    #[test]
+    fn feature_set_consistency() {
+        use Feature::*;
+
+        // FEAT_MEC needs FEAT_RME, which needs EL3
+        assert_eq!(
+            FeatureSet::from_features(&[FEAT_MEC, FEAT_SCTLR2]),
+            Err(FeatureError::Requires {
+                feature: FEAT_MEC,
+                requires: FEAT_RME,
+            })
+        );
+        let mut features =
+            FeatureSet::from_features(&[FEAT_MEC, FEAT_SCTLR2, FEAT_RME, FEAT_AA64EL3]).unwrap();
+        assert!(features.contains(FEAT_MEC));
+
+        // A refused change leaves the set as it was
+        let before = features.clone();
+        assert_eq!(
+            features.remove(FEAT_RME),
+            Err(FeatureError::Requires {
+                feature: FEAT_MEC,
+                requires: FEAT_RME,
+            })
+        );
+        assert_eq!(features, before);
+        assert!(FeatureSet::new().insert(FEAT_RME).is_err());
+        assert!(ProcessorElement::new().with_feature(FEAT_SEL2).is_err());
+
+        // Removing the dependent features first is allowed
+        features.remove(FEAT_MEC).unwrap();
+        features.remove(FEAT_RME).unwrap();
+        assert_eq!(
+            features.iter().collect::<Vec<_>>(),
+            [FEAT_AA64EL3, FEAT_SCTLR2]
+        );
+    }
+
+    #[test]
+    fn profile_mandatory_features() {
+        use Feature::*;
+
+        for &version in ArchVersion::ALL {
+            for optional in [&[][..], &[FEAT_AA64EL2][..]] {
+                let features = FeatureSet::from_profile(version, optional).unwrap();
+                assert_eq!(features.version(), version);
+                for &(since, feature, condition) in MANDATORY_FEATURES {
+                    let mandatory = version.includes(since)
+                        && condition.is_none_or(|cond| optional.contains(&cond));
+                    assert_eq!(
+                        features.contains(feature),
+                        mandatory,
+                        "{:?} in {:?} with {:?}",
+                        feature,
+                        version,
+                        optional
+                    );
+                    if mandatory {
+                        // A feature other members depend on is refused for that reason first
+                        let mut features = features.clone();
+                        match features.remove(feature) {
+                            Err(FeatureError::Mandatory { .. })
+                            | Err(FeatureError::Requires { .. }) => {}
+                            other => panic!("removed mandatory {:?}: {:?}", feature, other),
+                        }
+                        assert!(features.contains(feature));
+                    }
+                }
+            }
+        }
+
+        let mut features = FeatureSet::from_profile(ArchVersion::Armv8_2, &[]).unwrap();
+        assert_eq!(
+            features.remove(FEAT_HPDS2),
+            Err(FeatureError::Mandatory {
+                feature: FEAT_HPDS2,
+                version: ArchVersion::Armv8_2
+            })
+        );
+
+        // Leaving EL2 out also leaves out the features it makes mandatory
+        let mut features = FeatureSet::from_profile(ArchVersion::Armv8_4, &[FEAT_AA64EL2]).unwrap();
+        assert!(features.contains(FEAT_VHE) && features.contains(FEAT_S2FWB));
+        features.remove(FEAT_AA64EL2).unwrap();
+        assert!(!features.contains(FEAT_VHE) && !features.contains(FEAT_S2FWB));
+    }
+
+    #[test]
+    fn arch_version_includes() {
+        use ArchVersion::*;
+
+        // Each Armv8.x and Armv9.x level includes the levels before it in its series
+        for (i, a) in ArchVersion::ALL.iter().copied().enumerate() {
+            for (j, b) in ArchVersion::ALL.iter().copied().enumerate() {
+                if a.number().0 == b.number().0 {
+                    assert_eq!(a.includes(b), i >= j, "{:?} {:?}", a, b);
+                }
+            }
+        }
+        // Armv9.x includes Armv8.(x+5), and no Armv8 level includes an Armv9 one
+        assert!(Armv9_0.includes(Armv8_5));
+        assert!(!Armv9_0.includes(Armv8_6));
+        assert!(Armv9_4.includes(Armv8_9));
+        assert!(!Armv8_9.includes(Armv9_0));
+        // The relation is transitive
+        for &a in ArchVersion::ALL {
+            for &b in ArchVersion::ALL {
+                for &c in ArchVersion::ALL {
+                    if a.includes(b) && b.includes(c) {
+                        assert!(a.includes(c), "{:?} {:?} {:?}", a, b, c);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn feature_names() {
+        // Every feature and version parses back from its name
+        for &feature in Feature::ALL {
+            assert_eq!(format!("{:?}", feature).parse(), Ok(feature));
+        }
+        for &version in ArchVersion::ALL {
+            assert_eq!(format!("{:?}", version).parse(), Ok(version));
+        }
+        assert_eq!(
+            "FEAT_NOTHING".parse::<Feature>(),
+            Err(UnknownName("FEAT_NOTHING".to_string()))
+        );
+        assert!("feat_lpa2".parse::<Feature>().is_err());
+        assert!("Armv7".parse::<ArchVersion>().is_err());
+
+        // ALL lists each feature once, including every feature the tables mention
+        let all = Feature::ALL.iter().copied().collect::<BTreeSet<_>>();
+        assert_eq!(all.len(), Feature::ALL.len());
+        for &(feature, requires) in FEATURE_REQUIREMENTS {
+            assert!(all.contains(&feature) && all.contains(&requires));
+        }
+        for &(_, feature, condition) in MANDATORY_FEATURES {
+            assert!(all.contains(&feature));
+            assert!(condition.is_none_or(|cond| all.contains(&cond)));
+        }
+        // and ArchVersion::ALL is ordered oldest first
+        for pair in ArchVersion::ALL.windows(2) {
+            assert!(pair[0].number() < pair[1].number());
+        }
+    }
+
+    #[test]
+    fn independent_processor_elements() {
+        let mut host = ProcessorElement::new()
+            .with_feature(Feature::FEAT_AA64EL2)
+            .unwrap();
+        host.PSTATE.set(ProcState::EL, 2);
+        host.regs.VTTBR_EL2 = 0x10_0000;
+        let mut guest = ProcessorElement::new();
+        guest.PSTATE.set(ProcState::EL, 1);
+
+        assert!(EL2Enabled(&host));
+        assert!(!EL2Enabled(&guest));
+        assert_eq!(
+            NewAccDesc(&host, AccessType::AccessType_GPR).unwrap().el,
+            EL2
+        );
+        assert_eq!(
+            NewAccDesc(&guest, AccessType::AccessType_GPR).unwrap().el,
+            EL1
+        );
+
+        // Changing a copy leaves the original untouched
+        let mut copy = host.clone();
+        copy.PSTATE.set(ProcState::EL, 0);
+        copy.regs.VTTBR_EL2 = 0;
+        assert_eq!(host.PSTATE.get_EL(), EL2);
+        assert_eq!(host.regs.VTTBR_EL2, 0x10_0000);
+        assert_eq!(guest.regs.VTTBR_EL2, 0);
+    }
+}
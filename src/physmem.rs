@@ -0,0 +1,418 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+
+use std::collections::BTreeMap;
+
+use crate::shared_memory::*;
+use crate::shared_vmsa::*;
+
+/// # FlatMemory
+///
+/// A contiguous range of physical memory starting at `base`, backed by a `Vec<u8>`.
+///
+/// Accesses that fall outside the range are terminated with a synchronous External abort.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlatMemory {
+    pub base: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl FlatMemory {
+    /// Zero-filled memory of `size` bytes starting at physical address `base`.
+    pub fn new(base: u64, size: usize) -> Self {
+        Self {
+            base,
+            bytes: vec![0; size],
+        }
+    }
+
+    /// Memory starting at physical address `base` with initial contents `bytes`.
+    pub fn from_vec(base: u64, bytes: Vec<u8>) -> Self {
+        Self { base, bytes }
+    }
+
+    fn range(&self, address: u64, size: usize) -> Option<std::ops::Range<usize>> {
+        let start = usize::try_from(address.checked_sub(self.base)?).ok()?;
+        let end = start.checked_add(size)?;
+        (end <= self.bytes.len()).then_some(start..end)
+    }
+}
+
+impl PhysicalMemory for FlatMemory {
+    fn read(
+        &self,
+        desc: AddressDescriptor,
+        size: usize,
+        accdesc: AccessDescriptor,
+    ) -> (PhysMemRetStatus, Vec<u8>) {
+        match self.range(desc.paddress.address, size) {
+            Some(range) => (
+                PhysMemRetStatus::NoFault(accdesc.acctype),
+                self.bytes[range].to_vec(),
+            ),
+            None => (
+                PhysMemRetStatus::SyncExternal(accdesc.acctype),
+                vec![0; size],
+            ),
+        }
+    }
+
+    fn write(
+        &mut self,
+        desc: AddressDescriptor,
+        size: usize,
+        accdesc: AccessDescriptor,
+        value: &[u8],
+    ) -> PhysMemRetStatus {
+        match (self.range(desc.paddress.address, size), value.get(..size)) {
+            (Some(range), Some(value)) => {
+                self.bytes[range].copy_from_slice(value);
+                PhysMemRetStatus::NoFault(accdesc.acctype)
+            }
+            _ => PhysMemRetStatus::SyncExternal(accdesc.acctype),
+        }
+    }
+}
+
+/// # SparseMemory
+///
+/// The whole physical address space, allocated lazily in pages of `SparseMemory::PAGE_SIZE`
+/// bytes.
+///
+/// Memory that has never been written reads as zero.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SparseMemory {
+    pages: BTreeMap<u64, Box<[u8]>>,
+}
+
+impl SparseMemory {
+    pub const PAGE_SIZE: u64 = 4096;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of pages that have been allocated by writes.
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Copy `bytes` into memory starting at physical address `address`.
+    pub fn load(&mut self, address: u64, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            let address = address.wrapping_add(i as u64);
+            let page = self
+                .pages
+                .entry(address & !(Self::PAGE_SIZE - 1))
+                .or_insert_with(|| vec![0; Self::PAGE_SIZE as usize].into_boxed_slice());
+            page[(address & (Self::PAGE_SIZE - 1)) as usize] = *byte;
+        }
+    }
+
+    /// Copy `size` bytes starting at physical address `address` out of memory.
+    pub fn dump(&self, address: u64, size: usize) -> Vec<u8> {
+        (0..size as u64)
+            .map(|i| {
+                let address = address.wrapping_add(i);
+                self.pages
+                    .get(&(address & !(Self::PAGE_SIZE - 1)))
+                    .map_or(0, |page| page[(address & (Self::PAGE_SIZE - 1)) as usize])
+            })
+            .collect()
+    }
+}
+
+/// Writes translation tables for tests into a `SparseMemory`, with one table per
+/// lookup level. The table at `startlevel` is at `base`, and the table at each
+/// following level is `TableBuilder::STRIDE` bytes after the previous one.
+#[cfg(test)]
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TableBuilder {
+    pub base: u64,
+    pub granulebits: u64,
+    pub startlevel: i64,
+    /// Descriptor size in bits
+    pub N: usize,
+}
+
+#[cfg(test)]
+impl TableBuilder {
+    pub const STRIDE: u64 = 0x10_0000;
+
+    /// Tables of 64-bit descriptors.
+    pub const fn new(base: u64, granulebits: u64, startlevel: i64) -> Self {
+        Self {
+            base,
+            granulebits,
+            startlevel,
+            N: 64,
+        }
+    }
+
+    /// The same tables with 128-bit descriptors.
+    pub const fn d128(self) -> Self {
+        Self { N: 128, ..self }
+    }
+
+    /// Address of the table at `level`.
+    pub fn table(&self, level: i64) -> u64 {
+        self.base + Self::STRIDE * (level - self.startlevel) as u64
+    }
+
+    /// Address of the entry for `va` in the table at `level`.
+    pub fn entry(&self, va: u64, level: i64) -> u64 {
+        let stride = self.granulebits - (self.N / 8).trailing_zeros() as u64;
+        let shift = self.granulebits + stride * (FINAL_LEVEL - level) as u64;
+        let index = (va >> shift) & ((1 << stride) - 1);
+        self.table(level) + index * (self.N / 8) as u64
+    }
+
+    /// Write `entry` as entry `index` of the table at `level`.
+    pub fn store(&self, mem: &mut SparseMemory, level: i64, index: u64, entry: u128) {
+        let address = self.table(level) + index * (self.N / 8) as u64;
+        mem.load(address, &entry.to_le_bytes()[..self.N / 8]);
+    }
+
+    /// Write table descriptors from `startlevel` down to `level` for `va`, with `leaf`
+    /// as the entry at `level`.
+    pub fn map(&self, mem: &mut SparseMemory, va: u64, level: i64, leaf: u128) {
+        for l in self.startlevel..=level {
+            let entry = if l == level {
+                leaf
+            } else {
+                u128::from(self.table(l + 1)) | 0b11
+            };
+            mem.load(self.entry(va, l), &entry.to_le_bytes()[..self.N / 8]);
+        }
+    }
+}
+
+impl PhysicalMemory for SparseMemory {
+    fn read(
+        &self,
+        desc: AddressDescriptor,
+        size: usize,
+        accdesc: AccessDescriptor,
+    ) -> (PhysMemRetStatus, Vec<u8>) {
+        (
+            PhysMemRetStatus::NoFault(accdesc.acctype),
+            self.dump(desc.paddress.address, size),
+        )
+    }
+
+    fn write(
+        &mut self,
+        desc: AddressDescriptor,
+        size: usize,
+        accdesc: AccessDescriptor,
+        value: &[u8],
+    ) -> PhysMemRetStatus {
+        match value.get(..size) {
+            Some(value) => {
+                self.load(desc.paddress.address, value);
+                PhysMemRetStatus::NoFault(accdesc.acctype)
+            }
+            None => PhysMemRetStatus::SyncExternal(accdesc.acctype),
+        }
+    }
+}
+
+/// # MecMemory
+///
+/// Memory encryption contexts (FEAT_MEC) on top of another backend.
+///
+/// The contents of the Realm PAS are stored transformed with a key derived from the MECID of
+/// each access, so that a Realm access with a MECID other than the one the data was written with
+/// reads garbage instead of plaintext. Accesses to the other physical address spaces, or with
+/// `enabled` clear, pass through unchanged.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MecMemory<M> {
+    pub inner: M,
+    pub enabled: bool,
+}
+
+impl<M> MecMemory<M> {
+    /// Encrypt the Realm PAS contents of `inner`.
+    pub fn new(inner: M) -> Self {
+        Self {
+            inner,
+            enabled: true,
+        }
+    }
+
+    /// Transform `bytes` of `paspace` starting at physical address `address` between their
+    /// plaintext and stored forms for an access with `mecid`.
+    ///
+    /// The transformation is its own inverse.
+    pub fn transform(&self, paspace: PASpace, mecid: u16, address: u64, bytes: &mut [u8]) {
+        if !self.enabled || paspace != PASpace::PAS_Realm {
+            return;
+        }
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte ^= keystream(mecid, address.wrapping_add(i as u64));
+        }
+    }
+}
+
+/// The key byte for physical address `address` in the memory encryption context `mecid`.
+fn keystream(mecid: u16, address: u64) -> u8 {
+    // SplitMix64 finalizer over the MECID and the doubleword address
+    let mut z = ((address >> 3) ^ u64::from(mecid).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> ((address & 7) * 8)) as u8
+}
+
+impl<M: PhysicalMemory> PhysicalMemory for MecMemory<M> {
+    fn read(
+        &self,
+        desc: AddressDescriptor,
+        size: usize,
+        accdesc: AccessDescriptor,
+    ) -> (PhysMemRetStatus, Vec<u8>) {
+        let (memstatus, mut bytes) = self.inner.read(desc, size, accdesc);
+        self.transform(
+            desc.paddress.paspace,
+            desc.mecid,
+            desc.paddress.address,
+            &mut bytes,
+        );
+        (memstatus, bytes)
+    }
+
+    fn write(
+        &mut self,
+        desc: AddressDescriptor,
+        size: usize,
+        accdesc: AccessDescriptor,
+        value: &[u8],
+    ) -> PhysMemRetStatus {
+        let Some(value) = value.get(..size) else {
+            return PhysMemRetStatus::SyncExternal(accdesc.acctype);
+        };
+        let mut bytes = value.to_vec();
+        self.transform(
+            desc.paddress.paspace,
+            desc.mecid,
+            desc.paddress.address,
+            &mut bytes,
+        );
+        self.inner.write(desc, size, accdesc, &bytes)
+    }
+
+    fn compare_and_swap(
+        &mut self,
+        desc: AddressDescriptor,
+        size: usize,
+        accdesc: AccessDescriptor,
+        expected: &[u8],
+        value: &[u8],
+    ) -> (PhysMemRetStatus, Vec<u8>, Option<PhysMemRetStatus>) {
+        // Compare and swap the stored forms so that the inner backend stays atomic
+        let (paspace, mecid, address) = (desc.paddress.paspace, desc.mecid, desc.paddress.address);
+        let mut expected = expected.to_vec();
+        let mut value = value.to_vec();
+        self.transform(paspace, mecid, address, &mut expected);
+        self.transform(paspace, mecid, address, &mut value);
+        let (memstatus, mut bytes, writestatus) = self
+            .inner
+            .compare_and_swap(desc, size, accdesc, &expected, &value);
+        self.transform(paspace, mecid, address, &mut bytes);
+        (memstatus, bytes, writestatus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::*;
+
+    fn access(address: u64) -> (AddressDescriptor, AccessDescriptor) {
+        let pe = ProcessorElement::from_profile(ArchVersion::Armv8_0, &[]).unwrap();
+        let mut desc = AddressDescriptor::UNKNOWN;
+        desc.paddress.address = address;
+        let accdesc = CreateAccDescGPR(&pe, MemOp::MemOp_STORE, false, true, false).unwrap();
+        (desc, accdesc)
+    }
+
+    #[test]
+    fn short_writes_abort() {
+        let (desc, accdesc) = access(0x1000);
+
+        let mut flat = FlatMemory::new(0x1000, 16);
+        let status = flat.write(desc, 8, accdesc, &[0xff; 4]);
+        assert_eq!(status.statuscode, Fault::Fault_SyncExternal);
+        assert_eq!(flat.bytes, [0; 16]);
+        let status = flat.write(desc, 4, accdesc, &[0xff; 8]);
+        assert_eq!(status.statuscode, Fault::Fault_None);
+        assert_eq!(flat.bytes[..5], [0xff, 0xff, 0xff, 0xff, 0]);
+
+        let mut sparse = SparseMemory::new();
+        let status = sparse.write(desc, 8, accdesc, &[0xff; 4]);
+        assert_eq!(status.statuscode, Fault::Fault_SyncExternal);
+        assert_eq!(sparse.page_count(), 0);
+
+        let mut mec = MecMemory::new(SparseMemory::new());
+        let status = mec.write(desc, 8, accdesc, &[0xff; 4]);
+        assert_eq!(status.statuscode, Fault::Fault_SyncExternal);
+
+        // PhysMemWrite requires a value of exactly the access size
+        let status = PhysMemWrite(&mut sparse, desc, 8, accdesc, &[0xff; 16]);
+        assert_eq!(status.statuscode, Fault::Fault_SyncExternal);
+        assert_eq!(sparse.page_count(), 0);
+    }
+
+    #[test]
+    fn mec_realm_contents() {
+        let (mut desc, accdesc) = access(0x1000);
+        let plaintext = 0x0123_4567_89ab_cdef_u64.to_le_bytes();
+        let mut mec = MecMemory::new(SparseMemory::new());
+        let read = |mec: &MecMemory<SparseMemory>, desc: AddressDescriptor| {
+            let (memstatus, bytes) = mec.read(desc, 8, accdesc);
+            assert_eq!(memstatus.statuscode, Fault::Fault_None);
+            bytes
+        };
+
+        // Realm contents only read back as plaintext with the MECID they were written with
+        desc.paddress.paspace = PASpace::PAS_Realm;
+        desc.mecid = 0x10;
+        let status = mec.write(desc, 8, accdesc, &plaintext);
+        assert_eq!(status.statuscode, Fault::Fault_None);
+        assert_eq!(read(&mec, desc), plaintext);
+        assert_ne!(mec.inner.dump(0x1000, 8), plaintext);
+        let other = AddressDescriptor {
+            mecid: 0x11,
+            ..desc
+        };
+        assert_ne!(read(&mec, other), plaintext);
+
+        // Compare and swap matches against the plaintext of the access MECID
+        let value = [0xff; 8];
+        let (_, bytes, writestatus) = mec.compare_and_swap(other, 8, accdesc, &plaintext, &value);
+        assert_ne!(bytes, plaintext);
+        assert!(writestatus.is_none());
+        let (_, bytes, writestatus) = mec.compare_and_swap(desc, 8, accdesc, &plaintext, &value);
+        assert_eq!(bytes, plaintext);
+        assert_eq!(writestatus.unwrap().statuscode, Fault::Fault_None);
+        assert_eq!(read(&mec, desc), value);
+
+        // Other PA spaces, and disabled encryption, pass through unchanged
+        desc.paddress.paspace = PASpace::PAS_NonSecure;
+        mec.write(desc, 8, accdesc, &plaintext);
+        assert_eq!(mec.inner.dump(0x1000, 8), plaintext);
+        assert_eq!(
+            read(
+                &mec,
+                AddressDescriptor {
+                    mecid: 0x11,
+                    ..desc
+                }
+            ),
+            plaintext
+        );
+        desc.paddress.paspace = PASpace::PAS_Realm;
+        mec.enabled = false;
+        assert_eq!(read(&mec, desc), plaintext);
+    }
+}
@@ -0,0 +1,543 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+
+//! Python bindings.
+//!
+//! A [`PyProcessorElement`] owns a PE and a sparse physical memory; its
+//! `translate(va, access)` method runs `AArch64TranslateAddress` and returns an
+//! `AddressDescriptor`, or raises `TranslationFault` with the `FaultRecord`
+//! in its `fault` attribute.
+
+// pyo3 0.20's `#[pymethods]` expansion for `#[new]` trips this lint on newer
+// compilers.
+#![allow(non_local_definitions)]
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+
+use crate::error::*;
+use crate::pe::*;
+use crate::physmem::*;
+use crate::shared::*;
+use crate::shared_memory::*;
+use crate::shared_vmsa::*;
+use crate::sysregs::*;
+use crate::translation64::*;
+
+create_exception!(
+    libarmv8,
+    TranslationFault,
+    PyException,
+    "The translation generated a fault, available in the `fault` attribute."
+);
+create_exception!(
+    libarmv8,
+    ConfigurationError,
+    PyException,
+    "The PE configuration cannot be translated with."
+);
+
+impl From<TranslationError> for PyErr {
+    fn from(err: TranslationError) -> Self {
+        ConfigurationError::new_err(err.to_string())
+    }
+}
+
+impl From<FeatureError> for PyErr {
+    fn from(err: FeatureError) -> Self {
+        ConfigurationError::new_err(err.to_string())
+    }
+}
+
+impl From<UnknownName> for PyErr {
+    fn from(err: UnknownName) -> Self {
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+/// Enumeration values are exposed by their pseudocode name, e.g. `"Fault_Translation"`.
+fn name<T: std::fmt::Debug>(value: T) -> String {
+    format!("{:?}", value)
+}
+
+#[pyclass(name = "FaultRecord", get_all)]
+#[derive(Clone)]
+pub struct PyFaultRecord {
+    pub statuscode: String,
+    pub level: i64,
+    pub write: bool,
+    pub secondstage: bool,
+    pub s2fs1walk: bool,
+    pub dirtybit: bool,
+    pub overlay: bool,
+    pub ipaddress: u64,
+    pub ipaspace: String,
+    pub paddress: u64,
+    pub paspace: String,
+    pub gpcf: String,
+    pub gpcflevel: i64,
+    pub gpcfs2walk: bool,
+}
+
+impl From<FaultRecord> for PyFaultRecord {
+    fn from(fault: FaultRecord) -> Self {
+        Self {
+            statuscode: name(fault.statuscode),
+            level: fault.level,
+            write: fault.write,
+            secondstage: fault.secondstage,
+            s2fs1walk: fault.s2fs1walk,
+            dirtybit: fault.dirtybit,
+            overlay: fault.overlay,
+            ipaddress: fault.ipaddress.address,
+            ipaspace: name(fault.ipaddress.paspace),
+            paddress: fault.paddress.address,
+            paspace: name(fault.paddress.paspace),
+            gpcf: name(fault.gpcf.gpf),
+            gpcflevel: fault.gpcf.level,
+            gpcfs2walk: fault.gpcfs2walk,
+        }
+    }
+}
+
+#[pymethods]
+impl PyFaultRecord {
+    fn __repr__(&self) -> String {
+        format!(
+            "FaultRecord(statuscode={}, level={}, secondstage={})",
+            self.statuscode, self.level, self.secondstage
+        )
+    }
+}
+
+#[pyclass(name = "MemoryAttributes", get_all)]
+#[derive(Clone)]
+pub struct PyMemoryAttributes {
+    pub memtype: String,
+    pub device: String,
+    pub inner: String,
+    pub outer: String,
+    pub shareability: String,
+    pub tags: String,
+    pub xs: bool,
+}
+
+impl From<MemoryAttributes> for PyMemoryAttributes {
+    fn from(memattrs: MemoryAttributes) -> Self {
+        Self {
+            memtype: name(memattrs.memtype),
+            device: name(memattrs.device),
+            inner: name(memattrs.inner.attrs),
+            outer: name(memattrs.outer.attrs),
+            shareability: name(memattrs.shareability),
+            tags: name(memattrs.tags),
+            xs: memattrs.xs,
+        }
+    }
+}
+
+#[pymethods]
+impl PyMemoryAttributes {
+    fn __repr__(&self) -> String {
+        format!(
+            "MemoryAttributes(memtype={}, inner={}, outer={}, shareability={})",
+            self.memtype, self.inner, self.outer, self.shareability
+        )
+    }
+}
+
+#[pyclass(name = "AddressDescriptor", get_all)]
+#[derive(Clone)]
+pub struct PyAddressDescriptor {
+    pub vaddress: u64,
+    pub paddress: u64,
+    pub paspace: String,
+    pub memattrs: PyMemoryAttributes,
+    pub mecid: u16,
+    pub fault: PyFaultRecord,
+}
+
+impl From<AddressDescriptor> for PyAddressDescriptor {
+    fn from(desc: AddressDescriptor) -> Self {
+        Self {
+            vaddress: desc.vaddress,
+            paddress: desc.paddress.address,
+            paspace: name(desc.paddress.paspace),
+            memattrs: desc.memattrs.into(),
+            mecid: desc.mecid,
+            fault: desc.fault.into(),
+        }
+    }
+}
+
+#[pymethods]
+impl PyAddressDescriptor {
+    fn __repr__(&self) -> String {
+        format!(
+            "AddressDescriptor(vaddress={:#x}, paddress={:#x}, paspace={})",
+            self.vaddress, self.paddress, self.paspace
+        )
+    }
+}
+
+/// Access `register` by name as a 128-bit value.
+macro_rules! registers {
+    (bitfields { $($reg:ident),* $(,)? } integers { $($int:ident),* $(,)? }) => {
+        fn write_register(regs: &mut SystemRegisters, register: &str, value: u128) -> PyResult<()> {
+            let too_wide = || {
+                PyValueError::new_err(format!("{:#x} does not fit in {}", value, register))
+            };
+            match register {
+                $(stringify!($reg) => regs.$reg = regs.$reg.with_bits(value.try_into().map_err(|_| too_wide())?),)*
+                $(stringify!($int) => regs.$int = value.try_into().map_err(|_| too_wide())?,)*
+                _ => return Err(PyValueError::new_err(format!("unknown register {}", register))),
+            }
+            Ok(())
+        }
+
+        fn read_register(regs: &SystemRegisters, register: &str) -> PyResult<u128> {
+            match register {
+                $(stringify!($reg) => Ok(regs.$reg.bits().into()),)*
+                $(stringify!($int) => Ok(regs.$int.into()),)*
+                _ => Err(PyValueError::new_err(format!("unknown register {}", register))),
+            }
+        }
+    };
+}
+
+trait WithBits<T> {
+    fn with_bits(self, bits: T) -> Self;
+}
+
+macro_rules! with_bits {
+    ($($ty:ident: $bits:ty),* $(,)?) => {
+        $(impl WithBits<$bits> for $ty {
+            fn with_bits(self, bits: $bits) -> Self {
+                Self::from_bits(bits)
+            }
+        })*
+    };
+}
+
+with_bits! {
+    SCR_EL3_REG: u64,
+    SCR_REG: u32,
+    HCR_EL2_REG: u64,
+    HCRX_EL2_REG: u64,
+    SCTLR_ELx_REG: u64,
+    SCTLR2_ELx_REG: u64,
+    TCR_EL1_REG: u64,
+    TCR_EL2_REG: u64,
+    TCR_EL3_REG: u64,
+    TCR2_ELx_REG: u64,
+    VTCR_EL2_REG: u64,
+    VSTCR_EL2_REG: u64,
+    MAIR_ELx_REG: u64,
+    TTBR_ELx_REG: u128,
+    PIR_ELx_REG: u64,
+    POR_ELx_REG: u64,
+    GPCCR_EL3_REG: u64,
+    MECID_ELx_REG: u64,
+}
+
+registers! {
+    bitfields {
+        SCR_EL3, SCR, HCR_EL2, HCRX_EL2,
+        SCTLR_EL1, SCTLR_EL2, SCTLR_EL3, SCTLR2_EL1, SCTLR2_EL2, SCTLR2_EL3,
+        TCR_EL1, TCR_EL2, TCR_EL3, TCR2_EL1, TCR2_EL2,
+        MAIR_EL1, MAIR_EL2, MAIR_EL3, MAIR2_EL1, MAIR2_EL2, MAIR2_EL3,
+        TTBR0_EL1, TTBR1_EL1, TTBR0_EL2, TTBR1_EL2, TTBR0_EL3,
+        PIR_EL1, PIR_EL2, PIR_EL3, PIRE0_EL1, PIRE0_EL2,
+        POR_EL0, POR_EL1, POR_EL2, POR_EL3,
+        VTCR_EL2, VSTCR_EL2, GPCCR_EL3,
+        MECID_P0_EL2, MECID_A0_EL2, MECID_P1_EL2, MECID_A1_EL2,
+        VMECID_P_EL2, VMECID_A_EL2, MECID_RL_A_EL3,
+    }
+    integers {
+        S2POR_EL1, S2PIR_EL2, VTTBR_EL2, VSTTBR_EL2, GPTBR_EL3,
+    }
+}
+
+/// Parse a physical address space from its name, e.g. `"PAS_Realm"`.
+fn parse_paspace(paspace: &str) -> PyResult<PASpace> {
+    match paspace {
+        "PAS_NonSecure" => Ok(PASpace::PAS_NonSecure),
+        "PAS_Secure" => Ok(PASpace::PAS_Secure),
+        "PAS_Root" => Ok(PASpace::PAS_Root),
+        "PAS_Realm" => Ok(PASpace::PAS_Realm),
+        _ => Err(PyValueError::new_err(format!(
+            "invalid physical address space {:?}",
+            paspace
+        ))),
+    }
+}
+
+/// A PE with its own physical memory.
+#[pyclass(name = "ProcessorElement")]
+pub struct PyProcessorElement {
+    pe: ProcessorElement,
+    mem: MecMemory<SparseMemory>,
+}
+
+#[pymethods]
+impl PyProcessorElement {
+    /// A PE implementing the mandatory features of `version` (default
+    /// `"Armv8_0"`) and the optional `features`, given by name.
+    ///
+    /// With `mec_memory`, the contents of the Realm PAS are encrypted with
+    /// a key derived from the MECID of each access.
+    #[new]
+    #[pyo3(signature = (version = "Armv8_0", features = Vec::new(), mec_memory = false))]
+    fn new(version: &str, features: Vec<&str>, mec_memory: bool) -> PyResult<Self> {
+        let features = features
+            .into_iter()
+            .map(str::parse)
+            .collect::<Result<Vec<Feature>, _>>()?;
+        Ok(Self {
+            pe: ProcessorElement::from_profile(version.parse()?, &features)?,
+            mem: MecMemory {
+                inner: SparseMemory::new(),
+                enabled: mec_memory,
+            },
+        })
+    }
+
+    /// Names of the implemented features.
+    #[getter]
+    fn features(&self) -> Vec<String> {
+        self.pe.features.iter().map(name).collect()
+    }
+
+    /// PSTATE.EL
+    #[getter]
+    fn get_el(&self) -> u64 {
+        self.pe.PSTATE.get(ProcState::EL)
+    }
+
+    #[setter]
+    fn set_el(&mut self, el: u64) -> PyResult<()> {
+        if el > 3 {
+            return Err(PyValueError::new_err(format!(
+                "invalid Exception level {}",
+                el
+            )));
+        }
+        self.pe.PSTATE.set(ProcState::EL, el);
+        Ok(())
+    }
+
+    fn read_register(&self, register: &str) -> PyResult<u128> {
+        read_register(&self.pe.regs, register)
+    }
+
+    fn write_register(&mut self, register: &str, value: u128) -> PyResult<()> {
+        write_register(&mut self.pe.regs, register, value)
+    }
+
+    /// Copy `data` into physical memory starting at `address`, as an access
+    /// to `paspace` with `mecid`.
+    #[pyo3(signature = (address, data, paspace = "PAS_NonSecure", mecid = 0))]
+    fn load(&mut self, address: u64, data: &[u8], paspace: &str, mecid: u16) -> PyResult<()> {
+        let mut data = data.to_vec();
+        self.mem
+            .transform(parse_paspace(paspace)?, mecid, address, &mut data);
+        self.mem.inner.load(address, &data);
+        Ok(())
+    }
+
+    /// Read `size` bytes of physical memory starting at `address`, as an
+    /// access to `paspace` with `mecid`.
+    #[pyo3(signature = (address, size, paspace = "PAS_NonSecure", mecid = 0))]
+    fn dump(&self, address: u64, size: usize, paspace: &str, mecid: u16) -> PyResult<Vec<u8>> {
+        let mut data = self.mem.inner.dump(address, size);
+        self.mem
+            .transform(parse_paspace(paspace)?, mecid, address, &mut data);
+        Ok(data)
+    }
+
+    /// Translate `va` for an `access` of `size` bytes at the current Exception level.
+    ///
+    /// `access` is `"read"`, `"write"` or `"fetch"`, `"read_unprivileged"` and
+    /// `"write_unprivileged"` for the accesses of `LDTR` and `STTR`,
+    /// `"read_exclusive"` and `"write_exclusive"` for `LDXR` and `STXR`, or
+    /// `"atomic"` for `LDADD`.
+    #[pyo3(signature = (va, access, size = 1))]
+    fn translate(
+        &mut self,
+        py: Python<'_>,
+        va: u64,
+        access: &str,
+        size: u64,
+    ) -> PyResult<PyAddressDescriptor> {
+        // Exclusives and atomics are at most a pair of doublewords
+        let max_size = match access {
+            "atomic" | "read_exclusive" | "write_exclusive" => 16,
+            _ => 64,
+        };
+        if !size.is_power_of_two() || size > max_size {
+            return Err(PyValueError::new_err(format!(
+                "invalid access size {} for {:?}",
+                size, access
+            )));
+        }
+        let (nontemporal, tagchecked, acqrel) = (false, false, false);
+        let accdesc = match access {
+            "fetch" => CreateAccDescIFetch(&self.pe)?,
+            "read_exclusive" => {
+                CreateAccDescExLDST(&self.pe, MemOp::MemOp_LOAD, acqrel, tagchecked)?
+            }
+            "write_exclusive" => {
+                CreateAccDescExLDST(&self.pe, MemOp::MemOp_STORE, acqrel, tagchecked)?
+            }
+            "atomic" => CreateAccDescAtomicOp(
+                &self.pe,
+                MemAtomicOp::MemAtomicOp_ADD,
+                acqrel,
+                acqrel,
+                tagchecked,
+            )?,
+            "read" | "write" | "read_unprivileged" | "write_unprivileged" => {
+                let memop = if access.starts_with("read") {
+                    MemOp::MemOp_LOAD
+                } else {
+                    MemOp::MemOp_STORE
+                };
+                let privileged = if access.ends_with("_unprivileged") {
+                    AArch64IsUnprivAccessPriv(&self.pe)?
+                } else {
+                    self.pe.current_el()? != EL0
+                };
+                CreateAccDescGPR(&self.pe, memop, nontemporal, privileged, tagchecked)?
+            }
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "invalid access {:?}",
+                    access
+                )))
+            }
+        };
+
+        let aligned = va.is_multiple_of(size);
+        let fault = if !aligned && AArch64UnalignedAccessFaults(&self.pe, accdesc, va, size)? {
+            FaultRecord::AlignmentFault(accdesc)
+        } else {
+            let desc = AArch64TranslateAddress(&self.pe, &mut self.mem, va, accdesc, aligned)?;
+            if desc.fault.statuscode == Fault::Fault_None {
+                return Ok(desc.into());
+            }
+            desc.fault
+        };
+        let err = TranslationFault::new_err(name(fault.statuscode));
+        err.value(py)
+            .setattr("fault", PyFaultRecord::from(fault).into_py(py))?;
+        Err(err)
+    }
+}
+
+pub fn register(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyProcessorElement>()?;
+    m.add_class::<PyAddressDescriptor>()?;
+    m.add_class::<PyMemoryAttributes>()?;
+    m.add_class::<PyFaultRecord>()?;
+    m.add("TranslationFault", py.get_type::<TranslationFault>())?;
+    m.add("ConfigurationError", py.get_type::<ConfigurationError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    /// Run `code` with the module imported as `libarmv8`.
+    fn run(code: &str) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "libarmv8").unwrap();
+            register(py, module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("libarmv8", module).unwrap();
+            if let Err(err) = py.run(code, Some(globals), None) {
+                err.print(py);
+                panic!("Python test failed");
+            }
+        });
+    }
+
+    #[test]
+    fn python_memory() {
+        run(r#"
+pe = libarmv8.ProcessorElement(mec_memory=True)
+pe.load(0x5000, b"\x01\x02\x03\x04")
+assert pe.dump(0x5000, 4) == [1, 2, 3, 4]
+assert pe.dump(0x4ffe, 4) == [0, 0, 1, 2]
+
+# Realm contents only read back with the MECID they were written with
+pe.load(0x6000, b"\x01\x02\x03\x04", "PAS_Realm", 0x10)
+assert pe.dump(0x6000, 4, "PAS_Realm", 0x10) == [1, 2, 3, 4]
+assert pe.dump(0x6000, 4, "PAS_Realm", 0x11) != [1, 2, 3, 4]
+
+try:
+    pe.dump(0x6000, 4, "PAS_Everywhere")
+    raise AssertionError("expected ValueError")
+except ValueError:
+    pass
+"#);
+    }
+
+    #[test]
+    fn python_translate() {
+        run(r#"
+def expect(exception, f, *args):
+    try:
+        f(*args)
+    except exception as e:
+        return e
+    raise AssertionError(f"expected {exception.__name__}")
+
+# EL1 with a 39-bit VA range: VA 0x1000 maps to PA 0x4000_0000
+pe = libarmv8.ProcessorElement("Armv8_4", ["FEAT_AA64EL2"])
+assert "FEAT_AA64EL2" in pe.features
+pe.el = 1
+assert pe.el == 1
+pe.write_register("SCTLR_EL1", 1)
+pe.write_register("TCR_EL1", 25)
+pe.write_register("MAIR_EL1", 0xff)
+pe.write_register("TTBR0_EL1", 0x10_0000)
+assert pe.read_register("TTBR0_EL1") == 0x10_0000
+pe.write_register("TTBR1_EL1", 0xff << 80)
+assert pe.read_register("TTBR1_EL1") == 0xff << 80
+pe.load(0x10_0000, (0x20_0000 | 0b11).to_bytes(8, "little"))
+pe.load(0x20_0000, (0x30_0000 | 0b11).to_bytes(8, "little"))
+pe.load(0x30_0008, (0x4000_0000 | 1 << 10 | 0b11).to_bytes(8, "little"))
+
+desc = pe.translate(0x1234, "read")
+assert desc.paddress == 0x4000_0234, hex(desc.paddress)
+assert desc.paspace == "PAS_NonSecure"
+assert desc.memattrs.memtype == "MemType_Normal"
+assert pe.translate(0x1008, "atomic", 8).paddress == 0x4000_0008
+
+# Faults are raised with the FaultRecord
+e = expect(libarmv8.TranslationFault, pe.translate, 0x20_0000, "write")
+assert e.fault.statuscode == "Fault_Translation"
+assert e.fault.level == 2
+assert e.fault.write
+e = expect(libarmv8.TranslationFault, pe.translate, 0x1008, "atomic", 16)
+assert str(e) == "Fault_Alignment"
+
+# Invalid arguments are ValueErrors
+expect(ValueError, setattr, pe, "el", 4)
+expect(ValueError, pe.translate, 0x1000, "read", 3)
+expect(ValueError, pe.translate, 0x1000, "atomic", 32)
+expect(ValueError, pe.translate, 0x1000, "write_exclusive", 64)
+expect(ValueError, pe.translate, 0x1000, "prefetch")
+expect(ValueError, pe.write_register, "TTBR0_EL9", 0)
+expect(ValueError, pe.write_register, "MAIR_EL1", 1 << 64)
+expect(ValueError, libarmv8.ProcessorElement, "Armv8_0", ["FEAT_NOTHING"])
+expect(ValueError, libarmv8.ProcessorElement, "Armv7")
+
+# Inconsistent configurations are ConfigurationErrors
+expect(libarmv8.ConfigurationError, libarmv8.ProcessorElement, "Armv8_0", ["FEAT_MEC"])
+"#);
+    }
+}
@@ -1,8 +1,11 @@
 // SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+#![allow(clippy::enum_variant_names)]
+
+use crate::error::*;
+use crate::pe::*;
 use crate::shared_vmsa::*;
 use crate::stubs::*;
-
-pub static PSTATE: ProcState = ProcState::new();
+use crate::sysregs::*;
 
 // /// Library pseudocode for shared/functions/system/PhysicalCountInt
 
@@ -77,18 +80,16 @@ mycelium_bitfield::bitfield! {
 impl ProcState {
     pub fn get_EL(&self) -> PrivilegeLevel {
         match self.get(Self::EL) {
-            v if v == (EL0 as u64) => PrivilegeLevel::PL0,
-            v if v == (EL1 as u64) => PrivilegeLevel::PL1,
-            v if v == (EL2 as u64) => PrivilegeLevel::PL2,
+            0b00 => PrivilegeLevel::PL0,
+            0b01 => PrivilegeLevel::PL1,
+            0b10 => PrivilegeLevel::PL2,
             // TODO when EL3  return if !HaveAArch64() then PL1 else PL3;
-            v if v == (EL3 as u64) => PrivilegeLevel::PL3,
-            _ => unreachable!(),
+            _ => PrivilegeLevel::PL3,
         }
     }
 }
 
 /// Library pseudocode for shared/functions/system/SecurityState
-
 /// SecurityState
 /// =============
 /// The Security state of an execution context
@@ -104,48 +105,100 @@ pub enum SecurityState {
 /// SecurityStateAtEL()
 /// ===================
 /// Returns the effective security state at the exception level based off current settings.
-pub fn SecurityStateAtEL(el: PrivilegeLevel) -> SecurityState {
-    if IsFeatureImplemented("FEAT_RME") {
+pub fn SecurityStateAtEL(
+    pe: &ProcessorElement,
+    el: PrivilegeLevel,
+) -> Result<SecurityState, ConfigError> {
+    if IsFeatureImplemented(pe, Feature::FEAT_RME) {
         if el == EL3 {
-            return SecurityState::SS_Root;
+            return Ok(SecurityState::SS_Root);
         }
-        let effective_nse_ns = 0; //todo!(); //SCR_EL3.NSE : EffectiveSCR_EL3_NS();
+        let effective_nse_ns =
+            (pe.regs.SCR_EL3.get(SCR_EL3_REG::NSE) << 1) | EffectiveSCR_EL3_NS(pe);
         match effective_nse_ns {
-            0 if IsFeatureImplemented("FEAT_SEL2") => return SecurityState::SS_Secure,
-            0 => unreachable!(),
-            1 => return SecurityState::SS_NonSecure,
-            3 => return SecurityState::SS_Realm,
-            _ => unreachable!(),
+            0b00 if IsFeatureImplemented(pe, Feature::FEAT_SEL2) => {
+                return Ok(SecurityState::SS_Secure)
+            }
+            0b01 => return Ok(SecurityState::SS_NonSecure),
+            0b11 => return Ok(SecurityState::SS_Realm),
+            value => {
+                return Err(ConfigError::ReservedValue {
+                    register: "SCR_EL3",
+                    field: "{NSE, NS}",
+                    value,
+                })
+            }
         }
     }
 
-    if !HaveEL(EL3) {
-        if SecureOnlyImplementation() {
-            return SecurityState::SS_Secure;
+    if !HaveEL(pe, EL3) {
+        if SecureOnlyImplementation(pe) {
+            Ok(SecurityState::SS_Secure)
         } else {
-            return SecurityState::SS_NonSecure;
+            Ok(SecurityState::SS_NonSecure)
         }
     } else if el == EL3 {
-        return SecurityState::SS_Secure;
+        Ok(SecurityState::SS_Secure)
     } else {
         // For EL2 call only when EL2 is enabled in current security state
-        assert!(el != EL2 || EL2Enabled());
-        if !ELUsingAArch32(EL3) {
-            return if SCR_EL3.get(SCR_EL3_REG::NS) == 1 {
+        if el == EL2 && !EL2Enabled(pe) {
+            return Err(ConfigError::Unpredictable(
+                "Security state of EL2 while EL2 is disabled",
+            ));
+        }
+        if !ELUsingAArch32(pe, EL3) {
+            Ok(if pe.regs.SCR_EL3.get(SCR_EL3_REG::NS) == 1 {
                 SecurityState::SS_NonSecure
             } else {
                 SecurityState::SS_Secure
-            };
+            })
         } else {
-            return if SCR.get(SCR_REG::NS) == 1 {
+            Ok(if pe.regs.SCR.get(SCR_REG::NS) == 1 {
                 SecurityState::SS_NonSecure
             } else {
                 SecurityState::SS_Secure
-            };
+            })
         }
     }
 }
 
+/// Library pseudocode for shared/functions/system/EffectiveSCR_EL3_NS
+/// EffectiveSCR_EL3_NS()
+/// =====================
+/// Return Effective SCR_EL3.NS value.
+pub fn EffectiveSCR_EL3_NS(pe: &ProcessorElement) -> u64 {
+    if !HaveSecureState(pe) {
+        1
+    } else if !HaveEL(pe, EL3) {
+        0
+    } else {
+        pe.regs.SCR_EL3.get(SCR_EL3_REG::NS)
+    }
+}
+
+/// Library pseudocode for shared/functions/system/SecureOnlyImplementation
+/// SecureOnlyImplementation()
+/// ==========================
+/// Returns TRUE if the security state is always Secure for this implementation.
+pub fn SecureOnlyImplementation(pe: &ProcessorElement) -> bool {
+    pe.impdef.secure_only
+}
+
+/// Library pseudocode for shared/functions/system/HaveSecureState
+/// HaveSecureState()
+/// =================
+/// Return TRUE if Secure State is supported.
+pub fn HaveSecureState(pe: &ProcessorElement) -> bool {
+    if !HaveEL(pe, EL3) {
+        return SecureOnlyImplementation(pe);
+    }
+    if IsFeatureImplemented(pe, Feature::FEAT_RME) && !IsFeatureImplemented(pe, Feature::FEAT_SEL2)
+    {
+        return false;
+    }
+    true
+}
+
 /// Library pseudocode for shared/functions/system/EL2Enabled
 /// EL2Enabled()
 /// ============
@@ -154,43 +207,228 @@ pub fn SecurityStateAtEL(el: PrivilegeLevel) -> SecurityState {
 /// - with the PE in Realm state when Realm EL2 is implemented, or
 /// - with the PE in Secure state when Secure EL2 is implemented and enabled, or
 /// - when EL3 is not implemented.
-pub fn EL2Enabled() -> bool {
-    // FIXME HaveEL(EL2) && (!HaveEL(EL3) || SCR_curr[].NS == '1' || IsSecureEL2Enabled())
-    HaveEL(EL2) && (!HaveEL(EL3) || IsSecureEL2Enabled())
+pub fn EL2Enabled(pe: &ProcessorElement) -> bool {
+    let scr_ns = if ELUsingAArch32(pe, EL3) {
+        pe.regs.SCR.get(SCR_REG::NS) as u64
+    } else {
+        pe.regs.SCR_EL3.get(SCR_EL3_REG::NS)
+    };
+    HaveEL(pe, EL2) && (!HaveEL(pe, EL3) || scr_ns == 1 || IsSecureEL2Enabled(pe))
+}
+
+/// Library pseudocode for shared/functions/system/ELIsInHost
+/// ELIsInHost()
+/// ============
+/// Returns TRUE if the Exception level 'el' is executing with the Host
+/// configuration of FEAT_VHE, FALSE otherwise.
+pub fn ELIsInHost(pe: &ProcessorElement, el: PrivilegeLevel) -> bool {
+    if !IsFeatureImplemented(pe, Feature::FEAT_VHE) || ELUsingAArch32(pe, EL2) {
+        return false;
+    }
+    match el {
+        self::EL3 | self::EL1 => false,
+        self::EL2 => EL2Enabled(pe) && pe.regs.HCR_EL2.get(HCR_EL2_REG::E2H) == 1,
+        self::EL0 => {
+            EL2Enabled(pe)
+                && pe.regs.HCR_EL2.get(HCR_EL2_REG::E2H) == 1
+                && pe.regs.HCR_EL2.get(HCR_EL2_REG::TGE) == 1
+        }
+    }
+}
+
+/// Library pseudocode for aarch64/functions/memory/AArch64.IsUnprivAccessPriv
+/// AArch64.IsUnprivAccessPriv()
+/// ============================
+/// Returns TRUE if an unprivileged access is privileged, and FALSE otherwise.
+pub fn AArch64IsUnprivAccessPriv(pe: &ProcessorElement) -> Result<bool, ConfigError> {
+    let el = pe.current_el()?;
+    let mut privileged = match el {
+        self::EL0 => false,
+        // FEAT_NV is not modelled, so EffectiveHCR_EL2_NVx()<1:0> is never '11'
+        self::EL1 => false,
+        self::EL2 => !ELIsInHost(pe, EL0),
+        self::EL3 => true,
+    };
+    if IsFeatureImplemented(pe, Feature::FEAT_UAO) && pe.PSTATE.get(ProcState::UAO) == 1 {
+        privileged = el != EL0;
+    }
+    Ok(privileged)
 }
 
 ///Library pseudocode for shared/functions/system/HaveEL
 /// HaveEL()
 /// ========
 /// Return TRUE if Exception level 'el' is supported
-pub fn HaveEL(_el: PrivilegeLevel) -> bool {
-    todo!()
+pub fn HaveEL(pe: &ProcessorElement, el: PrivilegeLevel) -> bool {
+    match el {
+        // EL1 and EL0 must exist
+        PrivilegeLevel::PL1 | PrivilegeLevel::PL0 => true,
+        PrivilegeLevel::PL2 => {
+            IsFeatureImplemented(pe, Feature::FEAT_AA64EL2)
+                || IsFeatureImplemented(pe, Feature::FEAT_AA32EL2)
+        }
+        PrivilegeLevel::PL3 => {
+            IsFeatureImplemented(pe, Feature::FEAT_AA64EL3)
+                || IsFeatureImplemented(pe, Feature::FEAT_AA32EL3)
+        }
+    }
+}
 
-    // case el of
-    // when EL1,EL0
-    // return TRUE; // EL1 and EL0 must exist
-    // when EL2
-    // return IsFeatureImplemented(FEAT_AA64EL2) || IsFeatureImplemented(FEAT_AA32EL2);
-    // when EL3
-    // return IsFeatureImplemented(FEAT_AA64EL3) || IsFeatureImplemented(FEAT_AA32EL3);
-    // otherwise
-    // Unreachable();
+/// Library pseudocode for shared/functions/extension/HaveAccessFlagUpdateExt
+/// HaveAccessFlagUpdateExt()
+/// =========================
+/// Returns TRUE if hardware update of the Access flag is supported.
+pub fn HaveAccessFlagUpdateExt(pe: &ProcessorElement) -> bool {
+    IsFeatureImplemented(pe, Feature::FEAT_HAFDBS) || pe.impdef.af_update_only
+}
+
+/// Library pseudocode for shared/functions/extension/HaveDirtyBitModifierExt
+/// HaveDirtyBitModifierExt()
+/// =========================
+/// Returns TRUE if hardware update of dirty state is supported.
+pub fn HaveDirtyBitModifierExt(pe: &ProcessorElement) -> bool {
+    IsFeatureImplemented(pe, Feature::FEAT_HAFDBS)
 }
 
 /// Library pseudocode for shared/functions/system/IsSecureEL2Enabled
-/// // IsSecureEL2Enabled()
-/// // ====================
-/// // Returns TRUE if Secure EL2 is enabled, FALSE otherwise.
-pub fn IsSecureEL2Enabled() -> bool {
-    false
-    // if HaveEL(EL2) && IsFeatureImplemented(FEAT_SEL2) then
-    // if HaveEL(EL3) then
-    // if !ELUsingAArch32(EL3) && SCR_EL3.EEL2 == '1' then
-    // return TRUE;
-    // else
-    // return FALSE;
-    // else
-    // return SecureOnlyImplementation();
-    // else
-    // return FALSE;
+/// IsSecureEL2Enabled()
+/// ====================
+/// Returns TRUE if Secure EL2 is enabled, FALSE otherwise.
+pub fn IsSecureEL2Enabled(pe: &ProcessorElement) -> bool {
+    if HaveEL(pe, EL2) && IsFeatureImplemented(pe, Feature::FEAT_SEL2) {
+        if HaveEL(pe, EL3) {
+            !ELUsingAArch32(pe, EL3) && pe.regs.SCR_EL3.get(SCR_EL3_REG::EEL2) == 1
+        } else {
+            SecureOnlyImplementation(pe)
+        }
+    } else {
+        false
+    }
+}
+
+/// Library pseudocode for aarch64/functions/sysregisters/IsHCRXEL2Enabled
+/// IsHCRXEL2Enabled()
+/// ==================
+/// Returns TRUE if access to HCRX_EL2 register is enabled, and FALSE otherwise.
+/// Indirect read of HCRX_EL2 returns 0 when access is not enabled.
+pub fn IsHCRXEL2Enabled(pe: &ProcessorElement) -> bool {
+    if !IsFeatureImplemented(pe, Feature::FEAT_HCX) {
+        return false;
+    }
+    if HaveEL(pe, EL3) && pe.regs.SCR_EL3.get(SCR_EL3_REG::HXEn) == 0 {
+        return false;
+    }
+    EL2Enabled(pe)
+}
+
+/// Library pseudocode for aarch64/functions/sysregisters/IsSCTLR2EL1Enabled
+/// IsSCTLR2EL1Enabled()
+/// ====================
+/// Returns TRUE if access to SCTLR2_EL1 register is enabled, and FALSE otherwise.
+/// Indirect read of SCTLR2_EL1 returns 0 when access is not enabled.
+pub fn IsSCTLR2EL1Enabled(pe: &ProcessorElement) -> bool {
+    if !IsFeatureImplemented(pe, Feature::FEAT_SCTLR2) {
+        return false;
+    }
+    if HaveEL(pe, EL3) && pe.regs.SCR_EL3.get(SCR_EL3_REG::SCTLR2En) == 0 {
+        return false;
+    }
+    if EL2Enabled(pe)
+        && (!IsHCRXEL2Enabled(pe) || pe.regs.HCRX_EL2.get(HCRX_EL2_REG::SCTLR2En) == 0)
+    {
+        return false;
+    }
+    true
+}
+
+/// Library pseudocode for aarch64/functions/sysregisters/IsSCTLR2EL2Enabled
+/// IsSCTLR2EL2Enabled()
+/// ====================
+/// Returns TRUE if access to SCTLR2_EL2 register is enabled, and FALSE otherwise.
+/// Indirect read of SCTLR2_EL2 returns 0 when access is not enabled.
+pub fn IsSCTLR2EL2Enabled(pe: &ProcessorElement) -> bool {
+    if !IsFeatureImplemented(pe, Feature::FEAT_SCTLR2) {
+        return false;
+    }
+    if HaveEL(pe, EL3) && pe.regs.SCR_EL3.get(SCR_EL3_REG::SCTLR2En) == 0 {
+        return false;
+    }
+    EL2Enabled(pe)
+}
+
+/// Library pseudocode for aarch64/functions/sysregisters/IsTCR2EL1Enabled
+/// IsTCR2EL1Enabled()
+/// ==================
+/// Returns TRUE if access to TCR2_EL1 register is enabled, and FALSE otherwise.
+/// Indirect read of TCR2_EL1 returns 0 when access is not enabled.
+pub fn IsTCR2EL1Enabled(pe: &ProcessorElement) -> bool {
+    if !IsFeatureImplemented(pe, Feature::FEAT_TCR2) {
+        return false;
+    }
+    if HaveEL(pe, EL3) && pe.regs.SCR_EL3.get(SCR_EL3_REG::TCR2En) == 0 {
+        return false;
+    }
+    if EL2Enabled(pe) && (!IsHCRXEL2Enabled(pe) || pe.regs.HCRX_EL2.get(HCRX_EL2_REG::TCR2En) == 0)
+    {
+        return false;
+    }
+    true
+}
+
+/// Library pseudocode for aarch64/functions/sysregisters/IsTCR2EL2Enabled
+/// IsTCR2EL2Enabled()
+/// ==================
+/// Returns TRUE if access to TCR2_EL2 register is enabled, and FALSE otherwise.
+/// Indirect read of TCR2_EL2 returns 0 when access is not enabled.
+pub fn IsTCR2EL2Enabled(pe: &ProcessorElement) -> bool {
+    if !IsFeatureImplemented(pe, Feature::FEAT_TCR2) {
+        return false;
+    }
+    if HaveEL(pe, EL3) && pe.regs.SCR_EL3.get(SCR_EL3_REG::TCR2En) == 0 {
+        return false;
+    }
+    EL2Enabled(pe)
+}
+
+// Bit slicing helpers standing in for the pseudocode `x<hi:lo>` and `Align()` operators.
+
+/// `x<hi:lo>`
+pub const fn Bits(x: u128, hi: u32, lo: u32) -> u64 {
+    debug_assert!(hi >= lo && hi - lo < 64);
+    ((x >> lo) & ((1_u128 << (hi - lo + 1)) - 1)) as u64
+}
+
+/// `x<n>`
+pub const fn Bit(x: u128, n: u32) -> u64 {
+    ((x >> n) & 1) as u64
+}
+
+/// `x` with `x<hi:lo>` replaced by `value`
+pub const fn SetBits(x: u128, hi: u32, lo: u32, value: u64) -> u128 {
+    debug_assert!(hi >= lo && hi - lo < 64);
+    let mask = ((1_u128 << (hi - lo + 1)) - 1) << lo;
+    (x & !mask) | (((value as u128) << lo) & mask)
+}
+
+/// `Align(x, 1 << log2)`: clear the `log2` least significant bits of `x`.
+pub const fn Align(x: u64, log2: u64) -> u64 {
+    if log2 >= 64 {
+        0
+    } else {
+        x & !((1_u64 << log2) - 1)
+    }
+}
+
+/// `IsZero(x<hi:lo>)`, tolerating ranges that are empty because `lo > hi`.
+pub const fn IsZeroBits(x: u64, hi: u32, lo: u32) -> bool {
+    if lo > hi || lo >= 64 {
+        true
+    } else {
+        Bits(x as u128, hi, lo) == 0
+    }
+}
+
+/// `IsOnes(x<hi:lo>)`, tolerating ranges that are empty because `lo > hi`.
+pub const fn IsOnesBits(x: u64, hi: u32, lo: u32) -> bool {
+    IsZeroBits(!x, hi, lo)
 }
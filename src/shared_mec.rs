@@ -1,111 +1,136 @@
 // SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
 #![allow(non_camel_case_types)]
 
+use crate::error::*;
+use crate::pe::*;
 use crate::shared::*;
 use crate::shared_memory::*;
 use crate::shared_mpam::MPAMinfo;
 use crate::shared_translation::*;
 use crate::shared_vmsa::*;
+use crate::sysregs::*;
 
-// Library pseudocode for aarch64/functions/mec/AArch64.S1AMECFault
-
-// // AArch64.S1AMECFault()
-// // =====================
-// // Returns TRUE if a Translation fault should occur for Realm EL2 and Realm EL2&0
-// // stage 1 translated addresses to Realm PA space.
-
-// boolean AArch64.S1AMECFault(S1TTWParams walkparams, PASpace paspace, Regime regime,
-//                            bits(N) descriptor)
-//    assert N IN {64,128};
-//    bit descriptor_amec = if walkparams.d128 == '1' then descriptor<108> else descriptor<63>;
-
-//    return (walkparams.<emec,amec> == '10' &&
-//            regime IN {Regime_EL2, Regime_EL20} &&
-//            paspace == PAS_Realm &&
-//            descriptor_amec == '1');
-
-// Library pseudocode for aarch64/functions/mec/AArch64.S1DisabledOutputMECID
+/// Library pseudocode for aarch64/functions/mec/AArch64.S1AMECFault
+/// AArch64.S1AMECFault()
+/// =====================
+/// Returns TRUE if a Translation fault should occur for Realm EL2 and Realm EL2&0
+/// stage 1 translated addresses to Realm PA space.
+pub fn AArch64S1AMECFault(
+    walkparams: S1TTWParams,
+    paspace: PASpace,
+    regime: Regime,
+    descriptor: u128,
+) -> bool {
+    let descriptor_amec = if walkparams.get_d128() == 1 {
+        Bit(descriptor, 108)
+    } else {
+        Bit(descriptor, 63)
+    };
 
-// // AArch64.S1DisabledOutputMECID()
-// // ===============================
-// // Returns the output MECID when stage 1 address translation is disabled.
+    walkparams.get_emec() == 1
+        && walkparams.get_amec() == 0
+        && matches!(regime, Regime::Regime_EL2 | Regime::Regime_EL20)
+        && paspace == PASpace::PAS_Realm
+        && descriptor_amec == 1
+}
 
-// bits(16) AArch64.S1DisabledOutputMECID(S1TTWParams walkparams, Regime regime, PASpace paspace)
-//    if walkparams.emec == '0' then
-//        return DEFAULT_MECID;
+/// Library pseudocode for aarch64/functions/mec/AArch64.S1DisabledOutputMECID
+/// AArch64.S1DisabledOutputMECID()
+/// ===============================
+/// Returns the output MECID when stage 1 address translation is disabled.
+pub fn AArch64S1DisabledOutputMECID(
+    pe: &ProcessorElement,
+    walkparams: S1TTWParams,
+    regime: Regime,
+    paspace: PASpace,
+) -> u16 {
+    if walkparams.get_emec() == 0 {
+        return DEFAULT_MECID;
+    }
 
-//    if !(regime IN {Regime_EL2, Regime_EL20, Regime_EL10}) then
-//        return DEFAULT_MECID;
+    if !matches!(
+        regime,
+        Regime::Regime_EL2 | Regime::Regime_EL20 | Regime::Regime_EL10
+    ) {
+        return DEFAULT_MECID;
+    }
 
-//    if paspace != PAS_Realm then
-//        return DEFAULT_MECID;
+    if paspace != PASpace::PAS_Realm {
+        return DEFAULT_MECID;
+    }
 
-//    if regime == Regime_EL10 then
-//        return VMECID_P_EL2.MECID;
-//    else
-//        return MECID_P0_EL2.MECID;
+    if regime == Regime::Regime_EL10 {
+        MECID(pe.regs.VMECID_P_EL2)
+    } else {
+        MECID(pe.regs.MECID_P0_EL2)
+    }
+}
 
 /// Library pseudocode for aarch64/functions/mec/AArch64.S1OutputMECID
-
 /// AArch64.S1OutputMECID()
 /// =======================
 /// Returns the output MECID when stage 1 address translation is enabled.
-
-pub fn AArch64S1OutputMECID_64(
+pub fn AArch64S1OutputMECID(
+    pe: &ProcessorElement,
     walkparams: S1TTWParams,
-    _regime: Regime,
-    _varange: VARange,
+    regime: Regime,
+    varange: VARange,
     paspace: PASpace,
-    _descriptor: u64,
-) -> u16 {
-    if walkparams.bitfield.get(S1TTWParamsBits::emec) == 0 {
-        return DEFAULT_MECID;
+    descriptor: u128,
+) -> Result<u16, TranslationError> {
+    if walkparams.get_emec() == 0 {
+        return Ok(DEFAULT_MECID);
     }
 
     if paspace != PASpace::PAS_Realm {
-        return DEFAULT_MECID;
+        return Ok(DEFAULT_MECID);
     }
 
-    todo!()
-    // let descriptor_amec = if walkparams.d128 == 1 { descriptor<108> } else { descriptor<63> };
-    // match regime {
-    //     Regime::Regime_EL3 =>
-    //         return MECID_RL_A_EL3.MECID,
-    //     Regime::Regime_EL2 =>
-    //         if descriptor_amec == 0 {
-    //             return MECID_P0_EL2.MECID;
-    //         }
-    //         else {
-    //             return MECID_A0_EL2.MECID;
-    //         }
-    //     Regime::Regime_EL20 =>
-    //         if varange == VARange_LOWER {
-    //             if descriptor_amec == 0 {
-    //                 return MECID_P0_EL2.MECID;
-    //             } else {
-    //                 return MECID_A0_EL2.MECID;
-    //             }
-    //         } else {
-    //             if descriptor_amec == '0' {
-    //                 return MECID_P1_EL2.MECID;
-    //             }else {
-    //                 return MECID_A1_EL2.MECID;
-    //             }
-    //         }
-    //     Regime::Regime_EL10 =>
-    //         return VMECID_P_EL2.MECID,
-    //     Regime::Regime_EL30 => unreachable!(),
-    // }
+    let descriptor_amec = if walkparams.get_d128() == 1 {
+        Bit(descriptor, 108)
+    } else {
+        Bit(descriptor, 63)
+    };
+
+    Ok(match regime {
+        Regime::Regime_EL3 => MECID(pe.regs.MECID_RL_A_EL3),
+        Regime::Regime_EL2 => {
+            if descriptor_amec == 0 {
+                MECID(pe.regs.MECID_P0_EL2)
+            } else {
+                MECID(pe.regs.MECID_A0_EL2)
+            }
+        }
+        Regime::Regime_EL20 => {
+            if varange == VARange::VARange_LOWER {
+                if descriptor_amec == 0 {
+                    MECID(pe.regs.MECID_P0_EL2)
+                } else {
+                    MECID(pe.regs.MECID_A0_EL2)
+                }
+            } else if descriptor_amec == 0 {
+                MECID(pe.regs.MECID_P1_EL2)
+            } else {
+                MECID(pe.regs.MECID_A1_EL2)
+            }
+        }
+        Regime::Regime_EL10 => MECID(pe.regs.VMECID_P_EL2),
+        // AArch32 EL3 is not modelled
+        Regime::Regime_EL30 => return Err(TranslationError::Unsupported("AArch32 EL3")),
+    })
 }
 
-pub fn AArch64S1OutputMECID_128(
-    walkparams: S1TTWParams,
-    _regime: Regime,
-    _varange: VARange,
+/// Library pseudocode for aarch64/functions/mec/AArch64.S2OutputMECID
+/// AArch64.S2OutputMECID()
+/// =======================
+/// Returns the output MECID for stage 2 address translation.
+pub fn AArch64S2OutputMECID(
+    pe: &ProcessorElement,
+    walkparams: S2TTWParams,
     paspace: PASpace,
-    _descriptor: u128,
+    descriptor: u128,
 ) -> u16 {
-    if walkparams.bitfield.get(S1TTWParamsBits::emec) == 0 {
+    if walkparams.get_emec() == 0 {
         return DEFAULT_MECID;
     }
 
@@ -113,88 +138,190 @@ pub fn AArch64S1OutputMECID_128(
         return DEFAULT_MECID;
     }
 
-    todo!()
-    // let descriptor_amec = if walkparams.bitfield.get(S1TTWParamsBits::d128) == 1 { descriptor<108> } else { descriptor<63> };
-    // match regime {
-    //     Regime::Regime_EL3 =>
-    //         return MECID_RL_A_EL3.MECID,
-    //     Regime::Regime_EL2 =>
-    //         if descriptor_amec == 0 {
-    //             return MECID_P0_EL2.MECID;
-    //         }
-    //         else {
-    //             return MECID_A0_EL2.MECID;
-    //         }
-    //     Regime::Regime_EL20 =>
-    //         if varange == VARange_LOWER {
-    //             if descriptor_amec == 0 {
-    //                 return MECID_P0_EL2.MECID;
-    //             } else {
-    //                 return MECID_A0_EL2.MECID;
-    //             }
-    //         } else {
-    //             if descriptor_amec == '0' {
-    //                 return MECID_P1_EL2.MECID;
-    //             }else {
-    //                 return MECID_A1_EL2.MECID;
-    //             }
-    //         }
-    //     Regime::Regime_EL10 =>
-    //         return VMECID_P_EL2.MECID,
-    //     Regime::Regime_EL30 => unreachable!(),
-    // }
+    let descriptor_amec = if walkparams.get_d128() == 1 {
+        Bit(descriptor, 108)
+    } else {
+        Bit(descriptor, 63)
+    };
+
+    if descriptor_amec == 0 {
+        MECID(pe.regs.VMECID_P_EL2)
+    } else {
+        MECID(pe.regs.VMECID_A_EL2)
+    }
+}
+
+/// Library pseudocode for aarch64/functions/mec/AArch64.TTWalkMECID
+/// AArch64.TTWalkMECID()
+/// =====================
+/// Returns the associated MECID for the translation table walk of the given
+/// translation regime and Security state.
+pub fn AArch64TTWalkMECID(
+    pe: &ProcessorElement,
+    emec: u64,
+    regime: Regime,
+    ss: SecurityState,
+) -> Result<u16, TranslationError> {
+    if emec == 0 {
+        return Ok(DEFAULT_MECID);
+    }
+
+    if ss != SecurityState::SS_Realm {
+        return Ok(DEFAULT_MECID);
+    }
+
+    Ok(match regime {
+        Regime::Regime_EL2 => MECID(pe.regs.MECID_P0_EL2),
+        Regime::Regime_EL20 => {
+            if pe.regs.TCR_EL2_E2H().get(TCR_EL1_REG::A1) == 0 {
+                MECID(pe.regs.MECID_P1_EL2)
+            } else {
+                MECID(pe.regs.MECID_P0_EL2)
+            }
+        }
+        // This applies to stage 1 and stage 2 translation table walks for
+        // Realm EL1&0, but the stage 2 translation for a stage 1 walk
+        // might later override the MECID according to AMEC configuration.
+        Regime::Regime_EL10 => MECID(pe.regs.VMECID_P_EL2),
+        // EL3 is never in Realm state
+        Regime::Regime_EL3 => {
+            return Err(TranslationError::Unpredictable(
+                "Realm translation table walk in the EL3 regime",
+            ))
+        }
+        // AArch32 EL3 is not modelled
+        Regime::Regime_EL30 => return Err(TranslationError::Unsupported("AArch32 EL3")),
+    })
 }
 
-// Library pseudocode for aarch64/functions/mec/AArch64.S2OutputMECID
-
-// // AArch64.S2OutputMECID()
-// // =======================
-// // Returns the output MECID for stage 2 address translation.
-
-// bits(16) AArch64.S2OutputMECID(S2TTWParams walkparams, PASpace paspace, bits(N) descriptor)
-//    assert N IN {64,128};
-
-//    if walkparams.emec == '0' then
-//        return DEFAULT_MECID;
-
-//    if paspace != PAS_Realm then
-//        return DEFAULT_MECID;
-
-//    bit descriptor_amec = if walkparams.d128 == '1' then descriptor<108> else descriptor<63>;
-//    if descriptor_amec == '0' then
-//        return VMECID_P_EL2.MECID;
-//    else
-//        return VMECID_A_EL2.MECID;
-
-// Library pseudocode for aarch64/functions/mec/AArch64.TTWalkMECID
-
-// // AArch64.TTWalkMECID()
-// // =====================
-// // Returns the associated MECID for the translation table walk of the given
-// // translation regime and Security state.
-
-// bits(16) AArch64.TTWalkMECID(bit emec, Regime regime, SecurityState ss)
-//    if emec == '0' then
-//        return DEFAULT_MECID;
-
-//    if ss != SS_Realm then
-//        return DEFAULT_MECID;
-
-//    case regime of
-//        when Regime_EL2
-//            return MECID_P0_EL2.MECID;
-//        when Regime_EL20
-//            if TCR_EL2.A1 == '0' then
-//                return MECID_P1_EL2.MECID;
-//            else
-//                return MECID_P0_EL2.MECID;
-//        // This applies to stage 1 and stage 2 translation table walks for
-//        // Realm EL1&0, but the stage 2 translation for a stage 1 walk
-//        // might later override the MECID according to AMEC configuration.
-//        when Regime_EL10
-//            return VMECID_P_EL2.MECID;
-//        otherwise
-//            Unreachable();
+/// The MECID field of one of the MECID registers.
+fn MECID(reg: MECID_ELx_REG) -> u16 {
+    reg.get(MECID_ELx_REG::MECID) as u16
+}
 
 /// Library pseudocode for aarch64/functions/mec/DEFAULT_MECID
-pub const DEFAULT_MECID: u16 = 0_16;
+pub const DEFAULT_MECID: u16 = 0;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physmem::*;
+    use crate::translation64::*;
+    use std::cell::RefCell;
+
+    /// A backend that records the MECID of every read.
+    struct MecidMemory {
+        inner: SparseMemory,
+        mecids: RefCell<Vec<u16>>,
+    }
+
+    impl PhysicalMemory for MecidMemory {
+        fn read(
+            &self,
+            desc: AddressDescriptor,
+            size: usize,
+            accdesc: AccessDescriptor,
+        ) -> (PhysMemRetStatus, Vec<u8>) {
+            self.mecids.borrow_mut().push(desc.mecid);
+            self.inner.read(desc, size, accdesc)
+        }
+
+        fn write(
+            &mut self,
+            desc: AddressDescriptor,
+            size: usize,
+            accdesc: AccessDescriptor,
+            value: &[u8],
+        ) -> PhysMemRetStatus {
+            self.inner.write(desc, size, accdesc, value)
+        }
+    }
+
+    /// A Realm EL2 PE with SCTLR2_EL2.EMEC set, and a 4KB granule table with
+    /// `leaf` as the page descriptor for VA 0x1000.
+    fn realm_el2(leaf: u64) -> (ProcessorElement, MecidMemory) {
+        let mut pe = ProcessorElement::from_profile(
+            ArchVersion::Armv9_2,
+            &[
+                Feature::FEAT_AA64EL2,
+                Feature::FEAT_AA64EL3,
+                Feature::FEAT_RME,
+                Feature::FEAT_MEC,
+                Feature::FEAT_SCTLR2,
+                Feature::FEAT_TCR2,
+            ],
+        )
+        .unwrap();
+        pe.PSTATE.set(ProcState::EL, 2);
+        pe.regs.SCR_EL3 = pe
+            .regs
+            .SCR_EL3
+            .with(SCR_EL3_REG::NS, 1)
+            .with(SCR_EL3_REG::NSE, 1)
+            .with(SCR_EL3_REG::SCTLR2En, 1)
+            .with(SCR_EL3_REG::TCR2En, 1);
+        pe.regs.SCTLR2_EL2 = pe.regs.SCTLR2_EL2.with(SCTLR2_ELx_REG::EMEC, 1);
+        pe.regs.MECID_P0_EL2 = MECID_ELx_REG::new().with(MECID_ELx_REG::MECID, 0x10);
+        pe.regs.MECID_A0_EL2 = MECID_ELx_REG::new().with(MECID_ELx_REG::MECID, 0xa0);
+        pe.regs.SCTLR_EL2 = pe.regs.SCTLR_EL2.with(SCTLR_ELx_REG::M, 1);
+        pe.regs.TCR_EL2 = TCR_EL2_REG::new().with(TCR_EL2_REG::T0SZ, 32);
+        pe.regs.TTBR0_EL2 = TTBR_ELx_REG::from_bits(0x10_0000);
+
+        let mut inner = SparseMemory::new();
+        TableBuilder::new(0x10_0000, 12, 1).map(&mut inner, 0x1000, 3, leaf.into());
+        let mem = MecidMemory {
+            inner,
+            mecids: RefCell::new(vec![]),
+        };
+        (pe, mem)
+    }
+
+    fn translate(pe: &ProcessorElement, mem: &mut MecidMemory) -> AddressDescriptor {
+        mem.mecids.borrow_mut().clear();
+        let accdesc = CreateAccDescGPR(pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        AArch64TranslateAddress(pe, mem, 0x1000, accdesc, true).unwrap()
+    }
+
+    #[test]
+    fn mecid_selection() {
+        const PAGE: u64 = 0x4000_0000 | (1 << 10) | 0b11;
+        const AMEC: u64 = 1 << 63;
+        const NS: u64 = 1 << 5;
+
+        // The walk and the output use MECID_P0_EL2
+        let (pe, mut mem) = realm_el2(PAGE);
+        let addrdesc = translate(&pe, &mut mem);
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_None);
+        assert_eq!(addrdesc.paddress.paspace, PASpace::PAS_Realm);
+        assert_eq!(addrdesc.mecid, 0x10);
+        assert_eq!(*mem.mecids.borrow(), [0x10; 3]);
+
+        // The AMEC bit is a Translation fault unless TCR2_EL2.AMEC0 is set, and
+        // then selects MECID_A0_EL2
+        let (mut pe, mut mem) = realm_el2(PAGE | AMEC);
+        let addrdesc = translate(&pe, &mut mem);
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_Translation);
+        assert_eq!(addrdesc.fault.level, 3);
+        pe.regs.TCR2_EL2 = pe.regs.TCR2_EL2.with(TCR2_ELx_REG::AMEC0, 1);
+        let addrdesc = translate(&pe, &mut mem);
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_None);
+        assert_eq!(addrdesc.mecid, 0xa0);
+
+        // Outputs to the Non-secure PA space use the default MECID
+        let (pe, mut mem) = realm_el2(PAGE | AMEC | NS);
+        let addrdesc = translate(&pe, &mut mem);
+        assert_eq!(addrdesc.paddress.paspace, PASpace::PAS_NonSecure);
+        assert_eq!(addrdesc.mecid, DEFAULT_MECID);
+
+        // With the MMU off the output uses MECID_P0_EL2
+        let (mut pe, mut mem) = realm_el2(PAGE);
+        pe.regs.SCTLR_EL2 = pe.regs.SCTLR_EL2.with(SCTLR_ELx_REG::M, 0);
+        assert_eq!(translate(&pe, &mut mem).mecid, 0x10);
+
+        // Without SCTLR2_EL2.EMEC every access uses the default MECID
+        let (mut pe, mut mem) = realm_el2(PAGE);
+        pe.regs.SCTLR2_EL2 = pe.regs.SCTLR2_EL2.with(SCTLR2_ELx_REG::EMEC, 0);
+        assert_eq!(translate(&pe, &mut mem).mecid, DEFAULT_MECID);
+        assert_eq!(*mem.mecids.borrow(), [DEFAULT_MECID; 3]);
+    }
+}
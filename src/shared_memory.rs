@@ -1,9 +1,14 @@
 // SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
-#![allow(non_camel_case_types)]
+#![allow(non_camel_case_types, clippy::enum_variant_names)]
 
+use crate::error::*;
+use crate::pe::*;
 use crate::shared::*;
 use crate::shared_mpam::{GenMPAMCurEL, MPAMinfo};
+use crate::shared_translation::Regime;
 use crate::shared_vmsa::*;
+use crate::sysregs::*;
+use crate::translation64_gpc::{GPCFRecord, GPCNoFault};
 
 /// Library pseudocode for shared/functions/memory/Fault
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -29,6 +34,10 @@ pub enum Fault {
     Fault_Lockdown,
     Fault_Exclusive,
     Fault_ICacheMaint,
+    /// Granule protection fault on a translation table walk
+    Fault_GPCFOnWalk,
+    /// Granule protection fault on the output address
+    Fault_GPCFOnOutput,
 }
 
 /// Library pseudocode for shared/functions/memory/FaultRecord
@@ -39,14 +48,20 @@ pub struct FaultRecord {
     pub statuscode: Fault,
     // Type of access that faulted
     pub acctype: AccessType,
+    // Physical address of a granule protection fault
+    pub paddress: FullAddress,
+    // Granule protection check fault
+    pub gpcf: GPCFRecord,
     // Intermediate physical address
     pub ipaddress: FullAddress,
+    // Granule protection fault on a Stage 2 translation table walk
+    pub gpcfs2walk: bool,
     // Is on a Stage 1 translation table walk
     pub s2fs1walk: bool,
     // TRUE for a write, FALSE for a read
     pub write: bool,
     // For translation, access flag and permission faults
-    pub level: u64,
+    pub level: i64,
     // IMPLEMENTATION DEFINED bit syndrome for External aborts
     pub extflag: bool,
     // Is a Stage 2 abort
@@ -57,57 +72,64 @@ pub struct FaultRecord {
     pub errortype: u8,
     // Debug method of entry, from AArch32 only
     pub debugmoe: u8,
+    // Permission fault due to dirty state
+    pub dirtybit: bool,
+    // Fault due to overlay permissions
+    pub overlay: bool,
 }
 
 impl FaultRecord {
     /// Library pseudocode for shared/translation/faults/NoFault
     /// Return a clear fault record indicating no faults have occured
     pub fn NoFault() -> Self {
-        // FaultRecord fault;
-
-        // fault.statuscode  = Fault_None;
-        // fault.accessdesc  = AccessDescriptor UNKNOWN;
-        // fault.secondstage = FALSE;
-        // fault.s2fs1walk   = FALSE;
-        // fault.dirtybit    = FALSE;
-        // fault.overlay     = FALSE;
-        // fault.toplevel    = FALSE;
-        // fault.assuredonly = FALSE;
-        // fault.s1tagnotdata = FALSE;
-        // fault.tagaccess   = FALSE;
-        // fault.gpcfs2walk  = FALSE;
-        // fault.gpcf        = GPCNoFault();
-        // fault.hdbssf      = FALSE;
-
-        // return fault;
-        todo!()
+        Self {
+            statuscode: Fault::Fault_None,
+            // UNKNOWN
+            acctype: AccessType::AccessType_GPR,
+            paddress: FullAddress {
+                paspace: PASpace::PAS_NonSecure,
+                address: 0,
+            },
+            gpcf: GPCNoFault(),
+            ipaddress: FullAddress {
+                paspace: PASpace::PAS_NonSecure,
+                address: 0,
+            },
+            gpcfs2walk: false,
+            s2fs1walk: false,
+            write: false,
+            level: 0,
+            extflag: false,
+            secondstage: false,
+            domain: 0,
+            errortype: 0,
+            debugmoe: 0,
+            dirtybit: false,
+            overlay: false,
+        }
     }
 
     /// NoFault()
     /// =========
     /// Return a clear fault record indicating no faults have occured for a specific access
+    pub fn NoFaultForAccess(accdesc: AccessDescriptor) -> Self {
+        Self {
+            acctype: accdesc.acctype,
+            write: !accdesc.read && accdesc.write,
+            ..Self::NoFault()
+        }
+    }
 
-    pub fn NoFaultForAccess(_accdesc: AccessDescriptor) -> Self {
-        // FaultRecord NoFault(AccessDescriptor accdesc)
-        //     FaultRecord fault;
-
-        //     fault.statuscode  = Fault_None;
-        //     fault.accessdesc  = accdesc;
-        //     fault.secondstage = FALSE;
-        //     fault.s2fs1walk   = FALSE;
-        //     fault.dirtybit    = FALSE;
-        //     fault.overlay     = FALSE;
-        //     fault.toplevel    = FALSE;
-        //     fault.assuredonly = FALSE;
-        //     fault.s1tagnotdata = FALSE;
-        //     fault.tagaccess   = FALSE;
-        //     fault.write       = !accdesc.read && accdesc.write;
-        //     fault.gpcfs2walk  = FALSE;
-        //     fault.gpcf        = GPCNoFault();
-        //     fault.hdbssf      = FALSE;
-
-        //     return fault;
-        todo!()
+    /// Library pseudocode for shared/translation/faults/AlignmentFault
+    /// AlignmentFault()
+    /// ================
+    /// Return a fault record indicating that an Alignment fault not due to memory
+    /// type has occured for a specific access
+    pub fn AlignmentFault(accdesc: AccessDescriptor) -> Self {
+        Self {
+            statuscode: Fault::Fault_Alignment,
+            ..Self::NoFaultForAccess(accdesc)
+        }
     }
 }
 
@@ -149,7 +171,6 @@ pub enum AccessType {
 }
 
 /// Library pseudocode for shared/functions/memory/AccessDescriptor
-
 /// # AccessDescriptor
 ///
 /// Memory access or translation invocation details that steer architectural behavior
@@ -274,6 +295,20 @@ pub struct Permissions {
     pub uxn: u8,
     /// Stage 1 privileged execute-never bit
     pub pxn: u8,
+    /// Stage 1 privileged indirect permissions bits(4)
+    pub ppi: u8,
+    /// Stage 1 unprivileged indirect permissions bits(4)
+    pub upi: u8,
+    /// Stage 1 dirty state for indirect permissions scheme bit
+    pub ndirty: u8,
+    /// Stage 1 overlay permissions index bits(4)
+    pub po_index: u8,
+    /// Stage 2 indirect permissions index bits(4)
+    pub s2pi_index: u8,
+    /// Stage 2 overlay permissions index bits(4)
+    pub s2po_index: u8,
+    /// Stage 2 dirty state for indirect permissions scheme bit
+    pub s2dirty: u8,
     /// Stage 2 access permissions bits(2}
     pub s2ap: u8,
     /// Stage 2 extended execute-never bit
@@ -282,14 +317,25 @@ pub struct Permissions {
     pub s2xn: u8,
 }
 
-// Library pseudocode for shared/functions/memory/PhysMemRead
-
-// // Returns the value read from memory, and a status.
-// // Returned value is UNKNOWN if an external abort occurred while reading the
-// // memory.
-// // Otherwise the PhysMemRetStatus statuscode is Fault_None.
-// (PhysMemRetStatus, bits(8*size)) PhysMemRead(AddressDescriptor desc, integer size,
-//                                              AccessDescriptor accdesc);
+/// Library pseudocode for shared/functions/memory/PhysMemRead
+///
+/// Returns the value read from memory, and a status.
+/// Returned value is UNKNOWN if an external abort occurred while reading the
+/// memory.
+/// Otherwise the PhysMemRetStatus statuscode is Fault_None.
+pub fn PhysMemRead(
+    mem: &dyn PhysicalMemory,
+    desc: AddressDescriptor,
+    size: usize,
+    accdesc: AccessDescriptor,
+) -> (PhysMemRetStatus, Vec<u8>) {
+    let (memstatus, value) = mem.read(desc, size, accdesc);
+    // A backend that returns a value of the wrong size terminated the access
+    if !IsFault(memstatus.statuscode) && value.len() != size {
+        return (PhysMemRetStatus::SyncExternal(accdesc.acctype), value);
+    }
+    (memstatus, value)
+}
 
 /// Library pseudocode for shared/functions/memory/PhysMemRetStatus
 
@@ -307,12 +353,144 @@ pub struct PhysMemRetStatus {
     pub acctype: AccessType,
 }
 
-// Library pseudocode for shared/functions/memory/PhysMemWrite
+impl PhysMemRetStatus {
+    /// Status of a physical memory access that completed without error.
+    pub const fn NoFault(acctype: AccessType) -> Self {
+        Self {
+            statuscode: Fault::Fault_None,
+            extflag: 0,
+            errortype: 0,
+            store64bstatus: 0,
+            acctype,
+        }
+    }
+
+    /// Status of a physical memory access that was terminated by a synchronous External abort.
+    pub const fn SyncExternal(acctype: AccessType) -> Self {
+        Self {
+            statuscode: Fault::Fault_SyncExternal,
+            ..Self::NoFault(acctype)
+        }
+    }
+}
+
+/// Library pseudocode for shared/functions/memory/PhysMemWrite
+///
+/// Writes the value to memory, and returns the status of the write. If there is an external abort
+/// on the write, the PhysMemRetStatus indicates this. Otherwise the statuscode of PhysMemRetStatus
+/// is Fault_None.
+pub fn PhysMemWrite(
+    mem: &mut dyn PhysicalMemory,
+    desc: AddressDescriptor,
+    size: usize,
+    accdesc: AccessDescriptor,
+    value: &[u8],
+) -> PhysMemRetStatus {
+    if value.len() != size {
+        return PhysMemRetStatus::SyncExternal(accdesc.acctype);
+    }
+    mem.write(desc, size, accdesc, value)
+}
+
+/// # PhysicalMemory
+///
+/// Backing store for the physical memory array.
+///
+/// Every access the translation machinery performs (descriptor fetches, hardware updates of
+/// descriptors and data accesses) goes through this trait. Values are in little-endian byte
+/// order; callers reverse them when the access is big-endian.
+pub trait PhysicalMemory {
+    /// Read `size` bytes starting at `desc.paddress`.
+    fn read(
+        &self,
+        desc: AddressDescriptor,
+        size: usize,
+        accdesc: AccessDescriptor,
+    ) -> (PhysMemRetStatus, Vec<u8>);
+
+    /// Write the first `size` bytes of `value` starting at `desc.paddress`.
+    ///
+    /// A `value` shorter than `size` bytes is terminated with a synchronous External abort.
+    fn write(
+        &mut self,
+        desc: AddressDescriptor,
+        size: usize,
+        accdesc: AccessDescriptor,
+        value: &[u8],
+    ) -> PhysMemRetStatus;
+
+    /// Atomically replace the `size` bytes starting at `desc.paddress` with `value` if they are
+    /// equal to `expected`.
+    ///
+    /// Returns the status of the read, the bytes that were in memory, and the status of the
+    /// write if one was performed. The default implementation is a read followed by a write;
+    /// backends shared with other observers should override it with a real compare-and-swap.
+    fn compare_and_swap(
+        &mut self,
+        desc: AddressDescriptor,
+        size: usize,
+        accdesc: AccessDescriptor,
+        expected: &[u8],
+        value: &[u8],
+    ) -> (PhysMemRetStatus, Vec<u8>, Option<PhysMemRetStatus>) {
+        let (memstatus, bytes) = self.read(desc, size, accdesc);
+        if IsFault(memstatus.statuscode) || bytes != expected {
+            return (memstatus, bytes, None);
+        }
+        let writestatus = self.write(desc, size, accdesc, value);
+        (memstatus, bytes, Some(writestatus))
+    }
+}
+
+/// Library pseudocode for shared/functions/memory/IsFault
+/// IsFault()
+/// =========
+/// Return TRUE if a fault is associated with the given status code.
+pub fn IsFault(statuscode: Fault) -> bool {
+    statuscode != Fault::Fault_None
+}
+
+/// Library pseudocode for shared/functions/memory/IsExternalSyncAbort
+/// IsExternalSyncAbort()
+/// =====================
+/// Return TRUE if the abort currently being processed is an external
+/// synchronous abort and FALSE otherwise.
+pub fn IsExternalSyncAbort(statuscode: Fault) -> bool {
+    matches!(
+        statuscode,
+        Fault::Fault_SyncExternal
+            | Fault::Fault_SyncParity
+            | Fault::Fault_SyncExternalOnWalk
+            | Fault::Fault_SyncParityOnWalk
+    )
+}
 
-// Writes the value to memory, and returns the status of the write. If there is an external abort
-// on the write, the PhysMemRetStatus indicates this. Otherwise the statuscode of PhysMemRetStatus
-// is Fault_None. PhysMemRetStatus PhysMemWrite(AddressDescriptor desc, integer size,
-// AccessDescriptor accdesc, bits(8*size) value);
+/// Library pseudocode for shared/functions/memory/HandleExternalTTWAbort
+/// HandleExternalTTWAbort()
+/// ========================
+/// Take Asynchronous abort or update FaultRecord for Translation Table Walk
+/// based on PhysMemRetStatus.
+pub fn HandleExternalTTWAbort(
+    memretstatus: PhysMemRetStatus,
+    iswrite: bool,
+    _memaddrdesc: AddressDescriptor,
+    _accdesc: AccessDescriptor,
+    _size: usize,
+    input_fault: FaultRecord,
+) -> FaultRecord {
+    let mut output_fault = input_fault;
+    output_fault.extflag = memretstatus.extflag != 0;
+    output_fault.statuscode = memretstatus.statuscode;
+    output_fault.write = iswrite;
+    output_fault.errortype = memretstatus.errortype;
+    // A synchronous abort on a translation table walk is reported as such
+    output_fault.statuscode = match output_fault.statuscode {
+        Fault::Fault_SyncExternal => Fault::Fault_SyncExternalOnWalk,
+        Fault::Fault_SyncParity => Fault::Fault_SyncParityOnWalk,
+        other => other,
+    };
+    output_fault
+}
 
 /// Library pseudocode for shared/functions/memory/PrefetchHint
 
@@ -348,11 +526,9 @@ pub fn EffectiveShareability(memattrs: MemoryAttributes) -> Shareability {
 }
 
 /// Library pseudocode for shared/functions/memory/VARange
-
 /// VARange
 /// =======
 /// Virtual address ranges
-
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub enum VARange {
     #[default]
@@ -372,7 +548,6 @@ pub enum DeviceType {
 }
 
 /// Library pseudocode for shared/functions/memory/MemAtomicOp
-
 /// MemAtomicOp
 /// ===========
 /// Atomic data processing instruction types.
@@ -438,11 +613,9 @@ pub enum CachePASpace {
 }
 
 /// Library pseudocode for shared/functions/memory/MemOp
-
 /// MemOp
 /// =====
 /// Memory access instruction types.
-
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MemOp {
     MemOp_LOAD,
@@ -451,10 +624,8 @@ pub enum MemOp {
 }
 
 /// Library pseudocode for shared/functions/memory/Memory
-
 /// Memory Tag type
 /// ===============
-
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MemTagType {
     MemTag_Untagged,
@@ -516,11 +687,15 @@ pub enum MemHint {
 /// NewAccDesc()
 /// ============
 /// Create a new AccessDescriptor with initialised fields
-pub fn NewAccDesc(acctype: AccessType) -> AccessDescriptor {
+pub fn NewAccDesc(
+    pe: &ProcessorElement,
+    acctype: AccessType,
+) -> Result<AccessDescriptor, ConfigError> {
+    let el = pe.current_el()?;
     let accdesc: AccessDescriptor = AccessDescriptor {
         acctype,
-        el: PSTATE.get_EL(),
-        ss: SecurityStateAtEL(PSTATE.get_EL()),
+        el,
+        ss: SecurityStateAtEL(pe, el)?,
         acqsc: false,
         acqpc: false,
         relsc: false,
@@ -545,7 +720,7 @@ pub fn NewAccDesc(acctype: AccessType) -> AccessDescriptor {
         tagaccess: false,
         devstoreunpred: false,
         transactional: false,
-        mpam: GenMPAMCurEL(acctype),
+        mpam: GenMPAMCurEL(pe, acctype)?,
         ispair: false,
         highestaddressfirst: false,
         cacheop: CacheOp::default(),
@@ -555,7 +730,7 @@ pub fn NewAccDesc(acctype: AccessType) -> AccessDescriptor {
         toplevel: false,
         varange: VARange::default(),
     };
-    accdesc
+    Ok(accdesc)
 }
 
 /// Library pseudocode for shared/functions/memory/PASpace
@@ -574,8 +749,11 @@ pub enum PASpace {
 /// CreateAccDescTTEUpdate()
 /// ========================
 /// Access descriptor for translation table entry HW update
-pub fn CreateAccDescTTEUpdate(accdesc_in: AccessDescriptor) -> AccessDescriptor {
-    let mut accdesc: AccessDescriptor = NewAccDesc(AccessType::AccessType_TTW);
+pub fn CreateAccDescTTEUpdate(
+    pe: &ProcessorElement,
+    accdesc_in: AccessDescriptor,
+) -> Result<AccessDescriptor, ConfigError> {
+    let mut accdesc: AccessDescriptor = NewAccDesc(pe, AccessType::AccessType_TTW)?;
     accdesc.el = accdesc_in.el;
     accdesc.ss = accdesc_in.ss;
     accdesc.atomicop = true;
@@ -583,7 +761,7 @@ pub fn CreateAccDescTTEUpdate(accdesc_in: AccessDescriptor) -> AccessDescriptor
     accdesc.read = true;
     accdesc.write = true;
     accdesc.mpam = accdesc_in.mpam;
-    accdesc
+    Ok(accdesc)
 }
 
 /// Library pseudocode for shared/translation/attrs/NormalNCMemAttr
@@ -607,3 +785,821 @@ pub fn NormalNCMemAttr() -> MemoryAttributes {
         xs: false,
     }
 }
+
+/// Library pseudocode for shared/functions/memory/CreateAccDescS1TTW
+/// CreateAccDescS1TTW()
+/// ====================
+/// Access descriptor for stage 1 translation table walks
+pub fn CreateAccDescS1TTW(
+    pe: &ProcessorElement,
+    toplevel: bool,
+    varange: VARange,
+    accdesc_in: AccessDescriptor,
+) -> Result<AccessDescriptor, ConfigError> {
+    let mut accdesc: AccessDescriptor = NewAccDesc(pe, AccessType::AccessType_TTW)?;
+    accdesc.el = accdesc_in.el;
+    accdesc.ss = accdesc_in.ss;
+    accdesc.read = true;
+    accdesc.toplevel = toplevel;
+    accdesc.varange = varange;
+    accdesc.mpam = accdesc_in.mpam;
+    Ok(accdesc)
+}
+
+/// Library pseudocode for shared/functions/memory/CreateAccDescS2TTW
+/// CreateAccDescS2TTW()
+/// ====================
+/// Access descriptor for stage 2 translation table walks
+pub fn CreateAccDescS2TTW(
+    pe: &ProcessorElement,
+    accdesc_in: AccessDescriptor,
+) -> Result<AccessDescriptor, ConfigError> {
+    let mut accdesc: AccessDescriptor = NewAccDesc(pe, AccessType::AccessType_TTW)?;
+    accdesc.el = accdesc_in.el;
+    accdesc.ss = accdesc_in.ss;
+    accdesc.read = true;
+    accdesc.mpam = accdesc_in.mpam;
+    Ok(accdesc)
+}
+
+/// Library pseudocode for shared/functions/memory/CreateAccDescGPTW
+/// CreateAccDescGPTW()
+/// ===================
+/// Access descriptor for Granule Protection Table walks
+pub fn CreateAccDescGPTW(
+    pe: &ProcessorElement,
+    accdesc_in: AccessDescriptor,
+) -> Result<AccessDescriptor, ConfigError> {
+    let mut accdesc: AccessDescriptor = NewAccDesc(pe, AccessType::AccessType_GPTW)?;
+    accdesc.el = accdesc_in.el;
+    accdesc.ss = accdesc_in.ss;
+    accdesc.read = true;
+    accdesc.mpam = accdesc_in.mpam;
+    Ok(accdesc)
+}
+
+/// Library pseudocode for shared/functions/memory/ATAccess
+/// ATAccess
+/// ========
+/// Permissions checked by an AT instruction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ATAccess {
+    ATAccess_Read,
+    ATAccess_Write,
+    ATAccess_Any,
+    ATAccess_ReadPAN,
+    ATAccess_WritePAN,
+}
+
+/// Library pseudocode for aarch64/functions/memory/AArch64.UnalignedAccessFaults
+/// AArch64.UnalignedAccessFaults()
+/// ===============================
+/// Determine whether the unaligned access generates an Alignment fault
+pub fn AArch64UnalignedAccessFaults(
+    pe: &ProcessorElement,
+    accdesc: AccessDescriptor,
+    address: u64,
+    size: u64,
+) -> Result<bool, ConfigError> {
+    // SCTLR_ELx.A checks the alignment of every data access, and GCS, RCW and
+    // LS64 accesses must always be aligned
+    Ok(
+        if AlignmentEnforced(pe)?
+            || accdesc.acctype == AccessType::AccessType_GCS
+            || accdesc.rcw
+            || accdesc.ls64
+        {
+            true
+        } else if accdesc.exclusive || accdesc.atomicop {
+            // FEAT_LSE2 permits unaligned exclusives and atomics within a 16-byte quantity,
+            // and each register of a pair within its own quantity
+            let alignment = if accdesc.ispair { size / 2 } else { size };
+            !IsFeatureImplemented(pe, Feature::FEAT_LSE2)
+                || !AllInAlignedQuantity(address, alignment, 16)
+        } else if accdesc.acqsc || accdesc.acqpc || accdesc.relsc {
+            let alignment = if accdesc.ispair { size / 2 } else { size };
+            !IsFeatureImplemented(pe, Feature::FEAT_LSE2)
+                || (SCTLR_ELx(pe)?.get(SCTLR_ELx_REG::nAA) == 0
+                    && !AllInAlignedQuantity(address, alignment, 16))
+        } else {
+            false
+        },
+    )
+}
+
+/// Library pseudocode for shared/functions/memory/AlignmentEnforced
+/// AlignmentEnforced()
+/// ===================
+/// For the active translation regime, determine if alignment is required by all accesses
+pub fn AlignmentEnforced(pe: &ProcessorElement) -> Result<bool, ConfigError> {
+    Ok(SCTLR_ELx(pe)?.get(SCTLR_ELx_REG::A) == 1)
+}
+
+/// Library pseudocode for shared/functions/memory/AllInAlignedQuantity
+/// AllInAlignedQuantity()
+/// ======================
+/// Returns TRUE if all accessed bytes are within one aligned quantity, FALSE otherwise.
+pub fn AllInAlignedQuantity(address: u64, size: u64, alignment: u64) -> bool {
+    // An access larger than the quantity can never fit in one
+    if size == 0 || size > alignment {
+        return false;
+    }
+    let log2 = alignment.trailing_zeros() as u64;
+    Align(address.wrapping_add(size - 1), log2) == Align(address, log2)
+}
+
+/// `SCTLR_ELx[]`: the SCTLR_ELx of the translation regime of the current
+/// Exception level
+fn SCTLR_ELx(pe: &ProcessorElement) -> Result<SCTLR_ELx_REG, ConfigError> {
+    Ok(match TranslationRegime(pe, pe.current_el()?)? {
+        Regime::Regime_EL3 => pe.regs.SCTLR_EL3,
+        Regime::Regime_EL2 | Regime::Regime_EL20 => pe.regs.SCTLR_EL2,
+        Regime::Regime_EL10 => pe.regs.SCTLR_EL1,
+        // AArch32 EL3 is not modelled
+        Regime::Regime_EL30 => return Err(ConfigError::Unsupported("AArch32 EL3")),
+    })
+}
+
+/// Library pseudocode for shared/functions/memory/CreateAccDescAT
+/// CreateAccDescAT()
+/// =================
+/// Access descriptor for address translation operations
+pub fn CreateAccDescAT(
+    pe: &ProcessorElement,
+    ss: SecurityState,
+    el: PrivilegeLevel,
+    ataccess: ATAccess,
+) -> Result<AccessDescriptor, ConfigError> {
+    let mut accdesc: AccessDescriptor = NewAccDesc(pe, AccessType::AccessType_AT)?;
+    accdesc.el = el;
+    accdesc.ss = ss;
+    accdesc.read = matches!(
+        ataccess,
+        ATAccess::ATAccess_Read | ATAccess::ATAccess_ReadPAN
+    );
+    accdesc.write = matches!(
+        ataccess,
+        ATAccess::ATAccess_Write | ATAccess::ATAccess_WritePAN
+    );
+    accdesc.pan = matches!(
+        ataccess,
+        ATAccess::ATAccess_ReadPAN | ATAccess::ATAccess_WritePAN
+    );
+    Ok(accdesc)
+}
+
+/// Library pseudocode for shared/functions/memory/CreateAccDescAtomicOp
+/// CreateAccDescAtomicOp()
+/// =======================
+/// Access descriptor for atomic read-modify-write memory accesses
+pub fn CreateAccDescAtomicOp(
+    pe: &ProcessorElement,
+    modop: MemAtomicOp,
+    acquire: bool,
+    release: bool,
+    tagchecked: bool,
+) -> Result<AccessDescriptor, ConfigError> {
+    let mut accdesc: AccessDescriptor = NewAccDesc(pe, AccessType::AccessType_GPR)?;
+    accdesc.acqsc = acquire;
+    accdesc.relsc = release;
+    accdesc.atomicop = true;
+    accdesc.modop = modop;
+    accdesc.read = true;
+    accdesc.write = true;
+    accdesc.pan = true;
+    accdesc.tagchecked = tagchecked;
+    // FEAT_TME transactions are not modelled
+    accdesc.transactional = false;
+    Ok(accdesc)
+}
+
+/// Library pseudocode for shared/functions/memory/CreateAccDescExLDST
+/// CreateAccDescExLDST()
+/// =====================
+/// Access descriptor for general purpose register loads/stores with exclusive semantics
+pub fn CreateAccDescExLDST(
+    pe: &ProcessorElement,
+    memop: MemOp,
+    acqrel: bool,
+    tagchecked: bool,
+) -> Result<AccessDescriptor, ConfigError> {
+    let mut accdesc: AccessDescriptor = NewAccDesc(pe, AccessType::AccessType_GPR)?;
+    accdesc.acqsc = acqrel && memop == MemOp::MemOp_LOAD;
+    accdesc.relsc = acqrel && memop == MemOp::MemOp_STORE;
+    accdesc.exclusive = true;
+    accdesc.read = memop == MemOp::MemOp_LOAD;
+    accdesc.write = memop == MemOp::MemOp_STORE;
+    accdesc.pan = true;
+    accdesc.tagchecked = tagchecked;
+    // FEAT_TME transactions are not modelled
+    accdesc.transactional = false;
+    Ok(accdesc)
+}
+
+/// Library pseudocode for shared/functions/memory/CreateAccDescGPR
+/// CreateAccDescGPR()
+/// ==================
+/// Access descriptor for general purpose register loads/stores
+/// without exclusive or ordering semantics
+pub fn CreateAccDescGPR(
+    pe: &ProcessorElement,
+    memop: MemOp,
+    nontemporal: bool,
+    privileged: bool,
+    tagchecked: bool,
+) -> Result<AccessDescriptor, ConfigError> {
+    let mut accdesc: AccessDescriptor = NewAccDesc(pe, AccessType::AccessType_GPR)?;
+    if !privileged {
+        accdesc.el = EL0;
+    }
+    accdesc.nontemporal = nontemporal;
+    accdesc.read = memop == MemOp::MemOp_LOAD;
+    accdesc.write = memop == MemOp::MemOp_STORE;
+    accdesc.pan = true;
+    accdesc.tagchecked = tagchecked;
+    // FEAT_TME transactions are not modelled
+    accdesc.transactional = false;
+    Ok(accdesc)
+}
+
+/// Library pseudocode for shared/functions/memory/CreateAccDescIFetch
+/// CreateAccDescIFetch()
+/// =====================
+/// Access descriptor for instruction fetches
+pub fn CreateAccDescIFetch(pe: &ProcessorElement) -> Result<AccessDescriptor, ConfigError> {
+    NewAccDesc(pe, AccessType::AccessType_IFETCH)
+}
+
+/// Library pseudocode for shared/translation/attrs/DecodeSDFAttr
+/// DecodeSDFAttr()
+/// ===============
+/// Decode memory attributes using SDF (Short Descriptor Format) mapping
+pub fn DecodeSDFAttr(rgn: u64) -> MemAttrHints {
+    let (attrs, hints) = match rgn & 0b11 {
+        // Non-cacheable (no allocate)
+        0b00 => (MemAttr::MemAttr_NC, MemHint::MemHint_No),
+        // Write-back, Read and Write allocate
+        0b01 => (MemAttr::MemAttr_WB, MemHint::MemHint_RWA),
+        // Write-through, Read allocate
+        0b10 => (MemAttr::MemAttr_WT, MemHint::MemHint_RA),
+        // Write-back, Read allocate
+        _ => (MemAttr::MemAttr_WB, MemHint::MemHint_RA),
+    };
+    MemAttrHints {
+        attrs,
+        hints,
+        transient: false,
+    }
+}
+
+/// Library pseudocode for shared/translation/attrs/DecodeShareability
+/// DecodeShareability()
+/// ====================
+/// Decode shareability of target memory region
+pub fn DecodeShareability(sh: u64) -> Shareability {
+    match sh & 0b11 {
+        0b10 => Shareability::Shareability_OSH,
+        0b11 => Shareability::Shareability_ISH,
+        0b00 => Shareability::Shareability_NSH,
+        // CONSTRAINED UNPREDICTABLE: the reserved encoding behaves as Outer Shareable
+        _ => Shareability::Shareability_OSH,
+    }
+}
+
+/// Library pseudocode for shared/translation/attrs/WalkMemAttrs
+/// WalkMemAttrs()
+/// ==============
+/// Retrieve memory attributes of translation table walk
+pub fn WalkMemAttrs(sh: u64, irgn: u64, orgn: u64) -> MemoryAttributes {
+    let inner = DecodeSDFAttr(irgn);
+    let outer = DecodeSDFAttr(orgn);
+    MemoryAttributes {
+        memtype: MemType::MemType_Normal,
+        device: DeviceType::default(),
+        inner,
+        outer,
+        shareability: DecodeShareability(sh),
+        tags: MemTagType::MemTag_Untagged,
+        notagaccess: false,
+        xs: !(inner.attrs == MemAttr::MemAttr_WB && outer.attrs == MemAttr::MemAttr_WB),
+    }
+}
+
+/// Library pseudocode for shared/translation/attrs/DecodeDevice
+/// DecodeDevice()
+/// ==============
+/// Decode output Device type
+pub fn DecodeDevice(device: u64) -> DeviceType {
+    match device & 0b11 {
+        0b00 => DeviceType::DeviceType_nGnRnE,
+        0b01 => DeviceType::DeviceType_nGnRE,
+        0b10 => DeviceType::DeviceType_nGRE,
+        _ => DeviceType::DeviceType_GRE,
+    }
+}
+
+/// Library pseudocode for shared/translation/attrs/DecodeLDFAttr
+/// DecodeLDFAttr()
+/// ===============
+/// Decode memory attributes using LDF (Long Descriptor Format) mapping
+pub fn DecodeLDFAttr(attr: u64) -> MemAttrHints {
+    let attrs = if attr & 0b1111 == 0b0100 {
+        // Non-cacheable
+        MemAttr::MemAttr_NC
+    } else if attr & 0b0100 == 0 {
+        // Write-through
+        MemAttr::MemAttr_WT
+    } else {
+        // Write-back
+        MemAttr::MemAttr_WB
+    };
+
+    // Allocation hints are applicable only to cacheable memory.
+    let hints = if attrs != MemAttr::MemAttr_NC {
+        match attr & 0b11 {
+            // No allocation hints
+            0b00 => MemHint::MemHint_No,
+            // Write-allocate
+            0b01 => MemHint::MemHint_WA,
+            // Read-allocate
+            0b10 => MemHint::MemHint_RA,
+            // Read/Write allocate
+            _ => MemHint::MemHint_RWA,
+        }
+    } else {
+        MemHint::default()
+    };
+
+    // The Transient hint applies only to cacheable memory with some allocation hints.
+    let transient =
+        attrs != MemAttr::MemAttr_NC && hints != MemHint::MemHint_No && attr & 0b1000 == 0;
+
+    MemAttrHints {
+        attrs,
+        hints,
+        transient,
+    }
+}
+
+/// Library pseudocode for shared/translation/attrs/S1ConstrainUnpredictableRESMAIR
+/// S1ConstrainUnpredictableRESMAIR()
+/// =================================
+/// Determine whether a reserved value occupies MAIR_ELx.AttrN
+pub fn S1ConstrainUnpredictableRESMAIR(pe: &ProcessorElement, attr: u8, s1aarch64: bool) -> bool {
+    let xs = s1aarch64 && IsFeatureImplemented(pe, Feature::FEAT_XS);
+    match attr {
+        // '0000xx01'
+        _ if attr & 0b1111_0011 == 0b0000_0001 => !xs,
+        // '0000xxxx'
+        _ if attr & 0b1111_0000 == 0 => attr & 0b11 != 0b00,
+        0b0100_0000 | 0b1010_0000 => !xs,
+        0b1111_0000 => !(s1aarch64 && IsFeatureImplemented(pe, Feature::FEAT_MTE2)),
+        // 'xxxx0000'
+        _ => attr & 0b1111 == 0,
+    }
+}
+
+/// Library pseudocode for shared/translation/attrs/S1DecodeMemAttrs
+/// S1DecodeMemAttrs()
+/// ==================
+/// Converts the Stage 1 attribute fields, using the MAIR, to orthogonal
+/// attributes and hints
+pub fn S1DecodeMemAttrs(
+    pe: &ProcessorElement,
+    attr_in: u8,
+    sh: u64,
+    s1aarch64: bool,
+    walkparams: S1TTWParams,
+) -> MemoryAttributes {
+    let mut attr = attr_in;
+    if S1ConstrainUnpredictableRESMAIR(pe, attr, s1aarch64) {
+        // ConstrainUnpredictableBits(Unpredictable_RESMAIR): this model takes
+        // Constraint_UNKNOWN and uses the closest defined encoding.
+        attr = match attr {
+            // Device memory with the XS attribute
+            _ if attr & 0b1111_0000 == 0 => attr & 0b1111_1100,
+            // Normal Inner and Outer Non-cacheable
+            0b0100_0000 => 0b0100_0100,
+            // Normal Inner and Outer Write-Through Read-Allocate Non-transient
+            0b1010_0000 => 0b1010_1010,
+            // Normal Inner and Outer Write-Back Read/Write-Allocate Non-transient
+            0b1111_0000 => 0b1111_1111,
+            // Normal Inner Non-cacheable
+            _ => attr | 0b0100,
+        };
+    }
+
+    let inner_outer = |attrs: MemAttr, hints: MemHint| MemAttrHints {
+        attrs,
+        hints,
+        transient: false,
+    };
+    let mut memattrs = match attr {
+        // Device memory
+        _ if attr & 0b1111_0000 == 0 => MemoryAttributes {
+            memtype: MemType::MemType_Device,
+            device: DecodeDevice(u64::from(attr >> 2)),
+            xs: !s1aarch64 || attr & 0b1 == 0,
+            ..NormalNCMemAttr()
+        },
+        0b0100_0000 => MemoryAttributes {
+            memtype: MemType::MemType_Normal,
+            outer: inner_outer(MemAttr::MemAttr_NC, MemHint::MemHint_No),
+            inner: inner_outer(MemAttr::MemAttr_NC, MemHint::MemHint_No),
+            xs: false,
+            ..NormalNCMemAttr()
+        },
+        0b1010_0000 => MemoryAttributes {
+            memtype: MemType::MemType_Normal,
+            outer: inner_outer(MemAttr::MemAttr_WT, MemHint::MemHint_RA),
+            inner: inner_outer(MemAttr::MemAttr_WT, MemHint::MemHint_RA),
+            xs: false,
+            ..NormalNCMemAttr()
+        },
+        // Tagged memory
+        0b1111_0000 => MemoryAttributes {
+            memtype: MemType::MemType_Normal,
+            outer: inner_outer(MemAttr::MemAttr_WB, MemHint::MemHint_RWA),
+            inner: inner_outer(MemAttr::MemAttr_WB, MemHint::MemHint_RWA),
+            xs: false,
+            ..NormalNCMemAttr()
+        },
+        _ => {
+            let outer = DecodeLDFAttr(u64::from(attr >> 4));
+            let inner = DecodeLDFAttr(u64::from(attr & 0b1111));
+            MemoryAttributes {
+                memtype: MemType::MemType_Normal,
+                outer,
+                inner,
+                xs: !(inner.attrs == MemAttr::MemAttr_WB && outer.attrs == MemAttr::MemAttr_WB),
+                ..NormalNCMemAttr()
+            }
+        }
+    };
+
+    memattrs.tags = if s1aarch64 && attr == 0b1111_0000 {
+        MemTagType::MemTag_AllocationTagged
+    } else if s1aarch64 && walkparams.get_mtx() == 1 {
+        MemTagType::MemTag_CanonicallyTagged
+    } else {
+        MemTagType::MemTag_Untagged
+    };
+    memattrs.notagaccess = false;
+    memattrs.shareability = DecodeShareability(sh);
+
+    memattrs
+}
+
+/// Encode memory attributes using LDF (Long Descriptor Format) mapping, the
+/// inverse of [`DecodeLDFAttr`]
+pub fn EncodeLDFAttr(attrhints: MemAttrHints) -> u8 {
+    let hints = attrhints.hints as u8;
+    match attrhints.attrs {
+        MemAttr::MemAttr_NC => 0b0100,
+        // '0000' is reserved, a Transient Write-Through region always allocates
+        MemAttr::MemAttr_WT if attrhints.transient && hints != 0 => hints,
+        MemAttr::MemAttr_WT => 0b1000 | hints,
+        // '0100' is Non-cacheable, a Transient Write-Back region always allocates
+        MemAttr::MemAttr_WB if attrhints.transient && hints != 0 => 0b0100 | hints,
+        MemAttr::MemAttr_WB => 0b1100 | hints,
+    }
+}
+
+/// Encode memory attributes as a MAIR_ELx.AttrN value, the inverse of
+/// [`S1DecodeMemAttrs`]
+///
+/// Shareability is not part of the MAIR encoding, and neither is
+/// `MemTag_CanonicallyTagged` which comes from TCR_ELx.MTX. The XS attribute
+/// is only encoded for Device memory, Normal Non-cacheable memory and Normal
+/// Write-Through Read-Allocate memory, as with FEAT_XS.
+pub fn S1EncodeMemAttrs(memattrs: MemoryAttributes) -> u8 {
+    let non_transient = |attrs: MemAttr, hints: MemHint| MemAttrHints {
+        attrs,
+        hints,
+        transient: false,
+    };
+    if memattrs.memtype == MemType::MemType_Device {
+        let device = match memattrs.device {
+            DeviceType::DeviceType_nGnRnE => 0b00,
+            DeviceType::DeviceType_nGnRE => 0b01,
+            DeviceType::DeviceType_nGRE => 0b10,
+            DeviceType::DeviceType_GRE => 0b11,
+        };
+        (device << 2) | u8::from(!memattrs.xs)
+    } else if memattrs.tags == MemTagType::MemTag_AllocationTagged {
+        0b1111_0000
+    } else if !memattrs.xs
+        && memattrs.inner.attrs == MemAttr::MemAttr_NC
+        && memattrs.outer.attrs == MemAttr::MemAttr_NC
+    {
+        0b0100_0000
+    } else if !memattrs.xs
+        && memattrs.inner == non_transient(MemAttr::MemAttr_WT, MemHint::MemHint_RA)
+        && memattrs.outer == non_transient(MemAttr::MemAttr_WT, MemHint::MemHint_RA)
+    {
+        0b1010_0000
+    } else {
+        (EncodeLDFAttr(memattrs.outer) << 4) | EncodeLDFAttr(memattrs.inner)
+    }
+}
+
+/// Library pseudocode for shared/translation/attrs/S2DecodeCacheability
+/// S2DecodeCacheability()
+/// ======================
+/// Determine the stage 2 cacheability for Normal memory
+pub fn S2DecodeCacheability(attr: u64) -> MemAttrHints {
+    let attrs = match attr & 0b11 {
+        // Non-cacheable
+        0b01 => MemAttr::MemAttr_NC,
+        // Write-through
+        0b10 => MemAttr::MemAttr_WT,
+        // Write-back
+        0b11 => MemAttr::MemAttr_WB,
+        // ConstrainUnpredictable(Unpredictable_S2RESMEMATTR): this model takes
+        // Constraint_NC
+        _ => MemAttr::MemAttr_NC,
+    };
+
+    // Stage 2 does not assign hints or the transient property
+    // They are inherited from stage 1 if the result of the combination allows it
+    MemAttrHints {
+        attrs,
+        hints: MemHint::default(),
+        transient: false,
+    }
+}
+
+/// Library pseudocode for shared/translation/attrs/S2DecodeMemAttrs
+/// S2DecodeMemAttrs()
+/// ==================
+/// Converts the attribute fields for Normal memory as used in stage 2
+/// descriptors to orthogonal attributes and hints
+pub fn S2DecodeMemAttrs(
+    pe: &ProcessorElement,
+    attr: u64,
+    sh: u64,
+    s2aarch64: bool,
+) -> MemoryAttributes {
+    let mte_perm = s2aarch64 && IsFeatureImplemented(pe, Feature::FEAT_MTE_PERM);
+    let mut memattrs = NormalNCMemAttr();
+    if attr & 0b1100 == 0 {
+        // Device memory
+        memattrs.memtype = MemType::MemType_Device;
+        memattrs.device = DecodeDevice(attr);
+    } else if attr & 0b1111 == 0b0100 && mte_perm {
+        // Normal, Inner+Outer Write-Back cacheable, NoTagAccess
+        memattrs.memtype = MemType::MemType_Normal;
+        memattrs.outer = S2DecodeCacheability(0b11);
+        memattrs.inner = S2DecodeCacheability(0b11);
+    } else {
+        // Normal memory
+        memattrs.memtype = MemType::MemType_Normal;
+        memattrs.outer = S2DecodeCacheability(attr >> 2);
+        memattrs.inner = S2DecodeCacheability(attr);
+    }
+
+    memattrs.shareability = DecodeShareability(sh);
+    memattrs.notagaccess = mte_perm && attr & 0b1111 == 0b0100;
+    memattrs
+}
+
+/// Library pseudocode for shared/translation/attrs/S2CombineS1AttrHints
+/// S2CombineS1AttrHints()
+/// ======================
+/// Determine resultant Normal memory cacheability and allocation hints from
+/// combining stage 1 Normal memory attributes and stage 2 cacheability attributes.
+pub fn S2CombineS1AttrHints(
+    s1_attrhints: MemAttrHints,
+    s2_attrhints: MemAttrHints,
+) -> MemAttrHints {
+    let attrs = if s1_attrhints.attrs == MemAttr::MemAttr_NC
+        || s2_attrhints.attrs == MemAttr::MemAttr_NC
+    {
+        MemAttr::MemAttr_NC
+    } else if s1_attrhints.attrs == MemAttr::MemAttr_WT || s2_attrhints.attrs == MemAttr::MemAttr_WT
+    {
+        MemAttr::MemAttr_WT
+    } else {
+        MemAttr::MemAttr_WB
+    };
+
+    // Stage 2 does not assign any allocation hints
+    // Instead, they are inherited from stage 1
+    if attrs != MemAttr::MemAttr_NC {
+        MemAttrHints {
+            attrs,
+            hints: s1_attrhints.hints,
+            transient: s1_attrhints.transient,
+        }
+    } else {
+        MemAttrHints {
+            attrs,
+            hints: MemHint::default(),
+            transient: false,
+        }
+    }
+}
+
+/// Library pseudocode for shared/translation/attrs/S2CombineS1Device
+/// S2CombineS1Device()
+/// ===================
+/// Determine resultant Device type from combining output memory attributes
+/// in stage 1 and Device attributes in stage 2
+pub fn S2CombineS1Device(s1_device: DeviceType, s2_device: DeviceType) -> DeviceType {
+    if s2_device == DeviceType::DeviceType_nGnRnE || s1_device == DeviceType::DeviceType_nGnRnE {
+        DeviceType::DeviceType_nGnRnE
+    } else if s2_device == DeviceType::DeviceType_nGnRE || s1_device == DeviceType::DeviceType_nGnRE
+    {
+        DeviceType::DeviceType_nGnRE
+    } else if s2_device == DeviceType::DeviceType_nGRE || s1_device == DeviceType::DeviceType_nGRE {
+        DeviceType::DeviceType_nGRE
+    } else {
+        DeviceType::DeviceType_GRE
+    }
+}
+
+/// Library pseudocode for shared/translation/attrs/S2CombineS1MemAttrs
+/// S2CombineS1MemAttrs()
+/// =====================
+/// Combine stage 2 with stage 1 memory attributes
+pub fn S2CombineS1MemAttrs(
+    pe: &ProcessorElement,
+    s1_memattrs: MemoryAttributes,
+    s2_memattrs: MemoryAttributes,
+    s2aarch64: bool,
+) -> MemoryAttributes {
+    let mut memattrs = match (s1_memattrs.memtype, s2_memattrs.memtype) {
+        (MemType::MemType_Device, MemType::MemType_Device) => MemoryAttributes {
+            device: S2CombineS1Device(s1_memattrs.device, s2_memattrs.device),
+            ..s1_memattrs
+        },
+        // S2 Normal, S1 Device
+        (MemType::MemType_Device, _) => s1_memattrs,
+        // S2 Device, S1 Normal
+        (_, MemType::MemType_Device) => s2_memattrs,
+        // S2 Normal, S1 Normal
+        _ => MemoryAttributes {
+            memtype: MemType::MemType_Normal,
+            inner: S2CombineS1AttrHints(s1_memattrs.inner, s2_memattrs.inner),
+            outer: S2CombineS1AttrHints(s1_memattrs.outer, s2_memattrs.outer),
+            ..s1_memattrs
+        },
+    };
+
+    memattrs.tags = S2MemTagType(pe, memattrs, s1_memattrs.tags);
+
+    memattrs.notagaccess = if !IsFeatureImplemented(pe, Feature::FEAT_MTE_PERM) {
+        false
+    } else {
+        s2_memattrs.notagaccess && s1_memattrs.tags == MemTagType::MemTag_AllocationTagged
+    };
+
+    memattrs.shareability =
+        S2CombineS1Shareability(s1_memattrs.shareability, s2_memattrs.shareability);
+
+    memattrs.xs = if memattrs.memtype == MemType::MemType_Normal
+        && memattrs.inner.attrs == MemAttr::MemAttr_WB
+        && memattrs.outer.attrs == MemAttr::MemAttr_WB
+    {
+        false
+    } else if s2aarch64 {
+        s2_memattrs.xs && s1_memattrs.xs
+    } else {
+        s1_memattrs.xs
+    };
+
+    memattrs.shareability = EffectiveShareability(memattrs);
+    memattrs
+}
+
+/// Library pseudocode for shared/translation/attrs/S2CombineS1Shareability
+/// S2CombineS1Shareability()
+/// =========================
+/// Combine stage 2 shareability with stage 1
+pub fn S2CombineS1Shareability(
+    s1_shareability: Shareability,
+    s2_shareability: Shareability,
+) -> Shareability {
+    if s1_shareability == Shareability::Shareability_OSH
+        || s2_shareability == Shareability::Shareability_OSH
+    {
+        Shareability::Shareability_OSH
+    } else if s1_shareability == Shareability::Shareability_ISH
+        || s2_shareability == Shareability::Shareability_ISH
+    {
+        Shareability::Shareability_ISH
+    } else {
+        Shareability::Shareability_NSH
+    }
+}
+
+/// Library pseudocode for shared/translation/attrs/S2MemTagType
+/// S2MemTagType()
+/// ==============
+/// Determine whether the combined output memory attributes of stage 1 and
+/// stage 2 indicate tagged memory
+pub fn S2MemTagType(
+    pe: &ProcessorElement,
+    s2_memattrs: MemoryAttributes,
+    s1_tagtype: MemTagType,
+) -> MemTagType {
+    if !IsFeatureImplemented(pe, Feature::FEAT_MTE2) {
+        return MemTagType::MemTag_Untagged;
+    }
+
+    if s1_tagtype == MemTagType::MemTag_AllocationTagged
+        && s2_memattrs.memtype == MemType::MemType_Normal
+        && s2_memattrs.inner.attrs == MemAttr::MemAttr_WB
+        && s2_memattrs.inner.hints == MemHint::MemHint_RWA
+        && !s2_memattrs.inner.transient
+        && s2_memattrs.outer.attrs == MemAttr::MemAttr_WB
+        && s2_memattrs.outer.hints == MemHint::MemHint_RWA
+        && !s2_memattrs.outer.transient
+    {
+        return MemTagType::MemTag_AllocationTagged;
+    }
+
+    // Return what stage 1 asked for if we can, otherwise Untagged.
+    if s1_tagtype != MemTagType::MemTag_AllocationTagged {
+        return s1_tagtype;
+    }
+
+    MemTagType::MemTag_Untagged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translation64_attrs::AArch64MAIRAttr;
+
+    fn decode(pe: &ProcessorElement, attr: u8) -> MemoryAttributes {
+        let walkparams: S1TTWParams = unsafe { std::mem::zeroed() };
+        S1DecodeMemAttrs(pe, attr, 0b11, true, walkparams)
+    }
+
+    #[test]
+    fn mair_decode() {
+        let pe = ProcessorElement::from_profile(ArchVersion::Armv8_0, &[]).unwrap();
+
+        // AttrIndx 8-15 select MAIR2_ELx
+        assert_eq!(AArch64MAIRAttr(1, 0x44, 0xff00), 0xff);
+        assert_eq!(AArch64MAIRAttr(8, 0x44, 0xff00), 0x44);
+
+        let memattrs = decode(&pe, 0x04);
+        assert_eq!(memattrs.memtype, MemType::MemType_Device);
+        assert_eq!(memattrs.device, DeviceType::DeviceType_nGnRE);
+        assert!(memattrs.xs);
+
+        let memattrs = decode(&pe, 0xff);
+        assert_eq!(memattrs.memtype, MemType::MemType_Normal);
+        let wb = MemAttrHints {
+            attrs: MemAttr::MemAttr_WB,
+            hints: MemHint::MemHint_RWA,
+            transient: false,
+        };
+        assert_eq!((memattrs.inner, memattrs.outer), (wb, wb));
+        assert_eq!(memattrs.shareability, Shareability::Shareability_ISH);
+        assert!(!memattrs.xs);
+
+        // Outer Write-Back Transient Read-Allocate, Inner Write-Through Write-Allocate
+        let memattrs = decode(&pe, 0x69);
+        assert_eq!(
+            memattrs.outer,
+            MemAttrHints {
+                attrs: MemAttr::MemAttr_WB,
+                hints: MemHint::MemHint_RA,
+                transient: true,
+            }
+        );
+        assert_eq!(
+            memattrs.inner,
+            MemAttrHints {
+                attrs: MemAttr::MemAttr_WT,
+                hints: MemHint::MemHint_WA,
+                transient: false,
+            }
+        );
+        assert!(memattrs.xs);
+
+        // Without FEAT_XS and FEAT_MTE2 these are reserved and take the closest encoding
+        assert_eq!(decode(&pe, 0x01), decode(&pe, 0x00));
+        assert_eq!(decode(&pe, 0x40), decode(&pe, 0x44));
+        assert_eq!(decode(&pe, 0xf0), decode(&pe, 0xff));
+        assert_eq!(decode(&pe, 0xf0).tags, MemTagType::MemTag_Untagged);
+    }
+
+    #[test]
+    fn mair_encode_decode_round_trip() {
+        let pe = ProcessorElement::from_profile(
+            ArchVersion::Armv8_0,
+            &[Feature::FEAT_XS, Feature::FEAT_MTE, Feature::FEAT_MTE2],
+        )
+        .unwrap();
+        for attr in 0..=u8::MAX {
+            if !S1ConstrainUnpredictableRESMAIR(&pe, attr, true) {
+                assert_eq!(S1EncodeMemAttrs(decode(&pe, attr)), attr, "{attr:#04x}");
+            }
+        }
+        assert_eq!(decode(&pe, 0xf0).tags, MemTagType::MemTag_AllocationTagged);
+    }
+}
@@ -1,5 +1,9 @@
 // SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
 //
+#![allow(clippy::enum_variant_names)]
+
+use crate::error::*;
+use crate::pe::*;
 use crate::shared::*;
 use crate::shared_memory::*;
 use crate::shared_translation::*;
@@ -101,19 +105,18 @@ use crate::stubs::*;
 //            Unreachable();
 //    return PIDSpace;
 
-// Library pseudocode for shared/functions/mpam/DefaultMPAMInfo
-
-// // DefaultMPAMInfo()
-// // =================
-// // Returns default MPAM info.  The partidspace argument sets
-// // the PARTID space of the default MPAM information returned.
-
-// MPAMinfo DefaultMPAMInfo(PARTIDSpaceType partidspace)
-//    MPAMinfo defaultinfo;
-//    defaultinfo.mpam_sp = partidspace;
-//    defaultinfo.partid  = DEFAULT_PARTID;
-//    defaultinfo.pmg     = DEFAULT_PMG;
-//    return defaultinfo;
+/// Library pseudocode for shared/functions/mpam/DefaultMPAMInfo
+/// DefaultMPAMInfo()
+/// =================
+/// Returns default MPAM info.  The partidspace argument sets
+/// the PARTID space of the default MPAM information returned.
+pub fn DefaultMPAMInfo(partidspace: PARTIDSpaceType) -> MPAMinfo {
+    MPAMinfo {
+        mpam_sp: partidspace,
+        partid: DEFAULT_PARTID,
+        pmg: DEFAULT_PMG,
+    }
+}
 
 // Library pseudocode for shared/functions/mpam/GenMPAM
 
@@ -152,14 +155,22 @@ use crate::stubs::*;
 /// EL if can and use that to drive MPAM information generation.  If mode
 /// cannot be converted, MPAM is not implemented, or MPAM is disabled return
 /// default MPAM information for the current security state.
-pub fn GenMPAMAtEL(_acctype: AccessType, _el: PrivilegeLevel) -> MPAMinfo {
-    unimplemented!()
+pub fn GenMPAMAtEL(
+    pe: &ProcessorElement,
+    _acctype: AccessType,
+    el: PrivilegeLevel,
+) -> Result<MPAMinfo, ConfigError> {
+    let security: SecurityState = SecurityStateAtEL(pe, el)?;
+    let pspace: PARTIDSpaceType = PARTIDSpaceFromSS(security);
+    // The MPAM System registers are not modelled
+    if !IsFeatureImplemented(pe, Feature::FEAT_MPAM) {
+        return Ok(DefaultMPAMInfo(pspace));
+    }
+    Err(ConfigError::Unsupported("MPAM System registers"))
     // let mut mpamEL: PrivilegeLevel;
     // let mut  validEL = false;
-    // let security: SecurityState = SecurityStateAtEL(el);
     // let in_d = false;
     // let in_sm = false;
-    // let pspace: PARTIDSpaceType = PARTIDSpaceFromSS(security);
     // if pspace == PIDSpace_NonSecure && !MPAMIsEnabled() {
     //     return DefaultMPAMInfo(pspace);
     // }
@@ -219,8 +230,8 @@ pub fn GenMPAMAtEL(_acctype: AccessType, _el: PrivilegeLevel) -> MPAMinfo {
 /// EL if can and use that to drive MPAM information generation.  If mode
 /// cannot be converted, MPAM is not implemented, or MPAM is disabled return
 /// default MPAM information for the current security state.
-pub fn GenMPAMCurEL(acctype: AccessType) -> MPAMinfo {
-    GenMPAMAtEL(acctype, PSTATE.get_EL())
+pub fn GenMPAMCurEL(pe: &ProcessorElement, acctype: AccessType) -> Result<MPAMinfo, ConfigError> {
+    GenMPAMAtEL(pe, acctype, pe.current_el()?)
 }
 
 // Library pseudocode for shared/functions/mpam/GenPARTID
@@ -379,8 +390,8 @@ pub fn GenMPAMCurEL(acctype: AccessType) -> MPAMinfo {
 
 // Library pseudocode for shared/functions/mpam/MPAM
 
-// constant PARTIDType DEFAULT_PARTID = 0<15:0>;
-// constant PMGType    DEFAULT_PMG    = 0<7:0>;
+pub const DEFAULT_PARTID: PARTIDType = PARTIDType(0);
+pub const DEFAULT_PMG: PMGType = PMGType(0);
 
 // // Defines the MPAM _engine_. The _engine_ produces the MPAM labels for memory
 // // accesses from the state information stored in the MPAM System registers.
@@ -438,24 +449,18 @@ pub struct MPAMinfo {
 //            ((el == EL0 && MPAMHCR_EL2.EL0_VPMEN == '1' && !ELIsInHost(EL0)) ||
 //             (el == EL1 && MPAMHCR_EL2.EL1_VPMEN == '1')));
 
-// Library pseudocode for shared/functions/mpam/PARTIDSpaceFromSS
-
-// // PARTIDSpaceFromSS()
-// // ===================
-// // Returns the primary PARTID space from the Security State.
-
-// PARTIDSpaceType PARTIDSpaceFromSS(SecurityState security)
-//    case security of
-//        when SS_NonSecure
-//            return PIDSpace_NonSecure;
-//        when SS_Root
-//            return PIDSpace_Root;
-//        when SS_Realm
-//            return PIDSpace_Realm;
-//        when SS_Secure
-//            return PIDSpace_Secure;
-//        otherwise
-//            Unreachable();
+/// Library pseudocode for shared/functions/mpam/PARTIDSpaceFromSS
+/// PARTIDSpaceFromSS()
+/// ===================
+/// Returns the primary PARTID space from the Security State.
+pub fn PARTIDSpaceFromSS(security: SecurityState) -> PARTIDSpaceType {
+    match security {
+        SecurityState::SS_NonSecure => PARTIDSpaceType::PIDSpace_NonSecure,
+        SecurityState::SS_Root => PARTIDSpaceType::PIDSpace_Root,
+        SecurityState::SS_Realm => PARTIDSpaceType::PIDSpace_Realm,
+        SecurityState::SS_Secure => PARTIDSpaceType::PIDSpace_Secure,
+    }
+}
 
 // Library pseudocode for shared/functions/mpam/UsePrimarySpaceEL10
 
@@ -1,5 +1,5 @@
 // SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
-#![allow(non_camel_case_types)]
+#![allow(non_camel_case_types, clippy::enum_variant_names)]
 
 use crate::shared_memory::{FaultRecord, FullAddress, MemoryAttributes};
 
@@ -1,14 +1,19 @@
 // SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+#![allow(clippy::enum_variant_names)]
+
 use std::mem::MaybeUninit;
 
+use crate::error::*;
+use crate::pe::*;
 use crate::shared::*;
 use crate::shared_memory::*;
 use crate::shared_translation::*;
+use crate::stubs::*;
+use crate::translation64_gpc::*;
 
-/// Library pseudocode for shared/translation/vmsa/AddressDescriptor
-
-pub const FINAL_LEVEL: u64 = 3;
+pub const FINAL_LEVEL: i64 = 3;
 
+/// Library pseudocode for shared/translation/vmsa/AddressDescriptor
 /// AddressDescriptor
 /// =================
 /// Descriptor used to access the underlying memory array.
@@ -37,41 +42,53 @@ impl AddressDescriptor {
 /// ContiguousSize()
 /// ================
 /// Return the number of entries log 2 marking a contiguous output range
-pub fn ContiguousSize(d128: u64, tgx: TGx, level: u64) -> u64 {
+pub fn ContiguousSize(d128: u64, tgx: TGx, level: i64) -> u64 {
     if d128 == 1 {
         match tgx {
             TGx::TGx_4KB => {
                 //assert level IN {1, 2, 3};
-                return if level == 1 { 2 } else { 4 };
+                if level == 1 {
+                    2
+                } else {
+                    4
+                }
             }
             TGx::TGx_16KB => {
                 //assert level IN {1, 2, 3};
                 if level == 1 {
-                    return 2;
+                    2
                 } else if level == 2 {
-                    return 4;
+                    4
                 } else {
-                    return 6;
+                    6
                 }
             }
             TGx::TGx_64KB => {
                 //assert level IN {2, 3};
-                return if level == 2 { 6 } else { 4 };
+                if level == 2 {
+                    6
+                } else {
+                    4
+                }
             }
         }
     } else {
         match tgx {
             TGx::TGx_4KB => {
                 //assert level IN {1, 2, 3};
-                return 4;
+                4
             }
             TGx::TGx_16KB => {
                 //assert level IN {2, 3};
-                return if level == 2 { 5 } else { 7 };
+                if level == 2 {
+                    5
+                } else {
+                    7
+                }
             }
             TGx::TGx_64KB => {
                 //assert level IN {2, 3};
-                return 5;
+                5
             }
         }
     }
@@ -98,46 +115,40 @@ pub fn CreateAddressDescriptor(
 }
 
 /// Library pseudocode for shared/translation/vmsa/CreateFaultyAddressDescriptor
-
 /// CreateFaultyAddressDescriptor()
 /// ===============================
 /// Set internal members for address descriptor type with values indicating error
-
-pub fn CreateFaultyAddressDescriptor(_va: u64, _fault: FaultRecord) -> AddressDescriptor {
-    todo!()
-    // AddressDescriptor CreateFaultyAddressDescriptor(bits(64) va, FaultRecord fault)
-    //    AddressDescriptor addrdesc;
-
-    //    addrdesc.vaddress = va;
-    //    addrdesc.fault    = fault;
-
-    //    return addrdesc;
+pub fn CreateFaultyAddressDescriptor(va: u64, fault: FaultRecord) -> AddressDescriptor {
+    AddressDescriptor {
+        vaddress: va,
+        fault,
+        ..AddressDescriptor::UNKNOWN
+    }
 }
 
-// Library pseudocode for shared/translation/vmsa/DecodePASpace
-
-// // DecodePASpace()
-// // ===============
-// // Decode the target PA Space
-
-// PASpace DecodePASpace (bit nse, bit ns)
-//    case nse:ns of
-//        when '00'   return PAS_Secure;
-//        when '01'   return PAS_NonSecure;
-//        when '10'   return PAS_Root;
-//        when '11'   return PAS_Realm;
-
-// Library pseudocode for shared/translation/vmsa/DescriptorType
-
-// // DescriptorType
-// // ==============
-// // Translation table descriptor formats
+/// Library pseudocode for shared/translation/vmsa/DecodePASpace
+/// DecodePASpace()
+/// ===============
+/// Decode the target PA Space
+pub fn DecodePASpace(nse: u64, ns: u64) -> PASpace {
+    match (nse, ns) {
+        (0, 0) => PASpace::PAS_Secure,
+        (0, _) => PASpace::PAS_NonSecure,
+        (_, 0) => PASpace::PAS_Root,
+        (_, _) => PASpace::PAS_Realm,
+    }
+}
 
-// enumeration DescriptorType {
-//    DescriptorType_Table,
-//    DescriptorType_Leaf,
-//    DescriptorType_Invalid
-// };
+/// Library pseudocode for shared/translation/vmsa/DescriptorType
+/// DescriptorType
+/// ==============
+/// Translation table descriptor formats
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum DescriptorType {
+    DescriptorType_Table,
+    DescriptorType_Leaf,
+    DescriptorType_Invalid,
+}
 
 // Library pseudocode for shared/translation/vmsa/Domains
 
@@ -145,56 +156,64 @@ pub fn CreateFaultyAddressDescriptor(_va: u64, _fault: FaultRecord) -> AddressDe
 // constant bits(2) Domain_Client   = '01';
 // constant bits(2) Domain_Manager  = '11';
 
-// Library pseudocode for shared/translation/vmsa/FetchDescriptor
-
-// // FetchDescriptor()
-// // =================
-// // Fetch a translation table descriptor
-
-// (FaultRecord, bits(N)) FetchDescriptor(bit ee, AddressDescriptor walkaddress,
-//                                       AccessDescriptor walkaccess, FaultRecord fault_in,
-//                                       integer N)
-//    // 32-bit descriptors for AArch32 Short-descriptor format
-//    // 64-bit descriptors for AArch64 or AArch32 Long-descriptor format
-//    // 128-bit descriptors for AArch64 when FEAT_D128 is set and {V}TCR_ELx.d128 is set
-//    assert N == 32 || N == 64 || N == 128;
-//    bits(N) descriptor;
-//    FaultRecord fault = fault_in;
-
-//    if IsFeatureImplemented(FEAT_RME) then
-//        fault.gpcf = GranuleProtectionCheck(walkaddress, walkaccess);
-//        if fault.gpcf.gpf != GPCF_None then
-//            fault.statuscode = Fault_GPCFOnWalk;
-//            fault.paddress   = walkaddress.paddress;
-//            fault.gpcfs2walk = fault.secondstage;
-//            return (fault, bits(N) UNKNOWN);
-
-//    PhysMemRetStatus memstatus;
-//    (memstatus, descriptor) = PhysMemRead(walkaddress, N DIV 8, walkaccess);
-//    if IsFault(memstatus) then
-//        boolean iswrite = FALSE;
-//        fault = HandleExternalTTWAbort(memstatus, iswrite, walkaddress,
-//                                       walkaccess, N DIV 8, fault);
-//        if IsFault(fault.statuscode) then
-//            return (fault, bits(N) UNKNOWN);
-
-//    if ee == '1' then
-//        descriptor = BigEndianReverse(descriptor);
-
-//    return (fault, descriptor);
-
-// Library pseudocode for shared/translation/vmsa/HasUnprivileged
-
-// // HasUnprivileged()
-// // =================
-// // Returns whether a translation regime serves EL0 as well as a higher EL
-
-// boolean HasUnprivileged(Regime regime)
-//    return (regime IN {
-//        Regime_EL20,
-//        Regime_EL30,
-//        Regime_EL10
-//    });
+/// Library pseudocode for shared/translation/vmsa/FetchDescriptor
+/// FetchDescriptor()
+/// =================
+/// Fetch a translation table descriptor
+pub fn FetchDescriptor(
+    pe: &ProcessorElement,
+    mem: &dyn PhysicalMemory,
+    ee: u64,
+    walkaddress: AddressDescriptor,
+    walkaccess: AccessDescriptor,
+    fault_in: FaultRecord,
+    N: usize,
+) -> Result<(FaultRecord, u128), TranslationError> {
+    // 32-bit descriptors for AArch32 Short-descriptor format
+    // 64-bit descriptors for AArch64 or AArch32 Long-descriptor format
+    // 128-bit descriptors for AArch64 when FEAT_D128 is set and {V}TCR_ELx.d128 is set
+    assert!(N == 32 || N == 64 || N == 128);
+    let mut fault = fault_in;
+
+    if IsFeatureImplemented(pe, Feature::FEAT_RME) {
+        fault.gpcf = GranuleProtectionCheck(pe, mem, walkaddress, walkaccess)?;
+        if fault.gpcf.gpf != GPCF::GPCF_None {
+            fault.statuscode = Fault::Fault_GPCFOnWalk;
+            fault.paddress = walkaddress.paddress;
+            fault.gpcfs2walk = fault.secondstage;
+            return Ok((fault, 0));
+        }
+    }
+
+    let (memstatus, bytes) = PhysMemRead(mem, walkaddress, N / 8, walkaccess);
+    if IsFault(memstatus.statuscode) {
+        let iswrite = false;
+        fault = HandleExternalTTWAbort(memstatus, iswrite, walkaddress, walkaccess, N / 8, fault);
+        if IsFault(fault.statuscode) {
+            return Ok((fault, 0));
+        }
+    }
+
+    let mut raw = [0_u8; 16];
+    raw[..N / 8].copy_from_slice(&bytes);
+    if ee == 1 {
+        raw[..N / 8].reverse();
+    }
+    let descriptor = u128::from_le_bytes(raw);
+
+    Ok((fault, descriptor))
+}
+
+/// Library pseudocode for shared/translation/vmsa/HasUnprivileged
+/// HasUnprivileged()
+/// =================
+/// Returns whether a translation regime serves EL0 as well as a higher EL
+pub fn HasUnprivileged(regime: Regime) -> bool {
+    matches!(
+        regime,
+        Regime::Regime_EL20 | Regime::Regime_EL30 | Regime::Regime_EL10
+    )
+}
 
 // Library pseudocode for shared/translation/vmsa/Regime
 
@@ -288,14 +307,15 @@ pub struct S1TTWParams {
 }
 
 macro_rules! getter {
-    ($($getter:tt $ident:tt),*$(,)*) => {
+    ($bits:ident; $($getter:tt $ident:tt),*$(,)*) => {
         $(pub fn $getter(&self) -> u64 {
-            self.bitfield.get(S1TTWParamsBits::$ident)
+            self.bitfield.get($bits::$ident)
         })*
     };
 }
 impl S1TTWParams {
     getter! {
+        S1TTWParamsBits;
         get_ha ha,
         get_hd hd,
         get_tbi tbi,
@@ -333,7 +353,7 @@ impl S1TTWParams {
     }
 
     pub const fn get_tgx(&self) -> TGx {
-        TGx::TGx_4KB
+        self.tgx
     }
 }
 
@@ -389,12 +409,6 @@ mycelium_bitfield::bitfield! {
     }
 }
 
-// Library pseudocode for shared/translation/vmsa/S2TTWParams
-
-// // S2TTWParams
-// // ===========
-// // Register fields corresponding to stage 2 translation.
-
 // type S2TTWParams is (
 // // A64-VMSA exclusive parameters
 //    bit         ha,         // VTCR_EL2.HA
@@ -434,13 +448,98 @@ mycelium_bitfield::bitfield! {
 //    bit         ptw,        // HCR_EL2.PTW      / HCR.PTW
 //    bit         vm          // HCR_EL2.VM       / HCR.VM
 // )
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct S2TTWParams {
+    pub bitfield: S2TTWParamsBits,
+    /// S2PIR_EL2
+    pub s2pir: u64,
+    /// V{S}TCR_EL2.TG0  / Always TGx_4KB
+    pub tgx: TGx,
+}
 
-/// Library pseudocode for shared/translation/vmsa/SDFType
+impl S2TTWParams {
+    getter! {
+        S2TTWParamsBits;
+        get_ha ha,
+        get_hd hd,
+        get_sl2 sl2,
+        get_ds ds,
+        get_d128 d128,
+        get_sw sw,
+        get_nsw nsw,
+        get_sa sa,
+        get_nsa nsa,
+        get_ps ps,
+        get_txsz txsz,
+        get_fwb fwb,
+        get_cmow cmow,
+        get_skl skl,
+        get_s2pie s2pie,
+        get_tl0 tl0,
+        get_tl1 tl1,
+        get_assuredonly assuredonly,
+        get_haft haft,
+        get_emec emec,
+        get_hdbss hdbss,
+        get_sl0 sl0,
+        get_irgn irgn,
+        get_orgn orgn,
+        get_sh sh,
+        get_ee ee,
+        get_ptw ptw,
+        get_vm vm,
+    }
+
+    pub const fn get_tgx(&self) -> TGx {
+        self.tgx
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Library pseudocode for shared/translation/vmsa/S2TTWParams
+    /// S2TTWParams
+    /// ===========
+    /// Register fields corresponding to stage 2 translation.
+    #[derive(Eq, PartialEq)]
+    pub struct S2TTWParamsBits<u64> {
+        // A64-VMSA exclusive parameters
+        pub const ha = 1;          // VTCR_EL2.HA
+        pub const hd = 1;          // VTCR_EL2.HD
+        pub const sl2 = 1;         // V{S}TCR_EL2.SL2
+        pub const ds = 1;          // VTCR_EL2.DS
+        pub const d128 = 1;        // VTCR_ELx.D128
+        pub const sw = 1;          // VSTCR_EL2.SW
+        pub const nsw = 1;         // VTCR_EL2.NSW
+        pub const sa = 1;          // VSTCR_EL2.SA
+        pub const nsa = 1;         // VTCR_EL2.NSA
+        pub const ps = 3;          // VTCR_EL2.PS
+        pub const txsz = 6;        // V{S}TCR_EL2.T0SZ
+        pub const fwb = 1;         // HCR_EL2.FWB
+        pub const cmow = 1;        // HCRX_EL2.CMOW
+        pub const skl = 2;         // VTCR_EL2.SKL
+        pub const s2pie = 1;       // VTCR_EL2.S2PIE
+        pub const tl0 = 1;         // VTCR_EL2.TL0
+        pub const tl1 = 1;         // VTCR_EL2.TL1
+        pub const assuredonly = 1; // VTCR_EL2.AssuredOnly
+        pub const haft = 1;        // VTCR_EL2.HAFT
+        pub const emec = 1;        // SCTLR2_EL2.EMEC
+        pub const hdbss = 1;       // VTCR_EL2.HDBSS
+
+        // Parameters common to both A64-VMSA & A32-VMSA if implemented (A64/A32)
+        pub const sl0 = 2;         // V{S}TCR_EL2.SL0  / VTCR.SL0
+        pub const irgn = 2;        // VTCR_EL2.IRGN0   / VTCR.IRGN0
+        pub const orgn = 2;        // VTCR_EL2.ORGN0   / VTCR.ORGN0
+        pub const sh = 2;          // VTCR_EL2.SH0     / VTCR.SH0
+        pub const ee = 1;          // SCTLR_EL2.EE     / HSCTLR.EE
+        pub const ptw = 1;         // HCR_EL2.PTW      / HCR.PTW
+        pub const vm = 1;          // HCR_EL2.VM       / HCR.VM
+    }
+}
 
+/// Library pseudocode for shared/translation/vmsa/SDFType
 /// SDFType
 /// =======
 /// Short-descriptor format type
-
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum SDFType {
     SDFType_Table,
@@ -465,28 +564,82 @@ pub enum SDFType {
 //        when Regime_EL20    return SecurityStateAtEL(EL2);
 //        when Regime_EL10    return SecurityStateAtEL(EL1);
 
+/// Library pseudocode for shared/translation/vmsa/S1AccessControls
+/// S1AccessControls
+/// ================
+/// Effective access controls defined by stage 1 translation
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct S1AccessControls {
+    /// Stage base permission-read
+    pub r: bool,
+    /// Stage base permission-write
+    pub w: bool,
+    /// Stage base permission-execute
+    pub x: bool,
+    /// Stage GCS permission
+    pub gcs: bool,
+    /// Stage overlay restrictions
+    pub overlay: bool,
+    /// Stage overlay permission-read
+    pub or: bool,
+    /// Stage overlay permission-write
+    pub ow: bool,
+    /// Stage overlay permission-execute
+    pub ox: bool,
+    /// Stage write permission implies execute-never
+    pub wxn: bool,
+}
+
+/// Library pseudocode for shared/translation/vmsa/S2AccessControls
+/// S2AccessControls
+/// ================
+/// Effective access controls defined by stage 2 translation
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct S2AccessControls {
+    /// Stage base permission-read
+    pub r: bool,
+    /// Stage base permission-write
+    pub w: bool,
+    /// Stage base permission-write for stage 1 translation table updates
+    pub w_mmu: bool,
+    /// Stage base permission-execute
+    pub x: bool,
+    /// Stage 1 top level table for TTBR0 may be mapped
+    pub toplevel0: bool,
+    /// Stage 1 top level table for TTBR1 may be mapped
+    pub toplevel1: bool,
+    /// Stage overlay restrictions
+    pub overlay: bool,
+    /// Stage overlay permission-read
+    pub or: bool,
+    /// Stage overlay permission-write
+    pub ow: bool,
+    /// Stage overlay permission-write for stage 1 translation table updates
+    pub ow_mmu: bool,
+    /// Stage overlay permission-execute
+    pub ox: bool,
+}
+
 /// Library pseudocode for shared/translation/vmsa/StageOA
 /// StageOA()
 /// =========
 /// Given the final walk state (a page or block descriptor), map the untranslated
 /// input address bits to the output address
-pub fn StageOA(_ia: u64, d128: u64, tgx: TGx, walkstate: TTWState) -> FullAddress {
+pub fn StageOA(ia: u64, d128: u64, tgx: TGx, walkstate: TTWState) -> FullAddress {
     // Output Address
-    let mut oa: FullAddress = unsafe { MaybeUninit::zeroed().assume_init_read() };
-    let csize: u64;
-
     let tsize = TranslationSize(d128, tgx, walkstate.level);
-    csize = if walkstate.contiguous {
+    let csize = if walkstate.contiguous {
         ContiguousSize(d128, tgx, walkstate.level)
     } else {
         0
     };
 
-    let _ia_msb = tsize + csize;
-    oa.paspace = walkstate.baseaddress.paspace;
-    //oa.address = walkstate.baseaddress.address<55:ia_msb>:ia<ia_msb-1:0>;
-
-    oa
+    let ia_msb = (tsize + csize) as u32;
+    FullAddress {
+        paspace: walkstate.baseaddress.paspace,
+        address: (Bits(walkstate.baseaddress.address.into(), 55, ia_msb) << ia_msb)
+            | Bits(ia.into(), ia_msb - 1, 0),
+    }
 }
 
 /// Library pseudocode for shared/translation/vmsa/TGx
@@ -553,14 +706,13 @@ pub fn TGxGranuleBits(tgx: TGx) -> u64 {
 // )
 
 /// Library pseudocode for shared/translation/vmsa/TTWState
-
 /// TTWState
 /// ========
 /// Translation table walk state
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct TTWState {
     pub istable: bool,
-    pub level: u64,
+    pub level: i64,
     pub baseaddress: FullAddress,
     /// bit
     pub contiguous: bool,
@@ -582,8 +734,11 @@ pub struct TTWState {
     pub permissions: Permissions,
 }
 
-/// Library pseudocode for shared/functions/system/EL0
+impl TTWState {
+    pub const UNKNOWN: Self = unsafe { MaybeUninit::zeroed().assume_init_read() };
+}
 
+/// Library pseudocode for shared/functions/system/EL0
 pub const EL3: PrivilegeLevel = PrivilegeLevel::PL3;
 pub const EL2: PrivilegeLevel = PrivilegeLevel::PL2;
 pub const EL1: PrivilegeLevel = PrivilegeLevel::PL1;
@@ -600,25 +755,31 @@ pub enum PrivilegeLevel {
 }
 
 /// Library pseudocode for shared/translation/vmsa/TranslationRegime
-
 /// TranslationRegime()
 /// ===================
 /// Select the translation regime given the target EL and PE state
-
-pub fn TranslationRegime(_el: PrivilegeLevel) -> Regime {
-    todo!()
-    // match el {
-    //     self::EL3 if ELUsingAArch32(EL3) => Regime::Regime_EL30,
-    //     self::EL3 => Regime::Regime_EL3,
-    //     self::EL2 if ELIsInHost(EL2) => Regime::Regime_EL20,
-    //     self::EL2 => Regime::Regime_EL2,
-    //     self::EL1 => Regime::Regime_EL10,
-    //     self::EL0 if CurrentSecurityState() == SecurityState::SS_Secure && ELUsingAArch32(EL3) => {
-    //         Regime::Regime_EL30
-    //     }
-    //     self::EL0 if ELIsInHost(EL0) => Regime::Regime_EL20,
-    //     self::EL0 => Regime::Regime_EL10,
-    // }
+pub fn TranslationRegime(
+    pe: &ProcessorElement,
+    el: PrivilegeLevel,
+) -> Result<Regime, TranslationError> {
+    if !HaveEL(pe, el) {
+        return Err(TranslationError::ELNotImplemented(el));
+    }
+    Ok(match el {
+        self::EL3 if ELUsingAArch32(pe, EL3) => Regime::Regime_EL30,
+        self::EL3 => Regime::Regime_EL3,
+        self::EL2 if ELIsInHost(pe, EL2) => Regime::Regime_EL20,
+        self::EL2 => Regime::Regime_EL2,
+        self::EL1 => Regime::Regime_EL10,
+        self::EL0
+            if SecurityStateAtEL(pe, pe.current_el()?)? == SecurityState::SS_Secure
+                && ELUsingAArch32(pe, EL3) =>
+        {
+            Regime::Regime_EL30
+        }
+        self::EL0 if ELIsInHost(pe, EL0) => Regime::Regime_EL20,
+        self::EL0 => Regime::Regime_EL10,
+    })
 }
 
 /// Library pseudocode for shared/translation/vmsa/TranslationSize
@@ -626,10 +787,10 @@ pub fn TranslationRegime(_el: PrivilegeLevel) -> Regime {
 /// =================
 /// Compute the number of bits directly mapped from the input address
 /// to the output address
-pub fn TranslationSize(d128: u64, tgx: TGx, level: u64) -> u64 {
+pub fn TranslationSize(d128: u64, tgx: TGx, level: i64) -> u64 {
     let granulebits = TGxGranuleBits(tgx);
     let descsizelog2 = if d128 == 1 { 4 } else { 3 };
-    let blockbits = (FINAL_LEVEL - level) * (granulebits - descsizelog2);
+    let blockbits = (FINAL_LEVEL - level) as u64 * (granulebits - descsizelog2);
 
     granulebits + blockbits
 }
@@ -654,13 +815,942 @@ pub fn TranslationSize(d128: u64, tgx: TGx, level: u64) -> u64 {
 
 mod walkparams {
     use super::*;
+    use crate::pe::*;
+    use crate::stubs::*;
+    use crate::sysregs::*;
+
+    /// AArch64.GetS1TTWParams()
+    /// ========================
+    /// Returns stage 1 translation table walk parameters from respective controlling
+    /// System registers.
+    pub fn AArch64GetS1TTWParams(
+        pe: &ProcessorElement,
+        regime: Regime,
+        ss: SecurityState,
+        va: u64,
+    ) -> Result<S1TTWParams, TranslationError> {
+        let varange = AArch64GetVARange(va);
+
+        match regime {
+            Regime::Regime_EL3 => Ok(AArch64S1TTWParamsEL3(pe)),
+            Regime::Regime_EL2 => Ok(AArch64S1TTWParamsEL2(pe, ss)),
+            Regime::Regime_EL20 => Ok(AArch64S1TTWParamsEL20(pe, ss, varange)),
+            Regime::Regime_EL10 => Ok(AArch64S1TTWParamsEL10(pe, varange)),
+            Regime::Regime_EL30 => Err(TranslationError::Unsupported("AArch32 EL3")),
+        }
+    }
+
+    /// AArch64.S1TTWParamsEL10()
+    /// =========================
+    /// Gather stage 1 translation table walk parameters for EL1&0 regime
+    /// (with EL2 enabled or disabled)
+    pub fn AArch64S1TTWParamsEL10(pe: &ProcessorElement, varange: VARange) -> S1TTWParams {
+        let tcr = pe.regs.TCR_EL1;
+        let tcr2 = pe.regs.TCR2_EL1;
+        let tcr2_enabled = IsTCR2EL1Enabled(pe);
+        let sctlr = pe.regs.SCTLR_EL1;
+        let mut bitfield = S1TTWParamsBits::new();
+
+        let d128 = if IsFeatureImplemented(pe, Feature::FEAT_D128) && tcr2_enabled {
+            tcr2.get(TCR2_ELx_REG::D128)
+        } else {
+            0
+        };
+        bitfield.set(S1TTWParamsBits::d128, d128);
+
+        let (tgx, ttbr) = if varange == VARange::VARange_LOWER {
+            bitfield.set(S1TTWParamsBits::txsz, tcr.get(TCR_EL1_REG::T0SZ));
+            bitfield.set(S1TTWParamsBits::irgn, tcr.get(TCR_EL1_REG::IRGN0));
+            bitfield.set(S1TTWParamsBits::orgn, tcr.get(TCR_EL1_REG::ORGN0));
+            bitfield.set(S1TTWParamsBits::sh, tcr.get(TCR_EL1_REG::SH0));
+            bitfield.set(S1TTWParamsBits::tbi, tcr.get(TCR_EL1_REG::TBI0));
+            if IsFeatureImplemented(pe, Feature::FEAT_SVE)
+                || IsFeatureImplemented(pe, Feature::FEAT_TME)
+            {
+                bitfield.set(S1TTWParamsBits::nfd, tcr.get(TCR_EL1_REG::NFD0));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_PAuth) {
+                bitfield.set(S1TTWParamsBits::tbid, tcr.get(TCR_EL1_REG::TBID0));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_E0PD) {
+                bitfield.set(S1TTWParamsBits::e0pd, tcr.get(TCR_EL1_REG::E0PD0));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_HPDS) {
+                bitfield.set(S1TTWParamsBits::hpd, tcr.get(TCR_EL1_REG::HPD0));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_MTE4) {
+                bitfield.set(S1TTWParamsBits::mtx, tcr.get(TCR_EL1_REG::MTX0));
+            }
+            if d128 == 1 {
+                bitfield.set(S1TTWParamsBits::disch, tcr2.get(TCR2_ELx_REG::DisCH0));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_ASID2) && tcr2_enabled {
+                bitfield.set(S1TTWParamsBits::fng, tcr2.get(TCR2_ELx_REG::FNG0));
+            }
+            (
+                AArch64S1DecodeTG0(pe, tcr.get(TCR_EL1_REG::TG0)),
+                pe.regs.TTBR0_EL1,
+            )
+        } else {
+            bitfield.set(S1TTWParamsBits::txsz, tcr.get(TCR_EL1_REG::T1SZ));
+            bitfield.set(S1TTWParamsBits::irgn, tcr.get(TCR_EL1_REG::IRGN1));
+            bitfield.set(S1TTWParamsBits::orgn, tcr.get(TCR_EL1_REG::ORGN1));
+            bitfield.set(S1TTWParamsBits::sh, tcr.get(TCR_EL1_REG::SH1));
+            bitfield.set(S1TTWParamsBits::tbi, tcr.get(TCR_EL1_REG::TBI1));
+            if IsFeatureImplemented(pe, Feature::FEAT_SVE)
+                || IsFeatureImplemented(pe, Feature::FEAT_TME)
+            {
+                bitfield.set(S1TTWParamsBits::nfd, tcr.get(TCR_EL1_REG::NFD1));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_PAuth) {
+                bitfield.set(S1TTWParamsBits::tbid, tcr.get(TCR_EL1_REG::TBID1));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_E0PD) {
+                bitfield.set(S1TTWParamsBits::e0pd, tcr.get(TCR_EL1_REG::E0PD1));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_HPDS) {
+                bitfield.set(S1TTWParamsBits::hpd, tcr.get(TCR_EL1_REG::HPD1));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_MTE4) {
+                bitfield.set(S1TTWParamsBits::mtx, tcr.get(TCR_EL1_REG::MTX1));
+            }
+            if d128 == 1 {
+                bitfield.set(S1TTWParamsBits::disch, tcr2.get(TCR2_ELx_REG::DisCH1));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_ASID2) && tcr2_enabled {
+                bitfield.set(S1TTWParamsBits::fng, tcr2.get(TCR2_ELx_REG::FNG1));
+            }
+            (
+                AArch64S1DecodeTG1(pe, tcr.get(TCR_EL1_REG::TG1)),
+                pe.regs.TTBR1_EL1,
+            )
+        };
+        if d128 == 1 {
+            bitfield.set(S1TTWParamsBits::skl, ttbr.get(TTBR_ELx_REG::SKL) as u64);
+        }
+
+        bitfield.set(S1TTWParamsBits::ps, tcr.get(TCR_EL1_REG::IPS));
+        bitfield.set(S1TTWParamsBits::wxn, sctlr.get(SCTLR_ELx_REG::WXN));
+        bitfield.set(S1TTWParamsBits::ee, sctlr.get(SCTLR_ELx_REG::EE));
+        bitfield.set(S1TTWParamsBits::sif, AArch64S1SIF(pe));
+        if EL2Enabled(pe) {
+            bitfield.set(S1TTWParamsBits::dc, pe.regs.HCR_EL2.get(HCR_EL2_REG::DC));
+            bitfield.set(S1TTWParamsBits::dct, pe.regs.HCR_EL2.get(HCR_EL2_REG::DCT));
+            // HCR_EL2.<NV,NV1> == '01' is CONSTRAINED UNPREDICTABLE, this model
+            // takes Constraint_NVNV1_01 and uses the value of NV1 as is.
+            bitfield.set(S1TTWParamsBits::nv1, pe.regs.HCR_EL2.get(HCR_EL2_REG::NV1));
+        }
+        bitfield.set(
+            S1TTWParamsBits::ntlsmd,
+            if IsFeatureImplemented(pe, Feature::FEAT_LSMAOC) {
+                sctlr.get(SCTLR_ELx_REG::nTLSMD)
+            } else {
+                1
+            },
+        );
+        if IsFeatureImplemented(pe, Feature::FEAT_CMOW) {
+            bitfield.set(S1TTWParamsBits::cmow, sctlr.get(SCTLR_ELx_REG::CMOW));
+        }
+        let ha = if HaveAccessFlagUpdateExt(pe) {
+            tcr.get(TCR_EL1_REG::HA)
+        } else {
+            0
+        };
+        bitfield.set(S1TTWParamsBits::ha, ha);
+        if ha == 1 && HaveDirtyBitModifierExt(pe) {
+            bitfield.set(S1TTWParamsBits::hd, tcr.get(TCR_EL1_REG::HD));
+        }
+        if matches!(tgx, TGx::TGx_4KB | TGx::TGx_16KB)
+            && IsFeatureImplemented(pe, Feature::FEAT_LPA2)
+        {
+            bitfield.set(S1TTWParamsBits::ds, tcr.get(TCR_EL1_REG::DS));
+        }
+        let pie = if d128 == 1 {
+            1
+        } else if IsFeatureImplemented(pe, Feature::FEAT_S1PIE) && tcr2_enabled {
+            tcr2.get(TCR2_ELx_REG::PIE)
+        } else {
+            0
+        };
+        bitfield.set(S1TTWParamsBits::pie, pie);
+        if IsFeatureImplemented(pe, Feature::FEAT_PAN3) {
+            bitfield.set(
+                S1TTWParamsBits::epan,
+                if pie == 0 {
+                    sctlr.get(SCTLR_ELx_REG::EPAN)
+                } else {
+                    1
+                },
+            );
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_AIE) && tcr2_enabled {
+            bitfield.set(S1TTWParamsBits::aie, tcr2.get(TCR2_ELx_REG::AIE));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_THE) && tcr2_enabled {
+            bitfield.set(S1TTWParamsBits::pnch, tcr2.get(TCR2_ELx_REG::PnCH));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_HAFT) && ha == 1 && tcr2_enabled {
+            bitfield.set(S1TTWParamsBits::haft, tcr2.get(TCR2_ELx_REG::HAFT));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_MEC) && IsSCTLR2EL2Enabled(pe) {
+            bitfield.set(
+                S1TTWParamsBits::emec,
+                pe.regs.SCTLR2_EL2.get(SCTLR2_ELx_REG::EMEC),
+            );
+        }
+
+        S1TTWParams {
+            bitfield,
+            mair: pe.regs.MAIR_EL1.bits(),
+            mair2: if IsFeatureImplemented(pe, Feature::FEAT_AIE) {
+                pe.regs.MAIR2_EL1.bits()
+            } else {
+                0
+            },
+            pir: if IsFeatureImplemented(pe, Feature::FEAT_S1PIE) {
+                pe.regs.PIR_EL1.bits()
+            } else {
+                0
+            },
+            pire0: if IsFeatureImplemented(pe, Feature::FEAT_S1PIE) {
+                pe.regs.PIRE0_EL1.bits()
+            } else {
+                0
+            },
+            tgx,
+        }
+    }
+
+    /// AArch64.S1TTWParamsEL2()
+    /// ========================
+    /// Gather stage 1 translation table walk parameters for EL2 regime
+    pub fn AArch64S1TTWParamsEL2(pe: &ProcessorElement, ss: SecurityState) -> S1TTWParams {
+        let tcr = pe.regs.TCR_EL2;
+        let tcr2 = pe.regs.TCR2_EL2;
+        let tcr2_enabled = IsTCR2EL2Enabled(pe);
+        let sctlr = pe.regs.SCTLR_EL2;
+        let tgx = AArch64S1DecodeTG0(pe, tcr.get(TCR_EL2_REG::TG0));
+        let mut bitfield = S1TTWParamsBits::new();
+
+        bitfield.set(S1TTWParamsBits::txsz, tcr.get(TCR_EL2_REG::T0SZ));
+        bitfield.set(S1TTWParamsBits::ps, tcr.get(TCR_EL2_REG::PS));
+        bitfield.set(S1TTWParamsBits::irgn, tcr.get(TCR_EL2_REG::IRGN0));
+        bitfield.set(S1TTWParamsBits::orgn, tcr.get(TCR_EL2_REG::ORGN0));
+        bitfield.set(S1TTWParamsBits::sh, tcr.get(TCR_EL2_REG::SH0));
+        bitfield.set(S1TTWParamsBits::tbi, tcr.get(TCR_EL2_REG::TBI));
+        bitfield.set(S1TTWParamsBits::wxn, sctlr.get(SCTLR_ELx_REG::WXN));
+        bitfield.set(S1TTWParamsBits::ee, sctlr.get(SCTLR_ELx_REG::EE));
+        bitfield.set(S1TTWParamsBits::sif, AArch64S1SIF(pe));
+        bitfield.set(S1TTWParamsBits::ntlsmd, 1);
+        if IsFeatureImplemented(pe, Feature::FEAT_S1PIE) && tcr2_enabled {
+            bitfield.set(S1TTWParamsBits::pie, tcr2.get(TCR2_ELx_REG::PIE));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_AIE) && tcr2_enabled {
+            bitfield.set(S1TTWParamsBits::aie, tcr2.get(TCR2_ELx_REG::AIE));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_PAuth) {
+            bitfield.set(S1TTWParamsBits::tbid, tcr.get(TCR_EL2_REG::TBID));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_HPDS) {
+            bitfield.set(S1TTWParamsBits::hpd, tcr.get(TCR_EL2_REG::HPD));
+        }
+        let ha = if HaveAccessFlagUpdateExt(pe) {
+            tcr.get(TCR_EL2_REG::HA)
+        } else {
+            0
+        };
+        bitfield.set(S1TTWParamsBits::ha, ha);
+        if ha == 1 && HaveDirtyBitModifierExt(pe) {
+            bitfield.set(S1TTWParamsBits::hd, tcr.get(TCR_EL2_REG::HD));
+        }
+        if matches!(tgx, TGx::TGx_4KB | TGx::TGx_16KB)
+            && IsFeatureImplemented(pe, Feature::FEAT_LPA2)
+        {
+            bitfield.set(S1TTWParamsBits::ds, tcr.get(TCR_EL2_REG::DS));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_MTE4) {
+            bitfield.set(S1TTWParamsBits::mtx, tcr.get(TCR_EL2_REG::MTX));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_THE) && tcr2_enabled {
+            bitfield.set(S1TTWParamsBits::pnch, tcr2.get(TCR2_ELx_REG::PnCH));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_HAFT) && ha == 1 && tcr2_enabled {
+            bitfield.set(S1TTWParamsBits::haft, tcr2.get(TCR2_ELx_REG::HAFT));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_MEC) && IsSCTLR2EL2Enabled(pe) {
+            bitfield.set(
+                S1TTWParamsBits::emec,
+                pe.regs.SCTLR2_EL2.get(SCTLR2_ELx_REG::EMEC),
+            );
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_MEC)
+            && ss == SecurityState::SS_Realm
+            && tcr2_enabled
+        {
+            bitfield.set(S1TTWParamsBits::amec, tcr2.get(TCR2_ELx_REG::AMEC0));
+        }
+
+        S1TTWParams {
+            bitfield,
+            mair: pe.regs.MAIR_EL2.bits(),
+            mair2: if IsFeatureImplemented(pe, Feature::FEAT_AIE) {
+                pe.regs.MAIR2_EL2.bits()
+            } else {
+                0
+            },
+            pir: if IsFeatureImplemented(pe, Feature::FEAT_S1PIE) {
+                pe.regs.PIR_EL2.bits()
+            } else {
+                0
+            },
+            pire0: 0,
+            tgx,
+        }
+    }
+
+    /// AArch64.S1TTWParamsEL20()
+    /// =========================
+    /// Gather stage 1 translation table walk parameters for EL2&0 regime
+    pub fn AArch64S1TTWParamsEL20(
+        pe: &ProcessorElement,
+        ss: SecurityState,
+        varange: VARange,
+    ) -> S1TTWParams {
+        let tcr = pe.regs.TCR_EL2_E2H();
+        let tcr2 = pe.regs.TCR2_EL2;
+        let tcr2_enabled = IsTCR2EL2Enabled(pe);
+        let sctlr = pe.regs.SCTLR_EL2;
+        let mut bitfield = S1TTWParamsBits::new();
+
+        let d128 = if IsFeatureImplemented(pe, Feature::FEAT_D128) && tcr2_enabled {
+            tcr2.get(TCR2_ELx_REG::D128)
+        } else {
+            0
+        };
+        bitfield.set(S1TTWParamsBits::d128, d128);
+
+        let (tgx, ttbr, amec) = if varange == VARange::VARange_LOWER {
+            bitfield.set(S1TTWParamsBits::txsz, tcr.get(TCR_EL1_REG::T0SZ));
+            bitfield.set(S1TTWParamsBits::irgn, tcr.get(TCR_EL1_REG::IRGN0));
+            bitfield.set(S1TTWParamsBits::orgn, tcr.get(TCR_EL1_REG::ORGN0));
+            bitfield.set(S1TTWParamsBits::sh, tcr.get(TCR_EL1_REG::SH0));
+            bitfield.set(S1TTWParamsBits::tbi, tcr.get(TCR_EL1_REG::TBI0));
+            if IsFeatureImplemented(pe, Feature::FEAT_SVE)
+                || IsFeatureImplemented(pe, Feature::FEAT_TME)
+            {
+                bitfield.set(S1TTWParamsBits::nfd, tcr.get(TCR_EL1_REG::NFD0));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_PAuth) {
+                bitfield.set(S1TTWParamsBits::tbid, tcr.get(TCR_EL1_REG::TBID0));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_E0PD) {
+                bitfield.set(S1TTWParamsBits::e0pd, tcr.get(TCR_EL1_REG::E0PD0));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_HPDS) {
+                bitfield.set(S1TTWParamsBits::hpd, tcr.get(TCR_EL1_REG::HPD0));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_MTE4) {
+                bitfield.set(S1TTWParamsBits::mtx, tcr.get(TCR_EL1_REG::MTX0));
+            }
+            if d128 == 1 {
+                bitfield.set(S1TTWParamsBits::disch, tcr2.get(TCR2_ELx_REG::DisCH0));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_ASID2) && tcr2_enabled {
+                bitfield.set(S1TTWParamsBits::fng, tcr2.get(TCR2_ELx_REG::FNG0));
+            }
+            (
+                AArch64S1DecodeTG0(pe, tcr.get(TCR_EL1_REG::TG0)),
+                pe.regs.TTBR0_EL2,
+                tcr2.get(TCR2_ELx_REG::AMEC0),
+            )
+        } else {
+            bitfield.set(S1TTWParamsBits::txsz, tcr.get(TCR_EL1_REG::T1SZ));
+            bitfield.set(S1TTWParamsBits::irgn, tcr.get(TCR_EL1_REG::IRGN1));
+            bitfield.set(S1TTWParamsBits::orgn, tcr.get(TCR_EL1_REG::ORGN1));
+            bitfield.set(S1TTWParamsBits::sh, tcr.get(TCR_EL1_REG::SH1));
+            bitfield.set(S1TTWParamsBits::tbi, tcr.get(TCR_EL1_REG::TBI1));
+            if IsFeatureImplemented(pe, Feature::FEAT_SVE)
+                || IsFeatureImplemented(pe, Feature::FEAT_TME)
+            {
+                bitfield.set(S1TTWParamsBits::nfd, tcr.get(TCR_EL1_REG::NFD1));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_PAuth) {
+                bitfield.set(S1TTWParamsBits::tbid, tcr.get(TCR_EL1_REG::TBID1));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_E0PD) {
+                bitfield.set(S1TTWParamsBits::e0pd, tcr.get(TCR_EL1_REG::E0PD1));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_HPDS) {
+                bitfield.set(S1TTWParamsBits::hpd, tcr.get(TCR_EL1_REG::HPD1));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_MTE4) {
+                bitfield.set(S1TTWParamsBits::mtx, tcr.get(TCR_EL1_REG::MTX1));
+            }
+            if d128 == 1 {
+                bitfield.set(S1TTWParamsBits::disch, tcr2.get(TCR2_ELx_REG::DisCH1));
+            }
+            if IsFeatureImplemented(pe, Feature::FEAT_ASID2) && tcr2_enabled {
+                bitfield.set(S1TTWParamsBits::fng, tcr2.get(TCR2_ELx_REG::FNG1));
+            }
+            (
+                AArch64S1DecodeTG1(pe, tcr.get(TCR_EL1_REG::TG1)),
+                pe.regs.TTBR1_EL2,
+                tcr2.get(TCR2_ELx_REG::AMEC1),
+            )
+        };
+        if d128 == 1 {
+            bitfield.set(S1TTWParamsBits::skl, ttbr.get(TTBR_ELx_REG::SKL) as u64);
+        }
+
+        bitfield.set(S1TTWParamsBits::ps, tcr.get(TCR_EL1_REG::IPS));
+        bitfield.set(S1TTWParamsBits::wxn, sctlr.get(SCTLR_ELx_REG::WXN));
+        bitfield.set(S1TTWParamsBits::ee, sctlr.get(SCTLR_ELx_REG::EE));
+        bitfield.set(S1TTWParamsBits::sif, AArch64S1SIF(pe));
+        bitfield.set(
+            S1TTWParamsBits::ntlsmd,
+            if IsFeatureImplemented(pe, Feature::FEAT_LSMAOC) {
+                sctlr.get(SCTLR_ELx_REG::nTLSMD)
+            } else {
+                1
+            },
+        );
+        if IsFeatureImplemented(pe, Feature::FEAT_CMOW) {
+            bitfield.set(S1TTWParamsBits::cmow, sctlr.get(SCTLR_ELx_REG::CMOW));
+        }
+        let ha = if HaveAccessFlagUpdateExt(pe) {
+            tcr.get(TCR_EL1_REG::HA)
+        } else {
+            0
+        };
+        bitfield.set(S1TTWParamsBits::ha, ha);
+        if ha == 1 && HaveDirtyBitModifierExt(pe) {
+            bitfield.set(S1TTWParamsBits::hd, tcr.get(TCR_EL1_REG::HD));
+        }
+        if matches!(tgx, TGx::TGx_4KB | TGx::TGx_16KB)
+            && IsFeatureImplemented(pe, Feature::FEAT_LPA2)
+        {
+            bitfield.set(S1TTWParamsBits::ds, tcr.get(TCR_EL1_REG::DS));
+        }
+        let pie = if d128 == 1 {
+            1
+        } else if IsFeatureImplemented(pe, Feature::FEAT_S1PIE) && tcr2_enabled {
+            tcr2.get(TCR2_ELx_REG::PIE)
+        } else {
+            0
+        };
+        bitfield.set(S1TTWParamsBits::pie, pie);
+        if IsFeatureImplemented(pe, Feature::FEAT_PAN3) {
+            bitfield.set(
+                S1TTWParamsBits::epan,
+                if pie == 0 {
+                    sctlr.get(SCTLR_ELx_REG::EPAN)
+                } else {
+                    1
+                },
+            );
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_AIE) && tcr2_enabled {
+            bitfield.set(S1TTWParamsBits::aie, tcr2.get(TCR2_ELx_REG::AIE));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_THE) && tcr2_enabled {
+            bitfield.set(S1TTWParamsBits::pnch, tcr2.get(TCR2_ELx_REG::PnCH));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_HAFT) && ha == 1 && tcr2_enabled {
+            bitfield.set(S1TTWParamsBits::haft, tcr2.get(TCR2_ELx_REG::HAFT));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_MEC) && IsSCTLR2EL2Enabled(pe) {
+            bitfield.set(
+                S1TTWParamsBits::emec,
+                pe.regs.SCTLR2_EL2.get(SCTLR2_ELx_REG::EMEC),
+            );
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_MEC)
+            && ss == SecurityState::SS_Realm
+            && tcr2_enabled
+        {
+            bitfield.set(S1TTWParamsBits::amec, amec);
+        }
+
+        S1TTWParams {
+            bitfield,
+            mair: pe.regs.MAIR_EL2.bits(),
+            mair2: if IsFeatureImplemented(pe, Feature::FEAT_AIE) {
+                pe.regs.MAIR2_EL2.bits()
+            } else {
+                0
+            },
+            pir: if IsFeatureImplemented(pe, Feature::FEAT_S1PIE) {
+                pe.regs.PIR_EL2.bits()
+            } else {
+                0
+            },
+            pire0: if IsFeatureImplemented(pe, Feature::FEAT_S1PIE) {
+                pe.regs.PIRE0_EL2.bits()
+            } else {
+                0
+            },
+            tgx,
+        }
+    }
+
+    /// AArch64.S1TTWParamsEL3()
+    /// ========================
+    /// Gather stage 1 translation table walk parameters for EL3 regime
+    pub fn AArch64S1TTWParamsEL3(pe: &ProcessorElement) -> S1TTWParams {
+        let tcr = pe.regs.TCR_EL3;
+        let sctlr = pe.regs.SCTLR_EL3;
+        let tgx = AArch64S1DecodeTG0(pe, tcr.get(TCR_EL3_REG::TG0));
+        let mut bitfield = S1TTWParamsBits::new();
+
+        bitfield.set(S1TTWParamsBits::txsz, tcr.get(TCR_EL3_REG::T0SZ));
+        bitfield.set(S1TTWParamsBits::ps, tcr.get(TCR_EL3_REG::PS));
+        bitfield.set(S1TTWParamsBits::irgn, tcr.get(TCR_EL3_REG::IRGN0));
+        bitfield.set(S1TTWParamsBits::orgn, tcr.get(TCR_EL3_REG::ORGN0));
+        bitfield.set(S1TTWParamsBits::sh, tcr.get(TCR_EL3_REG::SH0));
+        bitfield.set(S1TTWParamsBits::tbi, tcr.get(TCR_EL3_REG::TBI));
+        bitfield.set(S1TTWParamsBits::wxn, sctlr.get(SCTLR_ELx_REG::WXN));
+        bitfield.set(S1TTWParamsBits::ee, sctlr.get(SCTLR_ELx_REG::EE));
+        bitfield.set(
+            S1TTWParamsBits::sif,
+            if !IsFeatureImplemented(pe, Feature::FEAT_RME)
+                || IsFeatureImplemented(pe, Feature::FEAT_SEL2)
+            {
+                pe.regs.SCR_EL3.get(SCR_EL3_REG::SIF)
+            } else {
+                0
+            },
+        );
+        bitfield.set(S1TTWParamsBits::ntlsmd, 1);
+
+        let d128 = if IsFeatureImplemented(pe, Feature::FEAT_D128) {
+            tcr.get(TCR_EL3_REG::D128)
+        } else {
+            0
+        };
+        bitfield.set(S1TTWParamsBits::d128, d128);
+        if d128 == 1 {
+            bitfield.set(
+                S1TTWParamsBits::skl,
+                pe.regs.TTBR0_EL3.get(TTBR_ELx_REG::SKL) as u64,
+            );
+            bitfield.set(S1TTWParamsBits::disch, tcr.get(TCR_EL3_REG::DisCH0));
+        }
+        let pie = if d128 == 1 {
+            1
+        } else if IsFeatureImplemented(pe, Feature::FEAT_S1PIE) {
+            tcr.get(TCR_EL3_REG::PIE)
+        } else {
+            0
+        };
+        bitfield.set(S1TTWParamsBits::pie, pie);
+        if IsFeatureImplemented(pe, Feature::FEAT_AIE) {
+            bitfield.set(S1TTWParamsBits::aie, tcr.get(TCR_EL3_REG::AIE));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_PAuth) {
+            bitfield.set(S1TTWParamsBits::tbid, tcr.get(TCR_EL3_REG::TBID));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_HPDS) {
+            bitfield.set(S1TTWParamsBits::hpd, tcr.get(TCR_EL3_REG::HPD));
+        }
+        let ha = if HaveAccessFlagUpdateExt(pe) {
+            tcr.get(TCR_EL3_REG::HA)
+        } else {
+            0
+        };
+        bitfield.set(S1TTWParamsBits::ha, ha);
+        if ha == 1 && HaveDirtyBitModifierExt(pe) {
+            bitfield.set(S1TTWParamsBits::hd, tcr.get(TCR_EL3_REG::HD));
+        }
+        if matches!(tgx, TGx::TGx_4KB | TGx::TGx_16KB)
+            && IsFeatureImplemented(pe, Feature::FEAT_LPA2)
+        {
+            bitfield.set(S1TTWParamsBits::ds, tcr.get(TCR_EL3_REG::DS));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_MTE4) {
+            bitfield.set(S1TTWParamsBits::mtx, tcr.get(TCR_EL3_REG::MTX));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_THE) {
+            bitfield.set(S1TTWParamsBits::pnch, tcr.get(TCR_EL3_REG::PnCH));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_HAFT) && ha == 1 {
+            bitfield.set(S1TTWParamsBits::haft, tcr.get(TCR_EL3_REG::HAFT));
+        }
+        if IsFeatureImplemented(pe, Feature::FEAT_MEC) {
+            bitfield.set(
+                S1TTWParamsBits::emec,
+                pe.regs.SCTLR2_EL3.get(SCTLR2_ELx_REG::EMEC),
+            );
+        }
+
+        S1TTWParams {
+            bitfield,
+            mair: pe.regs.MAIR_EL3.bits(),
+            mair2: if IsFeatureImplemented(pe, Feature::FEAT_AIE) {
+                pe.regs.MAIR2_EL3.bits()
+            } else {
+                0
+            },
+            pir: if IsFeatureImplemented(pe, Feature::FEAT_S1PIE) {
+                pe.regs.PIR_EL3.bits()
+            } else {
+                0
+            },
+            pire0: 0,
+            tgx,
+        }
+    }
+
+    /// SCR_EL3.SIF as seen by stage 1 walks below EL3.
+    fn AArch64S1SIF(pe: &ProcessorElement) -> u64 {
+        if HaveEL(pe, EL3)
+            && (!IsFeatureImplemented(pe, Feature::FEAT_RME)
+                || IsFeatureImplemented(pe, Feature::FEAT_SEL2))
+        {
+            pe.regs.SCR_EL3.get(SCR_EL3_REG::SIF)
+        } else {
+            0
+        }
+    }
+
+    /// AArch64.GetS2TTWParams()
+    /// ========================
+    /// Gather walk parameters for stage 2 translation
+    pub fn AArch64GetS2TTWParams(
+        pe: &ProcessorElement,
+        ss: SecurityState,
+        ipaspace: PASpace,
+        s1aarch64: bool,
+    ) -> Result<S2TTWParams, TranslationError> {
+        match ss {
+            SecurityState::SS_NonSecure => Ok(AArch64NSS2TTWParams(pe, s1aarch64)),
+            SecurityState::SS_Secure if IsFeatureImplemented(pe, Feature::FEAT_SEL2) => {
+                Ok(AArch64SS2TTWParams(pe, ipaspace, s1aarch64))
+            }
+            SecurityState::SS_Realm => Ok(AArch64RLS2TTWParams(pe, s1aarch64)),
+            _ => Err(TranslationError::Unpredictable(
+                "stage 2 translation in a Security state without stage 2",
+            )),
+        }
+    }
+
+    /// AArch64.NSS2TTWParams()
+    /// =======================
+    /// Gather walk parameters specific for Non-secure stage 2 translation
+    pub fn AArch64NSS2TTWParams(pe: &ProcessorElement, _s1aarch64: bool) -> S2TTWParams {
+        let tgx = AArch64S2DecodeTG0(pe, pe.regs.VTCR_EL2.get(VTCR_EL2_REG::TG0));
+        let d128 = if IsFeatureImplemented(pe, Feature::FEAT_D128) {
+            pe.regs.VTCR_EL2.get(VTCR_EL2_REG::D128)
+        } else {
+            0
+        };
+        let ha = if HaveAccessFlagUpdateExt(pe) {
+            pe.regs.VTCR_EL2.get(VTCR_EL2_REG::HA)
+        } else {
+            0
+        };
+        let hd = if ha == 1 && HaveDirtyBitModifierExt(pe) {
+            pe.regs.VTCR_EL2.get(VTCR_EL2_REG::HD)
+        } else {
+            0
+        };
+        let ds = if matches!(tgx, TGx::TGx_4KB | TGx::TGx_16KB)
+            && IsFeatureImplemented(pe, Feature::FEAT_LPA2)
+        {
+            pe.regs.VTCR_EL2.get(VTCR_EL2_REG::DS)
+        } else {
+            0
+        };
+        let sl2 = if tgx == TGx::TGx_4KB && IsFeatureImplemented(pe, Feature::FEAT_LPA2) {
+            pe.regs.VTCR_EL2.get(VTCR_EL2_REG::SL2) & pe.regs.VTCR_EL2.get(VTCR_EL2_REG::DS)
+        } else {
+            0
+        };
+        let s2pie = if d128 == 1 {
+            1
+        } else if IsFeatureImplemented(pe, Feature::FEAT_S2PIE) {
+            pe.regs.VTCR_EL2.get(VTCR_EL2_REG::S2PIE)
+        } else {
+            0
+        };
+        let the = IsFeatureImplemented(pe, Feature::FEAT_THE);
+
+        let mut bitfield = S2TTWParamsBits::new()
+            .with(
+                S2TTWParamsBits::vm,
+                pe.regs.HCR_EL2.get(HCR_EL2_REG::VM) | pe.regs.HCR_EL2.get(HCR_EL2_REG::DC),
+            )
+            .with(
+                S2TTWParamsBits::txsz,
+                pe.regs.VTCR_EL2.get(VTCR_EL2_REG::T0SZ),
+            )
+            .with(S2TTWParamsBits::ps, pe.regs.VTCR_EL2.get(VTCR_EL2_REG::PS))
+            .with(
+                S2TTWParamsBits::irgn,
+                pe.regs.VTCR_EL2.get(VTCR_EL2_REG::IRGN0),
+            )
+            .with(
+                S2TTWParamsBits::orgn,
+                pe.regs.VTCR_EL2.get(VTCR_EL2_REG::ORGN0),
+            )
+            .with(S2TTWParamsBits::sh, pe.regs.VTCR_EL2.get(VTCR_EL2_REG::SH0))
+            .with(
+                S2TTWParamsBits::ee,
+                pe.regs.SCTLR_EL2.get(SCTLR_ELx_REG::EE),
+            )
+            .with(S2TTWParamsBits::d128, d128)
+            .with(
+                S2TTWParamsBits::ptw,
+                if pe.regs.HCR_EL2.get(HCR_EL2_REG::TGE) == 0 {
+                    pe.regs.HCR_EL2.get(HCR_EL2_REG::PTW)
+                } else {
+                    0
+                },
+            )
+            .with(
+                S2TTWParamsBits::fwb,
+                if IsFeatureImplemented(pe, Feature::FEAT_S2FWB) {
+                    pe.regs.HCR_EL2.get(HCR_EL2_REG::FWB)
+                } else {
+                    0
+                },
+            )
+            .with(S2TTWParamsBits::ha, ha)
+            .with(S2TTWParamsBits::hd, hd)
+            .with(S2TTWParamsBits::ds, ds)
+            .with(S2TTWParamsBits::sl2, sl2)
+            .with(
+                S2TTWParamsBits::cmow,
+                if IsFeatureImplemented(pe, Feature::FEAT_CMOW) && IsHCRXEL2Enabled(pe) {
+                    pe.regs.HCRX_EL2.get(HCRX_EL2_REG::CMOW)
+                } else {
+                    0
+                },
+            )
+            .with(S2TTWParamsBits::s2pie, s2pie)
+            .with(
+                S2TTWParamsBits::assuredonly,
+                if the {
+                    pe.regs.VTCR_EL2.get(VTCR_EL2_REG::AssuredOnly)
+                } else {
+                    0
+                },
+            )
+            .with(
+                S2TTWParamsBits::tl0,
+                if the {
+                    pe.regs.VTCR_EL2.get(VTCR_EL2_REG::TL0)
+                } else {
+                    0
+                },
+            )
+            .with(
+                S2TTWParamsBits::tl1,
+                if the {
+                    pe.regs.VTCR_EL2.get(VTCR_EL2_REG::TL1)
+                } else {
+                    0
+                },
+            )
+            .with(
+                S2TTWParamsBits::haft,
+                if IsFeatureImplemented(pe, Feature::FEAT_HAFT) && ha == 1 {
+                    pe.regs.VTCR_EL2.get(VTCR_EL2_REG::HAFT)
+                } else {
+                    0
+                },
+            )
+            .with(
+                S2TTWParamsBits::hdbss,
+                if IsFeatureImplemented(pe, Feature::FEAT_HDBSS) && ha == 1 && hd == 1 {
+                    pe.regs.VTCR_EL2.get(VTCR_EL2_REG::HDBSS)
+                } else {
+                    0
+                },
+            );
+        if d128 == 1 {
+            bitfield.set(
+                S2TTWParamsBits::skl,
+                pe.regs.VTCR_EL2.get(VTCR_EL2_REG::SL0),
+            );
+        } else {
+            bitfield.set(
+                S2TTWParamsBits::sl0,
+                pe.regs.VTCR_EL2.get(VTCR_EL2_REG::SL0),
+            );
+        }
+
+        S2TTWParams {
+            bitfield,
+            s2pir: if IsFeatureImplemented(pe, Feature::FEAT_S2PIE) {
+                pe.regs.S2PIR_EL2
+            } else {
+                0
+            },
+            tgx,
+        }
+    }
+
+    /// AArch64.SS2TTWParams()
+    /// ======================
+    /// Gather walk parameters specific for secure stage 2 translation
+    pub fn AArch64SS2TTWParams(
+        pe: &ProcessorElement,
+        ipaspace: PASpace,
+        s1aarch64: bool,
+    ) -> S2TTWParams {
+        // Parameters not specific to the Secure IPA space are shared with the
+        // Non-secure stage 2 translation
+        let mut walkparams = AArch64NSS2TTWParams(pe, s1aarch64);
+
+        if ipaspace == PASpace::PAS_Secure {
+            let tgx = AArch64S2DecodeTG0(pe, pe.regs.VSTCR_EL2.get(VSTCR_EL2_REG::TG0));
+            let ds = if matches!(tgx, TGx::TGx_4KB | TGx::TGx_16KB)
+                && IsFeatureImplemented(pe, Feature::FEAT_LPA2)
+            {
+                pe.regs.VTCR_EL2.get(VTCR_EL2_REG::DS)
+            } else {
+                0
+            };
+            let sl2 = if tgx == TGx::TGx_4KB && IsFeatureImplemented(pe, Feature::FEAT_LPA2) {
+                pe.regs.VSTCR_EL2.get(VSTCR_EL2_REG::SL2) & pe.regs.VTCR_EL2.get(VTCR_EL2_REG::DS)
+            } else {
+                0
+            };
+            walkparams.tgx = tgx;
+            walkparams.bitfield.set(
+                S2TTWParamsBits::txsz,
+                pe.regs.VSTCR_EL2.get(VSTCR_EL2_REG::T0SZ),
+            );
+            if walkparams.get_d128() == 1 {
+                walkparams.bitfield.set(
+                    S2TTWParamsBits::skl,
+                    pe.regs.VSTCR_EL2.get(VSTCR_EL2_REG::SL0),
+                );
+            } else {
+                walkparams.bitfield.set(
+                    S2TTWParamsBits::sl0,
+                    pe.regs.VSTCR_EL2.get(VSTCR_EL2_REG::SL0),
+                );
+            }
+            walkparams.bitfield.set(S2TTWParamsBits::ds, ds);
+            walkparams.bitfield.set(S2TTWParamsBits::sl2, sl2);
+        }
+
+        walkparams.bitfield.set(
+            S2TTWParamsBits::sw,
+            pe.regs.VSTCR_EL2.get(VSTCR_EL2_REG::SW),
+        );
+        walkparams.bitfield.set(
+            S2TTWParamsBits::nsw,
+            pe.regs.VTCR_EL2.get(VTCR_EL2_REG::NSW),
+        );
+        walkparams.bitfield.set(
+            S2TTWParamsBits::sa,
+            pe.regs.VSTCR_EL2.get(VSTCR_EL2_REG::SA),
+        );
+        walkparams.bitfield.set(
+            S2TTWParamsBits::nsa,
+            pe.regs.VTCR_EL2.get(VTCR_EL2_REG::NSA),
+        );
+
+        walkparams
+    }
+
+    /// AArch64.RLS2TTWParams()
+    /// =======================
+    /// Gather walk parameters specific for Realm stage 2 translation
+    pub fn AArch64RLS2TTWParams(pe: &ProcessorElement, s1aarch64: bool) -> S2TTWParams {
+        // Realm stage 2 walk parameters are similar to Non-secure
+        let mut walkparams = AArch64NSS2TTWParams(pe, s1aarch64);
+        if IsFeatureImplemented(pe, Feature::FEAT_MEC) && IsSCTLR2EL2Enabled(pe) {
+            walkparams.bitfield.set(
+                S2TTWParamsBits::emec,
+                pe.regs.SCTLR2_EL2.get(SCTLR2_ELx_REG::EMEC),
+            );
+        }
+        walkparams
+    }
+
+    /// AArch64.HaveS1TG()
+    /// ==================
+    /// Determine whether the given translation granule is supported for stage 1
+    pub fn AArch64HaveS1TG(pe: &ProcessorElement, tgx: TGx) -> bool {
+        match tgx {
+            TGx::TGx_4KB => pe.impdef.s1_tgran4,
+            TGx::TGx_16KB => pe.impdef.s1_tgran16,
+            TGx::TGx_64KB => pe.impdef.s1_tgran64,
+        }
+    }
+
+    /// AArch64.HaveS2TG()
+    /// ==================
+    /// Determine whether the given translation granule is supported for stage 2
+    pub fn AArch64HaveS2TG(pe: &ProcessorElement, tgx: TGx) -> bool {
+        match tgx {
+            TGx::TGx_4KB => pe.impdef.s2_tgran4,
+            TGx::TGx_16KB => pe.impdef.s2_tgran16,
+            TGx::TGx_64KB => pe.impdef.s2_tgran64,
+        }
+    }
+
+    /// IMPLEMENTATION DEFINED granule used in place of an unsupported one: the
+    /// smallest supported granule.
+    fn SupportedTG(tgx: TGx, have: impl Fn(TGx) -> bool) -> TGx {
+        if have(tgx) {
+            return tgx;
+        }
+        [TGx::TGx_4KB, TGx::TGx_16KB, TGx::TGx_64KB]
+            .into_iter()
+            .find(|&tg| have(tg))
+            .unwrap_or(tgx)
+    }
+
+    /// AArch64.S1DecodeTG0()
+    /// =====================
+    /// Decode stage 1 granule size configuration bits TG0
+    pub fn AArch64S1DecodeTG0(pe: &ProcessorElement, tg0_in: u64) -> TGx {
+        // The reserved encoding 0b11 is IMPLEMENTATION DEFINED to behave as 4KB
+        let tgx = match tg0_in {
+            0b01 => TGx::TGx_64KB,
+            0b10 => TGx::TGx_16KB,
+            _ => TGx::TGx_4KB,
+        };
+        SupportedTG(tgx, |tg| AArch64HaveS1TG(pe, tg))
+    }
+
+    /// AArch64.S1DecodeTG1()
+    /// =====================
+    /// Decode stage 1 granule size configuration bits TG1
+    pub fn AArch64S1DecodeTG1(pe: &ProcessorElement, tg1_in: u64) -> TGx {
+        // The reserved encoding 0b00 is IMPLEMENTATION DEFINED to behave as 4KB
+        let tgx = match tg1_in {
+            0b01 => TGx::TGx_16KB,
+            0b11 => TGx::TGx_64KB,
+            _ => TGx::TGx_4KB,
+        };
+        SupportedTG(tgx, |tg| AArch64HaveS1TG(pe, tg))
+    }
+
+    /// AArch64.S2DecodeTG0()
+    /// =====================
+    /// Decode stage 2 granule size configuration bits TG0
+    pub fn AArch64S2DecodeTG0(pe: &ProcessorElement, tg0_in: u64) -> TGx {
+        // The reserved encoding 0b11 is IMPLEMENTATION DEFINED to behave as 4KB
+        let tgx = match tg0_in {
+            0b01 => TGx::TGx_64KB,
+            0b10 => TGx::TGx_16KB,
+            _ => TGx::TGx_4KB,
+        };
+        SupportedTG(tgx, |tg| AArch64HaveS2TG(pe, tg))
+    }
 
     /// AArch64.GetVARange()
     /// ====================
     /// Determines if the VA that is to be translated lies in LOWER or UPPER address range.
     pub fn AArch64GetVARange(va: u64) -> VARange {
-        //if va<55> == '0' {
-        if (va & (1 << 55)) == 0 {
+        // Bit 55 selects the range even when the top byte is ignored, or
+        // holds a Logical Address Tag or a pointer authentication code
+        if Bit(va as u128, 55) == 0 {
             return VARange::VARange_LOWER;
         }
         VARange::VARange_UPPER
@@ -668,3 +1758,262 @@ mod walkparams {
 }
 
 pub use walkparams::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sysregs::*;
+
+    #[test]
+    fn s1_ttw_params_per_regime() {
+        let mut pe = ProcessorElement::new()
+            .with_feature(Feature::FEAT_AA64EL2)
+            .unwrap()
+            .with_feature(Feature::FEAT_AA64EL3)
+            .unwrap();
+        pe.regs.TCR_EL1 = TCR_EL1_REG::new()
+            .with(TCR_EL1_REG::T0SZ, 25)
+            .with(TCR_EL1_REG::TG0, 0b10)
+            .with(TCR_EL1_REG::T1SZ, 30)
+            .with(TCR_EL1_REG::TG1, 0b11);
+        pe.regs.TCR_EL2 = TCR_EL2_REG::new()
+            .with(TCR_EL2_REG::T0SZ, 20)
+            .with(TCR_EL2_REG::TG0, 0b01);
+        pe.regs.TCR_EL3 = TCR_EL3_REG::new()
+            .with(TCR_EL3_REG::T0SZ, 16)
+            .with(TCR_EL3_REG::TG0, 0b00);
+        pe.regs.MAIR_EL1 = MAIR_ELx_REG::new().with(MAIR_ELx_REG::Attr0, 0x44);
+        pe.regs.MAIR_EL2 = MAIR_ELx_REG::new().with(MAIR_ELx_REG::Attr0, 0xff);
+        pe.regs.MAIR_EL3 = MAIR_ELx_REG::new().with(MAIR_ELx_REG::Attr0, 0x04);
+        let ns = SecurityState::SS_NonSecure;
+        let upper = 0xffff_ffff_f000_0000;
+
+        let params = |pe: &ProcessorElement, regime, va| {
+            let walkparams = AArch64GetS1TTWParams(pe, regime, ns, va).unwrap();
+            (walkparams.get_txsz(), walkparams.get_tgx(), walkparams.mair)
+        };
+        assert_eq!(
+            params(&pe, Regime::Regime_EL10, 0),
+            (25, TGx::TGx_16KB, 0x44)
+        );
+        assert_eq!(
+            params(&pe, Regime::Regime_EL10, upper),
+            (30, TGx::TGx_64KB, 0x44)
+        );
+        assert_eq!(
+            params(&pe, Regime::Regime_EL2, 0),
+            (20, TGx::TGx_64KB, 0xff)
+        );
+        assert_eq!(params(&pe, Regime::Regime_EL3, 0), (16, TGx::TGx_4KB, 0x04));
+
+        // With HCR_EL2.E2H set, TCR_EL2 has the layout of TCR_EL1 and holds both VA ranges
+        pe.regs.HCR_EL2 = pe.regs.HCR_EL2.with(HCR_EL2_REG::E2H, 1);
+        pe.regs.TCR_EL2 = TCR_EL2_REG::from_bits(
+            TCR_EL1_REG::new()
+                .with(TCR_EL1_REG::T0SZ, 24)
+                .with(TCR_EL1_REG::TG0, 0b00)
+                .with(TCR_EL1_REG::T1SZ, 28)
+                .with(TCR_EL1_REG::TG1, 0b01)
+                .bits(),
+        );
+        assert_eq!(
+            params(&pe, Regime::Regime_EL20, 0),
+            (24, TGx::TGx_4KB, 0xff)
+        );
+        assert_eq!(
+            params(&pe, Regime::Regime_EL20, upper),
+            (28, TGx::TGx_16KB, 0xff)
+        );
+    }
+
+    const TGX: [TGx; 3] = [TGx::TGx_4KB, TGx::TGx_16KB, TGx::TGx_64KB];
+
+    /// Bits of the input address mapped through by a block or page at levels 0 to 3.
+    fn translation_size(d128: u64, tgx: TGx) -> [u64; 4] {
+        match (d128 == 1, tgx) {
+            (false, TGx::TGx_4KB) => [39, 30, 21, 12],
+            (false, TGx::TGx_16KB) => [47, 36, 25, 14],
+            (false, TGx::TGx_64KB) => [55, 42, 29, 16],
+            (true, TGx::TGx_4KB) => [36, 28, 20, 12],
+            (true, TGx::TGx_16KB) => [44, 34, 24, 14],
+            (true, TGx::TGx_64KB) => [52, 40, 28, 16],
+        }
+    }
+
+    /// Log2 of the number of entries in a contiguous range, for the levels that have one.
+    fn contiguous_size(d128: u64, tgx: TGx, level: i64) -> Option<u64> {
+        match (d128, tgx, level) {
+            (0, TGx::TGx_4KB, 1..=3) => Some(4),
+            (0, TGx::TGx_16KB, 2) => Some(5),
+            (0, TGx::TGx_16KB, 3) => Some(7),
+            (0, TGx::TGx_64KB, 2..=3) => Some(5),
+            (1, TGx::TGx_4KB, 1) => Some(2),
+            (1, TGx::TGx_4KB, 2..=3) => Some(4),
+            (1, TGx::TGx_16KB, 1) => Some(2),
+            (1, TGx::TGx_16KB, 2) => Some(4),
+            (1, TGx::TGx_16KB, 3) => Some(6),
+            (1, TGx::TGx_64KB, 2) => Some(6),
+            (1, TGx::TGx_64KB, 3) => Some(4),
+            _ => None,
+        }
+    }
+
+    fn leaf(paspace: PASpace, address: u64, level: i64, contiguous: bool) -> TTWState {
+        TTWState {
+            level,
+            baseaddress: FullAddress { paspace, address },
+            contiguous,
+            ..TTWState::UNKNOWN
+        }
+    }
+
+    #[test]
+    fn test_translation_size() {
+        for d128 in [0, 1] {
+            for tgx in TGX {
+                for level in 0..=FINAL_LEVEL {
+                    assert_eq!(
+                        TranslationSize(d128, tgx, level),
+                        translation_size(d128, tgx)[level as usize],
+                        "d128 = {}, {:?}, level {}",
+                        d128,
+                        tgx,
+                        level
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_contiguous_size() {
+        for d128 in [0, 1] {
+            for tgx in TGX {
+                for level in 0..=FINAL_LEVEL {
+                    if let Some(csize) = contiguous_size(d128, tgx, level) {
+                        assert_eq!(ContiguousSize(d128, tgx, level), csize);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_stage_oa_splice() {
+        let base = 0x00ff_ffff_ffff_ffff;
+        let ia = 0x5555_5555_5555_5555;
+        for d128 in [0, 1] {
+            for tgx in TGX {
+                for level in 0..=FINAL_LEVEL {
+                    let tsize = translation_size(d128, tgx)[level as usize];
+                    let mut cases = vec![(false, tsize)];
+                    if let Some(csize) = contiguous_size(d128, tgx, level) {
+                        cases.push((true, tsize + csize));
+                    }
+                    for (contiguous, ia_msb) in cases {
+                        let mask = (1 << ia_msb) - 1;
+                        let walkstate = leaf(PASpace::PAS_Realm, base, level, contiguous);
+                        let oa = StageOA(ia, d128, tgx, walkstate);
+                        assert_eq!(oa.paspace, PASpace::PAS_Realm);
+                        assert_eq!(
+                            oa.address,
+                            (base & !mask) | (ia & mask),
+                            "d128 = {}, {:?}, level {}, contiguous = {}",
+                            d128,
+                            tgx,
+                            level,
+                            contiguous
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_stage_oa_examples() {
+        let ns = PASpace::PAS_NonSecure;
+        let va = 0xffff_0000_1234_5678;
+
+        // 4KB page
+        let oa = StageOA(va, 0, TGx::TGx_4KB, leaf(ns, 0x8000_0000, 3, false));
+        assert_eq!(oa.address, 0x8000_0678);
+        // 4KB granule, 2MB block
+        let oa = StageOA(va, 0, TGx::TGx_4KB, leaf(ns, 0x8000_0000, 2, false));
+        assert_eq!(oa.address, 0x8014_5678);
+        // 4KB granule, 1GB block
+        let oa = StageOA(va, 0, TGx::TGx_4KB, leaf(ns, 0x8000_0000, 1, false));
+        assert_eq!(oa.address, 0x9234_5678);
+        // 4KB granule, 16 contiguous 4KB pages
+        let oa = StageOA(va, 0, TGx::TGx_4KB, leaf(ns, 0x8000_0000, 3, true));
+        assert_eq!(oa.address, 0x8000_5678);
+        // 16KB granule, 32MB block
+        let oa = StageOA(va, 0, TGx::TGx_16KB, leaf(ns, 0x8000_0000, 2, false));
+        assert_eq!(oa.address, 0x8034_5678);
+        // 16KB granule, 128 contiguous 16KB pages
+        let oa = StageOA(va, 0, TGx::TGx_16KB, leaf(ns, 0x8000_0000, 3, true));
+        assert_eq!(oa.address, 0x8014_5678);
+        // 64KB granule, 512MB block
+        let oa = StageOA(va, 0, TGx::TGx_64KB, leaf(ns, 0x8000_0000, 2, false));
+        assert_eq!(oa.address, 0x9234_5678);
+        // 64KB granule with 128-bit descriptors, 256MB block
+        let oa = StageOA(va, 1, TGx::TGx_64KB, leaf(ns, 0x8000_0000, 2, false));
+        assert_eq!(oa.address, 0x8234_5678);
+    }
+
+    /// A backend that returns `len(size)` bytes when `size` bytes are requested.
+    struct WrongSizeMemory {
+        len: fn(usize) -> usize,
+    }
+
+    impl PhysicalMemory for WrongSizeMemory {
+        fn read(
+            &self,
+            _desc: AddressDescriptor,
+            size: usize,
+            accdesc: AccessDescriptor,
+        ) -> (PhysMemRetStatus, Vec<u8>) {
+            (
+                PhysMemRetStatus::NoFault(accdesc.acctype),
+                vec![0xff; (self.len)(size)],
+            )
+        }
+
+        fn write(
+            &mut self,
+            _desc: AddressDescriptor,
+            _size: usize,
+            accdesc: AccessDescriptor,
+            _value: &[u8],
+        ) -> PhysMemRetStatus {
+            PhysMemRetStatus::NoFault(accdesc.acctype)
+        }
+    }
+
+    #[test]
+    fn wrong_size_descriptor_read_aborts() {
+        let pe = ProcessorElement::from_profile(ArchVersion::Armv8_0, &[]).unwrap();
+        let accdesc = CreateAccDescGPR(&pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        let walkaccess = CreateAccDescS1TTW(&pe, true, VARange::VARange_LOWER, accdesc).unwrap();
+        let short = WrongSizeMemory {
+            len: |size| size / 2,
+        };
+        let long = WrongSizeMemory {
+            len: |size| size * 2,
+        };
+        for (mem, N) in [(&short, 64), (&short, 128), (&long, 64), (&long, 128)] {
+            let (fault, descriptor) = FetchDescriptor(
+                &pe,
+                mem,
+                0,
+                AddressDescriptor::UNKNOWN,
+                walkaccess,
+                FaultRecord::NoFaultForAccess(walkaccess),
+                N,
+            )
+            .unwrap();
+            assert_eq!(fault.statuscode, Fault::Fault_SyncExternalOnWalk);
+            assert_eq!(descriptor, 0);
+        }
+    }
+}
@@ -0,0 +1,695 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+#![allow(non_camel_case_types)]
+
+//! System register file of a processing element.
+
+mycelium_bitfield::bitfield! {
+    /// Secure Configuration Register
+    pub struct SCR_EL3_REG<u64> {
+        pub const NS = 1;
+        pub const IRQ = 1;
+        pub const FIQ = 1;
+        pub const EA = 1;
+        const _RES1 = 2;
+        const _RES0_0 = 1;
+        pub const SMD = 1;
+        pub const HCE = 1;
+        pub const SIF = 1;
+        pub const RW = 1;
+        pub const ST = 1;
+        pub const TWI = 1;
+        pub const TWE = 1;
+        pub const TLOR = 1;
+        pub const TERR = 1;
+        pub const APK = 1;
+        pub const API = 1;
+        pub const EEL2 = 1;
+        pub const EASE = 1;
+        pub const NMEA = 1;
+        pub const FIEN = 1;
+        pub const TID3 = 1;
+        pub const TID5 = 1;
+        pub const POE2En = 1;
+        pub const EnSCXT = 1;
+        pub const ATA = 1;
+        pub const FGTEn = 1;
+        pub const ECVEn = 1;
+        pub const TWEDEn = 1;
+        pub const TWEDEL = 4;
+        pub const TME = 1;
+        pub const AMVOFFEN = 1;
+        pub const EnAS0 = 1;
+        pub const ADEn = 1;
+        pub const HXEn = 1;
+        pub const GCSEn = 1;
+        pub const TRNDR = 1;
+        pub const EnTP2 = 1;
+        pub const RCWMASKEn = 1;
+        pub const TCR2En = 1;
+        pub const SCTLR2En = 1;
+        pub const PIEn = 1;
+        pub const AIEn = 1;
+        pub const D128En = 1;
+        pub const GPF = 1;
+        pub const MECEn = 1;
+        pub const EnFPM = 1;
+        pub const TMEA = 1;
+        pub const TWERR = 1;
+        pub const PFAREn = 1;
+        pub const SRMASKEn = 1;
+        const _RES0_1 = 7;
+        pub const NSE = 1;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Secure Configuration Register (AArch32)
+    ///
+    /// NSE, EEL2 and GPF only exist in the AArch64 `SCR_EL3`.
+    pub struct SCR_REG<u32> {
+        pub const NS = 1;
+        pub const IRQ = 1;
+        pub const FIQ = 1;
+        pub const EA = 1;
+        pub const FW = 1;
+        pub const AW = 1;
+        pub const nET = 1;
+        pub const SCD = 1;
+        pub const HCE = 1;
+        pub const SIF = 1;
+        const _RES0_0 = 2;
+        pub const TWI = 1;
+        pub const TWE = 1;
+        const _RES0_1 = 1;
+        pub const TERR = 1;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Hypervisor Configuration Register
+    pub struct HCR_EL2_REG<u64> {
+        pub const VM = 1;
+        pub const SWIO = 1;
+        pub const PTW = 1;
+        pub const FMO = 1;
+        pub const IMO = 1;
+        pub const AMO = 1;
+        pub const VF = 1;
+        pub const VI = 1;
+        pub const VSE = 1;
+        pub const FB = 1;
+        pub const BSU = 2;
+        pub const DC = 1;
+        pub const TWI = 1;
+        pub const TWE = 1;
+        pub const TID0 = 1;
+        pub const TID1 = 1;
+        pub const TID2 = 1;
+        pub const TID3 = 1;
+        pub const TSC = 1;
+        pub const TIDCP = 1;
+        pub const TACR = 1;
+        pub const TSW = 1;
+        pub const TPCP = 1;
+        pub const TPU = 1;
+        pub const TTLB = 1;
+        pub const TVM = 1;
+        pub const TGE = 1;
+        pub const TDZ = 1;
+        pub const HCD = 1;
+        pub const TRVM = 1;
+        pub const RW = 1;
+        pub const CD = 1;
+        pub const ID = 1;
+        pub const E2H = 1;
+        pub const TLOR = 1;
+        pub const TERR = 1;
+        pub const TEA = 1;
+        pub const MIOCNCE = 1;
+        pub const TME = 1;
+        pub const APK = 1;
+        pub const API = 1;
+        pub const NV = 1;
+        pub const NV1 = 1;
+        pub const AT = 1;
+        pub const NV2 = 1;
+        pub const FWB = 1;
+        pub const FIEN = 1;
+        pub const GPF = 1;
+        pub const TID4 = 1;
+        pub const TICAB = 1;
+        pub const AMVOFFEN = 1;
+        pub const TOCU = 1;
+        pub const EnSCXT = 1;
+        pub const TTLBIS = 1;
+        pub const TTLBOS = 1;
+        pub const ATA = 1;
+        pub const DCT = 1;
+        pub const TID5 = 1;
+        pub const TWEDEn = 1;
+        pub const TWEDEL = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Virtualization Translation Control Register
+    pub struct VTCR_EL2_REG<u64> {
+        pub const T0SZ = 6;
+        pub const SL0 = 2;
+        pub const IRGN0 = 2;
+        pub const ORGN0 = 2;
+        pub const SH0 = 2;
+        pub const TG0 = 2;
+        pub const PS = 3;
+        pub const VS = 1;
+        const _RES0_0 = 1;
+        pub const HA = 1;
+        pub const HD = 1;
+        const _RES0_1 = 2;
+        pub const HWU59 = 1;
+        pub const HWU60 = 1;
+        pub const HWU61 = 1;
+        pub const HWU62 = 1;
+        pub const NSW = 1;
+        pub const NSA = 1;
+        const _RES1 = 1;
+        pub const DS = 1;
+        pub const SL2 = 1;
+        pub const AssuredOnly = 1;
+        pub const TL1 = 1;
+        pub const S2PIE = 1;
+        pub const S2POE = 1;
+        pub const D128 = 1;
+        const _RES0_2 = 1;
+        pub const GCSH = 1;
+        pub const TL0 = 1;
+        const _RES0_3 = 2;
+        pub const HAFT = 1;
+        pub const HDBSS = 1;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Virtualization Secure Translation Control Register
+    pub struct VSTCR_EL2_REG<u64> {
+        pub const T0SZ = 6;
+        pub const SL0 = 2;
+        const _RES0_0 = 6;
+        pub const TG0 = 2;
+        const _RES0_1 = 13;
+        pub const SW = 1;
+        pub const SA = 1;
+        const _RES0_2 = 2;
+        pub const SL2 = 1;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Extended Hypervisor Configuration Register
+    pub struct HCRX_EL2_REG<u64> {
+        pub const EnAS0 = 1;
+        pub const EnALS = 1;
+        pub const EnASR = 1;
+        pub const FnXS = 1;
+        pub const FGTnXS = 1;
+        pub const SMPME = 1;
+        pub const TALLINT = 1;
+        pub const VINMI = 1;
+        pub const VFNMI = 1;
+        pub const CMOW = 1;
+        pub const MCE2 = 1;
+        pub const MSCEn = 1;
+        const _RES0_0 = 2;
+        pub const TCR2En = 1;
+        pub const SCTLR2En = 1;
+        pub const PTTWI = 1;
+        pub const D128En = 1;
+        pub const EnSNERR = 1;
+        pub const TMEA = 1;
+        pub const EnSDERR = 1;
+        pub const EnIDCP128 = 1;
+        pub const GCSEn = 1;
+        pub const EnFPM = 1;
+        pub const PACMEn = 1;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// System Control Register (EL1, EL2 and EL3)
+    ///
+    /// Fields that only exist at some Exception levels are RES0 or RES1 at the
+    /// others.
+    pub struct SCTLR_ELx_REG<u64> {
+        pub const M = 1;
+        pub const A = 1;
+        pub const C = 1;
+        pub const SA = 1;
+        pub const SA0 = 1;
+        pub const CP15BEN = 1;
+        pub const nAA = 1;
+        pub const ITD = 1;
+        pub const SED = 1;
+        pub const UMA = 1;
+        pub const EnRCTX = 1;
+        pub const EOS = 1;
+        pub const I = 1;
+        pub const EnDB = 1;
+        pub const DZE = 1;
+        pub const UCT = 1;
+        pub const nTWI = 1;
+        const _RES0_0 = 1;
+        pub const nTWE = 1;
+        pub const WXN = 1;
+        pub const TSCXT = 1;
+        pub const IESB = 1;
+        pub const EIS = 1;
+        pub const SPAN = 1;
+        pub const E0E = 1;
+        pub const EE = 1;
+        pub const UCI = 1;
+        pub const EnDA = 1;
+        pub const nTLSMD = 1;
+        pub const LSMAOE = 1;
+        pub const EnIB = 1;
+        pub const EnIA = 1;
+        pub const CMOW = 1;
+        pub const MSCEn = 1;
+        const _RES0_1 = 1;
+        pub const BT0 = 1;
+        pub const BT = 1;
+        pub const ITFSB = 1;
+        pub const TCF0 = 2;
+        pub const TCF = 2;
+        pub const ATA0 = 1;
+        pub const ATA = 1;
+        pub const DSSBS = 1;
+        pub const TWEDEn = 1;
+        pub const TWEDEL = 4;
+        pub const TMT0 = 1;
+        pub const TMT = 1;
+        pub const TME0 = 1;
+        pub const TME = 1;
+        pub const EnASR = 1;
+        pub const EnAS0 = 1;
+        pub const EnALS = 1;
+        pub const EPAN = 1;
+        pub const TCSO0 = 1;
+        pub const TCSO = 1;
+        pub const EnTP2 = 1;
+        pub const NMI = 1;
+        pub const SPINTMASK = 1;
+        pub const TIDCP = 1;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// System Control Register 2 (EL1, EL2 and EL3)
+    ///
+    /// Fields that are not defined at a given Exception level are RES0 there, e.g. EMEC in
+    /// SCTLR2_EL1 and the EL0 controls in SCTLR2_EL3.
+    pub struct SCTLR2_ELx_REG<u64> {
+        const _RES0_0 = 1;
+        pub const EMEC = 1;
+        pub const NMEA = 1;
+        pub const EnADERR = 1;
+        pub const EnANERR = 1;
+        pub const EASE = 1;
+        pub const EnIDCP128 = 1;
+        pub const EnPACM = 1;
+        pub const EnPACM0 = 1;
+        pub const CPTA = 1;
+        pub const CPTA0 = 1;
+        pub const CPTM = 1;
+        pub const CPTM0 = 1;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Translation Control Register (EL1)
+    ///
+    /// Also the layout of TCR_EL2 when HCR_EL2.E2H is 1.
+    pub struct TCR_EL1_REG<u64> {
+        pub const T0SZ = 6;
+        const _RES0_0 = 1;
+        pub const EPD0 = 1;
+        pub const IRGN0 = 2;
+        pub const ORGN0 = 2;
+        pub const SH0 = 2;
+        pub const TG0 = 2;
+        pub const T1SZ = 6;
+        pub const A1 = 1;
+        pub const EPD1 = 1;
+        pub const IRGN1 = 2;
+        pub const ORGN1 = 2;
+        pub const SH1 = 2;
+        pub const TG1 = 2;
+        pub const IPS = 3;
+        const _RES0_1 = 1;
+        pub const AS = 1;
+        pub const TBI0 = 1;
+        pub const TBI1 = 1;
+        pub const HA = 1;
+        pub const HD = 1;
+        pub const HPD0 = 1;
+        pub const HPD1 = 1;
+        pub const HWU059 = 1;
+        pub const HWU060 = 1;
+        pub const HWU061 = 1;
+        pub const HWU062 = 1;
+        pub const HWU159 = 1;
+        pub const HWU160 = 1;
+        pub const HWU161 = 1;
+        pub const HWU162 = 1;
+        pub const TBID0 = 1;
+        pub const TBID1 = 1;
+        pub const NFD0 = 1;
+        pub const NFD1 = 1;
+        pub const E0PD0 = 1;
+        pub const E0PD1 = 1;
+        pub const TCMA0 = 1;
+        pub const TCMA1 = 1;
+        pub const DS = 1;
+        pub const MTX0 = 1;
+        pub const MTX1 = 1;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Translation Control Register (EL2), when HCR_EL2.E2H is 0
+    pub struct TCR_EL2_REG<u64> {
+        pub const T0SZ = 6;
+        const _RES0_0 = 2;
+        pub const IRGN0 = 2;
+        pub const ORGN0 = 2;
+        pub const SH0 = 2;
+        pub const TG0 = 2;
+        pub const PS = 3;
+        const _RES0_1 = 1;
+        pub const TBI = 1;
+        pub const HA = 1;
+        pub const HD = 1;
+        const _RES1_0 = 1;
+        pub const HPD = 1;
+        pub const HWU59 = 1;
+        pub const HWU60 = 1;
+        pub const HWU61 = 1;
+        pub const HWU62 = 1;
+        pub const TBID = 1;
+        pub const TCMA = 1;
+        const _RES1_1 = 1;
+        pub const DS = 1;
+        pub const MTX = 1;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Translation Control Register (EL3)
+    pub struct TCR_EL3_REG<u64> {
+        pub const T0SZ = 6;
+        const _RES0_0 = 2;
+        pub const IRGN0 = 2;
+        pub const ORGN0 = 2;
+        pub const SH0 = 2;
+        pub const TG0 = 2;
+        pub const PS = 3;
+        const _RES0_1 = 1;
+        pub const TBI = 1;
+        pub const HA = 1;
+        pub const HD = 1;
+        const _RES1_0 = 1;
+        pub const HPD = 1;
+        pub const HWU59 = 1;
+        pub const HWU60 = 1;
+        pub const HWU61 = 1;
+        pub const HWU62 = 1;
+        pub const TBID = 1;
+        pub const TCMA = 1;
+        const _RES1_1 = 1;
+        pub const DS = 1;
+        pub const MTX = 1;
+        pub const PnCH = 1;
+        pub const PIE = 1;
+        pub const POE = 1;
+        pub const AIE = 1;
+        pub const D128 = 1;
+        const _RES0_2 = 4;
+        pub const DisCH0 = 1;
+        pub const HAFT = 1;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Extended Translation Control Register (EL1 and EL2)
+    ///
+    /// AMEC0 and AMEC1 are RES0 in TCR2_EL1.
+    pub struct TCR2_ELx_REG<u64> {
+        pub const PnCH = 1;
+        pub const PIE = 1;
+        pub const E0POE = 1;
+        pub const POE = 1;
+        pub const AIE = 1;
+        pub const D128 = 1;
+        const _RES0_0 = 4;
+        pub const PTTWI = 1;
+        pub const HAFT = 1;
+        pub const AMEC0 = 1;
+        pub const AMEC1 = 1;
+        pub const DisCH0 = 1;
+        pub const DisCH1 = 1;
+        pub const A2 = 1;
+        pub const FNG0 = 1;
+        pub const FNG1 = 1;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Memory Attribute Indirection Register (EL1, EL2 and EL3)
+    ///
+    /// Also the layout of MAIR2_ELx, whose Attr<n> is selected by AttrIndx 8 + n.
+    pub struct MAIR_ELx_REG<u64> {
+        pub const Attr0 = 8;
+        pub const Attr1 = 8;
+        pub const Attr2 = 8;
+        pub const Attr3 = 8;
+        pub const Attr4 = 8;
+        pub const Attr5 = 8;
+        pub const Attr6 = 8;
+        pub const Attr7 = 8;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Translation Table Base Register 0 and 1 (EL1, EL2 and EL3)
+    ///
+    /// This is the 128-bit layout used with FEAT_D128, where the table base is
+    /// BADDR_HI:BADDR:'00000'. With 64-bit descriptors, bits [47:1] of the
+    /// register are BADDR, including the bits of SKL.
+    pub struct TTBR_ELx_REG<u128> {
+        pub const CnP = 1;
+        pub const SKL = 2;
+        const _RES0_0 = 2;
+        pub const BADDR = 43;
+        pub const ASID = 16;
+        const _RES0_1 = 16;
+        pub const BADDR_HI = 8;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Permission Indirection Register (EL1, EL2 and EL3)
+    ///
+    /// Also the layout of PIRE0_EL1 and PIRE0_EL2.
+    pub struct PIR_ELx_REG<u64> {
+        pub const Perm0 = 4;
+        pub const Perm1 = 4;
+        pub const Perm2 = 4;
+        pub const Perm3 = 4;
+        pub const Perm4 = 4;
+        pub const Perm5 = 4;
+        pub const Perm6 = 4;
+        pub const Perm7 = 4;
+        pub const Perm8 = 4;
+        pub const Perm9 = 4;
+        pub const Perm10 = 4;
+        pub const Perm11 = 4;
+        pub const Perm12 = 4;
+        pub const Perm13 = 4;
+        pub const Perm14 = 4;
+        pub const Perm15 = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Permission Overlay Register (EL0, EL1, EL2 and EL3)
+    pub struct POR_ELx_REG<u64> {
+        pub const Perm0 = 4;
+        pub const Perm1 = 4;
+        pub const Perm2 = 4;
+        pub const Perm3 = 4;
+        pub const Perm4 = 4;
+        pub const Perm5 = 4;
+        pub const Perm6 = 4;
+        pub const Perm7 = 4;
+        pub const Perm8 = 4;
+        pub const Perm9 = 4;
+        pub const Perm10 = 4;
+        pub const Perm11 = 4;
+        pub const Perm12 = 4;
+        pub const Perm13 = 4;
+        pub const Perm14 = 4;
+        pub const Perm15 = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Granule Protection Check Control Register (EL3)
+    pub struct GPCCR_EL3_REG<u64> {
+        pub const PPS = 3;
+        const _RES0_0 = 5;
+        pub const IRGN = 2;
+        pub const ORGN = 2;
+        pub const SH = 2;
+        pub const PGS = 2;
+        pub const GPC = 1;
+        pub const GPCP = 1;
+        pub const TBGPCD = 1;
+        pub const NSO = 1;
+        pub const L0GPTSZ = 4;
+    }
+}
+
+mycelium_bitfield::bitfield! {
+    /// Memory Encryption Context ID Register
+    ///
+    /// Layout shared by MECID_{P0,A0,P1,A1}_EL2, VMECID_{P,A}_EL2 and MECID_RL_A_EL3.
+    pub struct MECID_ELx_REG<u64> {
+        pub const MECID = 16;
+    }
+}
+
+/// System registers consulted by the translation pseudocode.
+///
+/// Registers without named fields are kept as raw values. VTTBR_EL2 is 128
+/// bits wide so that it can hold FEAT_D128 table bases.
+#[derive(Copy, Clone, Debug)]
+pub struct SystemRegisters {
+    pub SCR_EL3: SCR_EL3_REG,
+    pub SCR: SCR_REG,
+    pub HCR_EL2: HCR_EL2_REG,
+    pub HCRX_EL2: HCRX_EL2_REG,
+    pub SCTLR_EL1: SCTLR_ELx_REG,
+    pub SCTLR_EL2: SCTLR_ELx_REG,
+    pub SCTLR_EL3: SCTLR_ELx_REG,
+    pub SCTLR2_EL1: SCTLR2_ELx_REG,
+    pub SCTLR2_EL2: SCTLR2_ELx_REG,
+    pub SCTLR2_EL3: SCTLR2_ELx_REG,
+    pub TCR_EL1: TCR_EL1_REG,
+    pub TCR_EL2: TCR_EL2_REG,
+    pub TCR_EL3: TCR_EL3_REG,
+    pub TCR2_EL1: TCR2_ELx_REG,
+    pub TCR2_EL2: TCR2_ELx_REG,
+    pub MAIR_EL1: MAIR_ELx_REG,
+    pub MAIR_EL2: MAIR_ELx_REG,
+    pub MAIR_EL3: MAIR_ELx_REG,
+    pub MAIR2_EL1: MAIR_ELx_REG,
+    pub MAIR2_EL2: MAIR_ELx_REG,
+    pub MAIR2_EL3: MAIR_ELx_REG,
+    pub TTBR0_EL1: TTBR_ELx_REG,
+    pub TTBR1_EL1: TTBR_ELx_REG,
+    pub TTBR0_EL2: TTBR_ELx_REG,
+    pub TTBR1_EL2: TTBR_ELx_REG,
+    pub TTBR0_EL3: TTBR_ELx_REG,
+    pub PIR_EL1: PIR_ELx_REG,
+    pub PIR_EL2: PIR_ELx_REG,
+    pub PIR_EL3: PIR_ELx_REG,
+    pub PIRE0_EL1: PIR_ELx_REG,
+    pub PIRE0_EL2: PIR_ELx_REG,
+    pub POR_EL0: POR_ELx_REG,
+    pub POR_EL1: POR_ELx_REG,
+    pub POR_EL2: POR_ELx_REG,
+    pub POR_EL3: POR_ELx_REG,
+    pub S2POR_EL1: u64,
+    pub S2PIR_EL2: u64,
+    pub VTCR_EL2: VTCR_EL2_REG,
+    pub VSTCR_EL2: VSTCR_EL2_REG,
+    pub VTTBR_EL2: u128,
+    pub VSTTBR_EL2: u64,
+    pub GPCCR_EL3: GPCCR_EL3_REG,
+    pub GPTBR_EL3: u64,
+    pub MECID_P0_EL2: MECID_ELx_REG,
+    pub MECID_A0_EL2: MECID_ELx_REG,
+    pub MECID_P1_EL2: MECID_ELx_REG,
+    pub MECID_A1_EL2: MECID_ELx_REG,
+    pub VMECID_P_EL2: MECID_ELx_REG,
+    pub VMECID_A_EL2: MECID_ELx_REG,
+    pub MECID_RL_A_EL3: MECID_ELx_REG,
+}
+
+impl SystemRegisters {
+    /// A register file with every register reset to zero.
+    pub const fn new() -> Self {
+        Self {
+            SCR_EL3: SCR_EL3_REG::new(),
+            SCR: SCR_REG::new(),
+            HCR_EL2: HCR_EL2_REG::new(),
+            HCRX_EL2: HCRX_EL2_REG::new(),
+            SCTLR_EL1: SCTLR_ELx_REG::new(),
+            SCTLR_EL2: SCTLR_ELx_REG::new(),
+            SCTLR_EL3: SCTLR_ELx_REG::new(),
+            SCTLR2_EL1: SCTLR2_ELx_REG::new(),
+            SCTLR2_EL2: SCTLR2_ELx_REG::new(),
+            SCTLR2_EL3: SCTLR2_ELx_REG::new(),
+            TCR_EL1: TCR_EL1_REG::new(),
+            TCR_EL2: TCR_EL2_REG::new(),
+            TCR_EL3: TCR_EL3_REG::new(),
+            TCR2_EL1: TCR2_ELx_REG::new(),
+            TCR2_EL2: TCR2_ELx_REG::new(),
+            MAIR_EL1: MAIR_ELx_REG::new(),
+            MAIR_EL2: MAIR_ELx_REG::new(),
+            MAIR_EL3: MAIR_ELx_REG::new(),
+            MAIR2_EL1: MAIR_ELx_REG::new(),
+            MAIR2_EL2: MAIR_ELx_REG::new(),
+            MAIR2_EL3: MAIR_ELx_REG::new(),
+            TTBR0_EL1: TTBR_ELx_REG::new(),
+            TTBR1_EL1: TTBR_ELx_REG::new(),
+            TTBR0_EL2: TTBR_ELx_REG::new(),
+            TTBR1_EL2: TTBR_ELx_REG::new(),
+            TTBR0_EL3: TTBR_ELx_REG::new(),
+            PIR_EL1: PIR_ELx_REG::new(),
+            PIR_EL2: PIR_ELx_REG::new(),
+            PIR_EL3: PIR_ELx_REG::new(),
+            PIRE0_EL1: PIR_ELx_REG::new(),
+            PIRE0_EL2: PIR_ELx_REG::new(),
+            POR_EL0: POR_ELx_REG::new(),
+            POR_EL1: POR_ELx_REG::new(),
+            POR_EL2: POR_ELx_REG::new(),
+            POR_EL3: POR_ELx_REG::new(),
+            S2POR_EL1: 0,
+            S2PIR_EL2: 0,
+            VTCR_EL2: VTCR_EL2_REG::new(),
+            VSTCR_EL2: VSTCR_EL2_REG::new(),
+            VTTBR_EL2: 0,
+            VSTTBR_EL2: 0,
+            GPCCR_EL3: GPCCR_EL3_REG::new(),
+            GPTBR_EL3: 0,
+            MECID_P0_EL2: MECID_ELx_REG::new(),
+            MECID_A0_EL2: MECID_ELx_REG::new(),
+            MECID_P1_EL2: MECID_ELx_REG::new(),
+            MECID_A1_EL2: MECID_ELx_REG::new(),
+            VMECID_P_EL2: MECID_ELx_REG::new(),
+            VMECID_A_EL2: MECID_ELx_REG::new(),
+            MECID_RL_A_EL3: MECID_ELx_REG::new(),
+        }
+    }
+
+    /// TCR_EL2 viewed with the layout it has when HCR_EL2.E2H is 1.
+    pub const fn TCR_EL2_E2H(&self) -> TCR_EL1_REG {
+        TCR_EL1_REG::from_bits(self.TCR_EL2.bits())
+    }
+}
+
+impl Default for SystemRegisters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -3,45 +3,88 @@
 
 use std::mem::MaybeUninit;
 
+use crate::error::*;
+use crate::pe::*;
 use crate::shared::*;
 use crate::shared_mec::*;
 use crate::shared_memory::*;
 use crate::shared_translation::*;
 use crate::shared_vmsa::*;
 use crate::stubs::*;
+use crate::sysregs::*;
+use crate::translation64_addrcalc::*;
+use crate::translation64_faults::*;
+use crate::translation64_gpc::*;
+use crate::translation64_walk::*;
+
+/// Library pseudocode for aarch64/translation/vmsa_translation/AArch64.TranslateAddress
+/// AArch64.TranslateAddress()
+/// ==========================
+/// Main entry point for translating an address
+///
+/// Debug exceptions (AArch64.CheckDebug) are not modelled.
+pub fn AArch64TranslateAddress(
+    pe: &ProcessorElement,
+    mem: &mut dyn PhysicalMemory,
+    va: u64,
+    accdesc: AccessDescriptor,
+    aligned: bool,
+) -> Result<AddressDescriptor, TranslationError> {
+    let mut result = AArch64FullTranslate(pe, mem, va, accdesc, aligned)?;
+
+    // IMPLEMENTATION DEFINED "GPC Fault on DC operations" is FALSE
+    if IsFeatureImplemented(pe, Feature::FEAT_RME)
+        && !IsFault(result.fault.statuscode)
+        && accdesc.acctype != AccessType::AccessType_DC
+    {
+        result.fault.gpcf = GranuleProtectionCheck(pe, mem, result, accdesc)?;
+        if result.fault.gpcf.gpf != GPCF::GPCF_None {
+            result.fault.statuscode = Fault::Fault_GPCFOnOutput;
+            result.fault.paddress = result.paddress;
+        }
+    }
+
+    // Update virtual address for abort functions
+    result.vaddress = va;
+    Ok(result)
+}
 
 /// Library pseudocode for aarch64/translation/vmsa_translation/AArch64FullTranslate
 ///
 /// Address translation as specified by VMSA
 /// Alignment check NOT due to memory type is expected to be done before translation
 pub fn AArch64FullTranslate(
+    pe: &ProcessorElement,
+    mem: &mut dyn PhysicalMemory,
     va: u64,
     accdesc: AccessDescriptor,
     aligned: bool,
-) -> AddressDescriptor {
-    let regime: Regime = TranslationRegime(accdesc.el);
+) -> Result<AddressDescriptor, TranslationError> {
+    let regime: Regime = TranslationRegime(pe, accdesc.el)?;
     let fault = FaultRecord::NoFaultForAccess(accdesc);
 
     let _ipa: AddressDescriptor;
-    let (fault, ipa) = AArch64S1Translate(fault, regime, va, aligned, accdesc);
+    let (fault, ipa) = AArch64S1Translate(pe, mem, fault, regime, va, aligned, accdesc)?;
 
     if !matches!(fault.statuscode, Fault::Fault_None) {
-        return CreateFaultyAddressDescriptor(va, fault);
+        return Ok(CreateFaultyAddressDescriptor(va, fault));
     }
 
-    // if accdesc.ss == SS_Realm {
-    //     assert!(EL2Enabled());
-    // }
-    if regime == Regime::Regime_EL10 && EL2Enabled() {
+    if accdesc.ss == SecurityState::SS_Realm && !EL2Enabled(pe) {
+        return Err(TranslationError::Unpredictable(
+            "Realm access with EL2 disabled",
+        ));
+    }
+    if regime == Regime::Regime_EL10 && EL2Enabled(pe) {
         let s1aarch64 = true;
-        let (fault, pa) = AArch64S2Translate(fault, ipa, s1aarch64, aligned, accdesc);
+        let (fault, pa) = AArch64S2Translate(pe, mem, fault, ipa, s1aarch64, aligned, accdesc)?;
 
         if !matches!(fault.statuscode, Fault::Fault_None) {
-            return CreateFaultyAddressDescriptor(va, fault);
+            return Ok(CreateFaultyAddressDescriptor(va, fault));
         }
-        return pa;
+        return Ok(pa);
     }
-    ipa
+    Ok(ipa)
 }
 
 /// Library pseudocode for aarch64/translation/vmsa_translation/AArch64S1Translate
@@ -50,61 +93,68 @@ pub fn AArch64FullTranslate(
 /// =====================
 /// Translate VA to IPA/PA depending on the regime
 pub fn AArch64S1Translate(
+    pe: &ProcessorElement,
+    mem: &mut dyn PhysicalMemory,
     fault_in: FaultRecord,
     regime: Regime,
     va: u64,
     aligned: bool,
     accdesc: AccessDescriptor,
-) -> (FaultRecord, AddressDescriptor) {
+) -> Result<(FaultRecord, AddressDescriptor), TranslationError> {
     let mut fault: FaultRecord = fault_in;
     // // Prepare fault fields in case a fault is detected
     fault.secondstage = false;
     fault.s2fs1walk = false;
-    if !AArch64S1Enabled(regime, accdesc.acctype) {
-        return AArch64S1DisabledOutput(fault, regime, va, accdesc, aligned);
+    if !AArch64S1Enabled(pe, regime, accdesc.acctype)? {
+        return AArch64S1DisabledOutput(pe, fault, regime, va, accdesc, aligned);
     }
-    let walkparams = AArch64GetS1TTWParams(regime, accdesc.ss, va);
+    let mut walkparams = AArch64GetS1TTWParams(pe, regime, accdesc.ss, va)?;
     let s1mintxsz = AArch64S1MinTxSZ(
+        pe,
         regime,
         walkparams.get_d128(),
         walkparams.get_ds(),
         walkparams.get_tgx(),
     );
-    let s1maxtxsz = AArch64MaxTxSZ(walkparams.get_tgx());
+    let s1maxtxsz = AArch64MaxTxSZ(pe, walkparams.get_tgx());
 
-    if AArch64S1TxSZFaults(regime, walkparams) {
+    if AArch64S1TxSZFaults(pe, regime, walkparams) {
         fault.statuscode = Fault::Fault_Translation;
         fault.level = 0;
-        return (fault, AddressDescriptor::UNKNOWN);
+        return Ok((fault, AddressDescriptor::UNKNOWN));
     } else if walkparams.get_txsz() < s1mintxsz {
-        //walkparams.txsz = s1mintxsz<5:0>;
+        walkparams
+            .bitfield
+            .set(S1TTWParamsBits::txsz, s1mintxsz & 0b111111);
     } else if walkparams.get_txsz() > s1maxtxsz {
-        //walkparams.txsz = s1maxtxsz<5:0>;
+        walkparams
+            .bitfield
+            .set(S1TTWParamsBits::txsz, s1maxtxsz & 0b111111);
     }
 
     if AArch64VAIsOutOfRange(va, accdesc.acctype, regime, walkparams) {
         fault.statuscode = Fault::Fault_Translation;
         fault.level = 0;
-        return (fault, AddressDescriptor::UNKNOWN);
+        return Ok((fault, AddressDescriptor::UNKNOWN));
     }
 
     if accdesc.el == EL0 && walkparams.get_e0pd() == 1 {
         fault.statuscode = Fault::Fault_Translation;
         fault.level = 0;
-        return (fault, AddressDescriptor::UNKNOWN);
+        return Ok((fault, AddressDescriptor::UNKNOWN));
     }
 
-    if IsFeatureImplemented("FEAT_TME")
+    if IsFeatureImplemented(pe, Feature::FEAT_TME)
         && accdesc.el == EL0
         && walkparams.get_nfd() == 1
         && accdesc.transactional
     {
         fault.statuscode = Fault::Fault_Translation;
         fault.level = 0;
-        return (fault, AddressDescriptor::UNKNOWN);
+        return Ok((fault, AddressDescriptor::UNKNOWN));
     }
 
-    if IsFeatureImplemented("FEAT_SVE")
+    if IsFeatureImplemented(pe, Feature::FEAT_SVE)
         && accdesc.el == EL0
         && walkparams.get_nfd() == 1
         && ((accdesc.nonfault && accdesc.contiguous)
@@ -112,28 +162,24 @@ pub fn AArch64S1Translate(
     {
         fault.statuscode = Fault::Fault_Translation;
         fault.level = 0;
-        return (fault, AddressDescriptor::UNKNOWN);
+        return Ok((fault, AddressDescriptor::UNKNOWN));
     }
 
-    let mut descipaddr: AddressDescriptor = unsafe { MaybeUninit::zeroed().assume_init_read() };
-    let mut walkstate: TTWState = unsafe { MaybeUninit::zeroed().assume_init_read() };
-    let mut descriptor: u128 = 0;
+    let mut descipaddr: AddressDescriptor;
+    let mut walkstate: TTWState;
+    let mut descriptor: u128;
     let mut new_desc: u128;
-    let mem_desc: u128 = 0;
+    let mut mem_desc: u128 = 0;
     loop {
-        if walkparams.get_d128() == 1 {
-            (fault, descipaddr, walkstate, descriptor) =
-                AArch64S1Walk_128(fault, walkparams, va, regime, accdesc);
-        } else {
-            //(fault, descipaddr, walkstate, descriptor<63:0>) = AArch64S1Walk(fault, walkparams, va, regime, accdesc, 64);
-        }
-        //descriptor<127:64> = Zeros(64);
+        let N = if walkparams.get_d128() == 1 { 128 } else { 64 };
+        (fault, descipaddr, walkstate, descriptor) =
+            AArch64S1Walk(pe, mem, fault, walkparams, va, regime, accdesc, N)?;
         if fault.statuscode != Fault::Fault_None {
-            return (fault, AddressDescriptor::UNKNOWN);
+            return Ok((fault, AddressDescriptor::UNKNOWN));
         }
         if accdesc.acctype == AccessType::AccessType_IFETCH {
             // Flag the fetched instruction is from a guarded page
-            // FIXME walkstate might be uninitialized SetInGuardedPage(walkstate.guardedpage);
+            SetInGuardedPage(walkstate.guardedpage);
         }
         if AArch64S1HasAlignmentFault(
             accdesc,
@@ -144,12 +190,12 @@ pub fn AArch64S1Translate(
             fault.statuscode = Fault::Fault_Alignment;
         }
         if fault.statuscode == Fault::Fault_None {
-            fault = AArch64S1CheckPermissions(fault, regime, walkstate, walkparams, accdesc);
+            fault = AArch64S1CheckPermissions(pe, fault, regime, walkstate, walkparams, accdesc)?;
         }
         new_desc = descriptor;
         if walkparams.get_ha() == 1 && AArch64SettingAccessFlagPermitted(fault) {
             // Set descriptor AF bit
-            // TODO new_desc<10> = 0b1;
+            new_desc = SetBits(new_desc, 10, 10, 0b1);
         }
 
         // If HW update of dirty bit is enabled, the walk state permissions
@@ -160,7 +206,7 @@ pub fn AArch64S1Translate(
         if AArch64SettingDirtyStatePermitted(fault)
             && walkparams.get_ha() == 1
             && walkparams.get_hd() == 1
-            && (walkparams.get_pie() == 1/* FIXME || descriptor<51> == 0b1 */)
+            && (walkparams.get_pie() == 1 || Bit(descriptor, 51) == 1)
             && accdesc.write
             && !([
                 AccessType::AccessType_AT,
@@ -170,33 +216,40 @@ pub fn AArch64S1Translate(
             .contains(&accdesc.acctype))
         {
             // Clear descriptor AP[2]/nDirty bit permitting stage 1 writes
-            // TODO new_desc<7> = 0b0;
+            new_desc = SetBits(new_desc, 7, 7, 0b0);
         }
 
         // Either the access flag was clear or AP[2]/nDirty is set
         if new_desc != descriptor {
             let descpaddr: AddressDescriptor;
-            let descaccess = CreateAccDescTTEUpdate(accdesc);
-            if regime == Regime::Regime_EL10 && EL2Enabled() {
-                let s2fault: FaultRecord;
+            let descaccess = CreateAccDescTTEUpdate(pe, accdesc)?;
+            if regime == Regime::Regime_EL10 && EL2Enabled(pe) {
                 let s1aarch64 = true;
                 let s2aligned = true;
-                let v: (FaultRecord, AddressDescriptor) =
-                    AArch64S2Translate(fault, descipaddr, s1aarch64, s2aligned, descaccess);
-                s2fault = v.0;
-                descpaddr = v.1;
+                let s2fault: FaultRecord;
+                (s2fault, descpaddr) = AArch64S2Translate(
+                    pe, mem, fault, descipaddr, s1aarch64, s2aligned, descaccess,
+                )?;
 
                 if s2fault.statuscode != Fault::Fault_None {
-                    return (s2fault, AddressDescriptor::UNKNOWN);
+                    return Ok((s2fault, AddressDescriptor::UNKNOWN));
                 }
             } else {
                 descpaddr = descipaddr;
             }
-            if walkparams.get_d128() == 1 {
-                //(fault, mem_desc) = AArch64MemSwapTableDesc(fault, descriptor, new_desc, walkparams.get_ee(), descaccess, descpaddr);
-            } else {
-                //(fault, mem_desc<63:0>) = AArch64MemSwapTableDesc(fault, descriptor<63:0>, new_desc<63:0>, walkparams.get_ee(), descaccess, descpaddr);
-                //mem_desc<127:64> = Zeros(64);
+            (fault, mem_desc) = AArch64MemSwapTableDesc(
+                pe,
+                mem,
+                fault,
+                descriptor,
+                new_desc,
+                walkparams.get_ee(),
+                descaccess,
+                descpaddr,
+                N,
+            )?;
+            if fault.statuscode != Fault::Fault_None {
+                return Ok((fault, AddressDescriptor::UNKNOWN));
             }
         }
         if new_desc == descriptor || mem_desc == new_desc {
@@ -204,7 +257,7 @@ pub fn AArch64S1Translate(
         }
     }
     if fault.statuscode != Fault::Fault_None {
-        return (fault, AddressDescriptor::UNKNOWN);
+        return Ok((fault, AddressDescriptor::UNKNOWN));
     }
 
     // // Output Address
@@ -212,13 +265,13 @@ pub fn AArch64S1Translate(
     let mut memattrs: MemoryAttributes;
     if accdesc.acctype == AccessType::AccessType_IFETCH
         && (walkstate.memattrs.memtype == MemType::MemType_Device
-            || !AArch64S1ICacheEnabled(regime))
+            || !AArch64S1ICacheEnabled(pe, regime)?)
     {
         // Treat memory attributes as Normal Non-Cacheable
         memattrs = NormalNCMemAttr();
         memattrs.xs = walkstate.memattrs.xs;
     } else if accdesc.acctype != AccessType::AccessType_IFETCH
-        && !AArch64S1DCacheEnabled(regime)
+        && !AArch64S1DCacheEnabled(pe, regime)?
         && walkstate.memattrs.memtype == MemType::MemType_Normal
     {
         // Treat memory attributes as Normal Non-Cacheable
@@ -227,7 +280,7 @@ pub fn AArch64S1Translate(
 
         // The effect of SCTLR_ELx.C when 0b0 is Constrained UNPREDICTABLE
         // on the Tagged attribute
-        // if (IsFeatureImplemented("FEAT_MTE2") &&
+        // if (IsFeatureImplemented(pe, Feature::FEAT_MTE2) &&
         //       walkstate.memattrs.tags == MemTagType::MemTag_AllocationTagged &&
         //       !ConstrainUnpredictableBool(Unpredictable_S1CTAGGED)) {
         //     memattrs.tags = MemTagType::MemTag_Untagged;
@@ -244,26 +297,27 @@ pub fn AArch64S1Translate(
     // memattrs.shareability = EffectiveShareability(memattrs);
     memattrs.shareability = EffectiveShareability(memattrs);
 
-    if accdesc.ls64 && memattrs.memtype == MemType::MemType_Normal {
-        if memattrs.inner.attrs != MemAttr::MemAttr_NC
-            || memattrs.outer.attrs != MemAttr::MemAttr_NC
-        {
-            fault.statuscode = Fault::Fault_Exclusive;
-            return (fault, AddressDescriptor::UNKNOWN);
-        }
+    if accdesc.ls64
+        && memattrs.memtype == MemType::MemType_Normal
+        && (memattrs.inner.attrs != MemAttr::MemAttr_NC
+            || memattrs.outer.attrs != MemAttr::MemAttr_NC)
+    {
+        fault.statuscode = Fault::Fault_Exclusive;
+        return Ok((fault, AddressDescriptor::UNKNOWN));
     }
 
     let mut ipa = CreateAddressDescriptor(va, oa, memattrs);
     ipa.s1assured = walkstate.s1assured;
     let varange = AArch64GetVARange(va);
-    ipa.mecid = AArch64S1OutputMECID_128(
+    ipa.mecid = AArch64S1OutputMECID(
+        pe,
         walkparams,
         regime,
         varange,
         ipa.paddress.paspace,
         descriptor,
-    );
-    return (fault, ipa);
+    )?;
+    Ok((fault, ipa))
 }
 
 /// Library pseudocode for aarch64/translation/vmsa_translation/AArch64S2Translate
@@ -271,136 +325,730 @@ pub fn AArch64S1Translate(
 /// =====================
 /// Translate stage 1 IPA to PA and combine memory attributes
 pub fn AArch64S2Translate(
-    _fault_in: FaultRecord,
-    _ipa: AddressDescriptor,
-    _s1aarch64: bool,
-    _aligned: bool,
-    _accdesc: AccessDescriptor,
-) -> (FaultRecord, AddressDescriptor) {
-    todo!()
-    // walkparams = AArch64GetS2TTWParams(accdesc.ss, ipa.paddress.paspace, s1aarch64);
-    // FaultRecord fault = fault_in;
-    // bool s2fs1mro;
-    // // Prepare fault fields in case a fault is detected
-    // fault.statuscode = Fault::Fault_None; // Ignore any faults from stage 1
-    // fault.dirtybit = FALSE;
-    // fault.overlay = FALSE;
-    // fault.tagaccess = FALSE;
-    // fault.s1tagnotdata = FALSE;
-    // fault.secondstage = TRUE;
-    // fault.s2fs1walk = accdesc.acctype == AccessType_TTW;
-    // fault.ipaddress = ipa.paddress;
-    // if walkparams.vm != 0b1 then
-    // // Stage 2 translation is disabled
-    // return (fault, ipa);
-    // constant integer s2mintxsz = AArch64S2MinTxSZ(walkparams.d128, walkparams.ds,
-    // walkparams.tgx, s1aarch64);
-    // constant integer s2maxtxsz = AArch64MaxTxSZ(walkparams.tgx);
-    // if AArch64S2TxSZFaults(walkparams, s1aarch64) then
-    // fault.statuscode = Fault::Fault_Translation;
-    // fault.level = 0;
-    // return (fault, AddressDescriptor::UNKNOWN);
-    // elsif UInt(walkparams.txsz) < s2mintxsz then
-    // walkparams.txsz = s2mintxsz<5:0>;
-    // elsif UInt(walkparams.txsz) > s2maxtxsz then
-    // walkparams.txsz = s2maxtxsz<5:0>;
-    // if (walkparams.d128 == 0b0 &&
-    // (AArch64S2InvalidSL(walkparams) || AArch64S2InconsistentSL(walkparams))) then
-    // fault.statuscode = Fault::Fault_Translation;
-    // fault.level = 0;
-    // return (fault, AddressDescriptor::UNKNOWN);
-    // if AArch64IPAIsOutOfRange(ipa.paddress.address, walkparams) then
-    // fault.statuscode = Fault::Fault_Translation;
-    // fault.level = 0;
-    // return (fault, AddressDescriptor::UNKNOWN);
-    // AddressDescriptor descpaddr;
-    // TTWState walkstate;
-    // bits(128) descriptor;
-    // bits(128) new_desc;
-    // bits(128) mem_desc;
-    // repeat
-    // if walkparams.d128 == 0b1 then
-    // (fault, descpaddr, walkstate, descriptor) = AArch64S2Walk(fault, ipa, walkparams,
-    // accdesc, 128);
-    // else
-    // (fault, descpaddr, walkstate, descriptor<63:0>) = AArch64S2Walk(fault, ipa,
-    // walkparams, accdesc,
-    // 64);
-    // descriptor<127:64> = Zeros(64);
-    // if fault.statuscode != Fault::Fault_None then
-    // return (fault, AddressDescriptor::UNKNOWN);
-    // if AArch64S2HasAlignmentFault(accdesc, aligned, walkstate.memattrs) then
-    // fault.statuscode = Fault::Fault_Alignment;
-    // if fault.statuscode == Fault::Fault_None then
-    // (fault, s2fs1mro) = AArch64S2CheckPermissions(fault, walkstate, walkparams, ipa,
-    // accdesc);
-    // new_desc = descriptor;
-    // if walkparams.ha == 0b1 && AArch64SettingAccessFlagPermitted(fault) then
-    // // Set descriptor AF bit
-    // new_desc<10> = 0b1;
-    // // If HW update of dirty bit is enabled, the walk state permissions
-    // // will already reflect a configuration permitting writes.
-    // // The update of the descriptor occurs only if the descriptor bits in
-    // // memory do not reflect that and the access instigates a write.
-    // if (AArch64SettingDirtyStatePermitted(fault) &&
-    // walkparams.ha == 0b1 &&
-    // walkparams.hd == 0b1 &&
-    // (walkparams.s2pie == 0b1 || descriptor<51> == 0b1) &&
-    // accdesc.write &&
-    // !(accdesc.acctype IN {AccessType_AT, AccessType_IC, AccessType_DC})) then
-    // // Set descriptor S2AP[1]/Dirty bit permitting stage 2 writes
-    // new_desc<7> = 0b1;
-    // // Either the access flag was clear or S2AP[1]/Dirty is clear
-    // if new_desc != descriptor then
-    // if walkparams.hdbss == 0b1 && descriptor<7> == 0b0 && new_desc<7> == 0b1 then
-    // fault = AppendToHDBSS(fault, ipa.paddress, accdesc, walkparams, walkstate.level);
-    // // If an error, other than a synchronous External abort, occurred on the HDBSS update,
-    // // stage 2 hardware update of dirty state is not permitted.
-    // if (HDBSSPROD_EL2.FSC != 0b101000 &&
-    // (!fault.hdbssf || IsExternalAbort(fault.statuscode))) then
-    // AccessDescriptor descaccess = CreateAccDescTTEUpdate(accdesc);
-    // if walkparams.d128 == 0b1 then
-    // (fault, mem_desc) = AArch64MemSwapTableDesc(fault, descriptor, new_desc,
-    // walkparams.ee, descaccess,
-    // descpaddr);
-    // else
-    // (fault, mem_desc<63:0>) = AArch64MemSwapTableDesc(fault, descriptor<63:0>,
-    // new_desc<63:0>,
-    // walkparams.ee,
-    // descaccess, descpaddr);
-    // mem_desc<127:64> = Zeros(64);
-    // if fault.statuscode != Fault::Fault_None then
-    // return (fault, AddressDescriptor::UNKNOWN);
-    // until new_desc == descriptor || mem_desc == new_desc;
-    // if fault.statuscode != Fault::Fault_None then
-    // return (fault, AddressDescriptor::UNKNOWN);
-    // ipa_64 = ZeroExtend(ipa.paddress.address, 64);
-    // // Output Address
-    // oa = StageOA(ipa_64, walkparams.d128, walkparams.tgx, walkstate);
-    // MemoryAttributes s2_memattrs;
-    // if ((accdesc.acctype == AccessType_TTW &&
-    // walkstate.memattrs.memtype == MemType_Device && walkparams.ptw == 0b0) ||
-    // (accdesc.acctype == AccessType_IFETCH &&
-    // (walkstate.memattrs.memtype == MemType_Device || HCR_EL2.ID == 0b1)) ||
-    // (accdesc.acctype != AccessType_IFETCH &&
-    // walkstate.memattrs.memtype == MemType_Normal && !S2DCacheEnabled())) then
-    // // Treat memory attributes as Normal Non-Cacheable
-    // s2_memattrs = NormalNCMemAttr();
-    // s2_memattrs.xs = walkstate.memattrs.xs;
-    // else
-    // s2_memattrs = walkstate.memattrs;
-    // if accdesc.ls64 && s2_memattrs.memtype == MemType_Normal then
-    // if s2_memattrs.inner.attrs != MemAttr_NC || s2_memattrs.outer.attrs != MemAttr_NC then
-    // fault.statuscode = Fault::Fault_Exclusive;
-    // return (fault, AddressDescriptor::UNKNOWN);
-    // s2aarch64 = TRUE;
-    // MemoryAttributes memattrs;
-    // if walkparams.fwb == 0b0 then
-    // memattrs = S2CombineS1MemAttrs(ipa.memattrs, s2_memattrs, s2aarch64);
-    // else
-    // memattrs = s2_memattrs;
-    // pa = CreateAddressDescriptor(ipa.vaddress, oa, memattrs);
-    // pa.s2fs1mro = s2fs1mro;
-    // pa.mecid = AArch64S2OutputMECID(walkparams, pa.paddress.paspace, descriptor);
-    // return (fault, pa);
+    pe: &ProcessorElement,
+    mem: &mut dyn PhysicalMemory,
+    fault_in: FaultRecord,
+    ipa: AddressDescriptor,
+    s1aarch64: bool,
+    aligned: bool,
+    accdesc: AccessDescriptor,
+) -> Result<(FaultRecord, AddressDescriptor), TranslationError> {
+    let mut walkparams = AArch64GetS2TTWParams(pe, accdesc.ss, ipa.paddress.paspace, s1aarch64)?;
+    let mut fault: FaultRecord = fault_in;
+    let mut s2fs1mro = false;
+    // Prepare fault fields in case a fault is detected
+    fault.statuscode = Fault::Fault_None; // Ignore any faults from stage 1
+    fault.secondstage = true;
+    fault.s2fs1walk = accdesc.acctype == AccessType::AccessType_TTW;
+    fault.ipaddress = ipa.paddress;
+
+    if walkparams.get_vm() != 1 {
+        // Stage 2 translation is disabled
+        return Ok((fault, ipa));
+    }
+
+    let s2mintxsz = AArch64S2MinTxSZ(
+        pe,
+        walkparams.get_d128(),
+        walkparams.get_ds(),
+        walkparams.get_tgx(),
+        s1aarch64,
+    );
+    let s2maxtxsz = AArch64MaxTxSZ(pe, walkparams.get_tgx());
+    if AArch64S2TxSZFaults(pe, walkparams, s1aarch64) {
+        fault.statuscode = Fault::Fault_Translation;
+        fault.level = 0;
+        return Ok((fault, AddressDescriptor::UNKNOWN));
+    } else if walkparams.get_txsz() < s2mintxsz {
+        walkparams
+            .bitfield
+            .set(S2TTWParamsBits::txsz, s2mintxsz & 0b111111);
+    } else if walkparams.get_txsz() > s2maxtxsz {
+        walkparams
+            .bitfield
+            .set(S2TTWParamsBits::txsz, s2maxtxsz & 0b111111);
+    }
+
+    if walkparams.get_d128() == 0
+        && (AArch64S2InvalidSL(pe, walkparams) || AArch64S2InconsistentSL(walkparams))
+    {
+        fault.statuscode = Fault::Fault_Translation;
+        fault.level = 0;
+        return Ok((fault, AddressDescriptor::UNKNOWN));
+    }
+
+    if AArch64IPAIsOutOfRange(ipa.paddress.address, walkparams) {
+        fault.statuscode = Fault::Fault_Translation;
+        fault.level = 0;
+        return Ok((fault, AddressDescriptor::UNKNOWN));
+    }
+
+    let mut descpaddr: AddressDescriptor;
+    let mut walkstate: TTWState;
+    let mut descriptor: u128;
+    let mut new_desc: u128;
+    let mut mem_desc: u128 = 0;
+    loop {
+        let N = if walkparams.get_d128() == 1 { 128 } else { 64 };
+        (fault, descpaddr, walkstate, descriptor) =
+            AArch64S2Walk(pe, mem, fault, ipa, walkparams, accdesc, N)?;
+        if fault.statuscode != Fault::Fault_None {
+            return Ok((fault, AddressDescriptor::UNKNOWN));
+        }
+
+        if AArch64S2HasAlignmentFault(accdesc, aligned, walkstate.memattrs) {
+            fault.statuscode = Fault::Fault_Alignment;
+        }
+        if fault.statuscode == Fault::Fault_None {
+            (fault, s2fs1mro) =
+                AArch64S2CheckPermissions(pe, fault, walkstate, walkparams, ipa, accdesc);
+        }
+
+        new_desc = descriptor;
+        if walkparams.get_ha() == 1 && AArch64SettingAccessFlagPermitted(fault) {
+            // Set descriptor AF bit
+            new_desc = SetBits(new_desc, 10, 10, 0b1);
+        }
+
+        // If HW update of dirty bit is enabled, the walk state permissions
+        // will already reflect a configuration permitting writes.
+        // The update of the descriptor occurs only if the descriptor bits in
+        // memory do not reflect that and the access instigates a write.
+        if AArch64SettingDirtyStatePermitted(fault)
+            && walkparams.get_ha() == 1
+            && walkparams.get_hd() == 1
+            && (walkparams.get_s2pie() == 1 || Bit(descriptor, 51) == 1)
+            && accdesc.write
+            && !([
+                AccessType::AccessType_AT,
+                AccessType::AccessType_IC,
+                AccessType::AccessType_DC,
+            ]
+            .contains(&accdesc.acctype))
+        {
+            // Set descriptor S2AP[1]/Dirty bit permitting stage 2 writes
+            new_desc = SetBits(new_desc, 7, 7, 0b1);
+        }
+
+        // Either the access flag was clear or S2AP[1]/Dirty is clear
+        if new_desc != descriptor {
+            // Recording the dirty state in the HDBSS advances HDBSSPROD_EL2, and
+            // translation does not modify the PE
+            if walkparams.get_hdbss() == 1 && Bit(descriptor, 7) == 0 && Bit(new_desc, 7) == 1 {
+                return Err(TranslationError::Unsupported(
+                    "dirty state tracking with VTCR_EL2.HDBSS (FEAT_HDBSS)",
+                ));
+            }
+            let descaccess = CreateAccDescTTEUpdate(pe, accdesc)?;
+            (fault, mem_desc) = AArch64MemSwapTableDesc(
+                pe,
+                mem,
+                fault,
+                descriptor,
+                new_desc,
+                walkparams.get_ee(),
+                descaccess,
+                descpaddr,
+                N,
+            )?;
+            if fault.statuscode != Fault::Fault_None {
+                return Ok((fault, AddressDescriptor::UNKNOWN));
+            }
+        }
+        if new_desc == descriptor || mem_desc == new_desc {
+            break;
+        }
+    }
+
+    if fault.statuscode != Fault::Fault_None {
+        return Ok((fault, AddressDescriptor::UNKNOWN));
+    }
+
+    // Output Address
+    let oa = StageOA(
+        ipa.paddress.address,
+        walkparams.get_d128(),
+        walkparams.get_tgx(),
+        walkstate,
+    );
+    let mut s2_memattrs: MemoryAttributes;
+    if (accdesc.acctype == AccessType::AccessType_TTW
+        && walkstate.memattrs.memtype == MemType::MemType_Device
+        && walkparams.get_ptw() == 0)
+        || (accdesc.acctype == AccessType::AccessType_IFETCH
+            && (walkstate.memattrs.memtype == MemType::MemType_Device
+                || pe.regs.HCR_EL2.get(HCR_EL2_REG::ID) == 1))
+        || (accdesc.acctype != AccessType::AccessType_IFETCH
+            && walkstate.memattrs.memtype == MemType::MemType_Normal
+            && !S2DCacheEnabled(pe))
+    {
+        // Treat memory attributes as Normal Non-Cacheable
+        s2_memattrs = NormalNCMemAttr();
+        s2_memattrs.xs = walkstate.memattrs.xs;
+    } else {
+        s2_memattrs = walkstate.memattrs;
+    }
+
+    if accdesc.ls64
+        && s2_memattrs.memtype == MemType::MemType_Normal
+        && (s2_memattrs.inner.attrs != MemAttr::MemAttr_NC
+            || s2_memattrs.outer.attrs != MemAttr::MemAttr_NC)
+    {
+        fault.statuscode = Fault::Fault_Exclusive;
+        return Ok((fault, AddressDescriptor::UNKNOWN));
+    }
+
+    let s2aarch64 = true;
+    let memattrs = if walkparams.get_fwb() == 0 {
+        S2CombineS1MemAttrs(pe, ipa.memattrs, s2_memattrs, s2aarch64)
+    } else {
+        s2_memattrs
+    };
+
+    let mut pa = CreateAddressDescriptor(ipa.vaddress, oa, memattrs);
+    pa.s2fs1mro = s2fs1mro;
+    pa.mecid = AArch64S2OutputMECID(pe, walkparams, pa.paddress.paspace, descriptor);
+    Ok((fault, pa))
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_translation/AArch64.MemSwapTableDesc
+/// AArch64.MemSwapTableDesc()
+/// ==========================
+/// Perform HW update of table descriptor as an atomic operation
+// Takes the ASL arguments plus the PE and memory backend
+#[allow(clippy::too_many_arguments)]
+pub fn AArch64MemSwapTableDesc(
+    pe: &ProcessorElement,
+    mem: &mut dyn PhysicalMemory,
+    fault_in: FaultRecord,
+    prev_desc: u128,
+    new_desc: u128,
+    ee: u64,
+    descaccess: AccessDescriptor,
+    descpaddr: AddressDescriptor,
+    N: usize,
+) -> Result<(FaultRecord, u128), TranslationError> {
+    assert!(N == 64 || N == 128);
+    let mut fault = fault_in;
+
+    if IsFeatureImplemented(pe, Feature::FEAT_RME) {
+        fault.gpcf = GranuleProtectionCheck(pe, mem, descpaddr, descaccess)?;
+        if fault.gpcf.gpf != GPCF::GPCF_None {
+            fault.statuscode = Fault::Fault_GPCFOnWalk;
+            fault.paddress = descpaddr.paddress;
+            fault.gpcfs2walk = fault.secondstage;
+            return Ok((fault, 0));
+        }
+    }
+
+    // Memory holds the descriptors in the byte order given by ee
+    let ordered = |desc: u128| {
+        let mut bytes = desc.to_le_bytes()[..N / 8].to_vec();
+        if ee == 1 {
+            bytes.reverse();
+        }
+        bytes
+    };
+
+    // All observers in the shareability domain observe the
+    // following memory read and write accesses atomically.
+    let (mut memstatus, bytes, writestatus) = mem.compare_and_swap(
+        descpaddr,
+        N / 8,
+        descaccess,
+        &ordered(prev_desc),
+        &ordered(new_desc),
+    );
+    // A backend that returns a value of the wrong size terminated the access
+    if !IsFault(memstatus.statuscode) && bytes.len() != N / 8 {
+        memstatus = PhysMemRetStatus::SyncExternal(descaccess.acctype);
+    }
+    if IsFault(memstatus.statuscode) {
+        let iswrite = false;
+        fault = HandleExternalTTWAbort(memstatus, iswrite, descpaddr, descaccess, N / 8, fault);
+        if IsFault(fault.statuscode) {
+            return Ok((fault, 0));
+        }
+    }
+
+    if let Some(memstatus) = writestatus {
+        if IsFault(memstatus.statuscode) {
+            let iswrite = true;
+            fault = HandleExternalTTWAbort(memstatus, iswrite, descpaddr, descaccess, N / 8, fault);
+            if IsFault(fault.statuscode) {
+                return Ok((fault, 0));
+            }
+        }
+        // Reflect what is now in memory (in little endian format)
+        return Ok((fault, new_desc));
+    }
+
+    let mut raw = [0_u8; 16];
+    raw[..N / 8].copy_from_slice(&bytes);
+    if ee == 1 {
+        raw[..N / 8].reverse();
+    }
+    let mem_desc = u128::from_le_bytes(raw);
+
+    Ok((fault, mem_desc))
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_translation/AArch64.SettingAccessFlagPermitted
+/// AArch64.SettingAccessFlagPermitted()
+/// ====================================
+/// Determine whether the access flag could be set by HW given the fault status
+pub fn AArch64SettingAccessFlagPermitted(fault: FaultRecord) -> bool {
+    match fault.statuscode {
+        Fault::Fault_None => true,
+        // ConstrainUnpredictableBool(Unpredictable_AFUPDATE) is FALSE
+        Fault::Fault_Alignment | Fault::Fault_Permission => false,
+        _ => false,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_translation/AArch64.SettingDirtyStatePermitted
+/// AArch64.SettingDirtyStatePermitted()
+/// ====================================
+/// Determine whether the dirty state could be set by HW given the fault status
+pub fn AArch64SettingDirtyStatePermitted(fault: FaultRecord) -> bool {
+    match fault.statuscode {
+        Fault::Fault_None => true,
+        // ConstrainUnpredictableBool(Unpredictable_DBUPDATE) is FALSE
+        Fault::Fault_Alignment => false,
+        _ => false,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_translation/AArch64.S1DCacheEnabled
+/// AArch64.S1DCacheEnabled()
+/// =========================
+/// Determine cacheability of stage 1 data accesses
+pub fn AArch64S1DCacheEnabled(
+    pe: &ProcessorElement,
+    regime: Regime,
+) -> Result<bool, TranslationError> {
+    Ok(match regime {
+        Regime::Regime_EL3 => pe.regs.SCTLR_EL3.get(SCTLR_ELx_REG::C) == 1,
+        Regime::Regime_EL2 | Regime::Regime_EL20 => pe.regs.SCTLR_EL2.get(SCTLR_ELx_REG::C) == 1,
+        Regime::Regime_EL10 => pe.regs.SCTLR_EL1.get(SCTLR_ELx_REG::C) == 1,
+        // AArch32 EL3 is not modelled
+        Regime::Regime_EL30 => return Err(TranslationError::Unsupported("AArch32 EL3")),
+    })
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_translation/AArch64.S1DisabledOutput
+/// AArch64.S1DisabledOutput()
+/// ==========================
+/// Map the VA to IPA/PA and assign default memory attributes
+pub fn AArch64S1DisabledOutput(
+    pe: &ProcessorElement,
+    fault_in: FaultRecord,
+    regime: Regime,
+    va_in: u64,
+    accdesc: AccessDescriptor,
+    aligned: bool,
+) -> Result<(FaultRecord, AddressDescriptor), TranslationError> {
+    let mut va = va_in;
+    let walkparams = AArch64GetS1TTWParams(pe, regime, accdesc.ss, va)?;
+    let mut fault = fault_in;
+
+    // No memory page is guarded when stage 1 address translation is disabled
+    SetInGuardedPage(false);
+
+    // Output Address
+    let oa = FullAddress {
+        address: Bits(va as u128, 55, 0),
+        paspace: match accdesc.ss {
+            SecurityState::SS_Secure => PASpace::PAS_Secure,
+            SecurityState::SS_NonSecure => PASpace::PAS_NonSecure,
+            SecurityState::SS_Root => PASpace::PAS_Root,
+            SecurityState::SS_Realm => PASpace::PAS_Realm,
+        },
+    };
+
+    let mut memattrs = if regime == Regime::Regime_EL10
+        && EL2Enabled(pe)
+        && pe.regs.HCR_EL2.get(HCR_EL2_REG::DC) == 1
+    {
+        let default_cacheability = MemAttrHints {
+            attrs: MemAttr::MemAttr_WB,
+            hints: MemHint::MemHint_RWA,
+            transient: false,
+        };
+
+        MemoryAttributes {
+            memtype: MemType::MemType_Normal,
+            device: DeviceType::default(),
+            outer: default_cacheability,
+            inner: default_cacheability,
+            shareability: Shareability::Shareability_NSH,
+            tags: if walkparams.get_mtx() == 1 {
+                MemTagType::MemTag_CanonicallyTagged
+            } else if pe.regs.HCR_EL2.get(HCR_EL2_REG::DCT) == 1 {
+                MemTagType::MemTag_AllocationTagged
+            } else {
+                MemTagType::MemTag_Untagged
+            },
+            notagaccess: false,
+            xs: false,
+        }
+    } else if accdesc.acctype == AccessType::AccessType_IFETCH {
+        let i_cache_attr = if AArch64S1ICacheEnabled(pe, regime)? {
+            MemAttrHints {
+                attrs: MemAttr::MemAttr_WT,
+                hints: MemHint::MemHint_RA,
+                transient: false,
+            }
+        } else {
+            MemAttrHints {
+                attrs: MemAttr::MemAttr_NC,
+                hints: Default::default(),
+                transient: Default::default(),
+            }
+        };
+
+        MemoryAttributes {
+            memtype: MemType::MemType_Normal,
+            device: DeviceType::default(),
+            outer: i_cache_attr,
+            inner: i_cache_attr,
+            shareability: Shareability::Shareability_OSH,
+            tags: MemTagType::MemTag_Untagged,
+            notagaccess: false,
+            xs: true,
+        }
+    } else {
+        MemoryAttributes {
+            memtype: MemType::MemType_Device,
+            device: DeviceType::DeviceType_nGnRnE,
+            shareability: Shareability::Shareability_OSH,
+            tags: if walkparams.get_mtx() == 1 {
+                MemTagType::MemTag_CanonicallyTagged
+            } else {
+                MemTagType::MemTag_Untagged
+            },
+            xs: true,
+            ..NormalNCMemAttr()
+        }
+    };
+    memattrs.notagaccess = false;
+
+    if walkparams.get_mtx() == 1
+        && walkparams.get_tbi() == 0
+        && accdesc.acctype != AccessType::AccessType_IFETCH
+    {
+        // For the purpose of the checks in this function, the MTE tag bits are ignored.
+        let tag = if HasUnprivileged(regime) && Bit(va as u128, 55) == 1 {
+            0b1111
+        } else {
+            0b0000
+        };
+        va = SetBits(va as u128, 59, 56, tag) as u64;
+    }
+
+    fault.level = 0;
+    let addrtop = AArch64AddrTop(walkparams.get_tbid(), accdesc.acctype, walkparams.get_tbi());
+    if !IsZeroBits(va, addrtop as u32, AArch64PAMax(pe) as u32) {
+        fault.statuscode = Fault::Fault_AddressSize;
+    } else if AArch64S1HasAlignmentFault(accdesc, aligned, walkparams.get_ntlsmd(), memattrs) {
+        fault.statuscode = Fault::Fault_Alignment;
+    }
+
+    if fault.statuscode != Fault::Fault_None {
+        return Ok((fault, AddressDescriptor::UNKNOWN));
+    }
+
+    let mut ipa = CreateAddressDescriptor(va_in, oa, memattrs);
+    ipa.mecid = AArch64S1DisabledOutputMECID(pe, walkparams, regime, ipa.paddress.paspace);
+    Ok((fault, ipa))
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_translation/AArch64.S1Enabled
+/// AArch64.S1Enabled()
+/// ===================
+/// Determine if stage 1 is enabled for the access type for this translation regime
+pub fn AArch64S1Enabled(
+    pe: &ProcessorElement,
+    regime: Regime,
+    _acctype: AccessType,
+) -> Result<bool, TranslationError> {
+    Ok(match regime {
+        Regime::Regime_EL3 => pe.regs.SCTLR_EL3.get(SCTLR_ELx_REG::M) == 1,
+        Regime::Regime_EL2 | Regime::Regime_EL20 => pe.regs.SCTLR_EL2.get(SCTLR_ELx_REG::M) == 1,
+        Regime::Regime_EL10 => {
+            (!EL2Enabled(pe)
+                || (pe.regs.HCR_EL2.get(HCR_EL2_REG::DC) == 0
+                    && pe.regs.HCR_EL2.get(HCR_EL2_REG::TGE) == 0))
+                && pe.regs.SCTLR_EL1.get(SCTLR_ELx_REG::M) == 1
+        }
+        // AArch32 EL3 is not modelled
+        Regime::Regime_EL30 => return Err(TranslationError::Unsupported("AArch32 EL3")),
+    })
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_translation/AArch64.S1ICacheEnabled
+/// AArch64.S1ICacheEnabled()
+/// =========================
+/// Determine cacheability of stage 1 instruction fetches
+pub fn AArch64S1ICacheEnabled(
+    pe: &ProcessorElement,
+    regime: Regime,
+) -> Result<bool, TranslationError> {
+    Ok(match regime {
+        Regime::Regime_EL3 => pe.regs.SCTLR_EL3.get(SCTLR_ELx_REG::I) == 1,
+        Regime::Regime_EL2 | Regime::Regime_EL20 => pe.regs.SCTLR_EL2.get(SCTLR_ELx_REG::I) == 1,
+        Regime::Regime_EL10 => pe.regs.SCTLR_EL1.get(SCTLR_ELx_REG::I) == 1,
+        // AArch32 EL3 is not modelled
+        Regime::Regime_EL30 => return Err(TranslationError::Unsupported("AArch32 EL3")),
+    })
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_translation/S2DCacheEnabled
+/// S2DCacheEnabled()
+/// =================
+/// Returns TRUE if Stage 2 Data access cacheability is enabled
+pub fn S2DCacheEnabled(pe: &ProcessorElement) -> bool {
+    pe.regs.HCR_EL2.get(HCR_EL2_REG::CD) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physmem::*;
+
+    #[test]
+    fn mem_swap_table_desc() {
+        let pe = ProcessorElement::new();
+        let descaccess = NewAccDesc(&pe, AccessType::AccessType_TTW).unwrap();
+        let fault = FaultRecord::NoFault();
+        let mut descpaddr = AddressDescriptor::UNKNOWN;
+        descpaddr.paddress.address = 0x30_0008;
+        descpaddr.paddress.paspace = PASpace::PAS_NonSecure;
+        let prev_desc: u128 = 0x4000_0000 | 0b11;
+        let new_desc = prev_desc | 1 << 10;
+
+        for (ee, N) in [(0, 64), (1, 64), (0, 128), (1, 128)] {
+            let mut stored = prev_desc.to_le_bytes()[..N / 8].to_vec();
+            if ee == 1 {
+                stored.reverse();
+            }
+            let mut mem = SparseMemory::new();
+            mem.load(0x30_0008, &stored);
+
+            // The descriptor is replaced when memory still holds the one that was walked
+            let (fault, desc) = AArch64MemSwapTableDesc(
+                &pe, &mut mem, fault, prev_desc, new_desc, ee, descaccess, descpaddr, N,
+            )
+            .unwrap();
+            assert_eq!(fault.statuscode, Fault::Fault_None);
+            assert_eq!(desc, new_desc);
+            let mut expected = new_desc.to_le_bytes()[..N / 8].to_vec();
+            if ee == 1 {
+                expected.reverse();
+            }
+            assert_eq!(mem.dump(0x30_0008, N / 8), expected);
+
+            // Otherwise memory is left alone and the current descriptor is returned
+            let (fault, desc) = AArch64MemSwapTableDesc(
+                &pe,
+                &mut mem,
+                fault,
+                prev_desc,
+                prev_desc | 1 << 51,
+                ee,
+                descaccess,
+                descpaddr,
+                N,
+            )
+            .unwrap();
+            assert_eq!(fault.statuscode, Fault::Fault_None);
+            assert_eq!(desc, new_desc, "ee = {}, N = {}", ee, N);
+            assert_eq!(mem.dump(0x30_0008, N / 8), expected);
+        }
+    }
+
+    #[test]
+    fn mmu_off_output() {
+        let mut pe =
+            ProcessorElement::from_profile(ArchVersion::Armv8_0, &[Feature::FEAT_AA64EL2]).unwrap();
+        pe.PSTATE.set(ProcState::EL, 1);
+        let load = CreateAccDescGPR(&pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        let ifetch = CreateAccDescIFetch(&pe).unwrap();
+        let regime = Regime::Regime_EL10;
+        assert!(!AArch64S1Enabled(&pe, regime, load.acctype).unwrap());
+        let output = |pe: &ProcessorElement, va: u64, accdesc: AccessDescriptor, aligned: bool| {
+            let fault = FaultRecord::NoFaultForAccess(accdesc);
+            AArch64S1DisabledOutput(pe, fault, regime, va, accdesc, aligned).unwrap()
+        };
+
+        // Data accesses are to Device-nGnRnE memory at PA == VA
+        let (fault, ipa) = output(&pe, 0x1234_5678, load, true);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+        assert_eq!(ipa.paddress.address, 0x1234_5678);
+        assert_eq!(ipa.paddress.paspace, PASpace::PAS_NonSecure);
+        assert_eq!(ipa.memattrs.memtype, MemType::MemType_Device);
+        assert_eq!(ipa.memattrs.device, DeviceType::DeviceType_nGnRnE);
+        assert_eq!(ipa.memattrs.shareability, Shareability::Shareability_OSH);
+
+        // which makes unaligned accesses fault
+        let (fault, _) = output(&pe, 0x1234_5679, load, false);
+        assert_eq!(fault.statuscode, Fault::Fault_Alignment);
+
+        // VAs above the PA size are an Address size fault at level 0
+        let (fault, _) = output(&pe, 0x0001_0000_0000_0000, load, true);
+        assert_eq!(fault.statuscode, Fault::Fault_AddressSize);
+        assert_eq!(fault.level, 0);
+
+        // Instruction fetches are Normal, Write-Through if SCTLR_EL1.I is set
+        let (_, ipa) = output(&pe, 0x1234_5678, ifetch, true);
+        assert_eq!(ipa.memattrs.memtype, MemType::MemType_Normal);
+        assert_eq!(ipa.memattrs.inner.attrs, MemAttr::MemAttr_NC);
+        pe.regs.SCTLR_EL1 = pe.regs.SCTLR_EL1.with(SCTLR_ELx_REG::I, 1);
+        let (_, ipa) = output(&pe, 0x1234_5678, ifetch, true);
+        assert_eq!(ipa.memattrs.inner.attrs, MemAttr::MemAttr_WT);
+        assert_eq!(ipa.memattrs.inner.hints, MemHint::MemHint_RA);
+
+        // HCR_EL2.DC makes all accesses Normal Write-Back
+        pe.regs.HCR_EL2 = pe.regs.HCR_EL2.with(HCR_EL2_REG::DC, 1);
+        let (fault, ipa) = output(&pe, 0x1234_5679, load, false);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+        assert_eq!(ipa.memattrs.memtype, MemType::MemType_Normal);
+        assert_eq!(ipa.memattrs.outer.attrs, MemAttr::MemAttr_WB);
+        assert_eq!(ipa.memattrs.shareability, Shareability::Shareability_NSH);
+    }
+
+    const TABLES: TableBuilder = TableBuilder::new(0x10_0000, 12, 1);
+    const PAGE: u64 = 0b11;
+    const AF: u64 = 1 << 10;
+    const AP2: u64 = 1 << 7;
+    const DBM: u64 = 1 << 51;
+
+    /// A Non-secure EL2 PE with hardware Access flag and dirty state updates, and
+    /// a 4KB granule table with `leaf` as the page descriptor for VA 0x1000.
+    fn el2_hafdbs(leaf: u64) -> (ProcessorElement, SparseMemory) {
+        let mut pe = ProcessorElement::from_profile(
+            ArchVersion::Armv8_1,
+            &[Feature::FEAT_AA64EL2, Feature::FEAT_HAFDBS],
+        )
+        .unwrap();
+        pe.PSTATE.set(ProcState::EL, 2);
+        pe.regs.SCTLR_EL2 = pe.regs.SCTLR_EL2.with(SCTLR_ELx_REG::M, 1);
+        pe.regs.TCR_EL2 = TCR_EL2_REG::new()
+            .with(TCR_EL2_REG::T0SZ, 32)
+            .with(TCR_EL2_REG::HA, 1)
+            .with(TCR_EL2_REG::HD, 1);
+        pe.regs.TTBR0_EL2 = TTBR_ELx_REG::from_bits(0x10_0000);
+
+        let mut mem = SparseMemory::new();
+        TABLES.map(&mut mem, 0x1000, 3, leaf.into());
+        (pe, mem)
+    }
+
+    fn leaf(mem: &SparseMemory) -> u64 {
+        u64::from_le_bytes(mem.dump(TABLES.entry(0x1000, 3), 8).try_into().unwrap())
+    }
+
+    #[test]
+    fn hardware_descriptor_updates() {
+        let page = 0x4000_0000 | PAGE | AP2 | DBM;
+
+        // A load sets the Access flag and leaves the page clean
+        let (pe, mut mem) = el2_hafdbs(page);
+        let accdesc = CreateAccDescGPR(&pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        let addrdesc = AArch64FullTranslate(&pe, &mut mem, 0x1000, accdesc, true).unwrap();
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_None);
+        assert_eq!(leaf(&mem), page | AF);
+
+        // A store also marks the page dirty by clearing AP[2]
+        let (pe, mut mem) = el2_hafdbs(page);
+        let accdesc = CreateAccDescGPR(&pe, MemOp::MemOp_STORE, false, true, false).unwrap();
+        let addrdesc = AArch64FullTranslate(&pe, &mut mem, 0x1000, accdesc, true).unwrap();
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_None);
+        assert_eq!(addrdesc.paddress.address, 0x4000_0000);
+        assert_eq!(leaf(&mem), (page | AF) & !AP2);
+
+        // Without DBM, the store is a Permission fault and the descriptor is left as is
+        let (pe, mut mem) = el2_hafdbs(page & !DBM);
+        let addrdesc = AArch64FullTranslate(&pe, &mut mem, 0x1000, accdesc, true).unwrap();
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_Permission);
+        assert_eq!(leaf(&mem), page & !DBM);
+    }
+
+    /// A backend whose compare and swap returns one byte more than was requested.
+    struct LongSwapMemory(SparseMemory);
+
+    impl PhysicalMemory for LongSwapMemory {
+        fn read(
+            &self,
+            desc: AddressDescriptor,
+            size: usize,
+            accdesc: AccessDescriptor,
+        ) -> (PhysMemRetStatus, Vec<u8>) {
+            self.0.read(desc, size, accdesc)
+        }
+
+        fn write(
+            &mut self,
+            desc: AddressDescriptor,
+            size: usize,
+            accdesc: AccessDescriptor,
+            value: &[u8],
+        ) -> PhysMemRetStatus {
+            self.0.write(desc, size, accdesc, value)
+        }
+
+        fn compare_and_swap(
+            &mut self,
+            desc: AddressDescriptor,
+            size: usize,
+            accdesc: AccessDescriptor,
+            expected: &[u8],
+            value: &[u8],
+        ) -> (PhysMemRetStatus, Vec<u8>, Option<PhysMemRetStatus>) {
+            let (memstatus, mut bytes, writestatus) = self
+                .0
+                .compare_and_swap(desc, size, accdesc, expected, value);
+            bytes.push(0);
+            (memstatus, bytes, writestatus)
+        }
+    }
+
+    #[test]
+    fn wrong_size_descriptor_swap_aborts() {
+        let (pe, mem) = el2_hafdbs(0x4000_0000 | PAGE);
+        let mut mem = LongSwapMemory(mem);
+        let accdesc = CreateAccDescGPR(&pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        let addrdesc = AArch64TranslateAddress(&pe, &mut mem, 0x1000, accdesc, true).unwrap();
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_SyncExternalOnWalk);
+    }
+
+    #[test]
+    fn aarch32_el3_is_an_error() {
+        let mut pe = ProcessorElement::new()
+            .with_feature(Feature::FEAT_AA32EL3)
+            .unwrap();
+        pe.PSTATE.set(ProcState::EL, 3);
+        assert_eq!(TranslationRegime(&pe, EL3).unwrap(), Regime::Regime_EL30);
+        let load = CreateAccDescGPR(&pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        let mut mem = SparseMemory::new();
+        let unsupported = |err| matches!(err, TranslationError::Unsupported(_));
+
+        let err = AArch64TranslateAddress(&pe, &mut mem, 0x1000, load, true).unwrap_err();
+        assert!(unsupported(err));
+
+        // The AArch64 helpers report the regime rather than panicking
+        let regime = Regime::Regime_EL30;
+        assert!(unsupported(
+            AArch64S1Enabled(&pe, regime, load.acctype).unwrap_err()
+        ));
+        assert!(unsupported(
+            AArch64S1DCacheEnabled(&pe, regime).unwrap_err()
+        ));
+        assert!(unsupported(
+            AArch64S1ICacheEnabled(&pe, regime).unwrap_err()
+        ));
+        assert!(unsupported(AArch64S1TTBR(&pe, regime, 0x1000).unwrap_err()));
+        assert!(unsupported(AArch64S1EPD(&pe, regime, 0x1000).unwrap_err()));
+        assert!(unsupported(AArch64S1POR(&pe, regime, EL3).unwrap_err()));
+    }
 }
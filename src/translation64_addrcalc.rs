@@ -0,0 +1,609 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+#![allow(non_camel_case_types)]
+
+use crate::pe::*;
+use crate::shared::*;
+use crate::shared_memory::*;
+use crate::shared_translation::*;
+use crate::shared_vmsa::*;
+use crate::stubs::*;
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.AddrTop
+/// AArch64.AddrTop()
+/// =================
+/// Get the top bit position of a virtual address.
+/// Bits above are not accounted as part of the translation process.
+pub fn AArch64AddrTop(tbid: u64, acctype: AccessType, tbi: u64) -> u64 {
+    if tbid == 1 && acctype == AccessType::AccessType_IFETCH {
+        return 63;
+    }
+
+    if tbi == 1 {
+        55
+    } else {
+        63
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.IASize
+/// AArch64.IASize()
+/// ================
+/// Retrieve the number of bits containing the input address
+pub fn AArch64IASize(txsz: u64) -> u64 {
+    64 - txsz
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.LeafBase
+/// AArch64.LeafBase()
+/// ==================
+/// Extract the address embedded in a block and page descriptor pointing to the
+/// base of a memory block
+pub fn AArch64LeafBase(
+    pe: &ProcessorElement,
+    descriptor: u128,
+    d128: u64,
+    ds: u64,
+    tgx: TGx,
+    level: i64,
+) -> u64 {
+    let granulebits = TGxGranuleBits(tgx);
+    let descsizelog2 = if d128 == 1 { 4 } else { 3 };
+    let stride = granulebits - descsizelog2;
+    let leafsize = granulebits + stride * (FINAL_LEVEL - level) as u64;
+
+    if d128 == 1 {
+        // 128-bit descriptors hold a 56-bit output address
+        return Align(Bits(descriptor, 55, 0), leafsize);
+    }
+
+    let leafbase = Align(Bits(descriptor, 47, 0), leafsize);
+
+    if tgx == TGx::TGx_64KB && AArch64PAMax(pe) >= 52 {
+        // IMPLEMENTATION DEFINED "descriptor[15:12] for 64KB OA" is FALSE
+        return leafbase | (Bits(descriptor, 15, 12) << 48);
+    } else if ds == 1 {
+        return leafbase | (Bits(descriptor, 9, 8) << 50) | (Bits(descriptor, 49, 48) << 48);
+    }
+
+    leafbase
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.MaxTxSZ
+/// AArch64.MaxTxSZ()
+/// =================
+/// Retrieve the maximum value of TxSZ indicating minimum input address size for both
+/// stages of translation
+pub fn AArch64MaxTxSZ(pe: &ProcessorElement, tgx: TGx) -> u64 {
+    if IsFeatureImplemented(pe, Feature::FEAT_TTST) {
+        return match tgx {
+            TGx::TGx_4KB => 48,
+            TGx::TGx_16KB => 48,
+            TGx::TGx_64KB => 47,
+        };
+    }
+    39
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.NextTableBase
+/// AArch64.NextTableBase()
+/// =======================
+/// Extract the address embedded in a table descriptor pointing to the base of
+/// the next level table of descriptors
+pub fn AArch64NextTableBase(
+    pe: &ProcessorElement,
+    descriptor: u128,
+    d128: u64,
+    skl: u64,
+    ds: u64,
+    tgx: TGx,
+) -> u64 {
+    let granulebits = TGxGranuleBits(tgx);
+    let tablesize = if d128 == 1 {
+        let descsizelog2 = 4;
+        let stride = granulebits - descsizelog2;
+        stride * (1 + skl) + descsizelog2
+    } else {
+        granulebits
+    };
+    let tablebase = match tgx {
+        TGx::TGx_4KB => Bits(descriptor, 47, 12) << 12,
+        TGx::TGx_16KB => Bits(descriptor, 47, 14) << 14,
+        TGx::TGx_64KB => Bits(descriptor, 47, 16) << 16,
+    };
+    let tablebase = Align(tablebase, tablesize);
+
+    if d128 == 1 {
+        // 128-bit descriptors hold a 56-bit next-level table address
+        return tablebase | (Bits(descriptor, 55, 48) << 48);
+    }
+
+    if tgx == TGx::TGx_64KB && AArch64PAMax(pe) >= 52 {
+        // IMPLEMENTATION DEFINED "descriptor[15:12] for 64KB OA" is FALSE
+        return tablebase | (Bits(descriptor, 15, 12) << 48);
+    } else if ds == 1 {
+        return tablebase | (Bits(descriptor, 9, 8) << 50) | (Bits(descriptor, 49, 48) << 48);
+    }
+
+    tablebase
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.PAMax
+/// AArch64.PAMax()
+/// ===============
+/// Returns the IMPLEMENTATION DEFINED maximum number of bits capable of representing
+/// physical address for this processor
+pub fn AArch64PAMax(pe: &ProcessorElement) -> u64 {
+    pe.impdef.pamax
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.PhysicalAddressSize
+/// AArch64.PhysicalAddressSize()
+/// =============================
+/// Retrieve the number of bits bounding the physical address
+pub fn AArch64PhysicalAddressSize(
+    pe: &ProcessorElement,
+    d128: u64,
+    ds: u64,
+    encoded_ps: u64,
+    tgx: TGx,
+) -> u64 {
+    let ps = match encoded_ps {
+        0b000 => 32,
+        0b001 => 36,
+        0b010 => 40,
+        0b011 => 42,
+        0b100 => 44,
+        0b101 => 48,
+        0b110 => 52,
+        _ => 56,
+    };
+    let max_ps = if d128 == 1 {
+        AArch64PAMax(pe)
+    } else if tgx != TGx::TGx_64KB && ds == 0 {
+        AArch64PAMax(pe).min(48)
+    } else {
+        AArch64PAMax(pe).min(52)
+    };
+
+    ps.min(max_ps)
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.S1MinTxSZ
+/// AArch64.S1MinTxSZ()
+/// ===================
+/// Retrieve the minimum value of TxSZ indicating maximum input address size for stage 1
+pub fn AArch64S1MinTxSZ(
+    pe: &ProcessorElement,
+    regime: Regime,
+    d128: u64,
+    ds: u64,
+    tgx: TGx,
+) -> u64 {
+    if IsFeatureImplemented(pe, Feature::FEAT_LVA3) && d128 == 1 {
+        // Regimes with two VA ranges use bit 55 to select between them
+        return if HasUnprivileged(regime) { 9 } else { 8 };
+    }
+
+    if (IsFeatureImplemented(pe, Feature::FEAT_LVA) && tgx == TGx::TGx_64KB) || ds == 1 {
+        return 12;
+    }
+
+    16
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.S1SLTTEntryAddress
+/// AArch64.S1SLTTEntryAddress()
+/// ============================
+/// Compute the first stage 1 translation table descriptor address within the
+/// table pointed to by the base at the start level
+pub fn AArch64S1SLTTEntryAddress(
+    level: i64,
+    walkparams: S1TTWParams,
+    ia: u64,
+    tablebase: FullAddress,
+) -> FullAddress {
+    // Input Address size
+    let iasize = AArch64IASize(walkparams.get_txsz());
+    let granulebits = TGxGranuleBits(walkparams.get_tgx());
+    let descsizelog2 = if walkparams.get_d128() == 1 { 4 } else { 3 };
+    let stride = granulebits - descsizelog2;
+    let levels = (FINAL_LEVEL - level) as u64;
+
+    // With 128-bit descriptors, TTBR_ELx.SKL makes the initial lookup
+    // resolve more than one level
+    let lsb = levels * stride + granulebits;
+    let msb = iasize - 1;
+    let index = Bits(ia as u128, msb as u32, lsb as u32) << descsizelog2;
+
+    FullAddress {
+        address: tablebase.address | index,
+        paspace: tablebase.paspace,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.S1StartLevel
+/// AArch64.S1StartLevel()
+/// ======================
+/// Compute the initial lookup level when performing a stage 1 translation
+/// table walk
+pub fn AArch64S1StartLevel(walkparams: S1TTWParams) -> i64 {
+    // Input Address size
+    let iasize = AArch64IASize(walkparams.get_txsz());
+    let granulebits = TGxGranuleBits(walkparams.get_tgx());
+    let descsizelog2 = if walkparams.get_d128() == 1 { 4 } else { 3 };
+    let stride = granulebits - descsizelog2;
+    let s1startlevel = FINAL_LEVEL - (((iasize - 1) - granulebits) / stride) as i64;
+
+    if walkparams.get_d128() == 1 {
+        return s1startlevel + walkparams.get_skl() as i64;
+    }
+
+    s1startlevel
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.S1TTBaseAddress
+/// AArch64.S1TTBaseAddress()
+/// =========================
+/// Retrieve the PA/IPA pointing to the base of the initial translation table of stage 1
+pub fn AArch64S1TTBaseAddress(
+    pe: &ProcessorElement,
+    walkparams: S1TTWParams,
+    regime: Regime,
+    ttbr: u128,
+) -> u64 {
+    if walkparams.get_d128() == 1 {
+        let iasize = AArch64IASize(walkparams.get_txsz());
+        let granulebits = TGxGranuleBits(walkparams.get_tgx());
+        let descsizelog2 = 4;
+        let stride = granulebits - descsizelog2;
+        let startlevel = AArch64S1StartLevel(walkparams);
+        let levels = (FINAL_LEVEL - startlevel) as u64;
+
+        // Base address is aligned to size of the initial translation table in bytes
+        let tsize = ((iasize - (levels * stride + granulebits)) + descsizelog2).max(5);
+        let tablebase = if regime == Regime::Regime_EL3 {
+            Bits(ttbr, 55, 5) << 5
+        } else {
+            (Bits(ttbr, 87, 80) << 48) | (Bits(ttbr, 47, 5) << 5)
+        };
+
+        return Align(tablebase, tsize);
+    }
+
+    // Input address size
+    let iasize = AArch64IASize(walkparams.get_txsz());
+    let granulebits = TGxGranuleBits(walkparams.get_tgx());
+    let descsizelog2 = 3;
+    let stride = granulebits - descsizelog2;
+    let startlevel = AArch64S1StartLevel(walkparams);
+    let levels = (FINAL_LEVEL - startlevel) as u64;
+
+    // Base address is aligned to size of the initial translation table in bytes
+    let tsize = (iasize - (levels * stride + granulebits)) + descsizelog2;
+
+    // IMPLEMENTATION DEFINED "BADDR expresses 52 bits for 64KB granule" is FALSE
+    if walkparams.get_ds() == 1
+        || (walkparams.get_tgx() == TGx::TGx_64KB
+            && walkparams.get_ps() == 0b110
+            && IsFeatureImplemented(pe, Feature::FEAT_LPA))
+    {
+        // TTBR_ELx[5:2] hold bits [51:48] of the base address
+        let tablebase = (Bits(ttbr, 5, 2) << 48) | (Bits(ttbr, 47, 6) << 6);
+        return Align(tablebase, tsize.max(6));
+    }
+
+    let tablebase = Bits(ttbr, 47, 1) << 1;
+
+    Align(tablebase, tsize)
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.S2MinTxSZ
+/// AArch64.S2MinTxSZ()
+/// ===================
+/// Retrieve the minimum value of TxSZ indicating maximum input address size for stage 2
+pub fn AArch64S2MinTxSZ(
+    pe: &ProcessorElement,
+    d128: u64,
+    ds: u64,
+    tgx: TGx,
+    s1aarch64: bool,
+) -> u64 {
+    let ips = if d128 == 0 {
+        if tgx != TGx::TGx_64KB && ds == 0 {
+            AArch64PAMax(pe).min(48)
+        } else {
+            AArch64PAMax(pe).min(52)
+        }
+    } else {
+        AArch64PAMax(pe)
+    };
+
+    let min_txsz = 64 - ips;
+    if !s1aarch64 {
+        // EL1 is AArch32
+        return min_txsz.min(24);
+    }
+
+    min_txsz
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.S2SLTTEntryAddress
+/// AArch64.S2SLTTEntryAddress()
+/// ============================
+/// Compute the first stage 2 translation table descriptor address within the
+/// table pointed to by the base at the start level
+pub fn AArch64S2SLTTEntryAddress(
+    walkparams: S2TTWParams,
+    ipa: u64,
+    tablebase: FullAddress,
+) -> FullAddress {
+    let startlevel = AArch64S2StartLevel(walkparams);
+    let iasize = AArch64IASize(walkparams.get_txsz());
+    let granulebits = TGxGranuleBits(walkparams.get_tgx());
+    let descsizelog2 = if walkparams.get_d128() == 1 { 4 } else { 3 };
+    let stride = granulebits - descsizelog2;
+    let levels = (FINAL_LEVEL - startlevel) as u64;
+
+    // The initial lookup may index into up to 16 concatenated tables
+    let lsb = levels * stride + granulebits;
+    let msb = iasize - 1;
+    let index = Bits(ipa as u128, msb as u32, lsb as u32) << descsizelog2;
+
+    FullAddress {
+        address: tablebase.address | index,
+        paspace: tablebase.paspace,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.S2StartLevel
+/// AArch64.S2StartLevel()
+/// ======================
+/// Determine the initial lookup level when performing a stage 2 translation
+/// table walk
+pub fn AArch64S2StartLevel(walkparams: S2TTWParams) -> i64 {
+    if walkparams.get_d128() == 1 {
+        let iasize = AArch64IASize(walkparams.get_txsz());
+        let granulebits = TGxGranuleBits(walkparams.get_tgx());
+        let descsizelog2 = 4;
+        let stride = granulebits - descsizelog2;
+        let s2startlevel = FINAL_LEVEL - (((iasize - 1) - granulebits) / stride) as i64;
+
+        return s2startlevel + walkparams.get_skl() as i64;
+    }
+
+    match walkparams.get_tgx() {
+        TGx::TGx_4KB => match (walkparams.get_sl2() << 2) | walkparams.get_sl0() {
+            0b000 => 2,
+            0b001 => 1,
+            0b010 => 0,
+            0b011 => 3,
+            0b100 => -1,
+            _ => unreachable!(),
+        },
+        TGx::TGx_16KB => match walkparams.get_sl0() {
+            0b00 => 3,
+            0b01 => 2,
+            0b10 => 1,
+            _ => 0,
+        },
+        TGx::TGx_64KB => match walkparams.get_sl0() {
+            0b00 => 3,
+            0b01 => 2,
+            0b10 => 1,
+            _ => unreachable!(),
+        },
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.S2TTBaseAddress
+/// AArch64.S2TTBaseAddress()
+/// =========================
+/// Retrieve the PA pointing to the base of the initial translation table of stage 2
+pub fn AArch64S2TTBaseAddress(
+    pe: &ProcessorElement,
+    walkparams: S2TTWParams,
+    _paspace: PASpace,
+    ttbr: u128,
+) -> u64 {
+    if walkparams.get_d128() == 1 {
+        let iasize = AArch64IASize(walkparams.get_txsz());
+        let granulebits = TGxGranuleBits(walkparams.get_tgx());
+        let descsizelog2 = 4;
+        let stride = granulebits - descsizelog2;
+        let startlevel = AArch64S2StartLevel(walkparams);
+        let levels = (FINAL_LEVEL - startlevel) as u64;
+
+        // Base address is aligned to size of the initial translation table in bytes
+        let tsize = ((iasize - (levels * stride + granulebits)) + descsizelog2).max(5);
+        let tablebase = (Bits(ttbr, 87, 80) << 48) | (Bits(ttbr, 47, 5) << 5);
+
+        return Align(tablebase, tsize);
+    }
+
+    // Input address size
+    let iasize = AArch64IASize(walkparams.get_txsz());
+    let granulebits = TGxGranuleBits(walkparams.get_tgx());
+    let descsizelog2 = 3;
+    let stride = granulebits - descsizelog2;
+    let startlevel = AArch64S2StartLevel(walkparams);
+    let levels = (FINAL_LEVEL - startlevel) as u64;
+
+    // Base address is aligned to size of the initial translation table in bytes
+    let tsize = (iasize - (levels * stride + granulebits)) + descsizelog2;
+
+    // IMPLEMENTATION DEFINED "BADDR expresses 52 bits for 64KB granule" is FALSE
+    if walkparams.get_ds() == 1
+        || (walkparams.get_tgx() == TGx::TGx_64KB
+            && walkparams.get_ps() == 0b110
+            && IsFeatureImplemented(pe, Feature::FEAT_LPA))
+    {
+        // TTBR_ELx[5:2] hold bits [51:48] of the base address
+        let tablebase = (Bits(ttbr, 5, 2) << 48) | (Bits(ttbr, 47, 6) << 6);
+        return Align(tablebase, tsize.max(6));
+    }
+
+    let tablebase = Bits(ttbr, 47, 1) << 1;
+
+    Align(tablebase, tsize)
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_addrcalc/AArch64.TTEntryAddress
+/// AArch64.TTEntryAddress()
+/// ========================
+/// Compute translation table descriptor address within the table pointed to by
+/// the table base
+pub fn AArch64TTEntryAddress(
+    level: i64,
+    d128: u64,
+    skl: u64,
+    tgx: TGx,
+    txsz: u64,
+    ia: u64,
+    tablebase: FullAddress,
+) -> FullAddress {
+    // Input Address size
+    let iasize = AArch64IASize(txsz);
+    let granulebits = TGxGranuleBits(tgx);
+    let descsizelog2 = if d128 == 1 { 4 } else { 3 };
+    let stride = granulebits - descsizelog2;
+    let levels = (FINAL_LEVEL - level) as u64;
+
+    let lsb = levels * stride + granulebits;
+    let nstride = if d128 == 1 { skl + 1 } else { 1 };
+    let msb = (iasize - 1).min((lsb + (stride * nstride)) - 1);
+    let index = Bits(ia as u128, msb as u32, lsb as u32) << descsizelog2;
+
+    FullAddress {
+        address: tablebase.address | index,
+        paspace: tablebase.paspace,
+    }
+}
+
+/// Restore the extension bits of `va` above the input address size, removing
+/// the Logical Address Tag and any pointer authentication code
+///
+/// Bits [63:IASize] are replaced by copies of bit 55 in regimes with two VA
+/// ranges and by zeros otherwise, which is the address that
+/// [`AArch64VAIsOutOfRange`](crate::translation64_faults::AArch64VAIsOutOfRange)
+/// accepts and the translation table walk resolves.
+pub fn AArch64StripTagAndPAC(va: u64, regime: Regime, walkparams: S1TTWParams) -> u64 {
+    let iasize = AArch64IASize(walkparams.get_txsz());
+    if iasize >= 64 {
+        return va;
+    }
+
+    let mask = !0_u64 << iasize;
+    if HasUnprivileged(regime) && AArch64GetVARange(va) == VARange::VARange_UPPER {
+        va | mask
+    } else {
+        va & !mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn s2_start_level() {
+        let startlevel = |tgx, sl2, sl0| {
+            AArch64S2StartLevel(S2TTWParams {
+                bitfield: S2TTWParamsBits::new()
+                    .with(S2TTWParamsBits::sl2, sl2)
+                    .with(S2TTWParamsBits::sl0, sl0),
+                s2pir: 0,
+                tgx,
+            })
+        };
+        for (sl0, level) in [(0b00, 2), (0b01, 1), (0b10, 0), (0b11, 3)] {
+            assert_eq!(startlevel(TGx::TGx_4KB, 0, sl0), level);
+        }
+        for (sl0, level) in [(0b00, 3), (0b01, 2), (0b10, 1), (0b11, 0)] {
+            assert_eq!(startlevel(TGx::TGx_16KB, 0, sl0), level);
+        }
+        for (sl0, level) in [(0b00, 3), (0b01, 2), (0b10, 1)] {
+            assert_eq!(startlevel(TGx::TGx_64KB, 0, sl0), level);
+        }
+    }
+
+    #[test]
+    fn output_address_52_bits() {
+        let pe = ProcessorElement::from_profile(
+            ArchVersion::Armv8_2,
+            &[Feature::FEAT_LPA, Feature::FEAT_LPA2, Feature::FEAT_LVA],
+        )
+        .unwrap();
+        let pe48 = ProcessorElement::from_profile(ArchVersion::Armv8_2, &[]).unwrap();
+
+        // A 52-bit PS needs 64KB granules or TCR_ELx.DS, and a 52-bit PAMax
+        assert_eq!(
+            AArch64PhysicalAddressSize(&pe, 0, 0, 0b110, TGx::TGx_4KB),
+            48
+        );
+        assert_eq!(
+            AArch64PhysicalAddressSize(&pe, 0, 1, 0b110, TGx::TGx_4KB),
+            52
+        );
+        assert_eq!(
+            AArch64PhysicalAddressSize(&pe, 0, 0, 0b110, TGx::TGx_64KB),
+            52
+        );
+        assert_eq!(
+            AArch64PhysicalAddressSize(&pe48, 0, 0, 0b110, TGx::TGx_64KB),
+            48
+        );
+
+        // With DS, OA[51:50] are in descriptor[9:8] and OA[49:48] in descriptor[49:48]
+        let descriptor = 0x1234_5678_9000 | (0b01 << 48) | (0b10 << 8) | 0b11;
+        assert_eq!(
+            AArch64LeafBase(&pe, descriptor, 0, 1, TGx::TGx_4KB, 3),
+            0x0009_1234_5678_9000
+        );
+        assert_eq!(
+            AArch64NextTableBase(&pe, descriptor, 0, 0, 1, TGx::TGx_4KB),
+            0x0009_1234_5678_9000
+        );
+        // Otherwise the OA is limited to descriptor[47:12]
+        assert_eq!(
+            AArch64LeafBase(&pe, descriptor, 0, 0, TGx::TGx_4KB, 3),
+            0x1234_5678_9000
+        );
+
+        // With 64KB granules, OA[51:48] are in descriptor[15:12]
+        let descriptor = 0x1234_5678_0000 | (0xa << 12) | 0b11;
+        assert_eq!(
+            AArch64LeafBase(&pe, descriptor, 0, 0, TGx::TGx_64KB, 3),
+            0x000a_1234_5678_0000
+        );
+        assert_eq!(
+            AArch64LeafBase(&pe48, descriptor, 0, 0, TGx::TGx_64KB, 3),
+            0x1234_5678_0000
+        );
+    }
+
+    #[test]
+    fn input_address_52_bits() {
+        let pe = ProcessorElement::from_profile(
+            ArchVersion::Armv8_2,
+            &[Feature::FEAT_LPA, Feature::FEAT_LPA2, Feature::FEAT_LVA],
+        )
+        .unwrap();
+        let regime = Regime::Regime_EL10;
+        assert_eq!(AArch64S1MinTxSZ(&pe, regime, 0, 0, TGx::TGx_4KB), 16);
+        assert_eq!(AArch64S1MinTxSZ(&pe, regime, 0, 1, TGx::TGx_4KB), 12);
+        assert_eq!(AArch64S1MinTxSZ(&pe, regime, 0, 0, TGx::TGx_64KB), 12);
+
+        // A 52-bit VA with 4KB granules starts at level -1, and TTBR_ELx[5:2]
+        // hold bits [51:48] of the table base
+        let mut walkparams: S1TTWParams = unsafe { std::mem::zeroed() };
+        walkparams.bitfield = S1TTWParamsBits::new()
+            .with(S1TTWParamsBits::ds, 1)
+            .with(S1TTWParamsBits::txsz, 12);
+        assert_eq!(AArch64S1StartLevel(walkparams), -1);
+        let ttbr = 0x10_0000 | (0b0101 << 2);
+        assert_eq!(
+            AArch64S1TTBaseAddress(&pe, walkparams, regime, ttbr),
+            0x0005_0000_0010_0000
+        );
+    }
+}
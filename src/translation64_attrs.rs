@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+#![allow(non_camel_case_types)]
+
+use crate::pe::*;
+use crate::shared::*;
+use crate::shared_memory::*;
+use crate::shared_vmsa::*;
+
+/// Library pseudocode for aarch64/translation/attrs/AArch64.MAIRAttr
+/// AArch64.MAIRAttr()
+/// ==================
+/// Retrieve the memory attribute encoding indexed in the given MAIR
+pub fn AArch64MAIRAttr(index: u64, mair2: u64, mair: u64) -> u8 {
+    assert!(index < 16);
+    if index > 7 {
+        // Read from LSB at MAIR2
+        (mair2 >> (8 * (index - 8))) as u8
+    } else {
+        (mair >> (8 * index)) as u8
+    }
+}
+
+/// Library pseudocode for aarch64/translation/attrs/AArch64.S2ApplyFWBMemAttrs
+/// AArch64.S2ApplyFWBMemAttrs()
+/// ============================
+/// Apply stage 2 forced Write-Back on stage 1 memory attributes.
+pub fn AArch64S2ApplyFWBMemAttrs(
+    pe: &ProcessorElement,
+    s1_memattrs: MemoryAttributes,
+    walkparams: S2TTWParams,
+    descriptor: u128,
+) -> MemoryAttributes {
+    let s2_attr = Bits(descriptor, 5, 2);
+    let s2_sh = if walkparams.get_ds() == 1 {
+        walkparams.get_sh()
+    } else {
+        Bits(descriptor, 9, 8)
+    };
+    let s2_fnxs = Bit(descriptor, 11);
+
+    let mut memattrs = if s2_attr & 0b0100 == 0 {
+        // S2 Device, S1 any
+        let s2_device = DecodeDevice(s2_attr);
+        MemoryAttributes {
+            memtype: MemType::MemType_Device,
+            device: if s1_memattrs.memtype == MemType::MemType_Device {
+                S2CombineS1Device(s1_memattrs.device, s2_device)
+            } else {
+                s2_device
+            },
+            xs: s1_memattrs.xs,
+            ..s1_memattrs
+        }
+    } else if s2_attr & 0b11 == 0b11 {
+        // S2 attr = S1 attr
+        s1_memattrs
+    } else if s2_attr & 0b11 == 0b10 {
+        // Force writeback
+        let force_wb = |s1_attrhints: MemAttrHints| {
+            if s1_memattrs.memtype == MemType::MemType_Normal
+                && s1_attrhints.attrs != MemAttr::MemAttr_NC
+            {
+                MemAttrHints {
+                    attrs: MemAttr::MemAttr_WB,
+                    ..s1_attrhints
+                }
+            } else {
+                MemAttrHints {
+                    attrs: MemAttr::MemAttr_WB,
+                    hints: MemHint::MemHint_RWA,
+                    transient: false,
+                }
+            }
+        };
+        MemoryAttributes {
+            memtype: MemType::MemType_Normal,
+            inner: force_wb(s1_memattrs.inner),
+            outer: force_wb(s1_memattrs.outer),
+            xs: false,
+            ..s1_memattrs
+        }
+    } else if s1_memattrs.memtype == MemType::MemType_Device {
+        // Non-cacheable unless S1 is device
+        s1_memattrs
+    } else {
+        MemoryAttributes {
+            xs: s1_memattrs.xs,
+            ..NormalNCMemAttr()
+        }
+    };
+
+    let s2_shareability = DecodeShareability(s2_sh);
+    memattrs.shareability = S2CombineS1Shareability(s1_memattrs.shareability, s2_shareability);
+    memattrs.tags = S2MemTagType(pe, memattrs, s1_memattrs.tags);
+    memattrs.notagaccess = IsFeatureImplemented(pe, Feature::FEAT_MTE_PERM)
+        && s2_attr & 0b1110 == 0b1110
+        && memattrs.tags == MemTagType::MemTag_AllocationTagged;
+    if s2_fnxs == 1 {
+        memattrs.xs = false;
+    }
+    memattrs.shareability = EffectiveShareability(memattrs);
+    memattrs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stage 1 attributes for a MAIR encoding, Inner Shareable
+    fn s1(pe: &ProcessorElement, attr: u8) -> MemoryAttributes {
+        let walkparams: S1TTWParams = unsafe { std::mem::zeroed() };
+        S1DecodeMemAttrs(pe, attr, 0b11, true, walkparams)
+    }
+
+    #[test]
+    fn s2_combine_memattrs() {
+        let pe = ProcessorElement::from_profile(ArchVersion::Armv8_0, &[]).unwrap();
+        let combine = |s1_attr: u8, s2_attr: u64, s2_sh: u64| {
+            let s2_memattrs = S2DecodeMemAttrs(&pe, s2_attr, s2_sh, true);
+            S2CombineS1MemAttrs(&pe, s1(&pe, s1_attr), s2_memattrs, true)
+        };
+
+        // Write-Back at both stages keeps the stage 1 allocation hints
+        let memattrs = combine(0xff, 0b1111, 0b00);
+        assert_eq!(memattrs.inner, s1(&pe, 0xff).inner);
+        assert_eq!(memattrs.shareability, Shareability::Shareability_ISH);
+
+        // The stage 2 Non-cacheable inner attribute wins, without hints
+        let memattrs = combine(0xff, 0b1101, 0b10);
+        assert_eq!(memattrs.outer, s1(&pe, 0xff).outer);
+        assert_eq!(memattrs.inner.attrs, MemAttr::MemAttr_NC);
+        assert_eq!(memattrs.inner.hints, MemHint::MemHint_No);
+        assert_eq!(memattrs.shareability, Shareability::Shareability_OSH);
+
+        // The most restrictive Device type wins
+        let memattrs = combine(0xff, 0b0001, 0b11);
+        assert_eq!(memattrs.memtype, MemType::MemType_Device);
+        assert_eq!(memattrs.device, DeviceType::DeviceType_nGnRE);
+        let memattrs = combine(0x0c, 0b0010, 0b11);
+        assert_eq!(memattrs.device, DeviceType::DeviceType_nGRE);
+    }
+
+    #[test]
+    fn s2_fwb_memattrs() {
+        let pe = ProcessorElement::from_profile(ArchVersion::Armv8_4, &[]).unwrap();
+        let walkparams = S2TTWParams {
+            bitfield: S2TTWParamsBits::new(),
+            s2pir: 0,
+            tgx: TGx::TGx_4KB,
+        };
+        let fwb = |s1_attr: u8, s2_attr: u128| {
+            // Non-shareable at stage 2
+            let descriptor = (s2_attr << 2) | 0b11;
+            AArch64S2ApplyFWBMemAttrs(&pe, s1(&pe, s1_attr), walkparams, descriptor)
+        };
+
+        // Forced Write-Back turns stage 1 Non-cacheable into Write-Back
+        let memattrs = fwb(0x44, 0b0110);
+        assert_eq!(memattrs.memtype, MemType::MemType_Normal);
+        assert_eq!(memattrs.inner.attrs, MemAttr::MemAttr_WB);
+        assert_eq!(memattrs.inner.hints, MemHint::MemHint_RWA);
+        // and keeps the hints of stage 1 cacheable memory
+        let memattrs = fwb(0xaa, 0b0110);
+        assert_eq!(memattrs.outer.attrs, MemAttr::MemAttr_WB);
+        assert_eq!(memattrs.outer.hints, MemHint::MemHint_RA);
+        assert_eq!(memattrs.shareability, Shareability::Shareability_ISH);
+        // but overrides stage 1 Device memory
+        assert_eq!(fwb(0x00, 0b0110).memtype, MemType::MemType_Normal);
+
+        // Stage 1 attributes are used as is
+        assert_eq!(fwb(0xaa, 0b0111).inner, s1(&pe, 0xaa).inner);
+        assert_eq!(fwb(0x0c, 0b0111).device, DeviceType::DeviceType_GRE);
+
+        // Non-cacheable, unless stage 1 is Device
+        let memattrs = fwb(0xff, 0b0101);
+        assert_eq!(memattrs.inner.attrs, MemAttr::MemAttr_NC);
+        assert_eq!(memattrs.shareability, Shareability::Shareability_OSH);
+        assert_eq!(fwb(0x08, 0b0101).device, DeviceType::DeviceType_nGRE);
+
+        // Device combines with the stage 1 Device type
+        let memattrs = fwb(0xff, 0b0010);
+        assert_eq!(memattrs.memtype, MemType::MemType_Device);
+        assert_eq!(memattrs.device, DeviceType::DeviceType_nGRE);
+        assert_eq!(fwb(0x00, 0b0010).device, DeviceType::DeviceType_nGnRnE);
+    }
+}
@@ -0,0 +1,1326 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+#![allow(non_camel_case_types)]
+
+use crate::error::*;
+use crate::pe::*;
+use crate::shared::*;
+use crate::shared_memory::*;
+use crate::shared_translation::*;
+use crate::shared_vmsa::*;
+use crate::stubs::*;
+use crate::sysregs::*;
+use crate::translation64_addrcalc::*;
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.ContiguousBitFaults
+/// AArch64.ContiguousBitFaults()
+/// =============================
+/// If contiguous bit is set, returns whether the translation size exceeds the
+/// input address size and if the implementation generates a fault
+pub fn AArch64ContiguousBitFaults(d128: u64, txsz: u64, tgx: TGx, level: i64) -> bool {
+    // Input Address size
+    let iasize = AArch64IASize(txsz);
+    // Translation size
+    let tsize = TranslationSize(d128, tgx, level) + ContiguousSize(d128, tgx, level);
+    // IMPLEMENTATION DEFINED "Translation fault on misprogrammed contiguous bit"
+    let fault = true;
+
+    tsize > iasize && fault
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.OAOutOfRange
+/// AArch64.OAOutOfRange()
+/// ======================
+/// Returns whether output address is expressed in the configured size number of bits
+pub fn AArch64OAOutOfRange(
+    pe: &ProcessorElement,
+    address: u64,
+    d128: u64,
+    ds: u64,
+    ps: u64,
+    tgx: TGx,
+) -> bool {
+    // Output Address size
+    let oasize = AArch64PhysicalAddressSize(pe, d128, ds, ps, tgx);
+
+    if oasize < 56 {
+        !IsZeroBits(address, 55, oasize as u32)
+    } else {
+        false
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.IPAIsOutOfRange
+/// AArch64.IPAIsOutOfRange()
+/// =========================
+/// Check bits not resolved by translation are ZERO
+pub fn AArch64IPAIsOutOfRange(ipa: u64, walkparams: S2TTWParams) -> bool {
+    // Input Address size
+    let iasize = AArch64IASize(walkparams.get_txsz());
+
+    if iasize < 56 {
+        !IsZeroBits(ipa, 55, iasize as u32)
+    } else {
+        false
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S1CheckPermissions
+/// AArch64.S1CheckPermissions()
+/// ============================
+/// Checks whether stage 1 access violates permissions of target memory
+/// and returns a fault record
+pub fn AArch64S1CheckPermissions(
+    pe: &ProcessorElement,
+    fault_in: FaultRecord,
+    regime: Regime,
+    walkstate: TTWState,
+    walkparams: S1TTWParams,
+    accdesc: AccessDescriptor,
+) -> Result<FaultRecord, TranslationError> {
+    let mut fault = fault_in;
+    let s1perms = AArch64S1ComputePermissions(pe, regime, walkstate, walkparams, accdesc)?;
+
+    // DC CIVAC to the PoC requires write permission when SCTLR_ELx.CMOW is set
+    let cmow_civac = walkparams.get_cmow() == 1
+        && accdesc.opscope == CacheOpScope::CacheOpScope_PoC
+        && accdesc.cacheop == CacheOp::CacheOp_CleanInvalidate;
+
+    if accdesc.acctype == AccessType::AccessType_IFETCH {
+        if s1perms.overlay && !s1perms.ox {
+            fault.statuscode = Fault::Fault_Permission;
+            fault.overlay = true;
+        // Instruction fetches from Device memory are CONSTRAINED UNPREDICTABLE,
+        // this model takes Constraint_FAULT.
+        } else if walkstate.memattrs.memtype == MemType::MemType_Device || !s1perms.x {
+            fault.statuscode = Fault::Fault_Permission;
+        }
+    } else if accdesc.acctype == AccessType::AccessType_DC {
+        if accdesc.cacheop == CacheOp::CacheOp_Invalidate {
+            if s1perms.overlay && !s1perms.ow {
+                fault.statuscode = Fault::Fault_Permission;
+                fault.overlay = true;
+            } else if !s1perms.w {
+                fault.statuscode = Fault::Fault_Permission;
+            }
+        // DC from privileged context which clean cannot generate a Permission fault
+        } else if accdesc.el == EL0 {
+            if s1perms.overlay && (!s1perms.or || (cmow_civac && !s1perms.ow)) {
+                fault.statuscode = Fault::Fault_Permission;
+                fault.overlay = true;
+            } else if !s1perms.r || (cmow_civac && !s1perms.w) {
+                fault.statuscode = Fault::Fault_Permission;
+            }
+        }
+    } else if accdesc.acctype == AccessType::AccessType_IC {
+        // IC from privileged context cannot generate Permission fault.
+        // Whether EL0 IC IVAU without read permission faults is IMPLEMENTATION
+        // DEFINED, this model does not fault.
+        if accdesc.el == EL0 && walkparams.get_cmow() == 1 {
+            if s1perms.overlay && !s1perms.ow {
+                fault.statuscode = Fault::Fault_Permission;
+                fault.overlay = true;
+            } else if !s1perms.w {
+                fault.statuscode = Fault::Fault_Permission;
+            }
+        }
+    // FEAT_GCS is not modelled, so the GCS permission never applies
+    } else if accdesc.read && s1perms.overlay && !s1perms.or {
+        fault.statuscode = Fault::Fault_Permission;
+        fault.overlay = true;
+        fault.write = false;
+    } else if accdesc.write && s1perms.overlay && !s1perms.ow {
+        fault.statuscode = Fault::Fault_Permission;
+        fault.overlay = true;
+        fault.write = true;
+    } else if accdesc.read && !s1perms.r {
+        fault.statuscode = Fault::Fault_Permission;
+        fault.write = false;
+    } else if accdesc.write && !s1perms.w {
+        fault.statuscode = Fault::Fault_Permission;
+        fault.write = true;
+    } else if accdesc.write
+        && !(walkparams.get_ha() == 1 && walkparams.get_hd() == 1)
+        && walkparams.get_pie() == 1
+        && walkstate.permissions.ndirty == 1
+    {
+        fault.statuscode = Fault::Fault_Permission;
+        fault.dirtybit = true;
+        fault.write = true;
+    }
+
+    Ok(fault)
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S1HasAlignmentFault
+/// AArch64.S1HasAlignmentFault()
+/// =============================
+/// Returns whether stage 1 output fails alignment requirement on data accesses
+/// to Device memory
+pub fn AArch64S1HasAlignmentFault(
+    accdesc: AccessDescriptor,
+    aligned: bool,
+    ntlsmd: u64,
+    memattrs: MemoryAttributes,
+) -> bool {
+    if accdesc.acctype == AccessType::AccessType_IFETCH {
+        false
+    } else if accdesc.a32lsmd && ntlsmd == 0 {
+        // A32 load/store multiple to Device memory other than Device-GRE, even if
+        // aligned, when SCTLR_ELx.nTLSMD is 0
+        memattrs.memtype == MemType::MemType_Device && memattrs.device != DeviceType::DeviceType_GRE
+    } else if accdesc.acctype == AccessType::AccessType_DCZero {
+        // DC ZVA and its variants to any type of Device memory
+        memattrs.memtype == MemType::MemType_Device
+    } else {
+        // Unaligned accesses to any type of Device memory
+        memattrs.memtype == MemType::MemType_Device && !aligned
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S1ComputePermissions
+/// AArch64.S1ComputePermissions()
+/// ==============================
+/// Computes the overall stage 1 permissions
+pub fn AArch64S1ComputePermissions(
+    pe: &ProcessorElement,
+    regime: Regime,
+    walkstate: TTWState,
+    walkparams: S1TTWParams,
+    accdesc: AccessDescriptor,
+) -> Result<S1AccessControls, TranslationError> {
+    let mut s1perms = if walkparams.get_pie() == 1 {
+        AArch64S1IndirectBasePermissions(pe, regime, walkstate, walkparams, accdesc)
+    } else {
+        AArch64S1DirectBasePermissions(pe, regime, walkstate, walkparams, accdesc)
+    };
+
+    let poenabled = if accdesc.el == EL0 {
+        AArch64S1E0POEnabled(pe, regime, walkparams.get_nv1())
+    } else {
+        AArch64S1POEnabled(pe, regime)
+    };
+    if !poenabled {
+        s1perms.overlay = false;
+    }
+
+    if s1perms.overlay {
+        let s1overlay_perms = AArch64S1OverlayPermissions(pe, regime, walkstate, accdesc)?;
+        s1perms.or = s1overlay_perms.or;
+        s1perms.ow = s1overlay_perms.ow;
+        s1perms.ox = s1overlay_perms.ox;
+    }
+
+    // If wxn is set, overlay execute permission is set to 0
+    if s1perms.overlay && s1perms.wxn && s1perms.ox {
+        s1perms.ow = false;
+    } else if s1perms.wxn {
+        s1perms.x = false;
+    }
+
+    Ok(s1perms)
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S1OverlayPermissions
+/// AArch64.S1OverlayPermissions()
+/// ==============================
+/// Computes the stage 1 overlay permissions
+pub fn AArch64S1OverlayPermissions(
+    pe: &ProcessorElement,
+    regime: Regime,
+    walkstate: TTWState,
+    accdesc: AccessDescriptor,
+) -> Result<S1AccessControls, TranslationError> {
+    let por = AArch64S1POR(pe, regime, accdesc.el)?;
+    let index = 4 * u32::from(walkstate.permissions.po_index);
+    let (or, ow, ox) = match Bits(por.bits().into(), index + 3, index) {
+        0b0000 => (false, false, false), // No access
+        0b0001 => (true, false, false),  // Read
+        0b0010 => (false, false, true),  // Execute
+        0b0011 => (true, false, true),   // Read and execute
+        0b0100 => (false, true, false),  // Write
+        0b0101 => (true, true, false),   // Read and write
+        0b0110 => (false, true, true),   // Write and execute
+        0b0111 => (true, true, true),    // Read, write and execute
+        _ => (false, false, false),      // Reserved
+    };
+
+    Ok(S1AccessControls {
+        r: false,
+        w: false,
+        x: false,
+        gcs: false,
+        overlay: true,
+        or,
+        ow,
+        ox,
+        wxn: false,
+    })
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S1POR
+/// AArch64.S1POR()
+/// ===============
+/// Identify the permission overlay register for the acting EL of the access
+pub fn AArch64S1POR(
+    pe: &ProcessorElement,
+    regime: Regime,
+    el: PrivilegeLevel,
+) -> Result<POR_ELx_REG, TranslationError> {
+    Ok(match regime {
+        Regime::Regime_EL3 => pe.regs.POR_EL3,
+        Regime::Regime_EL2 => pe.regs.POR_EL2,
+        Regime::Regime_EL20 if el == EL0 => pe.regs.POR_EL0,
+        Regime::Regime_EL20 => pe.regs.POR_EL2,
+        Regime::Regime_EL10 if el == EL0 => pe.regs.POR_EL0,
+        Regime::Regime_EL10 => pe.regs.POR_EL1,
+        // AArch32 EL3 is not modelled
+        Regime::Regime_EL30 => return Err(TranslationError::Unsupported("AArch32 EL3")),
+    })
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S1POEnabled
+/// AArch64.S1POEnabled()
+/// =====================
+/// Determine whether stage 1 permission overlay is enabled for privileged accesses
+pub fn AArch64S1POEnabled(pe: &ProcessorElement, regime: Regime) -> bool {
+    if !IsFeatureImplemented(pe, Feature::FEAT_S1POE) {
+        return false;
+    }
+    match regime {
+        Regime::Regime_EL10 => IsTCR2EL1Enabled(pe) && pe.regs.TCR2_EL1.get(TCR2_ELx_REG::POE) == 1,
+        Regime::Regime_EL2 | Regime::Regime_EL20 => {
+            IsTCR2EL2Enabled(pe) && pe.regs.TCR2_EL2.get(TCR2_ELx_REG::POE) == 1
+        }
+        Regime::Regime_EL3 => pe.regs.TCR_EL3.get(TCR_EL3_REG::POE) == 1,
+        Regime::Regime_EL30 => false,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S1E0POEnabled
+/// AArch64.S1E0POEnabled()
+/// =======================
+/// Determine whether stage 1 permission overlay is enabled for unprivileged accesses
+pub fn AArch64S1E0POEnabled(pe: &ProcessorElement, regime: Regime, nv1: u64) -> bool {
+    if !IsFeatureImplemented(pe, Feature::FEAT_S1POE) {
+        return false;
+    }
+    match regime {
+        Regime::Regime_EL10 => {
+            IsTCR2EL1Enabled(pe) && nv1 == 0 && pe.regs.TCR2_EL1.get(TCR2_ELx_REG::E0POE) == 1
+        }
+        Regime::Regime_EL20 => {
+            IsTCR2EL2Enabled(pe) && pe.regs.TCR2_EL2.get(TCR2_ELx_REG::E0POE) == 1
+        }
+        _ => false,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S1DirectBasePermissions
+/// AArch64.S1DirectBasePermissions()
+/// =================================
+/// Computes the stage 1 direct base permissions
+pub fn AArch64S1DirectBasePermissions(
+    pe: &ProcessorElement,
+    regime: Regime,
+    walkstate: TTWState,
+    walkparams: S1TTWParams,
+    accdesc: AccessDescriptor,
+) -> S1AccessControls {
+    let permissions = walkstate.permissions;
+    let (mut pr, mut pw, px);
+    let (mut ur, mut uw, mut ux) = (false, false, false);
+
+    if HasUnprivileged(regime) {
+        // Apply leaf permissions
+        (pr, pw, ur, uw) = match (permissions.ap >> 1) & 0b11 {
+            0b00 => (true, true, false, false),  // Privileged access
+            0b01 => (true, true, true, true),    // No effect
+            0b10 => (true, false, false, false), // Read-only, privileged access
+            _ => (true, false, true, false),     // Read-only
+        };
+
+        // Apply hierarchical permissions
+        (pr, pw, ur, uw) = match permissions.ap_table & 0b11 {
+            0b00 => (pr, pw, ur, uw),       // No effect
+            0b01 => (pr, pw, false, false), // Privileged access
+            0b10 => (pr, false, ur, false), // Read-only
+            _ => (pr, false, false, false), // Read-only, privileged access
+        };
+
+        // Locations writable by unprivileged cannot be executed by privileged
+        px = !(permissions.pxn == 1 || permissions.pxn_table == 1 || uw);
+        ux = !(permissions.uxn == 1 || permissions.uxn_table == 1);
+
+        if IsFeatureImplemented(pe, Feature::FEAT_PAN)
+            && accdesc.pan
+            && !(regime == Regime::Regime_EL10 && walkparams.get_nv1() == 1)
+        {
+            let pan = pe.PSTATE.get(ProcState::PAN) == 1
+                && (ur || uw || (walkparams.get_epan() == 1 && ux));
+            pr = pr && !pan;
+            pw = pw && !pan;
+        }
+    } else {
+        // Apply leaf permissions
+        pr = true;
+        pw = permissions.ap & 0b100 == 0;
+
+        // Apply hierarchical permissions
+        pw = pw && permissions.ap_table & 0b10 == 0;
+
+        px = !(permissions.xn == 1 || permissions.xn_table == 1);
+    }
+
+    let (r, w, mut x) = if accdesc.el == EL0 {
+        (ur, uw, ux)
+    } else {
+        (pr, pw, px)
+    };
+
+    // Compute WXN value
+    let wxn = walkparams.get_wxn() == 1 && w && x;
+
+    // Prevent execution from Non-secure space by PE in secure state if SIF is set
+    if accdesc.ss == SecurityState::SS_Secure
+        && walkstate.baseaddress.paspace == PASpace::PAS_NonSecure
+    {
+        x = x && walkparams.get_sif() == 0;
+    }
+    // Prevent execution from non-Root space by Root
+    if accdesc.ss == SecurityState::SS_Root && walkstate.baseaddress.paspace != PASpace::PAS_Root {
+        x = false;
+    }
+    // Prevent execution from non-Realm space by Realm EL2 and Realm EL2&0
+    if accdesc.ss == SecurityState::SS_Realm
+        && matches!(regime, Regime::Regime_EL2 | Regime::Regime_EL20)
+        && walkstate.baseaddress.paspace != PASpace::PAS_Realm
+    {
+        x = false;
+    }
+
+    S1AccessControls {
+        r,
+        w,
+        x,
+        gcs: false,
+        overlay: true,
+        or: false,
+        ow: false,
+        ox: false,
+        wxn,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S1IndirectBasePermissions
+/// AArch64.S1IndirectBasePermissions()
+/// ===================================
+/// Computes the stage 1 indirect base permissions
+pub fn AArch64S1IndirectBasePermissions(
+    pe: &ProcessorElement,
+    regime: Regime,
+    walkstate: TTWState,
+    walkparams: S1TTWParams,
+    accdesc: AccessDescriptor,
+) -> S1AccessControls {
+    let permissions = walkstate.permissions;
+
+    // Apply privileged indirect permissions
+    let mut p = S1DecodePIEntry(permissions.ppi);
+
+    if accdesc.ss == SecurityState::SS_Secure
+        && walkstate.baseaddress.paspace == PASpace::PAS_NonSecure
+    {
+        // Prevent execution from Non-secure space by PE in secure state if SIF is set
+        p.x = p.x && walkparams.get_sif() == 0;
+    }
+    // Prevent execution from non-Root space by Root
+    if accdesc.ss == SecurityState::SS_Root && walkstate.baseaddress.paspace != PASpace::PAS_Root {
+        p.x = false;
+    }
+    // Prevent execution from non-Realm space by Realm EL2 and Realm EL2&0
+    if accdesc.ss == SecurityState::SS_Realm
+        && matches!(regime, Regime::Regime_EL2 | Regime::Regime_EL20)
+        && walkstate.baseaddress.paspace != PASpace::PAS_Realm
+    {
+        p.x = false;
+    }
+
+    let mut u = S1DecodePIEntry(0b0000);
+    if HasUnprivileged(regime) {
+        // Apply unprivileged indirect permissions
+        u = S1DecodePIEntry(permissions.upi);
+
+        if IsFeatureImplemented(pe, Feature::FEAT_PAN)
+            && accdesc.pan
+            && !(regime == Regime::Regime_EL10 && walkparams.get_nv1() == 1)
+        {
+            // EPAN is treated as 1 with indirect permissions
+            let pan = pe.PSTATE.get(ProcState::PAN) == 1 && (u.r || u.w || u.x);
+            p.r = p.r && !pan;
+            p.w = p.w && !pan;
+        }
+    }
+
+    if accdesc.el == EL0 {
+        u
+    } else {
+        p
+    }
+}
+
+/// Decode a PIR_ELx or PIRE0_ELx permission encoding.
+fn S1DecodePIEntry(pi: u8) -> S1AccessControls {
+    //                                   R      W      X      GCS    WXN    overlay
+    let (r, w, x, gcs, wxn, overlay) = match pi & 0b1111 {
+        0b0000 => (false, false, false, false, false, true), // No access
+        0b0001 => (true, false, false, false, false, true),  // Read
+        0b0010 => (false, false, true, false, false, true),  // Execute
+        0b0011 => (true, false, true, false, false, true),   // Read and execute
+        0b0101 => (true, true, false, false, false, true),   // Read and write
+        0b0110 => (true, true, true, false, true, true),     // Read, write and execute, WXN
+        0b0111 => (true, true, true, false, false, true),    // Read, write and execute
+        0b1000 => (true, false, false, false, false, false), // Read
+        0b1001 => (true, false, false, true, false, false),  // Read and GCS
+        0b1010 => (true, false, true, false, false, false),  // Read and execute
+        0b1100 => (true, true, false, false, false, false),  // Read and write
+        0b1110 => (true, true, true, false, false, false),   // Read, write and execute
+        _ => (false, false, false, false, false, false),     // Reserved
+    };
+    S1AccessControls {
+        r,
+        w,
+        x,
+        gcs,
+        overlay,
+        or: false,
+        ow: false,
+        ox: false,
+        wxn,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S1TxSZFaults
+/// AArch64.S1TxSZFaults()
+/// ======================
+/// Detect erroneous configuration of stage 1 TxSZ field if the implementation
+/// does not constrain the value of TxSZ
+pub fn AArch64S1TxSZFaults(pe: &ProcessorElement, regime: Regime, walkparams: S1TTWParams) -> bool {
+    let mintxsz = AArch64S1MinTxSZ(
+        pe,
+        regime,
+        walkparams.get_d128(),
+        walkparams.get_ds(),
+        walkparams.get_tgx(),
+    );
+    let maxtxsz = AArch64MaxTxSZ(pe, walkparams.get_tgx());
+
+    if walkparams.get_txsz() < mintxsz {
+        // IMPLEMENTATION DEFINED "Fault on TxSZ value below minimum" is FALSE
+        return IsFeatureImplemented(pe, Feature::FEAT_LVA);
+    }
+    if walkparams.get_txsz() > maxtxsz {
+        // IMPLEMENTATION DEFINED "Fault on TxSZ value above maximum" is FALSE
+        return false;
+    }
+
+    false
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S2CheckPermissions
+/// AArch64.S2CheckPermissions()
+/// ============================
+/// Verifies memory access with available permissions.
+pub fn AArch64S2CheckPermissions(
+    pe: &ProcessorElement,
+    fault_in: FaultRecord,
+    walkstate: TTWState,
+    walkparams: S2TTWParams,
+    _ipa: AddressDescriptor,
+    accdesc: AccessDescriptor,
+) -> (FaultRecord, bool) {
+    let mut fault = fault_in;
+    let permissions = walkstate.permissions;
+
+    let mut s2perms = if walkparams.get_s2pie() == 1 {
+        AArch64S2IndirectBasePermissions(walkparams, permissions, accdesc)
+    } else {
+        AArch64S2DirectBasePermissions(permissions, accdesc)
+    };
+
+    // Stage 2 overlays are only defined for the indirect permission scheme
+    s2perms.overlay = walkparams.get_s2pie() == 1
+        && IsFeatureImplemented(pe, Feature::FEAT_S2POE)
+        && pe.regs.VTCR_EL2.get(VTCR_EL2_REG::S2POE) == 1;
+    if s2perms.overlay {
+        let s2overlay_perms = AArch64S2OverlayPermissions(pe, permissions, accdesc);
+        s2perms.or = s2overlay_perms.or;
+        s2perms.ow = s2overlay_perms.ow;
+        s2perms.ow_mmu = s2overlay_perms.ow_mmu;
+        s2perms.ox = s2overlay_perms.ox;
+    }
+
+    // Stage 2 MRO permission for Stage 1, which only exists with FEAT_S2PIE
+    let s2fs1mro = accdesc.acctype == AccessType::AccessType_TTW
+        && s2perms.w_mmu
+        && !s2perms.w
+        && (!s2perms.overlay || s2perms.ow_mmu);
+
+    let (failedread, failedwrite, overlayread, overlaywrite) = match accdesc.acctype {
+        AccessType::AccessType_TTW => {
+            // Prevent translation table walks in Device memory
+            if walkstate.memattrs.memtype == MemType::MemType_Device && walkparams.get_ptw() == 1 {
+                fault.statuscode = Fault::Fault_Permission;
+                fault.write = false;
+                return (fault, s2fs1mro);
+            }
+            // Stage 1 top level tables must be mapped with a TopLevel permission
+            // when VTCR_EL2.TL0 or VTCR_EL2.TL1 is set
+            let toplevel = match accdesc.varange {
+                VARange::VARange_LOWER => walkparams.get_tl0() == 1 && !s2perms.toplevel0,
+                VARange::VARange_UPPER => walkparams.get_tl1() == 1 && !s2perms.toplevel1,
+            };
+            if walkparams.get_s2pie() == 1 && accdesc.toplevel && toplevel {
+                fault.statuscode = Fault::Fault_Permission;
+                fault.write = false;
+                return (fault, s2fs1mro);
+            }
+            (
+                accdesc.read && !s2perms.r,
+                accdesc.write && !(s2perms.w || s2perms.w_mmu),
+                accdesc.read && !s2perms.or,
+                accdesc.write && !(s2perms.ow || s2perms.ow_mmu),
+            )
+        }
+        AccessType::AccessType_IFETCH => (!s2perms.x, false, !s2perms.ox, false),
+        // Cache maintenance by VA that invalidates requires write permission
+        AccessType::AccessType_DC => {
+            let invalidate = accdesc.cacheop == CacheOp::CacheOp_Invalidate;
+            (
+                false,
+                invalidate && !s2perms.w,
+                false,
+                invalidate && !s2perms.ow,
+            )
+        }
+        AccessType::AccessType_IC => (false, false, false, false),
+        _ if accdesc.atomicop => (!s2perms.r, !s2perms.w, !s2perms.or, !s2perms.ow),
+        _ => (
+            accdesc.read && !s2perms.r,
+            accdesc.write && !s2perms.w,
+            accdesc.read && !s2perms.or,
+            accdesc.write && !s2perms.ow,
+        ),
+    };
+
+    if s2perms.overlay && overlayread {
+        fault.statuscode = Fault::Fault_Permission;
+        fault.overlay = true;
+        fault.write = false;
+    } else if s2perms.overlay && overlaywrite {
+        fault.statuscode = Fault::Fault_Permission;
+        fault.overlay = true;
+        fault.write = true;
+    } else if failedread {
+        fault.statuscode = Fault::Fault_Permission;
+        fault.write = false;
+    } else if failedwrite {
+        fault.statuscode = Fault::Fault_Permission;
+        fault.write = true;
+    } else if accdesc.write
+        && accdesc.acctype != AccessType::AccessType_TTW
+        && !(walkparams.get_ha() == 1 && walkparams.get_hd() == 1)
+        && walkparams.get_s2pie() == 1
+        && permissions.s2dirty == 0
+    {
+        fault.statuscode = Fault::Fault_Permission;
+        fault.dirtybit = true;
+        fault.write = true;
+    }
+
+    (fault, s2fs1mro)
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S2DirectBasePermissions
+/// AArch64.S2DirectBasePermissions()
+/// =================================
+/// Computes the stage 2 direct base permissions
+pub fn AArch64S2DirectBasePermissions(
+    permissions: Permissions,
+    accdesc: AccessDescriptor,
+) -> S2AccessControls {
+    let r = permissions.s2ap & 0b01 != 0;
+    let w = permissions.s2ap & 0b10 != 0;
+    let (px, ux) = match (permissions.s2xn, permissions.s2xnx) {
+        (0, 0) => (true, true),
+        (0, _) => (false, true),
+        (_, 0) => (false, false),
+        _ => (true, false),
+    };
+    let x = if accdesc.el == EL0 { ux } else { px };
+
+    S2AccessControls {
+        r,
+        w,
+        w_mmu: w,
+        x,
+        toplevel0: false,
+        toplevel1: false,
+        overlay: false,
+        or: false,
+        ow: false,
+        ow_mmu: false,
+        ox: false,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S2IndirectBasePermissions
+/// AArch64.S2IndirectBasePermissions()
+/// ===================================
+/// Computes the stage 2 indirect base permissions
+pub fn AArch64S2IndirectBasePermissions(
+    walkparams: S2TTWParams,
+    permissions: Permissions,
+    accdesc: AccessDescriptor,
+) -> S2AccessControls {
+    let index = 4 * u32::from(permissions.s2pi_index);
+    S2DecodePIEntry(Bits(walkparams.s2pir.into(), index + 3, index), accdesc.el)
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S2OverlayPermissions
+/// AArch64.S2OverlayPermissions()
+/// ==============================
+/// Computes the stage 2 overlay permissions
+pub fn AArch64S2OverlayPermissions(
+    pe: &ProcessorElement,
+    permissions: Permissions,
+    accdesc: AccessDescriptor,
+) -> S2AccessControls {
+    let index = 4 * u32::from(permissions.s2po_index);
+    let s2po = S2DecodePIEntry(Bits(pe.regs.S2POR_EL1.into(), index + 3, index), accdesc.el);
+
+    S2AccessControls {
+        r: false,
+        w: false,
+        w_mmu: false,
+        x: false,
+        toplevel0: false,
+        toplevel1: false,
+        overlay: true,
+        or: s2po.r,
+        ow: s2po.w,
+        ow_mmu: s2po.w_mmu,
+        ox: s2po.x,
+    }
+}
+
+/// Decode a S2PIR_EL2 or S2POR_EL1 permission encoding.
+fn S2DecodePIEntry(pi: u64, el: PrivilegeLevel) -> S2AccessControls {
+    //                                    R      W      W_MMU  PX     UX     TL0    TL1
+    let (r, w, w_mmu, px, ux, toplevel0, toplevel1) = match pi & 0b1111 {
+        0b0010 => (true, false, true, false, false, false, false), // MRO
+        0b0011 => (true, false, true, false, false, false, true),  // MRO-TL1
+        0b0100 => (false, true, true, false, false, false, false), // Write only
+        0b0110 => (true, false, true, false, false, true, false),  // MRO-TL0
+        0b0111 => (true, false, true, false, false, true, true),   // MRO-TL01
+        0b1000 => (true, false, false, false, false, false, false), // Read only
+        0b1001 => (true, false, false, false, true, false, false), // Read, unprivileged execute
+        0b1010 => (true, false, false, true, false, false, false), // Read, privileged execute
+        0b1011 => (true, false, false, true, true, false, false),  // Read, all execute
+        0b1100 => (true, true, true, false, false, false, false),  // Read and write
+        0b1101 => (true, true, true, false, true, false, false), // Read, write, unprivileged execute
+        0b1110 => (true, true, true, true, false, false, false), // Read, write, privileged execute
+        0b1111 => (true, true, true, true, true, false, false),  // Read, write, all execute
+        _ => (false, false, false, false, false, false, false),  // No access or reserved
+    };
+    S2AccessControls {
+        r,
+        w,
+        w_mmu,
+        x: if el == EL0 { ux } else { px },
+        toplevel0,
+        toplevel1,
+        overlay: false,
+        or: false,
+        ow: false,
+        ow_mmu: false,
+        ox: false,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S2HasAlignmentFault
+/// AArch64.S2HasAlignmentFault()
+/// =============================
+/// Returns whether stage 2 output fails alignment requirement on data accesses
+/// to Device memory
+pub fn AArch64S2HasAlignmentFault(
+    accdesc: AccessDescriptor,
+    aligned: bool,
+    memattrs: MemoryAttributes,
+) -> bool {
+    if accdesc.acctype == AccessType::AccessType_IFETCH {
+        false
+    } else if accdesc.acctype == AccessType::AccessType_DCZero {
+        // DC ZVA and its variants to any type of Device memory
+        memattrs.memtype == MemType::MemType_Device
+    } else {
+        // Unaligned accesses to any type of Device memory
+        memattrs.memtype == MemType::MemType_Device && !aligned
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S2InconsistentSL
+/// AArch64.S2InconsistentSL()
+/// ==========================
+/// Detect inconsistent configuration of stage 2 TxSZ and SL fields
+pub fn AArch64S2InconsistentSL(walkparams: S2TTWParams) -> bool {
+    let startlevel = AArch64S2StartLevel(walkparams);
+    let levels = (FINAL_LEVEL - startlevel) as u64;
+    let granulebits = TGxGranuleBits(walkparams.get_tgx());
+    let descsizelog2 = 3;
+    let stride = granulebits - descsizelog2;
+
+    // Input address size must at least be large enough to be resolved from the start level
+    let sl_min_iasize = levels * stride // Bits resolved by table walk, except initial level
+        + granulebits // Bits directly mapped to output address
+        + 1; // At least 1 more bit to be decoded by initial level
+
+    // Can resolve 4 additional bits with concatenated translation tables
+    let sl_max_iasize = sl_min_iasize + (stride - 1) + 4;
+    // Configured Input Address size
+    let iasize = AArch64IASize(walkparams.get_txsz());
+
+    iasize < sl_min_iasize || iasize > sl_max_iasize
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S2InvalidSL
+/// AArch64.S2InvalidSL()
+/// =====================
+/// Detect invalid configuration of SL field
+pub fn AArch64S2InvalidSL(pe: &ProcessorElement, walkparams: S2TTWParams) -> bool {
+    match walkparams.get_tgx() {
+        TGx::TGx_4KB => match (walkparams.get_sl2() << 2) | walkparams.get_sl0() {
+            // '1x1' and '11x'
+            0b101..=0b111 => true,
+            0b100 => AArch64PAMax(pe) < 52,
+            0b010 => AArch64PAMax(pe) < 44,
+            0b011 => !IsFeatureImplemented(pe, Feature::FEAT_TTST),
+            _ => false,
+        },
+        TGx::TGx_16KB => match walkparams.get_sl0() {
+            0b11 => walkparams.get_ds() == 0 || AArch64PAMax(pe) < 52,
+            0b10 => AArch64PAMax(pe) < 42,
+            _ => false,
+        },
+        TGx::TGx_64KB => match walkparams.get_sl0() {
+            0b11 => true,
+            0b10 => AArch64PAMax(pe) < 44,
+            _ => false,
+        },
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.S2TxSZFaults
+/// AArch64.S2TxSZFaults()
+/// ======================
+/// Detect erroneous configuration of stage 2 TxSZ field if the implementation
+/// does not constrain the value of TxSZ
+pub fn AArch64S2TxSZFaults(
+    pe: &ProcessorElement,
+    walkparams: S2TTWParams,
+    s1aarch64: bool,
+) -> bool {
+    let mintxsz = AArch64S2MinTxSZ(
+        pe,
+        walkparams.get_d128(),
+        walkparams.get_ds(),
+        walkparams.get_tgx(),
+        s1aarch64,
+    );
+    let maxtxsz = AArch64MaxTxSZ(pe, walkparams.get_tgx());
+
+    if walkparams.get_txsz() < mintxsz {
+        // IMPLEMENTATION DEFINED "Fault on TxSZ value below minimum" is FALSE
+        return IsFeatureImplemented(pe, Feature::FEAT_LPA);
+    }
+    if walkparams.get_txsz() > maxtxsz {
+        // IMPLEMENTATION DEFINED "Fault on TxSZ value above maximum" is FALSE
+        return false;
+    }
+
+    false
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_faults/AArch64.VAIsOutOfRange
+/// AArch64.VAIsOutOfRange()
+/// ========================
+/// Check bits not resolved by translation are identical and of accepted value
+pub fn AArch64VAIsOutOfRange(
+    va_in: u64,
+    acctype: AccessType,
+    regime: Regime,
+    walkparams: S1TTWParams,
+) -> bool {
+    let mut va = va_in;
+    let addrtop = AArch64AddrTop(walkparams.get_tbid(), acctype, walkparams.get_tbi());
+
+    // If the VA has a Logical Address Tag then the bits holding the Logical Address Tag are
+    // ignored when checking if the address is out of range.
+    if walkparams.get_mtx() == 1 && acctype != AccessType::AccessType_IFETCH {
+        let tag = if AArch64GetVARange(va) == VARange::VARange_UPPER {
+            0b1111
+        } else {
+            0b0000
+        };
+        va = SetBits(va as u128, 59, 56, tag) as u64;
+    }
+
+    // Input Address size
+    let iasize = AArch64IASize(walkparams.get_txsz());
+
+    // The min value of TxSZ can be 8, with LVA3 implemented.
+    // If TxSZ is set to 8 iasize becomes 64 - 8 = 56
+    // If tbi is also set, addrtop becomes 55
+    // Then the return statements check va<56:55>
+    // The check here is to guard against this corner case.
+    if addrtop < iasize {
+        return false;
+    }
+
+    if HasUnprivileged(regime) && AArch64GetVARange(va) == VARange::VARange_UPPER {
+        !IsOnesBits(va, addrtop as u32, iasize as u32)
+    } else {
+        !IsZeroBits(va, addrtop as u32, iasize as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walkparams(tgx: TGx, sl2: u64, sl0: u64, txsz: u64) -> S2TTWParams {
+        S2TTWParams {
+            bitfield: S2TTWParamsBits::new()
+                .with(S2TTWParamsBits::sl2, sl2)
+                .with(S2TTWParamsBits::sl0, sl0)
+                .with(S2TTWParamsBits::txsz, txsz),
+            s2pir: 0,
+            tgx,
+        }
+    }
+
+    #[test]
+    fn s2_invalid_sl() {
+        // With a 48-bit PAMax
+        let pe = ProcessorElement::new();
+        let invalid = |tgx, sl2, sl0| AArch64S2InvalidSL(&pe, walkparams(tgx, sl2, sl0, 25));
+        for sl0 in 0b00..=0b11 {
+            // Starting at level 3 needs FEAT_TTST
+            assert_eq!(invalid(TGx::TGx_4KB, 0, sl0), sl0 == 0b11, "SL0 {:#b}", sl0);
+            // Level -1 needs a 52-bit PAMax, the other SL2 encodings are reserved
+            assert!(invalid(TGx::TGx_4KB, 1, sl0), "SL0 {:#b}", sl0);
+        }
+        let pe = ProcessorElement::new()
+            .with_feature(Feature::FEAT_TTST)
+            .unwrap();
+        assert!(!AArch64S2InvalidSL(
+            &pe,
+            walkparams(TGx::TGx_4KB, 0, 0b11, 25)
+        ));
+        assert!(!invalid(TGx::TGx_16KB, 0, 0b10));
+        assert!(invalid(TGx::TGx_16KB, 0, 0b11));
+        assert!(!invalid(TGx::TGx_64KB, 0, 0b10));
+        assert!(invalid(TGx::TGx_64KB, 0, 0b11));
+    }
+
+    #[test]
+    fn s2_inconsistent_sl() {
+        // Starting at level 1 with 4KB granules resolves 31 to 43 bits of IPA
+        let inconsistent = |txsz| AArch64S2InconsistentSL(walkparams(TGx::TGx_4KB, 0, 0b01, txsz));
+        assert!(!inconsistent(25));
+        assert!(!inconsistent(64 - 31));
+        assert!(!inconsistent(64 - 43));
+        assert!(inconsistent(64 - 30));
+        assert!(inconsistent(64 - 44));
+    }
+
+    #[test]
+    fn ipa_range() {
+        let walkparams = walkparams(TGx::TGx_4KB, 0, 0b01, 24);
+        assert!(!AArch64IPAIsOutOfRange(0xff_ffff_f000, walkparams));
+        assert!(AArch64IPAIsOutOfRange(1 << 40, walkparams));
+        assert!(AArch64IPAIsOutOfRange(1 << 55, walkparams));
+    }
+
+    /// Check an access against a stage 1 walk state in the EL1&0 regime.
+    fn s1_check(
+        pe: &ProcessorElement,
+        permissions: Permissions,
+        walkparams: S1TTWParams,
+        accdesc: AccessDescriptor,
+    ) -> FaultRecord {
+        let mut walkstate = TTWState::UNKNOWN;
+        walkstate.memattrs = NormalNCMemAttr();
+        walkstate.permissions = permissions;
+        AArch64S1CheckPermissions(
+            pe,
+            FaultRecord::NoFaultForAccess(accdesc),
+            Regime::Regime_EL10,
+            walkstate,
+            walkparams,
+            accdesc,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn s1_direct_permissions() {
+        let mut pe =
+            ProcessorElement::from_profile(ArchVersion::Armv8_1, &[Feature::FEAT_PAN]).unwrap();
+        let walkparams: S1TTWParams = unsafe { std::mem::zeroed() };
+        let mut permissions = TTWState::UNKNOWN.permissions;
+
+        // AP[2:1] == 0b00: privileged read and write only
+        pe.PSTATE.set(ProcState::EL, 1);
+        let load = CreateAccDescGPR(&pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        let store = CreateAccDescGPR(&pe, MemOp::MemOp_STORE, false, true, false).unwrap();
+        let ifetch = CreateAccDescIFetch(&pe).unwrap();
+        let fault = s1_check(&pe, permissions, walkparams, store);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+        let fault = s1_check(&pe, permissions, walkparams, ifetch);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+        pe.PSTATE.set(ProcState::EL, 0);
+        let el0_load = CreateAccDescGPR(&pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        let fault = s1_check(&pe, permissions, walkparams, el0_load);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        assert!(!fault.write);
+
+        // AP[2:1] == 0b11: read only at both levels
+        permissions.ap = 0b11 << 1;
+        let fault = s1_check(&pe, permissions, walkparams, el0_load);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+        let fault = s1_check(&pe, permissions, walkparams, store);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        assert!(fault.write);
+
+        // APTable[1] makes the hierarchy read only despite AP[2:1] == 0b00
+        permissions.ap = 0;
+        permissions.ap_table = 0b10;
+        let fault = s1_check(&pe, permissions, walkparams, store);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        permissions.ap_table = 0;
+
+        // PXN only affects privileged fetches
+        permissions.pxn = 1;
+        let fault = s1_check(&pe, permissions, walkparams, ifetch);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        permissions.pxn = 0;
+
+        // Locations writable at EL0 are never executable at EL1
+        permissions.ap = 0b01 << 1;
+        let fault = s1_check(&pe, permissions, walkparams, ifetch);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+
+        // PAN blocks privileged data accesses to EL0 accessible locations
+        let fault = s1_check(&pe, permissions, walkparams, load);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+        pe.PSTATE.set(ProcState::PAN, 1);
+        let fault = s1_check(&pe, permissions, walkparams, load);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        pe.PSTATE.set(ProcState::PAN, 0);
+
+        // WXN removes execute permission from writable locations
+        permissions.ap = 0;
+        let wxn = S1TTWParams {
+            bitfield: S1TTWParamsBits::new().with(S1TTWParamsBits::wxn, 1),
+            ..walkparams
+        };
+        let fault = s1_check(&pe, permissions, wxn, ifetch);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        permissions.ap = 0b10 << 1;
+        let fault = s1_check(&pe, permissions, wxn, ifetch);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+    }
+
+    /// Check an access against a stage 2 indirect permission walk state.
+    fn s2pie_check(
+        pe: &ProcessorElement,
+        permissions: Permissions,
+        accdesc: AccessDescriptor,
+    ) -> (FaultRecord, bool) {
+        let walkparams = S2TTWParams {
+            bitfield: S2TTWParamsBits::new().with(S2TTWParamsBits::s2pie, 1),
+            // Index 1: MRO, index 2: read only, index 3: read and write
+            s2pir: 0xc820,
+            tgx: TGx::TGx_4KB,
+        };
+        let mut walkstate = TTWState::UNKNOWN;
+        walkstate.memattrs = NormalNCMemAttr();
+        walkstate.permissions = permissions;
+        AArch64S2CheckPermissions(
+            pe,
+            FaultRecord::NoFaultForAccess(accdesc),
+            walkstate,
+            walkparams,
+            AddressDescriptor::UNKNOWN,
+            accdesc,
+        )
+    }
+
+    #[test]
+    fn s2_indirect_and_overlay_permissions() {
+        let mut pe = ProcessorElement::from_profile(
+            ArchVersion::Armv9_4,
+            &[
+                Feature::FEAT_AA64EL2,
+                Feature::FEAT_S1PIE,
+                Feature::FEAT_S1POE,
+                Feature::FEAT_S2PIE,
+                Feature::FEAT_S2POE,
+            ],
+        )
+        .unwrap();
+        pe.PSTATE.set(ProcState::EL, 1);
+        let load = CreateAccDescGPR(&pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        let store = CreateAccDescGPR(&pe, MemOp::MemOp_STORE, false, true, false).unwrap();
+        let mut permissions = TTWState::UNKNOWN.permissions;
+        permissions.s2dirty = 1;
+
+        // Read only
+        permissions.s2pi_index = 2;
+        let (fault, _) = s2pie_check(&pe, permissions, load);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+        let (fault, _) = s2pie_check(&pe, permissions, store);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        assert!(fault.write && !fault.overlay);
+
+        // Read and write, but not yet dirty
+        permissions.s2pi_index = 3;
+        let (fault, _) = s2pie_check(&pe, permissions, store);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+        permissions.s2dirty = 0;
+        let (fault, _) = s2pie_check(&pe, permissions, store);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        assert!(fault.dirtybit);
+        permissions.s2dirty = 1;
+
+        // MRO permits stage 1 descriptor updates, but no other writes
+        permissions.s2pi_index = 1;
+        let mut ttw = CreateAccDescS1TTW(&pe, false, VARange::VARange_LOWER, load).unwrap();
+        ttw.write = true;
+        let (fault, s2fs1mro) = s2pie_check(&pe, permissions, ttw);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+        assert!(s2fs1mro);
+        let (fault, s2fs1mro) = s2pie_check(&pe, permissions, store);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        assert!(!s2fs1mro);
+
+        // S2POR_EL1 index 0 restricts the read and write base permissions to read only
+        pe.regs.VTCR_EL2 = pe.regs.VTCR_EL2.with(VTCR_EL2_REG::S2POE, 1);
+        pe.regs.S2POR_EL1 = 0x8;
+        permissions.s2pi_index = 3;
+        let (fault, _) = s2pie_check(&pe, permissions, load);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+        let (fault, _) = s2pie_check(&pe, permissions, store);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        assert!(fault.write && fault.overlay);
+    }
+
+    #[test]
+    fn va_range_checks() {
+        let ifetch = AccessType::AccessType_IFETCH;
+        let load = AccessType::AccessType_GPR;
+        let el10 = Regime::Regime_EL10;
+        let params = |tbi, tbid, mtx| {
+            let mut walkparams: S1TTWParams = unsafe { std::mem::zeroed() };
+            walkparams.bitfield = S1TTWParamsBits::new()
+                .with(S1TTWParamsBits::txsz, 16)
+                .with(S1TTWParamsBits::tbi, tbi)
+                .with(S1TTWParamsBits::tbid, tbid)
+                .with(S1TTWParamsBits::mtx, mtx);
+            walkparams
+        };
+
+        // 48-bit lower and upper VA ranges
+        let walkparams = params(0, 0, 0);
+        assert!(!AArch64VAIsOutOfRange(
+            0x0000_ffff_ffff_f000,
+            load,
+            el10,
+            walkparams
+        ));
+        assert!(!AArch64VAIsOutOfRange(
+            0xffff_0000_0000_0000,
+            load,
+            el10,
+            walkparams
+        ));
+        assert!(AArch64VAIsOutOfRange(
+            0x0001_0000_0000_0000,
+            load,
+            el10,
+            walkparams
+        ));
+        assert!(AArch64VAIsOutOfRange(
+            0xff00_0000_0000_1000,
+            load,
+            el10,
+            walkparams
+        ));
+        // Regimes with a single VA range have no upper range
+        let el2 = Regime::Regime_EL2;
+        assert!(AArch64VAIsOutOfRange(
+            0xffff_0000_0000_0000,
+            load,
+            el2,
+            walkparams
+        ));
+
+        // TBI ignores the top byte, and bit 55 selects the range
+        let walkparams = params(1, 0, 0);
+        assert!(!AArch64VAIsOutOfRange(
+            0xff00_0000_0000_1000,
+            load,
+            el10,
+            walkparams
+        ));
+        assert!(!AArch64VAIsOutOfRange(
+            0x00ff_8000_0000_0000,
+            ifetch,
+            el10,
+            walkparams
+        ));
+        assert!(AArch64VAIsOutOfRange(
+            0x00fe_8000_0000_0000,
+            load,
+            el10,
+            walkparams
+        ));
+
+        // TBID limits TBI to data accesses
+        let walkparams = params(1, 1, 0);
+        assert!(!AArch64VAIsOutOfRange(
+            0xff00_0000_0000_1000,
+            load,
+            el10,
+            walkparams
+        ));
+        assert!(AArch64VAIsOutOfRange(
+            0xff00_0000_0000_1000,
+            ifetch,
+            el10,
+            walkparams
+        ));
+
+        // MTX ignores the Logical Address Tag of data accesses
+        let walkparams = params(0, 0, 1);
+        assert!(!AArch64VAIsOutOfRange(
+            0x0f00_0000_0000_1000,
+            load,
+            el10,
+            walkparams
+        ));
+        assert!(!AArch64VAIsOutOfRange(
+            0xf5ff_8000_0000_1000,
+            load,
+            el10,
+            walkparams
+        ));
+        assert!(AArch64VAIsOutOfRange(
+            0x1f00_0000_0000_1000,
+            load,
+            el10,
+            walkparams
+        ));
+        assert!(AArch64VAIsOutOfRange(
+            0x0f00_0000_0000_1000,
+            ifetch,
+            el10,
+            walkparams
+        ));
+
+        // Stripping the tag restores the address that the walk resolves
+        let walkparams = params(1, 0, 0);
+        let strip = |va, regime| AArch64StripTagAndPAC(va, regime, walkparams);
+        assert_eq!(strip(0x12ff_8000_0000_1000, el10), 0xffff_8000_0000_1000);
+        assert_eq!(strip(0x1234_0000_0000_1000, el10), 0x1000);
+        assert_eq!(strip(0xffff_8000_0000_1000, el2), 0x8000_0000_1000);
+    }
+
+    #[test]
+    fn alignment_faults() {
+        let mut pe = ProcessorElement::from_profile(ArchVersion::Armv8_0, &[]).unwrap();
+        pe.PSTATE.set(ProcState::EL, 1);
+        let load = CreateAccDescGPR(&pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        let ifetch = CreateAccDescIFetch(&pe).unwrap();
+        let device = |device| MemoryAttributes {
+            memtype: MemType::MemType_Device,
+            device,
+            ..NormalNCMemAttr()
+        };
+        let ngnre = device(DeviceType::DeviceType_nGnRE);
+        let gre = device(DeviceType::DeviceType_GRE);
+        let normal = NormalNCMemAttr();
+
+        // Unaligned accesses fault on Device memory only
+        assert!(AArch64S1HasAlignmentFault(load, false, 1, ngnre));
+        assert!(!AArch64S1HasAlignmentFault(load, true, 1, ngnre));
+        assert!(!AArch64S1HasAlignmentFault(load, false, 1, normal));
+        assert!(!AArch64S1HasAlignmentFault(ifetch, false, 1, ngnre));
+        assert!(AArch64S2HasAlignmentFault(load, false, gre));
+        assert!(!AArch64S2HasAlignmentFault(load, false, normal));
+
+        // DC ZVA to Device memory always faults
+        let dczva = AccessDescriptor {
+            acctype: AccessType::AccessType_DCZero,
+            ..load
+        };
+        assert!(AArch64S1HasAlignmentFault(dczva, true, 1, gre));
+        assert!(AArch64S2HasAlignmentFault(dczva, true, gre));
+
+        // A32 load/store multiple to Device memory other than GRE when SCTLR_ELx.nTLSMD is 0
+        let lsmd = AccessDescriptor {
+            a32lsmd: true,
+            ..load
+        };
+        assert!(AArch64S1HasAlignmentFault(lsmd, true, 0, ngnre));
+        assert!(!AArch64S1HasAlignmentFault(lsmd, true, 0, gre));
+        assert!(!AArch64S1HasAlignmentFault(lsmd, true, 1, ngnre));
+
+        // SCTLR_ELx.A checks every data access
+        assert!(!AArch64UnalignedAccessFaults(&pe, load, 0x1001, 4).unwrap());
+        pe.regs.SCTLR_EL1 = pe.regs.SCTLR_EL1.with(SCTLR_ELx_REG::A, 1);
+        assert!(AArch64UnalignedAccessFaults(&pe, load, 0x1001, 4).unwrap());
+        pe.regs.SCTLR_EL1 = pe.regs.SCTLR_EL1.with(SCTLR_ELx_REG::A, 0);
+
+        // Exclusives must be aligned without FEAT_LSE2
+        let exclusive = CreateAccDescExLDST(&pe, MemOp::MemOp_LOAD, false, false).unwrap();
+        assert!(AArch64UnalignedAccessFaults(&pe, exclusive, 0x1001, 4).unwrap());
+        let mut pe = ProcessorElement::from_profile(ArchVersion::Armv8_4, &[]).unwrap();
+        pe.PSTATE.set(ProcState::EL, 1);
+        assert!(!AArch64UnalignedAccessFaults(&pe, exclusive, 0x1001, 4).unwrap());
+        assert!(AArch64UnalignedAccessFaults(&pe, exclusive, 0x100e, 4).unwrap());
+        // Accesses larger than 16 bytes never fit in one quantity
+        assert!(!AllInAlignedQuantity(0x1000, 0, 16));
+        assert!(!AllInAlignedQuantity(0x1000, 32, 16));
+        assert!(AArch64UnalignedAccessFaults(&pe, exclusive, 0x1001, 32).unwrap());
+        // Each register of a pair needs to be within a 16-byte quantity
+        let pair = AccessDescriptor {
+            ispair: true,
+            ..exclusive
+        };
+        assert!(AArch64UnalignedAccessFaults(&pe, exclusive, 0x1008, 16).unwrap());
+        assert!(!AArch64UnalignedAccessFaults(&pe, pair, 0x1008, 16).unwrap());
+        assert!(!AArch64UnalignedAccessFaults(&pe, pair, 0x1000, 32).unwrap());
+        assert!(AArch64UnalignedAccessFaults(&pe, pair, 0x100c, 16).unwrap());
+
+        // Ordered accesses may cross a 16-byte boundary when SCTLR_ELx.nAA is 1
+        let acquire = AccessDescriptor {
+            acqsc: true,
+            ..load
+        };
+        assert!(!AArch64UnalignedAccessFaults(&pe, acquire, 0x1001, 4).unwrap());
+        assert!(AArch64UnalignedAccessFaults(&pe, acquire, 0x100e, 4).unwrap());
+        pe.regs.SCTLR_EL1 = pe.regs.SCTLR_EL1.with(SCTLR_ELx_REG::nAA, 1);
+        assert!(!AArch64UnalignedAccessFaults(&pe, acquire, 0x100e, 4).unwrap());
+    }
+}
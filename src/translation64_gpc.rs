@@ -0,0 +1,676 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+#![allow(
+    non_camel_case_types,
+    clippy::enum_variant_names,
+    clippy::upper_case_acronyms
+)]
+
+use crate::error::*;
+use crate::pe::*;
+use crate::shared::*;
+use crate::shared_memory::*;
+use crate::shared_vmsa::*;
+use crate::sysregs::*;
+use crate::translation64_addrcalc::*;
+
+/// Library pseudocode for aarch64/translation/gpc/GPCF
+/// GPCF
+/// ====
+/// Possible Granule Protection Check Fault reasons
+#[derive(Default, Copy, Clone, Eq, PartialEq, Debug)]
+pub enum GPCF {
+    /// No fault
+    #[default]
+    GPCF_None,
+    /// GPT address size fault
+    GPCF_AddressSize,
+    /// GPT walk fault
+    GPCF_Walk,
+    /// Synchronous External abort on GPT fetch
+    GPCF_EABT,
+    /// Granule protection fault
+    GPCF_Fail,
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPCFRecord
+/// GPCFRecord
+/// ==========
+/// Full details of a Granule Protection Check Fault
+#[derive(Default, Copy, Clone, Eq, PartialEq, Debug)]
+pub struct GPCFRecord {
+    pub gpf: GPCF,
+    pub level: i64,
+}
+
+/// Library pseudocode for aarch64/translation/gpc/PGSe
+/// PGSe
+/// ====
+/// Physical granule size
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum PGSe {
+    PGS_4KB,
+    PGS_16KB,
+    PGS_64KB,
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPTEntry
+/// GPTEntry
+/// ========
+/// Result of a Granule Protection Table walk
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct GPTEntry {
+    /// GPI value for this region bits(4)
+    pub gpi: u64,
+    /// Region size
+    pub size: u64,
+    /// Contiguous region size
+    pub contig_size: u64,
+    /// GPT level of the descriptor
+    pub level: i64,
+    /// Region base address
+    pub pa_base: u64,
+}
+
+impl GPTEntry {
+    pub const UNKNOWN: Self = Self {
+        gpi: 0,
+        size: 0,
+        contig_size: 0,
+        level: 0,
+        pa_base: 0,
+    };
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPTTable
+/// GPTTable
+/// ========
+/// Decoded level 0 GPT Table descriptor
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct GPTTable {
+    /// Base address of the level 1 table bits(56)
+    pub address: u64,
+}
+
+// GPI encodings
+pub const GPT_NoAccess: u64 = 0b0000;
+pub const GPT_Secure: u64 = 0b1000;
+pub const GPT_NonSecure: u64 = 0b1001;
+pub const GPT_Root: u64 = 0b1010;
+pub const GPT_Realm: u64 = 0b1011;
+pub const GPT_Any: u64 = 0b1111;
+
+// Descriptor type encodings
+pub const GPT_Block: u64 = 0b0001;
+pub const GPT_Table: u64 = 0b0011;
+pub const GPT_Contig: u64 = 0b0001;
+
+// log2 of the region sizes used by the GPT
+pub const GPTRange_4KB: u64 = 12;
+pub const GPTRange_16KB: u64 = 14;
+pub const GPTRange_64KB: u64 = 16;
+pub const GPTRange_2MB: u64 = 21;
+pub const GPTRange_32MB: u64 = 25;
+pub const GPTRange_512MB: u64 = 29;
+pub const GPTRange_1GB: u64 = 30;
+pub const GPTRange_16GB: u64 = 34;
+pub const GPTRange_64GB: u64 = 36;
+pub const GPTRange_512GB: u64 = 39;
+
+/// Library pseudocode for aarch64/translation/gpc/GPCFault
+/// GPCFault()
+/// ==========
+/// Constructs and returns a GPCF
+pub fn GPCFault(gpf: GPCF, level: i64) -> GPCFRecord {
+    GPCFRecord { gpf, level }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPCNoFault
+/// GPCNoFault()
+/// ============
+/// Returns the default properties of a GPCF that does not represent a fault
+pub fn GPCNoFault() -> GPCFRecord {
+    GPCFRecord {
+        gpf: GPCF::GPCF_None,
+        level: 0,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/DecodePGS
+/// DecodePGS()
+/// ===========
+pub fn DecodePGS(pgs: u64) -> PGSe {
+    match pgs {
+        0b00 => PGSe::PGS_4KB,
+        0b10 => PGSe::PGS_16KB,
+        0b01 => PGSe::PGS_64KB,
+        _ => unreachable!(),
+    }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/PGSSize
+/// PGSSize()
+/// =========
+/// log2 of the physical granule size
+pub fn PGSSize(pgs: PGSe) -> u64 {
+    match pgs {
+        PGSe::PGS_4KB => GPTRange_4KB,
+        PGSe::PGS_16KB => GPTRange_16KB,
+        PGSe::PGS_64KB => GPTRange_64KB,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/DecodePPS
+/// DecodePPS()
+/// ===========
+/// Size of region protected by the GPT, in bits.
+pub fn DecodePPS(pe: &ProcessorElement) -> u64 {
+    match pe.regs.GPCCR_EL3.get(GPCCR_EL3_REG::PPS) {
+        0b000 => 32,
+        0b001 => 36,
+        0b010 => 40,
+        0b011 => 42,
+        0b100 => 44,
+        0b101 => 48,
+        0b110 => 52,
+        _ => 56,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPTL0Size
+/// GPTL0Size()
+/// ===========
+/// Returns number of bits covered by a level 0 GPT entry
+pub fn GPTL0Size(pe: &ProcessorElement) -> u64 {
+    match pe.regs.GPCCR_EL3.get(GPCCR_EL3_REG::L0GPTSZ) {
+        0b0000 => GPTRange_1GB,
+        0b0100 => GPTRange_16GB,
+        0b0110 => GPTRange_64GB,
+        0b1001 => GPTRange_512GB,
+        _ => unreachable!(),
+    }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPTLevel0Index
+/// GPTLevel0Index()
+/// ================
+/// Compute the level 0 index based on input PA.
+pub fn GPTLevel0Index(pe: &ProcessorElement, pa: u64) -> u64 {
+    // Input address and index bounds
+    let pps = DecodePPS(pe);
+    let l0sz = GPTL0Size(pe);
+    if pps <= l0sz {
+        return 0;
+    }
+    Bits(pa as u128, (pps - 1) as u32, l0sz as u32)
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPTLevel1Index
+/// GPTLevel1Index()
+/// ================
+/// Compute the level 1 index based on input PA.
+pub fn GPTLevel1Index(pe: &ProcessorElement, pa: u64) -> u64 {
+    // Input address and index bounds
+    let pgs = PGSSize(DecodePGS(pe.regs.GPCCR_EL3.get(GPCCR_EL3_REG::PGS)));
+    let l0sz = GPTL0Size(pe);
+    Bits(pa as u128, (l0sz - 1) as u32, (pgs + 4) as u32)
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPIIndex
+/// GPIIndex()
+/// ==========
+/// Compute the index of the GPI of a granule within a level 1 Granules descriptor.
+pub fn GPIIndex(pe: &ProcessorElement, pa: u64) -> u64 {
+    let pgs = PGSSize(DecodePGS(pe.regs.GPCCR_EL3.get(GPCCR_EL3_REG::PGS)));
+    Bits(pa as u128, (pgs + 3) as u32, pgs as u32)
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPIValid
+/// GPIValid()
+/// ==========
+/// Returns whether a given value is a valid encoding for a GPI value
+pub fn GPIValid(gpi: u64) -> bool {
+    // FEAT_RME_GPC2 Non-secure only GPI is not implemented
+    matches!(
+        gpi,
+        GPT_NoAccess | GPT_Secure | GPT_NonSecure | GPT_Root | GPT_Realm | GPT_Any
+    )
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPTL0EntryIsValid
+/// GPTL0EntryIsValid()
+/// ===================
+/// Returns whether a level 0 GPT entry is a valid Block or Table descriptor
+pub fn GPTL0EntryIsValid(gpt_entry: u64) -> bool {
+    match Bits(gpt_entry as u128, 3, 0) {
+        GPT_Block => IsZeroBits(gpt_entry, 63, 8) && GPIValid(Bits(gpt_entry as u128, 7, 4)),
+        GPT_Table => IsZeroBits(gpt_entry, 63, 52) && IsZeroBits(gpt_entry, 11, 4),
+        _ => false,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPTL1EntryIsValid
+/// GPTL1EntryIsValid()
+/// ===================
+/// Returns whether a level 1 GPT entry is a valid Contiguous or Granules descriptor
+pub fn GPTL1EntryIsValid(gpt_entry: u64) -> bool {
+    if Bits(gpt_entry as u128, 3, 0) == GPT_Contig {
+        return IsZeroBits(gpt_entry, 63, 10)
+            && Bits(gpt_entry as u128, 9, 8) != 0b00
+            && GPIValid(Bits(gpt_entry as u128, 7, 4));
+    }
+    (0..16).all(|i| GPIValid(Bits(gpt_entry as u128, i * 4 + 3, i * 4)))
+}
+
+/// Library pseudocode for aarch64/translation/gpc/DecodeGPTBlock
+/// DecodeGPTBlock()
+/// ================
+/// Decode a level 0 GPT Block descriptor
+pub fn DecodeGPTBlock(pe: &ProcessorElement, pgs: PGSe, gpt_entry: u64) -> GPTEntry {
+    assert!(Bits(gpt_entry as u128, 3, 0) == GPT_Block);
+    // GPT information from a level 0 GPT Block descriptor is permitted
+    // to be cached in a TLB as though the Block is a contiguous region
+    // of granules each of the size configured in GPCCR_EL3.PGS.
+    GPTEntry {
+        gpi: Bits(gpt_entry as u128, 7, 4),
+        size: PGSSize(pgs),
+        contig_size: GPTL0Size(pe),
+        level: 0,
+        pa_base: 0,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/DecodeGPTContiguous
+/// DecodeGPTContiguous()
+/// =====================
+/// Decode a level 1 GPT Contiguous descriptor
+pub fn DecodeGPTContiguous(pgs: PGSe, gpt_entry: u64) -> GPTEntry {
+    assert!(Bits(gpt_entry as u128, 3, 0) == GPT_Contig);
+    GPTEntry {
+        gpi: Bits(gpt_entry as u128, 7, 4),
+        size: PGSSize(pgs),
+        contig_size: match Bits(gpt_entry as u128, 9, 8) {
+            0b01 => GPTRange_2MB,
+            0b10 => GPTRange_32MB,
+            0b11 => GPTRange_512MB,
+            _ => unreachable!(),
+        },
+        level: 1,
+        pa_base: 0,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/DecodeGPTGranules
+/// DecodeGPTGranules()
+/// ===================
+/// Decode the GPI at `index` of a level 1 GPT Granules descriptor
+pub fn DecodeGPTGranules(pgs: PGSe, index: u64, gpt_entry: u64) -> GPTEntry {
+    GPTEntry {
+        gpi: Bits(
+            gpt_entry as u128,
+            (index * 4 + 3) as u32,
+            (index * 4) as u32,
+        ),
+        size: PGSSize(pgs),
+        // No contiguity
+        contig_size: PGSSize(pgs),
+        level: 1,
+        pa_base: 0,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/DecodeGPTTable
+/// DecodeGPTTable()
+/// ================
+/// Decode a level 0 GPT Table descriptor
+pub fn DecodeGPTTable(pgs: PGSe, gpt_entry: u64) -> GPTTable {
+    assert!(Bits(gpt_entry as u128, 3, 0) == GPT_Table);
+    // The level 1 table is aligned to its size, which is at least that of
+    // a table covering the smallest level 0 region
+    let align = match pgs {
+        PGSe::PGS_4KB => 17,
+        PGSe::PGS_16KB => 15,
+        PGSe::PGS_64KB => 13,
+    };
+    GPTTable {
+        address: Align(Bits(gpt_entry as u128, 51, 12) << 12, align),
+    }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPCRegistersConsistent
+/// GPCRegistersConsistent()
+/// ========================
+/// Returns whether the GPT registers are configured correctly.
+/// This returns false if any fields select a Reserved value.
+pub fn GPCRegistersConsistent(pe: &ProcessorElement) -> bool {
+    let gpccr = pe.regs.GPCCR_EL3;
+    // Check for Physical Address Size
+    if DecodePPS(pe) > AArch64PAMax(pe) {
+        return false;
+    }
+    // Check for reserved L0GPTSZ encoding
+    if !matches!(
+        gpccr.get(GPCCR_EL3_REG::L0GPTSZ),
+        0b0000 | 0b0100 | 0b0110 | 0b1001
+    ) {
+        return false;
+    }
+    // Check for reserved PGS encoding
+    if gpccr.get(GPCCR_EL3_REG::PGS) == 0b11 {
+        return false;
+    }
+    // Check for reserved SH encoding
+    if gpccr.get(GPCCR_EL3_REG::SH) == 0b01 {
+        return false;
+    }
+    // Inner and Outer Non-cacheable GPT fetches must be Outer Shareable
+    if gpccr.get(GPCCR_EL3_REG::IRGN) == 0b00
+        && gpccr.get(GPCCR_EL3_REG::ORGN) == 0b00
+        && gpccr.get(GPCCR_EL3_REG::SH) != 0b10
+    {
+        return false;
+    }
+    true
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPICheck
+/// GPICheck()
+/// ==========
+/// Returns whether an access to a given physical address space is permitted
+/// given the configured GPI value.
+/// paspace: Physical address space of the access
+/// gpi: Value read from GPT for the access
+pub fn GPICheck(paspace: PASpace, gpi: u64) -> bool {
+    match gpi {
+        GPT_NoAccess => false,
+        GPT_Secure => paspace == PASpace::PAS_Secure,
+        GPT_NonSecure => paspace == PASpace::PAS_NonSecure,
+        GPT_Root => paspace == PASpace::PAS_Root,
+        GPT_Realm => paspace == PASpace::PAS_Realm,
+        GPT_Any => true,
+        _ => unreachable!(),
+    }
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GPTWalk
+/// GPTWalk()
+/// =========
+/// Get the GPT entry for a given physical address, pa
+pub fn GPTWalk(
+    pe: &ProcessorElement,
+    mem: &dyn PhysicalMemory,
+    target_pa: u64,
+    accdesc: AccessDescriptor,
+) -> Result<(GPCFRecord, GPTEntry), TranslationError> {
+    let gpccr = pe.regs.GPCCR_EL3;
+    let pgs = DecodePGS(gpccr.get(GPCCR_EL3_REG::PGS));
+
+    // The level 0 GPT base address is aligned to the greater of:
+    // * the size of the level 0 GPT, determined by GPCCR_EL3.{PPS, L0GPTSZ}.
+    // * 4KB
+    let pps = DecodePPS(pe);
+    let l0sz = GPTL0Size(pe);
+    let alignment = (pps.saturating_sub(l0sz) + 3).max(12);
+    let base = Align(Bits(pe.regs.GPTBR_EL3 as u128, 39, 0) << 12, alignment);
+
+    let gptaccdesc = CreateAccDescGPTW(pe, accdesc)?;
+
+    // Access attributes and address for GPT fetches
+    let mut gptaddrdesc = AddressDescriptor {
+        fault: FaultRecord::NoFaultForAccess(gptaccdesc),
+        memattrs: WalkMemAttrs(
+            gpccr.get(GPCCR_EL3_REG::SH),
+            gpccr.get(GPCCR_EL3_REG::IRGN),
+            gpccr.get(GPCCR_EL3_REG::ORGN),
+        ),
+        paddress: FullAddress {
+            paspace: PASpace::PAS_Root,
+            address: base + GPTLevel0Index(pe, target_pa) * 8,
+        },
+        ..AddressDescriptor::UNKNOWN
+    };
+
+    // Fetch L0GPT entry
+    let (memstatus, bytes) = PhysMemRead(mem, gptaddrdesc, 8, gptaccdesc);
+    let level_0_entry = match bytes.first_chunk() {
+        Some(entry) if !IsFault(memstatus.statuscode) => u64::from_le_bytes(*entry),
+        // A backend that returns fewer bytes than requested terminated the access
+        _ => return Ok((GPCFault(GPCF::GPCF_EABT, 0), GPTEntry::UNKNOWN)),
+    };
+
+    if !GPTL0EntryIsValid(level_0_entry) {
+        return Ok((GPCFault(GPCF::GPCF_Walk, 0), GPTEntry::UNKNOWN));
+    }
+
+    let table = match Bits(level_0_entry as u128, 3, 0) {
+        GPT_Block => {
+            // Decode the GPI value and return that
+            let mut result = DecodeGPTBlock(pe, pgs, level_0_entry);
+            result.pa_base = Align(target_pa, l0sz);
+            return Ok((GPCNoFault(), result));
+        }
+        GPT_Table => {
+            // Decode the table entry and continue walking
+            let table = DecodeGPTTable(pgs, level_0_entry);
+            // The address must be within the range covered by the GPT
+            if !IsZeroBits(table.address, 63, AArch64PAMax(pe) as u32) {
+                return Ok((GPCFault(GPCF::GPCF_AddressSize, 0), GPTEntry::UNKNOWN));
+            }
+            table
+        }
+        // An invalid encoding would be caught by GPTL0EntryIsValid()
+        _ => unreachable!(),
+    };
+
+    // Address of level 1 GPT entry
+    let offset = GPTLevel1Index(pe, target_pa) * 8;
+    gptaddrdesc.paddress.address = table.address + offset;
+
+    // Fetch L1GPT entry
+    let (memstatus, bytes) = PhysMemRead(mem, gptaddrdesc, 8, gptaccdesc);
+    let level_1_entry = match bytes.first_chunk() {
+        Some(entry) if !IsFault(memstatus.statuscode) => u64::from_le_bytes(*entry),
+        // A backend that returns fewer bytes than requested terminated the access
+        _ => return Ok((GPCFault(GPCF::GPCF_EABT, 1), GPTEntry::UNKNOWN)),
+    };
+
+    if !GPTL1EntryIsValid(level_1_entry) {
+        return Ok((GPCFault(GPCF::GPCF_Walk, 1), GPTEntry::UNKNOWN));
+    }
+
+    let mut result = if Bits(level_1_entry as u128, 3, 0) == GPT_Contig {
+        DecodeGPTContiguous(pgs, level_1_entry)
+    } else {
+        DecodeGPTGranules(pgs, GPIIndex(pe, target_pa), level_1_entry)
+    };
+    result.pa_base = Align(target_pa, result.contig_size);
+
+    Ok((GPCNoFault(), result))
+}
+
+/// Library pseudocode for aarch64/translation/gpc/GranuleProtectionCheck
+/// GranuleProtectionCheck()
+/// ========================
+/// Returns whether a given access is permitted, according to the
+/// granule protection check.
+/// addrdesc and accdesc describe the access to be checked.
+pub fn GranuleProtectionCheck(
+    pe: &ProcessorElement,
+    mem: &dyn PhysicalMemory,
+    addrdesc: AddressDescriptor,
+    accdesc: AccessDescriptor,
+) -> Result<GPCFRecord, TranslationError> {
+    assert!(IsFeatureImplemented(pe, Feature::FEAT_RME));
+
+    // The address to be checked
+    let address = addrdesc.paddress;
+
+    // Bypass mode - all accesses pass
+    if pe.regs.GPCCR_EL3.get(GPCCR_EL3_REG::GPC) == 0 {
+        return Ok(GPCNoFault());
+    }
+
+    // Configuration consistency check
+    if !GPCRegistersConsistent(pe) {
+        return Ok(GPCFault(GPCF::GPCF_Walk, 0));
+    }
+
+    // Input address size check
+    let pps = DecodePPS(pe);
+    if !IsZeroBits(address.address, 63, pps as u32) {
+        // Only Root PAS accesses are permitted beyond the protected
+        // physical address space
+        if address.paspace == PASpace::PAS_Root {
+            return Ok(GPCNoFault());
+        }
+        return Ok(GPCFault(GPCF::GPCF_Fail, 0));
+    }
+
+    // GPT base address size check
+    let gpt_base = Bits(pe.regs.GPTBR_EL3 as u128, 39, 0) << 12;
+    if !IsZeroBits(gpt_base, 63, AArch64PAMax(pe) as u32) {
+        return Ok(GPCFault(GPCF::GPCF_AddressSize, 0));
+    }
+
+    // GPT lookup
+    let (gpf, gpt_entry) = GPTWalk(pe, mem, address.address, accdesc)?;
+    if gpf.gpf != GPCF::GPCF_None {
+        return Ok(gpf);
+    }
+
+    // Check input physical address space against GPI
+    if !GPICheck(address.paspace, gpt_entry.gpi) {
+        return Ok(GPCFault(GPCF::GPCF_Fail, gpt_entry.level));
+    }
+
+    // Check passed
+    Ok(GPCNoFault())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physmem::*;
+
+    /// A Non-secure EL1 PE with a 4GB protected physical address space, 4KB physical
+    /// granules and 1GB level 0 entries, and a GPT in `mem` where:
+    /// * `0x0000_0000`: block of any access
+    /// * `0x4000_0000`: Realm, Non-secure, then no access granules, followed by a contiguous
+    ///   Realm range
+    /// * `0x8000_0000`: invalid level 0 entry
+    fn gpc_pe() -> (ProcessorElement, SparseMemory) {
+        let mut pe = ProcessorElement::from_profile(
+            ArchVersion::Armv9_2,
+            &[Feature::FEAT_RME, Feature::FEAT_AA64EL3],
+        )
+        .unwrap();
+        pe.PSTATE.set(ProcState::EL, 1);
+        pe.regs.SCR_EL3 = pe.regs.SCR_EL3.with(SCR_EL3_REG::NS, 1);
+        pe.regs.GPCCR_EL3 = GPCCR_EL3_REG::new()
+            .with(GPCCR_EL3_REG::SH, 0b11)
+            .with(GPCCR_EL3_REG::IRGN, 0b01)
+            .with(GPCCR_EL3_REG::ORGN, 0b01)
+            .with(GPCCR_EL3_REG::GPC, 1);
+        pe.regs.GPTBR_EL3 = 0x10_0000 >> 12;
+
+        let gpt = TableBuilder::new(0x10_0000, 12, 0);
+        let mut mem = SparseMemory::new();
+        gpt.store(&mut mem, 0, 0, (GPT_Any << 4 | GPT_Block).into());
+        gpt.store(&mut mem, 0, 1, (gpt.table(1) | GPT_Table).into());
+        gpt.store(&mut mem, 1, 0, (GPT_NonSecure << 4 | GPT_Realm).into());
+        // Contiguous descriptor for a 2MB range
+        gpt.store(
+            &mut mem,
+            1,
+            1,
+            (0b01 << 8 | GPT_Realm << 4 | GPT_Contig).into(),
+        );
+        (pe, mem)
+    }
+
+    fn gpc(pe: &ProcessorElement, mem: &dyn PhysicalMemory, address: u64) -> GPCFRecord {
+        let accdesc = CreateAccDescGPR(pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        let addrdesc = AddressDescriptor {
+            paddress: FullAddress {
+                paspace: PASpace::PAS_NonSecure,
+                address,
+            },
+            ..AddressDescriptor::UNKNOWN
+        };
+        GranuleProtectionCheck(pe, mem, addrdesc, accdesc).unwrap()
+    }
+
+    #[test]
+    fn gpt_walk() {
+        let (pe, mem) = gpc_pe();
+
+        assert_eq!(gpc(&pe, &mem, 0x1000), GPCNoFault());
+        assert_eq!(gpc(&pe, &mem, 0x4000_1000), GPCNoFault());
+        assert_eq!(gpc(&pe, &mem, 0x4000_0000), GPCFault(GPCF::GPCF_Fail, 1));
+        assert_eq!(gpc(&pe, &mem, 0x4000_2000), GPCFault(GPCF::GPCF_Fail, 1));
+        assert_eq!(gpc(&pe, &mem, 0x4001_0000), GPCFault(GPCF::GPCF_Fail, 1));
+        assert_eq!(gpc(&pe, &mem, 0x8000_0000), GPCFault(GPCF::GPCF_Walk, 0));
+        // Outside of the protected physical address space
+        assert_eq!(gpc(&pe, &mem, 0x1_0000_0000), GPCFault(GPCF::GPCF_Fail, 0));
+    }
+
+    #[test]
+    fn gpt_walk_configuration() {
+        let (mut pe, mem) = gpc_pe();
+
+        // Reserved physical granule size
+        pe.regs.GPCCR_EL3 = pe.regs.GPCCR_EL3.with(GPCCR_EL3_REG::PGS, 0b11);
+        assert_eq!(gpc(&pe, &mem, 0x1000), GPCFault(GPCF::GPCF_Walk, 0));
+
+        // Granule protection checks disabled
+        pe.regs.GPCCR_EL3 = pe.regs.GPCCR_EL3.with(GPCCR_EL3_REG::GPC, 0);
+        assert_eq!(gpc(&pe, &mem, 0x4000_0000), GPCNoFault());
+    }
+
+    /// A backend that returns fewer bytes than requested from `short`.
+    struct ShortMemory {
+        inner: SparseMemory,
+        short: u64,
+    }
+
+    impl PhysicalMemory for ShortMemory {
+        fn read(
+            &self,
+            desc: AddressDescriptor,
+            size: usize,
+            accdesc: AccessDescriptor,
+        ) -> (PhysMemRetStatus, Vec<u8>) {
+            let (memstatus, mut bytes) = self.inner.read(desc, size, accdesc);
+            if desc.paddress.address == self.short {
+                bytes.truncate(size / 2);
+            }
+            (memstatus, bytes)
+        }
+
+        fn write(
+            &mut self,
+            desc: AddressDescriptor,
+            size: usize,
+            accdesc: AccessDescriptor,
+            value: &[u8],
+        ) -> PhysMemRetStatus {
+            self.inner.write(desc, size, accdesc, value)
+        }
+    }
+
+    #[test]
+    fn gpt_short_read() {
+        let (pe, inner) = gpc_pe();
+
+        let mem = ShortMemory {
+            inner,
+            short: 0x10_0008,
+        };
+        assert_eq!(gpc(&pe, &mem, 0x4000_0000), GPCFault(GPCF::GPCF_EABT, 0));
+
+        let mem = ShortMemory {
+            inner: mem.inner,
+            short: 0x20_0000,
+        };
+        assert_eq!(gpc(&pe, &mem, 0x4000_0000), GPCFault(GPCF::GPCF_EABT, 1));
+    }
+}
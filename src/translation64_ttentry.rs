@@ -0,0 +1,337 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+#![allow(non_camel_case_types)]
+
+use crate::pe::*;
+use crate::shared::*;
+use crate::shared_memory::*;
+use crate::shared_translation::*;
+use crate::shared_vmsa::*;
+use crate::stubs::*;
+use crate::translation64_addrcalc::*;
+
+/// Library pseudocode for aarch64/translation/vmsa_ttentry/AArch64.BlockDescSupported
+/// AArch64.BlockDescSupported()
+/// ============================
+/// Determine whether a block descriptor is valid for the given granule size
+/// and level
+pub fn AArch64BlockDescSupported(
+    pe: &ProcessorElement,
+    d128: u64,
+    ds: u64,
+    tgx: TGx,
+    level: i64,
+) -> bool {
+    match tgx {
+        TGx::TGx_4KB => (level == 0 && (ds == 1 || d128 == 1)) || level == 1 || level == 2,
+        TGx::TGx_16KB => (level == 1 && (ds == 1 || d128 == 1)) || level == 2,
+        TGx::TGx_64KB => (level == 1 && (d128 == 1 || AArch64PAMax(pe) >= 52)) || level == 2,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_ttentry/AArch64.ContiguousBit
+/// AArch64.ContiguousBit()
+/// =======================
+/// Get the value of the contiguous bit
+pub fn AArch64ContiguousBit(tgx: TGx, d128: u64, level: i64, descriptor: u128) -> bool {
+    if d128 == 1 {
+        if (tgx == TGx::TGx_64KB && level == 1) || (tgx == TGx::TGx_4KB && level == 0) {
+            return false; // RES0
+        }
+        return Bit(descriptor, 111) == 1;
+    }
+
+    // When using TGx 64KB and FEAT_LPA is implememted,
+    // the Contiguous bit is RES0 for Block descriptors at level 1
+    if tgx == TGx::TGx_64KB && level == 1 {
+        return false; // RES0
+    }
+
+    // When the effective value of TCR_ELx.DS is '1',
+    // the Contiguous bit is RES0 for all the following:
+    //   * For TGx 4KB, Block descriptors at level 0
+    //   * For TGx 16KB, Block descriptors at level 1
+    if tgx == TGx::TGx_16KB && level == 1 {
+        return false; // RES0
+    }
+    if tgx == TGx::TGx_4KB && level == 0 {
+        return false; // RES0
+    }
+
+    Bit(descriptor, 52) == 1
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_ttentry/AArch64.DecodeDescriptorType
+/// AArch64.DecodeDescriptorType()
+/// ==============================
+/// Determine whether the descriptor is a page, block or table
+pub fn AArch64DecodeDescriptorType(
+    pe: &ProcessorElement,
+    descriptor: u128,
+    d128: u64,
+    ds: u64,
+    tgx: TGx,
+    level: i64,
+) -> DescriptorType {
+    if Bit(descriptor, 0) == 0 {
+        DescriptorType::DescriptorType_Invalid
+    } else if Bit(descriptor, 1) == 1 {
+        if level == FINAL_LEVEL {
+            DescriptorType::DescriptorType_Leaf
+        } else if d128 == 1 {
+            // The next-level table skips SKL levels, and must not go past the final level
+            let skl = Bits(descriptor, 110, 109);
+            if (matches!(tgx, TGx::TGx_16KB | TGx::TGx_64KB) && skl == 3)
+                || level + skl as i64 >= FINAL_LEVEL
+            {
+                DescriptorType::DescriptorType_Invalid
+            } else {
+                DescriptorType::DescriptorType_Table
+            }
+        } else {
+            DescriptorType::DescriptorType_Table
+        }
+    } else if AArch64BlockDescSupported(pe, d128, ds, tgx, level) {
+        DescriptorType::DescriptorType_Leaf
+    } else {
+        DescriptorType::DescriptorType_Invalid
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_ttentry/AArch64.S1ApplyOutputPerms
+/// AArch64.S1ApplyOutputPerms()
+/// ============================
+/// Apply output permissions encoded in stage 1 page/block descriptors
+pub fn AArch64S1ApplyOutputPerms(
+    pe: &ProcessorElement,
+    permissions_in: Permissions,
+    descriptor: u128,
+    regime: Regime,
+    walkparams: S1TTWParams,
+) -> Permissions {
+    let mut permissions = permissions_in;
+
+    if walkparams.get_pie() == 1 {
+        let pi_index = if walkparams.get_d128() == 1 {
+            Bits(descriptor, 118, 115)
+        } else {
+            (Bits(descriptor, 54, 53) << 2) | (Bit(descriptor, 51) << 1) | Bit(descriptor, 6)
+        };
+        permissions.ppi = AArch64S1PIEntry(walkparams.pir, pi_index);
+        permissions.upi = AArch64S1PIEntry(walkparams.pire0, pi_index);
+        permissions.ndirty = Bit(descriptor, 7) as u8;
+    } else {
+        if regime == Regime::Regime_EL10 && EL2Enabled(pe) && walkparams.get_nv1() == 1 {
+            permissions.ap = (Bit(descriptor, 7) << 2) as u8;
+            permissions.pxn = Bit(descriptor, 54) as u8;
+        } else if HasUnprivileged(regime) {
+            permissions.ap = (Bits(descriptor, 7, 6) << 1) as u8;
+            permissions.uxn = Bit(descriptor, 54) as u8;
+            permissions.pxn = Bit(descriptor, 53) as u8;
+        } else {
+            permissions.ap = ((Bit(descriptor, 7) << 2) | 0b010) as u8;
+            permissions.xn = Bit(descriptor, 54) as u8;
+        }
+
+        // Descriptors marked with DBM set have the effective value of AP[2] cleared.
+        // This implies no Permission faults caused by lack of write permissions are
+        // reported, and the Dirty bit can be set.
+        if walkparams.get_ha() == 1 && walkparams.get_hd() == 1 && Bit(descriptor, 51) == 1 {
+            permissions.ap &= !0b100;
+        }
+    }
+
+    if IsFeatureImplemented(pe, Feature::FEAT_S1POE) {
+        permissions.po_index = if walkparams.get_d128() == 1 {
+            Bits(descriptor, 124, 121)
+        } else {
+            Bits(descriptor, 62, 60)
+        } as u8;
+    }
+
+    permissions
+}
+
+/// `Elem[pir, pi_index, 4]`: the 4-bit permission encoding selected by `pi_index` in
+/// PIR_ELx or PIRE0_ELx
+pub fn AArch64S1PIEntry(pir: u64, pi_index: u64) -> u8 {
+    Bits(pir.into(), (pi_index * 4 + 3) as u32, (pi_index * 4) as u32) as u8
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_ttentry/AArch64.S1ApplyTablePerms
+/// AArch64.S1ApplyTablePerms()
+/// ===========================
+/// Apply hierarchical permissions encoded in stage 1 table descriptors
+pub fn AArch64S1ApplyTablePerms(
+    pe: &ProcessorElement,
+    permissions_in: Permissions,
+    descriptor: u128,
+    regime: Regime,
+    walkparams: S1TTWParams,
+) -> Permissions {
+    let mut permissions = permissions_in;
+
+    if regime == Regime::Regime_EL10 && EL2Enabled(pe) && walkparams.get_nv1() == 1 {
+        let ap_table = Bit(descriptor, 62) << 1;
+        let pxn_table = Bit(descriptor, 60);
+        permissions.ap_table |= ap_table as u8;
+        permissions.pxn_table |= pxn_table as u8;
+    } else if HasUnprivileged(regime) {
+        let ap_table = Bits(descriptor, 62, 61);
+        let uxn_table = Bit(descriptor, 60);
+        let pxn_table = Bit(descriptor, 59);
+        permissions.ap_table |= ap_table as u8;
+        permissions.uxn_table |= uxn_table as u8;
+        permissions.pxn_table |= pxn_table as u8;
+    } else {
+        let ap_table = Bit(descriptor, 62) << 1;
+        let xn_table = Bit(descriptor, 60);
+        permissions.ap_table |= ap_table as u8;
+        permissions.xn_table |= xn_table as u8;
+    }
+
+    permissions
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_ttentry/AArch64.S2ApplyOutputPerms
+/// AArch64.S2ApplyOutputPerms()
+/// ============================
+/// Apply output permissions encoded in stage 2 page/block descriptors
+pub fn AArch64S2ApplyOutputPerms(
+    pe: &ProcessorElement,
+    descriptor: u128,
+    walkparams: S2TTWParams,
+) -> Permissions {
+    let s2pie = walkparams.get_s2pie() == 1;
+    let d128 = walkparams.get_d128() == 1;
+    let mut permissions = Permissions {
+        ap_table: 0,
+        xn_table: 0,
+        pxn_table: 0,
+        uxn_table: 0,
+        ap: 0,
+        xn: 0,
+        uxn: 0,
+        pxn: 0,
+        ppi: 0,
+        upi: 0,
+        ndirty: 0,
+        po_index: 0,
+        s2pi_index: 0,
+        s2po_index: 0,
+        s2dirty: 0,
+        s2ap: 0,
+        s2xnx: 0,
+        s2xn: 0,
+    };
+
+    if s2pie {
+        permissions.s2pi_index = if d128 {
+            Bits(descriptor, 118, 115)
+        } else {
+            Bit(descriptor, 54) << 3
+                | Bit(descriptor, 53) << 2
+                | Bit(descriptor, 51) << 1
+                | Bit(descriptor, 6)
+        } as u8;
+        permissions.s2dirty = Bit(descriptor, 7) as u8;
+        if IsFeatureImplemented(pe, Feature::FEAT_S2POE) {
+            permissions.s2po_index = if d128 {
+                Bits(descriptor, 124, 121)
+            } else {
+                Bits(descriptor, 62, 59)
+            } as u8;
+        }
+    } else {
+        permissions.s2ap = Bits(descriptor, 7, 6) as u8;
+        permissions.s2xn = Bit(descriptor, 54) as u8;
+        if IsFeatureImplemented(pe, Feature::FEAT_XNX) {
+            permissions.s2xnx = Bit(descriptor, 53) as u8;
+        }
+
+        // Descriptors marked with DBM set have the effective value of S2AP[1] set.
+        // This implies no Permission faults caused by lack of write permissions are
+        // reported, and the Dirty bit can be set.
+        if walkparams.get_ha() == 1 && walkparams.get_hd() == 1 && Bit(descriptor, 51) == 1 {
+            permissions.s2ap |= 0b10;
+        }
+    }
+
+    permissions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::translation64_faults::*;
+
+    #[test]
+    fn s1_indirect_permissions() {
+        let mut pe = ProcessorElement::from_profile(
+            ArchVersion::Armv8_0,
+            &[Feature::FEAT_TCR2, Feature::FEAT_S1PIE],
+        )
+        .unwrap();
+        let mut walkparams: S1TTWParams = unsafe { std::mem::zeroed() };
+        walkparams.bitfield = S1TTWParamsBits::new().with(S1TTWParamsBits::pie, 1);
+        // Index 5: read and write at EL1, read only at EL0
+        walkparams.pir = 0b0101 << 20;
+        walkparams.pire0 = 0b0001 << 20;
+
+        // PIIndex is {UXN, PXN, DBM, AP[1]}; AP[2] is nDirty
+        let descriptor = (1 << 53) | (1 << 6) | 0b11;
+        let permissions = AArch64S1ApplyOutputPerms(
+            &pe,
+            TTWState::UNKNOWN.permissions,
+            descriptor,
+            Regime::Regime_EL10,
+            walkparams,
+        );
+        assert_eq!(
+            (permissions.ppi, permissions.upi, permissions.ndirty),
+            (0b0101, 0b0001, 0)
+        );
+
+        let check = |pe: &ProcessorElement, permissions: Permissions, memop: MemOp| {
+            let accdesc = CreateAccDescGPR(pe, memop, false, true, false).unwrap();
+            let mut walkstate = TTWState::UNKNOWN;
+            walkstate.memattrs = NormalNCMemAttr();
+            walkstate.permissions = permissions;
+            AArch64S1CheckPermissions(
+                pe,
+                FaultRecord::NoFaultForAccess(accdesc),
+                Regime::Regime_EL10,
+                walkstate,
+                walkparams,
+                accdesc,
+            )
+            .unwrap()
+        };
+
+        pe.PSTATE.set(ProcState::EL, 1);
+        assert_eq!(
+            check(&pe, permissions, MemOp::MemOp_STORE).statuscode,
+            Fault::Fault_None
+        );
+        pe.PSTATE.set(ProcState::EL, 0);
+        assert_eq!(
+            check(&pe, permissions, MemOp::MemOp_LOAD).statuscode,
+            Fault::Fault_None
+        );
+        let fault = check(&pe, permissions, MemOp::MemOp_STORE);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        assert!(fault.write && !fault.dirtybit);
+
+        // A writable but clean location faults on the first write
+        let permissions = AArch64S1ApplyOutputPerms(
+            &pe,
+            TTWState::UNKNOWN.permissions,
+            descriptor | (1 << 7),
+            Regime::Regime_EL10,
+            walkparams,
+        );
+        pe.PSTATE.set(ProcState::EL, 1);
+        let fault = check(&pe, permissions, MemOp::MemOp_STORE);
+        assert_eq!(fault.statuscode, Fault::Fault_Permission);
+        assert!(fault.dirtybit);
+    }
+}
@@ -0,0 +1,1341 @@
+// SPDX-License-Identifier: EUPL-1.2 OR GPL-3.0-or-later
+#![allow(non_camel_case_types)]
+
+use crate::error::*;
+use crate::pe::*;
+use crate::shared::*;
+use crate::shared_mec::*;
+use crate::shared_memory::*;
+use crate::shared_translation::*;
+use crate::shared_vmsa::*;
+use crate::stubs::*;
+use crate::sysregs::*;
+use crate::translation64::*;
+use crate::translation64_addrcalc::*;
+use crate::translation64_attrs::*;
+use crate::translation64_faults::*;
+use crate::translation64_ttentry::*;
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.S1EPD
+/// AArch64.S1EPD()
+/// ===============
+/// Determine whether stage 1 translation table walk is allowed for the VA range
+pub fn AArch64S1EPD(
+    pe: &ProcessorElement,
+    regime: Regime,
+    va: u64,
+) -> Result<u64, TranslationError> {
+    let varange = AArch64GetVARange(va);
+
+    Ok(match (regime, varange) {
+        (Regime::Regime_EL20, VARange::VARange_LOWER) => {
+            pe.regs.TCR_EL2_E2H().get(TCR_EL1_REG::EPD0)
+        }
+        (Regime::Regime_EL20, VARange::VARange_UPPER) => {
+            pe.regs.TCR_EL2_E2H().get(TCR_EL1_REG::EPD1)
+        }
+        (Regime::Regime_EL10, VARange::VARange_LOWER) => pe.regs.TCR_EL1.get(TCR_EL1_REG::EPD0),
+        (Regime::Regime_EL10, VARange::VARange_UPPER) => pe.regs.TCR_EL1.get(TCR_EL1_REG::EPD1),
+        (Regime::Regime_EL3 | Regime::Regime_EL2, _) => {
+            return Err(TranslationError::Unpredictable(
+                "EPD of a regime without unprivileged accesses",
+            ))
+        }
+        // AArch32 EL3 is not modelled
+        (Regime::Regime_EL30, _) => return Err(TranslationError::Unsupported("AArch32 EL3")),
+    })
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.S1InitialTTWState
+/// AArch64.S1InitialTTWState()
+/// ===========================
+/// Set properties of first access to translation tables in stage 1
+pub fn AArch64S1InitialTTWState(
+    pe: &ProcessorElement,
+    walkparams: S1TTWParams,
+    va: u64,
+    regime: Regime,
+    ss: SecurityState,
+) -> Result<TTWState, TranslationError> {
+    let ttbr = AArch64S1TTBR(pe, regime, va)?;
+    let paspace = match ss {
+        SecurityState::SS_Secure => PASpace::PAS_Secure,
+        SecurityState::SS_NonSecure => PASpace::PAS_NonSecure,
+        SecurityState::SS_Root => PASpace::PAS_Root,
+        SecurityState::SS_Realm => PASpace::PAS_Realm,
+    };
+    let tablebase = FullAddress {
+        paspace,
+        address: AArch64S1TTBaseAddress(pe, walkparams, regime, ttbr.bits()),
+    };
+
+    // Hierarchical permissions start out permissive, and are only ever restricted by
+    // table descriptors.
+    let permissions = Permissions {
+        ap_table: 0b00,
+        xn_table: 0,
+        pxn_table: 0,
+        uxn_table: 0,
+        ap: 0,
+        xn: 0,
+        uxn: 0,
+        pxn: 0,
+        ppi: 0,
+        upi: 0,
+        ndirty: 0,
+        po_index: 0,
+        s2pi_index: 0,
+        s2po_index: 0,
+        s2dirty: 0,
+        s2ap: 0,
+        s2xnx: 0,
+        s2xn: 0,
+    };
+
+    Ok(TTWState {
+        istable: true,
+        level: AArch64S1StartLevel(walkparams),
+        baseaddress: tablebase,
+        contiguous: false,
+        s1assured: false,
+        s2assuredonly: false,
+        disch: walkparams.get_disch() == 1,
+        // In regimes that support global and non-global translations, translation
+        // table entries from lookup levels other than the final level of lookup
+        // are treated as being non-global
+        nG: HasUnprivileged(regime),
+        guardedpage: false,
+        sdftype: SDFType::SDFType_Invalid,
+        domain: 0,
+        memattrs: WalkMemAttrs(
+            walkparams.get_sh(),
+            walkparams.get_irgn(),
+            walkparams.get_orgn(),
+        ),
+        permissions,
+    })
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.S1NextWalkStateLeaf
+/// AArch64.S1NextWalkStateLeaf()
+/// =============================
+/// Decode stage 1 page or block descriptor as output to this stage of translation
+pub fn AArch64S1NextWalkStateLeaf(
+    pe: &ProcessorElement,
+    currentstate: TTWState,
+    s2fs1mro: bool,
+    regime: Regime,
+    ss: SecurityState,
+    walkparams: S1TTWParams,
+    descriptor: u128,
+) -> Result<TTWState, TranslationError> {
+    let address = AArch64LeafBase(
+        pe,
+        descriptor,
+        walkparams.get_d128(),
+        walkparams.get_ds(),
+        walkparams.get_tgx(),
+        currentstate.level,
+    );
+    let ns = if walkparams.get_d128() == 1 {
+        Bit(descriptor, 127)
+    } else {
+        Bit(descriptor, 5)
+    };
+    let paspace = match currentstate.baseaddress.paspace {
+        // Determine PA space of the block from NS bit
+        PASpace::PAS_Secure if ns == 0 => PASpace::PAS_Secure,
+        PASpace::PAS_Secure => PASpace::PAS_NonSecure,
+        PASpace::PAS_Root => {
+            // Determine PA space of the block from NSE and NS bits
+            match DecodePASpace(Bit(descriptor, 11), ns) {
+                // If Secure state is not implemented, but RME is,
+                // force Secure space accesses to Non-secure space
+                PASpace::PAS_Secure if !HaveSecureState(pe) => PASpace::PAS_NonSecure,
+                paspace => paspace,
+            }
+        }
+        // Realm EL2 and EL2&0 regimes have a stage 1 NS bit
+        PASpace::PAS_Realm
+            if matches!(regime, Regime::Regime_EL2 | Regime::Regime_EL20) && ns == 1 =>
+        {
+            PASpace::PAS_NonSecure
+        }
+        // Realm EL1&0 regime does not have a stage 1 NS bit
+        PASpace::PAS_Realm => PASpace::PAS_Realm,
+        PASpace::PAS_NonSecure => PASpace::PAS_NonSecure,
+    };
+
+    let attrindx = if walkparams.get_d128() == 1 {
+        Bits(descriptor, 5, 2)
+    } else if walkparams.get_aie() == 1 {
+        (Bit(descriptor, 59) << 3) | Bits(descriptor, 4, 2)
+    } else {
+        Bits(descriptor, 4, 2)
+    };
+    let sh = if walkparams.get_ds() == 1 {
+        walkparams.get_sh()
+    } else {
+        Bits(descriptor, 9, 8)
+    };
+    let attr = AArch64MAIRAttr(attrindx, walkparams.mair2, walkparams.mair);
+    let s1aarch64 = true;
+
+    let protectedbit = if walkparams.get_pnch() == 0 {
+        0
+    } else if walkparams.get_d128() == 1 {
+        Bit(descriptor, 114)
+    } else {
+        Bit(descriptor, 52)
+    };
+
+    let contiguous = if walkparams.get_pnch() == 1 || currentstate.disch {
+        false
+    } else {
+        AArch64ContiguousBit(
+            walkparams.get_tgx(),
+            walkparams.get_d128(),
+            currentstate.level,
+            descriptor,
+        )
+    };
+
+    let nG = if !HasUnprivileged(regime) {
+        false
+    } else if ss == SecurityState::SS_Secure
+        && currentstate.baseaddress.paspace == PASpace::PAS_NonSecure
+    {
+        // In Secure state, a translation must be treated as non-global,
+        // regardless of the value of the nG bit,
+        // if NSTable is set to 1 at any level of the translation table walk
+        true
+    } else if walkparams.get_fng() == 1 {
+        // Translations are treated as non-global regardless of the value of the nG bit.
+        true
+    } else {
+        Bit(descriptor, 11) == 1
+    };
+
+    Ok(TTWState {
+        istable: false,
+        level: currentstate.level,
+        baseaddress: FullAddress { paspace, address },
+        contiguous,
+        s1assured: currentstate.s1assured && s2fs1mro && protectedbit == 1,
+        s2assuredonly: false,
+        disch: currentstate.disch,
+        nG,
+        guardedpage: if walkparams.get_d128() == 1 {
+            Bit(descriptor, 113) == 1
+        } else {
+            Bit(descriptor, 50) == 1
+        },
+        sdftype: currentstate.sdftype,
+        domain: currentstate.domain,
+        memattrs: S1DecodeMemAttrs(pe, attr, sh, s1aarch64, walkparams),
+        permissions: AArch64S1ApplyOutputPerms(
+            pe,
+            currentstate.permissions,
+            descriptor,
+            regime,
+            walkparams,
+        ),
+    })
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.S1NextWalkStateTable
+/// AArch64.S1NextWalkStateTable()
+/// ==============================
+/// Decode stage 1 table descriptor to transition to the next level
+pub fn AArch64S1NextWalkStateTable(
+    pe: &ProcessorElement,
+    currentstate: TTWState,
+    skl: u64,
+    regime: Regime,
+    walkparams: S1TTWParams,
+    descriptor: u128,
+) -> TTWState {
+    let address = AArch64NextTableBase(
+        pe,
+        descriptor,
+        walkparams.get_d128(),
+        skl,
+        walkparams.get_ds(),
+        walkparams.get_tgx(),
+    );
+    let nstable = if walkparams.get_d128() == 1 {
+        Bit(descriptor, 127)
+    } else {
+        Bit(descriptor, 63)
+    };
+    let paspace = if currentstate.baseaddress.paspace == PASpace::PAS_Secure {
+        // Determine PA space of the next table from NSTable bit
+        if nstable == 0 {
+            PASpace::PAS_Secure
+        } else {
+            PASpace::PAS_NonSecure
+        }
+    } else {
+        // Otherwise bit is ignored and PA space of the next table is the same
+        currentstate.baseaddress.paspace
+    };
+
+    let permissions = if walkparams.get_hpd() == 0 && walkparams.get_pie() == 0 {
+        AArch64S1ApplyTablePerms(pe, currentstate.permissions, descriptor, regime, walkparams)
+    } else {
+        currentstate.permissions
+    };
+
+    let protectedbit = if walkparams.get_pnch() == 0 {
+        0
+    } else if walkparams.get_d128() == 1 {
+        Bit(descriptor, 114)
+    } else {
+        Bit(descriptor, 52)
+    };
+
+    TTWState {
+        istable: true,
+        level: currentstate.level + skl as i64 + 1,
+        baseaddress: FullAddress { paspace, address },
+        contiguous: false,
+        s1assured: currentstate.s1assured && walkparams.get_pnch() == 1 && protectedbit == 1,
+        s2assuredonly: false,
+        disch: currentstate.disch,
+        nG: currentstate.nG,
+        guardedpage: false,
+        sdftype: currentstate.sdftype,
+        domain: currentstate.domain,
+        memattrs: currentstate.memattrs,
+        permissions,
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.S1TTBR
+/// AArch64.S1TTBR()
+/// ================
+/// Determine the TTBR value for the stage 1 translation
+pub fn AArch64S1TTBR(
+    pe: &ProcessorElement,
+    regime: Regime,
+    va: u64,
+) -> Result<TTBR_ELx_REG, TranslationError> {
+    let varange = AArch64GetVARange(va);
+
+    Ok(match (regime, varange) {
+        (Regime::Regime_EL3, _) => pe.regs.TTBR0_EL3,
+        (Regime::Regime_EL2, _) => pe.regs.TTBR0_EL2,
+        (Regime::Regime_EL20, VARange::VARange_LOWER) => pe.regs.TTBR0_EL2,
+        (Regime::Regime_EL20, VARange::VARange_UPPER) => pe.regs.TTBR1_EL2,
+        (Regime::Regime_EL10, VARange::VARange_LOWER) => pe.regs.TTBR0_EL1,
+        (Regime::Regime_EL10, VARange::VARange_UPPER) => pe.regs.TTBR1_EL1,
+        // AArch32 EL3 is not modelled
+        (Regime::Regime_EL30, _) => return Err(TranslationError::Unsupported("AArch32 EL3")),
+    })
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.S1Walk
+/// AArch64.S1Walk()
+/// ================
+/// Traverse stage 1 translation tables obtaining the final descriptor
+/// as well as the address leading to that descriptor
+// Takes the ASL arguments plus the PE and memory backend
+#[allow(clippy::too_many_arguments)]
+pub fn AArch64S1Walk(
+    pe: &ProcessorElement,
+    mem: &mut dyn PhysicalMemory,
+    fault_in: FaultRecord,
+    walkparams: S1TTWParams,
+    va: u64,
+    regime: Regime,
+    accdesc: AccessDescriptor,
+    N: usize,
+) -> Result<(FaultRecord, AddressDescriptor, TTWState, u128), TranslationError> {
+    assert!(N == 64 || N == 128);
+    let mut fault = fault_in;
+
+    if HasUnprivileged(regime) && AArch64S1EPD(pe, regime, va)? == 1 {
+        fault.statuscode = Fault::Fault_Translation;
+        fault.level = 0;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    let mut walkstate = AArch64S1InitialTTWState(pe, walkparams, va, regime, accdesc.ss)?;
+    let startlevel = walkstate.level;
+
+    if startlevel > 3 {
+        fault.statuscode = Fault::Fault_Translation;
+        fault.level = 0;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    // Detect Address Size Fault by TTB
+    if AArch64OAOutOfRange(
+        pe,
+        walkstate.baseaddress.address,
+        walkparams.get_d128(),
+        walkparams.get_ds(),
+        walkparams.get_ps(),
+        walkparams.get_tgx(),
+    ) {
+        fault.statuscode = Fault::Fault_AddressSize;
+        fault.level = 0;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    let mut descriptor: u128;
+    let mut walkaddress = AddressDescriptor::UNKNOWN;
+    let mut skl = 0;
+    let mut s2fs1mro = false;
+
+    walkaddress.vaddress = va;
+    walkaddress.mecid = AArch64TTWalkMECID(pe, walkparams.get_emec(), regime, accdesc.ss)?;
+
+    if !AArch64S1DCacheEnabled(pe, regime)? {
+        walkaddress.memattrs = NormalNCMemAttr();
+        walkaddress.memattrs.xs = walkstate.memattrs.xs;
+    } else {
+        walkaddress.memattrs = walkstate.memattrs;
+    }
+
+    // Shareability value of stage 1 translation subject to stage 2 is IMPLEMENTATION DEFINED
+    // to be either effective value or descriptor value
+    walkaddress.memattrs.shareability = EffectiveShareability(walkaddress.memattrs);
+
+    loop {
+        fault.level = walkstate.level;
+        walkaddress.paddress = if walkstate.level == startlevel {
+            // Initial lookup might index into the levels skipped by TTBR_ELx.SKL
+            AArch64S1SLTTEntryAddress(walkstate.level, walkparams, va, walkstate.baseaddress)
+        } else {
+            AArch64TTEntryAddress(
+                walkstate.level,
+                walkparams.get_d128(),
+                skl,
+                walkparams.get_tgx(),
+                walkparams.get_txsz(),
+                va,
+                walkstate.baseaddress,
+            )
+        };
+
+        let toplevel = walkstate.level == startlevel;
+        let varange = AArch64GetVARange(va);
+        let walkaccess = CreateAccDescS1TTW(pe, toplevel, varange, accdesc)?;
+        if regime == Regime::Regime_EL10 && EL2Enabled(pe) {
+            let s1aarch64 = true;
+            let aligned = true;
+            let (s2fault, s2walkaddress) =
+                AArch64S2Translate(pe, mem, fault, walkaddress, s1aarch64, aligned, walkaccess)?;
+
+            if s2fault.statuscode != Fault::Fault_None {
+                return Ok((s2fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+            }
+
+            s2fs1mro = s2walkaddress.s2fs1mro;
+            (fault, descriptor) = FetchDescriptor(
+                pe,
+                mem,
+                walkparams.get_ee(),
+                s2walkaddress,
+                walkaccess,
+                fault,
+                N,
+            )?;
+        } else {
+            (fault, descriptor) = FetchDescriptor(
+                pe,
+                mem,
+                walkparams.get_ee(),
+                walkaddress,
+                walkaccess,
+                fault,
+                N,
+            )?;
+        }
+
+        if fault.statuscode != Fault::Fault_None {
+            return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+        }
+
+        match AArch64DecodeDescriptorType(
+            pe,
+            descriptor,
+            walkparams.get_d128(),
+            walkparams.get_ds(),
+            walkparams.get_tgx(),
+            walkstate.level,
+        ) {
+            DescriptorType::DescriptorType_Table => {
+                skl = if walkparams.get_d128() == 1 {
+                    Bits(descriptor, 110, 109)
+                } else {
+                    0
+                };
+                walkstate =
+                    AArch64S1NextWalkStateTable(pe, walkstate, skl, regime, walkparams, descriptor);
+
+                // Detect Address Size Fault by table descriptor
+                if AArch64OAOutOfRange(
+                    pe,
+                    walkstate.baseaddress.address,
+                    walkparams.get_d128(),
+                    walkparams.get_ds(),
+                    walkparams.get_ps(),
+                    walkparams.get_tgx(),
+                ) {
+                    fault.statuscode = Fault::Fault_AddressSize;
+                    return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+                }
+            }
+            DescriptorType::DescriptorType_Leaf => {
+                walkstate = AArch64S1NextWalkStateLeaf(
+                    pe, walkstate, s2fs1mro, regime, accdesc.ss, walkparams, descriptor,
+                )?;
+                break;
+            }
+            DescriptorType::DescriptorType_Invalid => {
+                fault.statuscode = Fault::Fault_Translation;
+                return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+            }
+        }
+    }
+
+    // Detect Address Size Fault by final output
+    if AArch64OAOutOfRange(
+        pe,
+        walkstate.baseaddress.address,
+        walkparams.get_d128(),
+        walkparams.get_ds(),
+        walkparams.get_ps(),
+        walkparams.get_tgx(),
+    ) {
+        fault.statuscode = Fault::Fault_AddressSize;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    if AArch64S1AMECFault(
+        walkparams,
+        walkstate.baseaddress.paspace,
+        regime,
+        descriptor,
+    ) {
+        fault.statuscode = Fault::Fault_Translation;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    // Check descriptor AF bit
+    // IMPLEMENTATION DEFINED "Generate access flag fault on IC/DC operations" is TRUE.
+    if Bit(descriptor, 10) == 0 && walkparams.get_ha() == 0 {
+        fault.statuscode = Fault::Fault_AccessFlag;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    // Check inconsistent programming of the Contiguous bit
+    if walkstate.contiguous
+        && AArch64ContiguousBitFaults(
+            walkparams.get_d128(),
+            walkparams.get_txsz(),
+            walkparams.get_tgx(),
+            walkstate.level,
+        )
+    {
+        fault.statuscode = Fault::Fault_Translation;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    Ok((fault, walkaddress, walkstate, descriptor))
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.S2InitialTTWState
+/// AArch64.S2InitialTTWState()
+/// ===========================
+/// Set properties of first access to translation tables in stage 2
+pub fn AArch64S2InitialTTWState(
+    pe: &ProcessorElement,
+    ss: SecurityState,
+    walkparams: S2TTWParams,
+) -> TTWState {
+    let ttbr = pe.regs.VTTBR_EL2;
+    let paspace = match ss {
+        SecurityState::SS_NonSecure => PASpace::PAS_NonSecure,
+        SecurityState::SS_Realm => PASpace::PAS_Realm,
+        _ => unreachable!(),
+    };
+    let tablebase = FullAddress {
+        paspace,
+        address: AArch64S2TTBaseAddress(pe, walkparams, paspace, ttbr),
+    };
+
+    TTWState {
+        istable: true,
+        level: AArch64S2StartLevel(walkparams),
+        baseaddress: tablebase,
+        sdftype: SDFType::SDFType_Invalid,
+        memattrs: WalkMemAttrs(
+            walkparams.get_sh(),
+            walkparams.get_irgn(),
+            walkparams.get_orgn(),
+        ),
+        ..TTWState::UNKNOWN
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.SS2InitialTTWState
+/// AArch64.SS2InitialTTWState()
+/// ============================
+/// Set properties of first access to translation tables in Secure stage 2
+pub fn AArch64SS2InitialTTWState(
+    pe: &ProcessorElement,
+    walkparams: S2TTWParams,
+    ipaspace: PASpace,
+) -> TTWState {
+    let ttbr = if ipaspace == PASpace::PAS_Secure {
+        pe.regs.VSTTBR_EL2 as u128
+    } else {
+        pe.regs.VTTBR_EL2
+    };
+    let paspace = if ipaspace == PASpace::PAS_Secure {
+        if walkparams.get_sw() == 0 {
+            PASpace::PAS_Secure
+        } else {
+            PASpace::PAS_NonSecure
+        }
+    } else if walkparams.get_nsw() == 0 {
+        PASpace::PAS_Secure
+    } else {
+        PASpace::PAS_NonSecure
+    };
+    let tablebase = FullAddress {
+        paspace,
+        address: AArch64S2TTBaseAddress(pe, walkparams, paspace, ttbr),
+    };
+
+    TTWState {
+        istable: true,
+        level: AArch64S2StartLevel(walkparams),
+        baseaddress: tablebase,
+        sdftype: SDFType::SDFType_Invalid,
+        memattrs: WalkMemAttrs(
+            walkparams.get_sh(),
+            walkparams.get_irgn(),
+            walkparams.get_orgn(),
+        ),
+        ..TTWState::UNKNOWN
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.SS2OutputPASpace
+/// AArch64.SS2OutputPASpace()
+/// ==========================
+/// Assign PA Space to output of Secure stage 2 translation
+pub fn AArch64SS2OutputPASpace(walkparams: S2TTWParams, ipaspace: PASpace) -> PASpace {
+    if ipaspace == PASpace::PAS_Secure {
+        if walkparams.get_sw() == 0 && walkparams.get_sa() == 0 {
+            return PASpace::PAS_Secure;
+        }
+        return PASpace::PAS_NonSecure;
+    }
+
+    if walkparams.get_sw() == 0
+        && walkparams.get_sa() == 0
+        && walkparams.get_nsw() == 0
+        && walkparams.get_nsa() == 0
+    {
+        return PASpace::PAS_Secure;
+    }
+    PASpace::PAS_NonSecure
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.S2NextWalkStateLeaf
+/// AArch64.S2NextWalkStateLeaf()
+/// =============================
+/// Decode stage 2 page or block descriptor as output to this stage of translation
+pub fn AArch64S2NextWalkStateLeaf(
+    pe: &ProcessorElement,
+    currentstate: TTWState,
+    ss: SecurityState,
+    walkparams: S2TTWParams,
+    ipa: AddressDescriptor,
+    descriptor: u128,
+) -> Result<TTWState, TranslationError> {
+    let paspace = match ss {
+        SecurityState::SS_Secure => AArch64SS2OutputPASpace(walkparams, ipa.paddress.paspace),
+        SecurityState::SS_Realm => {
+            let ns = if walkparams.get_d128() == 1 {
+                Bit(descriptor, 127)
+            } else {
+                Bit(descriptor, 55)
+            };
+            if ns == 1 {
+                PASpace::PAS_NonSecure
+            } else {
+                PASpace::PAS_Realm
+            }
+        }
+        _ => PASpace::PAS_NonSecure,
+    };
+    let address = AArch64LeafBase(
+        pe,
+        descriptor,
+        walkparams.get_d128(),
+        walkparams.get_ds(),
+        walkparams.get_tgx(),
+        currentstate.level,
+    );
+
+    let s2_attr = Bits(descriptor, 5, 2);
+    let s2_sh = if walkparams.get_ds() == 1 {
+        walkparams.get_sh()
+    } else {
+        Bits(descriptor, 9, 8)
+    };
+    let s2_fnxs = Bit(descriptor, 11);
+    let memattrs = if walkparams.get_fwb() == 1 {
+        AArch64S2ApplyFWBMemAttrs(pe, ipa.memattrs, walkparams, descriptor)
+    } else {
+        let s2aarch64 = true;
+        let mut memattrs = S2DecodeMemAttrs(pe, s2_attr, s2_sh, s2aarch64);
+        // FnXS is used later to mask the XS value from stage 1
+        memattrs.xs = s2_fnxs == 0;
+        memattrs
+    };
+
+    let s2assuredonly = if walkparams.get_assuredonly() == 0 {
+        false
+    } else if walkparams.get_d128() == 1 {
+        Bit(descriptor, 114) == 1
+    } else {
+        Bit(descriptor, 58) == 1
+    };
+
+    Ok(TTWState {
+        istable: false,
+        level: currentstate.level,
+        baseaddress: FullAddress { paspace, address },
+        contiguous: AArch64ContiguousBit(
+            walkparams.get_tgx(),
+            walkparams.get_d128(),
+            currentstate.level,
+            descriptor,
+        ),
+        s2assuredonly,
+        memattrs,
+        permissions: AArch64S2ApplyOutputPerms(pe, descriptor, walkparams),
+        ..currentstate
+    })
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.S2NextWalkStateTable
+/// AArch64.S2NextWalkStateTable()
+/// ==============================
+/// Decode stage 2 table descriptor to transition to the next level
+pub fn AArch64S2NextWalkStateTable(
+    pe: &ProcessorElement,
+    currentstate: TTWState,
+    skl: u64,
+    walkparams: S2TTWParams,
+    descriptor: u128,
+) -> TTWState {
+    let address = AArch64NextTableBase(
+        pe,
+        descriptor,
+        walkparams.get_d128(),
+        skl,
+        walkparams.get_ds(),
+        walkparams.get_tgx(),
+    );
+
+    TTWState {
+        istable: true,
+        level: currentstate.level + skl as i64 + 1,
+        baseaddress: FullAddress {
+            paspace: currentstate.baseaddress.paspace,
+            address,
+        },
+        ..currentstate
+    }
+}
+
+/// Library pseudocode for aarch64/translation/vmsa_walk/AArch64.S2Walk
+/// AArch64.S2Walk()
+/// ================
+/// Traverse stage 2 translation tables obtaining the final descriptor
+/// as well as the address leading to that descriptor
+pub fn AArch64S2Walk(
+    pe: &ProcessorElement,
+    mem: &mut dyn PhysicalMemory,
+    fault_in: FaultRecord,
+    ipa: AddressDescriptor,
+    walkparams: S2TTWParams,
+    accdesc: AccessDescriptor,
+    N: usize,
+) -> Result<(FaultRecord, AddressDescriptor, TTWState, u128), TranslationError> {
+    assert!(N == 64 || N == 128);
+    let mut fault = fault_in;
+
+    let mut walkstate = if accdesc.ss == SecurityState::SS_Secure {
+        AArch64SS2InitialTTWState(pe, walkparams, ipa.paddress.paspace)
+    } else {
+        AArch64S2InitialTTWState(pe, accdesc.ss, walkparams)
+    };
+    let startlevel = walkstate.level;
+
+    if startlevel > 3 {
+        fault.statuscode = Fault::Fault_Translation;
+        fault.level = 0;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    // Detect Address Size Fault by TTB
+    if AArch64OAOutOfRange(
+        pe,
+        walkstate.baseaddress.address,
+        walkparams.get_d128(),
+        walkparams.get_ds(),
+        walkparams.get_ps(),
+        walkparams.get_tgx(),
+    ) {
+        fault.statuscode = Fault::Fault_AddressSize;
+        fault.level = 0;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    let mut descriptor: u128;
+    let walkaccess = CreateAccDescS2TTW(pe, accdesc)?;
+    let mut walkaddress = AddressDescriptor::UNKNOWN;
+    let mut skl = 0;
+
+    walkaddress.vaddress = ipa.vaddress;
+    walkaddress.mecid =
+        AArch64TTWalkMECID(pe, walkparams.get_emec(), Regime::Regime_EL10, accdesc.ss)?;
+
+    if !S2DCacheEnabled(pe) {
+        walkaddress.memattrs = NormalNCMemAttr();
+        walkaddress.memattrs.xs = walkstate.memattrs.xs;
+    } else {
+        walkaddress.memattrs = walkstate.memattrs;
+    }
+
+    walkaddress.memattrs.shareability = EffectiveShareability(walkaddress.memattrs);
+
+    loop {
+        fault.level = walkstate.level;
+        walkaddress.paddress = if walkstate.level == startlevel {
+            // Initial lookup might index into concatenated tables
+            AArch64S2SLTTEntryAddress(walkparams, ipa.paddress.address, walkstate.baseaddress)
+        } else {
+            AArch64TTEntryAddress(
+                walkstate.level,
+                walkparams.get_d128(),
+                skl,
+                walkparams.get_tgx(),
+                walkparams.get_txsz(),
+                ipa.paddress.address,
+                walkstate.baseaddress,
+            )
+        };
+
+        (fault, descriptor) = FetchDescriptor(
+            pe,
+            mem,
+            walkparams.get_ee(),
+            walkaddress,
+            walkaccess,
+            fault,
+            N,
+        )?;
+        if fault.statuscode != Fault::Fault_None {
+            return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+        }
+
+        match AArch64DecodeDescriptorType(
+            pe,
+            descriptor,
+            walkparams.get_d128(),
+            walkparams.get_ds(),
+            walkparams.get_tgx(),
+            walkstate.level,
+        ) {
+            DescriptorType::DescriptorType_Table => {
+                skl = if walkparams.get_d128() == 1 {
+                    Bits(descriptor, 110, 109)
+                } else {
+                    0
+                };
+                walkstate = AArch64S2NextWalkStateTable(pe, walkstate, skl, walkparams, descriptor);
+
+                // Detect Address Size Fault by table descriptor
+                if AArch64OAOutOfRange(
+                    pe,
+                    walkstate.baseaddress.address,
+                    walkparams.get_d128(),
+                    walkparams.get_ds(),
+                    walkparams.get_ps(),
+                    walkparams.get_tgx(),
+                ) {
+                    fault.statuscode = Fault::Fault_AddressSize;
+                    return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+                }
+            }
+            DescriptorType::DescriptorType_Leaf => {
+                walkstate = AArch64S2NextWalkStateLeaf(
+                    pe, walkstate, accdesc.ss, walkparams, ipa, descriptor,
+                )?;
+                break;
+            }
+            DescriptorType::DescriptorType_Invalid => {
+                fault.statuscode = Fault::Fault_Translation;
+                return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+            }
+        }
+    }
+
+    // Detect Address Size Fault by final output
+    if AArch64OAOutOfRange(
+        pe,
+        walkstate.baseaddress.address,
+        walkparams.get_d128(),
+        walkparams.get_ds(),
+        walkparams.get_ps(),
+        walkparams.get_tgx(),
+    ) {
+        fault.statuscode = Fault::Fault_AddressSize;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    // Check descriptor AF bit
+    // IMPLEMENTATION DEFINED "Generate access flag fault on IC/DC operations" is TRUE.
+    if Bit(descriptor, 10) == 0 && walkparams.get_ha() == 0 {
+        fault.statuscode = Fault::Fault_AccessFlag;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    // Check inconsistent programming of the Contiguous bit
+    if walkstate.contiguous
+        && AArch64ContiguousBitFaults(
+            walkparams.get_d128(),
+            walkparams.get_txsz(),
+            walkparams.get_tgx(),
+            walkstate.level,
+        )
+    {
+        fault.statuscode = Fault::Fault_Translation;
+        return Ok((fault, AddressDescriptor::UNKNOWN, TTWState::UNKNOWN, 0));
+    }
+
+    Ok((fault, walkaddress, walkstate, descriptor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physmem::*;
+
+    const TABLE: u128 = 0b11;
+    const BLOCK: u128 = 0b01;
+
+    fn walkparams(txsz: u64) -> S1TTWParams {
+        S1TTWParams {
+            bitfield: S1TTWParamsBits::new()
+                .with(S1TTWParamsBits::txsz, txsz)
+                .with(S1TTWParamsBits::hpd, 1),
+            mair2: 0,
+            pir: 0,
+            pire0: 0,
+            tgx: TGx::TGx_4KB,
+            mair: 0,
+        }
+    }
+
+    #[test]
+    fn descriptor_types() {
+        use DescriptorType::*;
+
+        let pe = ProcessorElement::new();
+        // Level 0 to 3 types of a table/page descriptor and of a block descriptor
+        for (tgx, tables, blocks) in [
+            (
+                TGx::TGx_4KB,
+                [DescriptorType_Table; 3],
+                [
+                    DescriptorType_Invalid,
+                    DescriptorType_Leaf,
+                    DescriptorType_Leaf,
+                ],
+            ),
+            (
+                TGx::TGx_16KB,
+                [DescriptorType_Table; 3],
+                [
+                    DescriptorType_Invalid,
+                    DescriptorType_Invalid,
+                    DescriptorType_Leaf,
+                ],
+            ),
+        ] {
+            for level in 0..FINAL_LEVEL {
+                let decode =
+                    |descriptor| AArch64DecodeDescriptorType(&pe, descriptor, 0, 0, tgx, level);
+                assert_eq!(decode(0), DescriptorType_Invalid);
+                assert_eq!(decode(TABLE), tables[level as usize], "{:?}", tgx);
+                assert_eq!(decode(BLOCK), blocks[level as usize], "{:?}", tgx);
+            }
+            // Pages at the final level, and no blocks
+            let decode =
+                |descriptor| AArch64DecodeDescriptorType(&pe, descriptor, 0, 0, tgx, FINAL_LEVEL);
+            assert_eq!(decode(TABLE), DescriptorType_Leaf);
+            assert_eq!(decode(BLOCK), DescriptorType_Invalid);
+        }
+    }
+
+    #[test]
+    fn table_entry_addresses() {
+        let tablebase = FullAddress {
+            paspace: PASpace::PAS_NonSecure,
+            address: 0x10_0000,
+        };
+        let va = 0x12_3456_7000;
+
+        // A 39-bit VA range starts at level 1, a 48-bit one at level 0 and a 30-bit one at
+        // level 2
+        assert_eq!(AArch64S1StartLevel(walkparams(25)), 1);
+        assert_eq!(AArch64S1StartLevel(walkparams(16)), 0);
+        assert_eq!(AArch64S1StartLevel(walkparams(34)), 2);
+
+        // Each level indexes the table with the next 9 bits of the VA above the page offset
+        for (level, index) in [(1, 0x48), (2, 0x1a2), (3, 0x167)] {
+            let entry = AArch64TTEntryAddress(level, 0, 0, TGx::TGx_4KB, 25, va, tablebase);
+            assert_eq!(
+                entry.address,
+                tablebase.address + index * 8,
+                "level {}",
+                level
+            );
+            assert_eq!(entry.paspace, PASpace::PAS_NonSecure);
+        }
+    }
+
+    #[test]
+    fn next_table_state() {
+        let mut walkstate = TTWState::UNKNOWN;
+        walkstate.level = 1;
+        walkstate.baseaddress.paspace = PASpace::PAS_Secure;
+        let walkparams = walkparams(25);
+        let descriptor = 0x8765_4000 | TABLE;
+
+        let pe = ProcessorElement::new();
+        let next = AArch64S1NextWalkStateTable(
+            &pe,
+            walkstate,
+            0,
+            Regime::Regime_EL2,
+            walkparams,
+            descriptor,
+        );
+        assert!(next.istable);
+        assert_eq!(next.level, 2);
+        assert_eq!(next.baseaddress.address, 0x8765_4000);
+        assert_eq!(next.baseaddress.paspace, PASpace::PAS_Secure);
+
+        // NSTable moves the rest of a Secure walk to the Non-secure PA space
+        let next = AArch64S1NextWalkStateTable(
+            &pe,
+            walkstate,
+            0,
+            Regime::Regime_EL2,
+            walkparams,
+            descriptor | (1 << 63),
+        );
+        assert_eq!(next.baseaddress.paspace, PASpace::PAS_NonSecure);
+    }
+
+    #[test]
+    fn root_leaf_secure_paspace() {
+        let walkparams = walkparams(25);
+        let currentstate = TTWState {
+            level: 3,
+            baseaddress: FullAddress {
+                paspace: PASpace::PAS_Root,
+                address: 0,
+            },
+            ..TTWState::UNKNOWN
+        };
+        let leaf_paspace = |features: &[Feature], descriptor: u128| {
+            let pe = ProcessorElement::from_profile(ArchVersion::Armv8_0, features).unwrap();
+            AArch64S1NextWalkStateLeaf(
+                &pe,
+                currentstate,
+                false,
+                Regime::Regime_EL3,
+                SecurityState::SS_Root,
+                walkparams,
+                descriptor,
+            )
+            .unwrap()
+            .baseaddress
+            .paspace
+        };
+
+        // NSE = 0, NS = 0 selects the Secure PA space when Secure state is implemented
+        let rme = [Feature::FEAT_AA64EL3, Feature::FEAT_RME];
+        let sel2 = [
+            Feature::FEAT_AA64EL3,
+            Feature::FEAT_RME,
+            Feature::FEAT_AA64EL2,
+            Feature::FEAT_SEL2,
+        ];
+        let page = TABLE | (1 << 10);
+        assert_eq!(leaf_paspace(&rme, page), PASpace::PAS_NonSecure);
+        assert_eq!(leaf_paspace(&sel2, page), PASpace::PAS_Secure);
+        // NSE = 1, NS = 0 selects the Root PA space
+        assert_eq!(leaf_paspace(&rme, page | (1 << 11)), PASpace::PAS_Root);
+    }
+
+    #[test]
+    fn s2_walk() {
+        let mut pe = ProcessorElement::new()
+            .with_feature(Feature::FEAT_AA64EL2)
+            .unwrap();
+        pe.PSTATE.set(ProcState::EL, 1);
+        pe.regs.VTTBR_EL2 = 0x10_0000;
+        // 39-bit IPA space starting at level 1, 48-bit PA
+        let walkparams = S2TTWParams {
+            bitfield: S2TTWParamsBits::new()
+                .with(S2TTWParamsBits::sl0, 0b01)
+                .with(S2TTWParamsBits::txsz, 25)
+                .with(S2TTWParamsBits::ps, 0b101),
+            s2pir: 0,
+            tgx: TGx::TGx_4KB,
+        };
+        let mut accdesc = NewAccDesc(&pe, AccessType::AccessType_GPR).unwrap();
+        accdesc.read = true;
+        let mut fault: FaultRecord = unsafe { std::mem::zeroed() };
+        fault.statuscode = Fault::Fault_None;
+        let mut ipa = AddressDescriptor::UNKNOWN;
+        ipa.paddress.address = 0x12_3456_7abc;
+        ipa.paddress.paspace = PASpace::PAS_NonSecure;
+
+        let walk = |mem: &mut SparseMemory, walkparams| {
+            AArch64S2Walk(&pe, mem, fault, ipa, walkparams, accdesc, 64)
+                .unwrap()
+                .0
+        };
+
+        // Each missing entry faults at the level of its table
+        let mut mem = SparseMemory::new();
+        for (level, table, index, next) in [
+            (1, 0x10_0000, 0x48, 0x20_0000),
+            (2, 0x20_0000, 0x1a2, 0x30_0000),
+        ] {
+            let fault = walk(&mut mem, walkparams);
+            assert_eq!(fault.statuscode, Fault::Fault_Translation);
+            assert_eq!(fault.level, level);
+            mem.load(table + index * 8, &(next | TABLE as u64).to_le_bytes());
+        }
+        let fault = walk(&mut mem, walkparams);
+        assert_eq!(fault.statuscode, Fault::Fault_Translation);
+        assert_eq!(fault.level, 3);
+
+        // A level 2 table above the 32-bit output address size
+        mem.load(
+            0x10_0000 + 0x48 * 8,
+            &((1 << 33) | TABLE as u64).to_le_bytes(),
+        );
+        let mut walkparams = walkparams;
+        walkparams.bitfield.set(S2TTWParamsBits::ps, 0b000);
+        let fault = walk(&mut mem, walkparams);
+        assert_eq!(fault.statuscode, Fault::Fault_AddressSize);
+        assert_eq!(fault.level, 1);
+    }
+
+    #[test]
+    fn granule_walk_levels() {
+        let ttbr = 0x100_0000;
+        // TG0, granule size and the start level for a 39-bit VA range
+        for (tg0, granulebits, startlevel) in [(0b00, 12, 1), (0b10, 14, 1), (0b01, 16, 2)] {
+            let mut pe = ProcessorElement::new();
+            pe.PSTATE.set(ProcState::EL, 1);
+            pe.regs.TCR_EL1 = TCR_EL1_REG::new()
+                .with(TCR_EL1_REG::T0SZ, 25)
+                .with(TCR_EL1_REG::TG0, tg0)
+                .with(TCR_EL1_REG::IPS, 0b101);
+            pe.regs.TTBR0_EL1 = TTBR_ELx_REG::from_bits(ttbr.into());
+            let va = 0x12_3456_0000 & !((1 << granulebits) - 1);
+            let regime = Regime::Regime_EL10;
+            let walkparams =
+                AArch64GetS1TTWParams(&pe, regime, SecurityState::SS_NonSecure, va).unwrap();
+            let mut accdesc = NewAccDesc(&pe, AccessType::AccessType_GPR).unwrap();
+            accdesc.read = true;
+            let mut fault: FaultRecord = unsafe { std::mem::zeroed() };
+            fault.statuscode = Fault::Fault_None;
+
+            // Each missing entry faults at the level of its table
+            for level in startlevel..=FINAL_LEVEL {
+                let mut mem = SparseMemory::new();
+                TableBuilder::new(ttbr, granulebits, startlevel).map(&mut mem, va, level, 0);
+                let (fault, _, _, _) =
+                    AArch64S1Walk(&pe, &mut mem, fault, walkparams, va, regime, accdesc, 64)
+                        .unwrap();
+                assert_eq!(fault.statuscode, Fault::Fault_Translation);
+                assert_eq!(fault.level, level, "TG0 {:#b}", tg0);
+            }
+        }
+    }
+
+    /// A Non-secure EL1 PE translating a 39-bit VA range with 4KB granules and
+    /// its tables at `ttbr`.
+    fn el1_pe(ttbr: u64) -> ProcessorElement {
+        let mut pe = ProcessorElement::from_profile(ArchVersion::Armv8_0, &[]).unwrap();
+        pe.PSTATE.set(ProcState::EL, 1);
+        pe.regs.SCTLR_EL1 = pe.regs.SCTLR_EL1.with(SCTLR_ELx_REG::M, 1);
+        pe.regs.TCR_EL1 = TCR_EL1_REG::new().with(TCR_EL1_REG::T0SZ, 25);
+        pe.regs.MAIR_EL1 = MAIR_ELx_REG::new().with(MAIR_ELx_REG::Attr0, 0xff);
+        pe.regs.TTBR0_EL1 = TTBR_ELx_REG::from_bits(ttbr.into());
+        pe
+    }
+
+    fn translate(
+        pe: &ProcessorElement,
+        mem: &mut SparseMemory,
+        memop: MemOp,
+        va: u64,
+    ) -> AddressDescriptor {
+        let accdesc = CreateAccDescGPR(pe, memop, false, true, false).unwrap();
+        AArch64FullTranslate(pe, mem, va, accdesc, true).unwrap()
+    }
+
+    #[test]
+    fn el1_4kb_walk() {
+        const PAGE: u128 = 0b11 | (1 << 10);
+        const BLOCK: u128 = 0b01 | (1 << 10);
+        const AP2: u128 = 1 << 7;
+        let ttbr = 0x100_0000;
+        let pe = el1_pe(ttbr);
+        let tables = TableBuilder::new(ttbr, 12, 1);
+        let load = MemOp::MemOp_LOAD;
+        let store = MemOp::MemOp_STORE;
+
+        // 4KB page
+        let mut mem = SparseMemory::new();
+        tables.map(&mut mem, 0x1234_5000, 3, 0x8000_0000 | PAGE);
+        let addrdesc = translate(&pe, &mut mem, load, 0x1234_5678);
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_None);
+        assert_eq!(addrdesc.paddress.address, 0x8000_0678);
+        assert_eq!(addrdesc.paddress.paspace, PASpace::PAS_NonSecure);
+        assert_eq!(addrdesc.memattrs.memtype, MemType::MemType_Normal);
+
+        // 2MB block
+        let mut mem = SparseMemory::new();
+        tables.map(&mut mem, 0x1234_5000, 2, 0x8020_0000 | BLOCK);
+        let addrdesc = translate(&pe, &mut mem, load, 0x1234_5678);
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_None);
+        assert_eq!(addrdesc.paddress.address, 0x8034_5678);
+
+        // Access flag clear without hardware update
+        let mut mem = SparseMemory::new();
+        tables.map(&mut mem, 0x1234_5000, 3, 0x8000_0000 | 0b11);
+        let addrdesc = translate(&pe, &mut mem, load, 0x1234_5678);
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_AccessFlag);
+        assert_eq!(addrdesc.fault.level, 3);
+
+        // AP[2:1] = 0b10, read-only at EL1
+        let mut mem = SparseMemory::new();
+        tables.map(&mut mem, 0x1234_5000, 3, 0x8000_0000 | PAGE | AP2);
+        let addrdesc = translate(&pe, &mut mem, load, 0x1234_5678);
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_None);
+        let addrdesc = translate(&pe, &mut mem, store, 0x1234_5678);
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_Permission);
+        assert_eq!(addrdesc.fault.level, 3);
+        assert!(addrdesc.fault.write);
+
+        // With the MMU off, the VA is the PA
+        let mut pe = pe;
+        pe.regs.SCTLR_EL1 = pe.regs.SCTLR_EL1.with(SCTLR_ELx_REG::M, 0);
+        let addrdesc = translate(&pe, &mut SparseMemory::new(), store, 0x1234_5678);
+        assert_eq!(addrdesc.fault.statuscode, Fault::Fault_None);
+        assert_eq!(addrdesc.paddress.address, 0x1234_5678);
+        assert_eq!(addrdesc.paddress.paspace, PASpace::PAS_NonSecure);
+    }
+
+    const AF: u128 = 1 << 10;
+
+    /// A Secure EL2 PE with FEAT_D128, 4KB granules and a four level table for `va`
+    /// whose page descriptor is `leaf`.
+    fn d128_walk(leaf: u128, va: u64) -> (FaultRecord, TTWState) {
+        let mut pe = ProcessorElement::from_profile(
+            ArchVersion::Armv9_4,
+            &[
+                Feature::FEAT_D128,
+                Feature::FEAT_S1PIE,
+                Feature::FEAT_TCR2,
+                Feature::FEAT_AA64EL2,
+                Feature::FEAT_AA64EL3,
+                Feature::FEAT_SEL2,
+            ],
+        )
+        .unwrap();
+        pe.impdef.pamax = 56;
+        pe.PSTATE.set(ProcState::EL, 2);
+        pe.regs.SCR_EL3 = pe.regs.SCR_EL3.with(SCR_EL3_REG::EEL2, 1);
+        pe.regs.TTBR0_EL2 = TTBR_ELx_REG::from_bits(0x10_0000);
+
+        let mut mem = SparseMemory::new();
+        TableBuilder::new(0x10_0000, 12, 0)
+            .d128()
+            .map(&mut mem, va, 3, leaf);
+
+        let mut walkparams: S1TTWParams = unsafe { std::mem::zeroed() };
+        walkparams.tgx = TGx::TGx_4KB;
+        walkparams.mair = 0xff00;
+        walkparams.bitfield.set(S1TTWParamsBits::d128, 1);
+        walkparams.bitfield.set(S1TTWParamsBits::pie, 1);
+        walkparams.bitfield.set(S1TTWParamsBits::txsz, 20);
+        walkparams.bitfield.set(S1TTWParamsBits::ps, 0b111);
+        let accdesc = CreateAccDescGPR(&pe, MemOp::MemOp_LOAD, false, true, false).unwrap();
+        assert_eq!(accdesc.ss, SecurityState::SS_Secure);
+        let (fault, _, walkstate, _) = AArch64S1Walk(
+            &pe,
+            &mut mem,
+            FaultRecord::NoFault(),
+            walkparams,
+            va,
+            Regime::Regime_EL2,
+            accdesc,
+            128,
+        )
+        .unwrap();
+        (fault, walkstate)
+    }
+
+    #[test]
+    fn d128_leaf_fields() {
+        let va = 0x12_3456_7000;
+        let oa: u64 = 0xab_cdef_1234_5000;
+
+        // AttrIndx 1 in descriptor<5:2> selects the Normal attribute in MAIR byte 1
+        let (fault, walkstate) = d128_walk(TABLE | AF | oa as u128 | (1 << 2), va);
+        assert_eq!(fault.statuscode, Fault::Fault_None);
+        assert_eq!(walkstate.level, 3);
+        assert_eq!(walkstate.baseaddress.address, oa);
+        assert_eq!(walkstate.baseaddress.paspace, PASpace::PAS_Secure);
+        assert_eq!(walkstate.memattrs.memtype, MemType::MemType_Normal);
+        assert!(!walkstate.guardedpage);
+
+        // descriptor<5> is AttrIndx[3], not NS: index 9 is the zero MAIR2 byte 1
+        let (_, walkstate) = d128_walk(TABLE | AF | oa as u128 | (1 << 5) | (1 << 2), va);
+        assert_eq!(walkstate.baseaddress.paspace, PASpace::PAS_Secure);
+        assert_eq!(walkstate.memattrs.memtype, MemType::MemType_Device);
+
+        // NS is descriptor<127>
+        let (_, walkstate) = d128_walk(TABLE | AF | oa as u128 | (1 << 127), va);
+        assert_eq!(walkstate.baseaddress.paspace, PASpace::PAS_NonSecure);
+
+        // GP is descriptor<113>, descriptor<50> is part of the output address
+        let (_, walkstate) = d128_walk(TABLE | AF | oa as u128 | (1 << 113), va);
+        assert!(walkstate.guardedpage);
+        let (_, walkstate) = d128_walk(TABLE | AF | (1 << 50), va);
+        assert!(!walkstate.guardedpage);
+        assert_eq!(walkstate.baseaddress.address, 1 << 50);
+    }
+}